@@ -73,6 +73,10 @@ fn create_sample_policy() -> CompiledPolicy {
         },
         code,
         constants,
+        patterns: Vec::new(),
+        sets: Vec::new(),
+        effects: Default::default(),
+        conditions: Vec::new(),
     }
 }
 
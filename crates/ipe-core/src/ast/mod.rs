@@ -7,8 +7,9 @@ pub mod types;
 pub mod visitor;
 
 pub use nodes::{
-    AggregateFunc, BinaryOp, ComparisonOp, Condition, Expression, LogicalOp, Metadata, Path,
-    Policy, Requirements, Value,
+    AggregateFunc, ArithmeticOp, BinaryOp, ComparisonOp, Condition, Definition, DefinitionKind,
+    Expression, FileId, Item, LogicalOp, Metadata, Module, Obligation, Path, Policy, PolicySet,
+    Requirements, SetOperator, SourceLocation, Value,
 };
 pub use types::{Type, TypeChecker};
-pub use visitor::{walk_policy, Visitor};
+pub use visitor::{walk_definition, walk_module, walk_policy, Visitor};
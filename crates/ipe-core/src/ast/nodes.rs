@@ -1,7 +1,98 @@
 //! AST node definitions
 
+use super::types::Type;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// A parsed source file holding any number of policies and policy sets
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    /// Optional `package` name shared by everything in the file
+    pub package: Option<String>,
+    /// Top-level policies and policy sets, in source order
+    pub items: Vec<Item>,
+    /// `const` and `predicate` definitions, in source order
+    pub definitions: Vec<Definition>,
+}
+
+/// A top-level policy or policy set
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    /// A policy outside of any set
+    Policy(Policy),
+    /// A policy set and its members
+    PolicySet(PolicySet),
+}
+
+impl Item {
+    /// The item's own policy, or the members of its set
+    pub fn policies(&self) -> &[Policy] {
+        match self {
+            Item::Policy(policy) => std::slice::from_ref(policy),
+            Item::PolicySet(set) => &set.policies,
+        }
+    }
+
+    /// The item's own policy, or the members of its set
+    pub fn policies_mut(&mut self) -> &mut [Policy] {
+        match self {
+            Item::Policy(policy) => std::slice::from_mut(policy),
+            Item::PolicySet(set) => &mut set.policies,
+        }
+    }
+}
+
+impl Module {
+    /// Total number of policies in the module, including those in sets
+    pub fn policy_count(&self) -> usize {
+        self.items.iter().map(|item| item.policies().len()).sum()
+    }
+
+    /// Every policy, including those in sets, in source order
+    pub fn policies(&self) -> impl Iterator<Item = &Policy> {
+        self.items.iter().flat_map(Item::policies)
+    }
+
+    /// Every policy, including those in sets, in source order
+    pub fn policies_mut(&mut self) -> impl Iterator<Item = &mut Policy> {
+        self.items.iter_mut().flat_map(Item::policies_mut)
+    }
+
+    /// The policy sets, in source order
+    pub fn policy_sets(&self) -> impl Iterator<Item = &PolicySet> {
+        self.items.iter().filter_map(|item| match item {
+            Item::PolicySet(set) => Some(set),
+            Item::Policy(_) => None,
+        })
+    }
+
+    /// Qualify a name with the module's package (e.g. `acme.deploy.Name`)
+    pub fn qualified_name(&self, name: &str) -> String {
+        match &self.package {
+            Some(package) => format!("{}.{}", package, name),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// A named group of policies combined with their own algorithm
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicySet {
+    /// Policy set name (identifier)
+    pub name: String,
+    /// Natural language intent string
+    pub intent: String,
+    /// Name of the algorithm used to combine the member policies, such as
+    /// `"first-applicable"` (defaults to deny-overrides)
+    pub combining: Option<String>,
+    /// Metadata shared by every member policy
+    pub metadata: Option<Metadata>,
+    /// Member policies, in source order
+    pub policies: Vec<Policy>,
+    /// Source location
+    pub location: SourceLocation,
+}
+
 /// A complete policy definition
 #[derive(Debug, Clone, PartialEq)]
 pub struct Policy {
@@ -13,8 +104,14 @@ pub struct Policy {
     pub triggers: Vec<Condition>,
     /// Requirements (what must be true for Allow)
     pub requirements: Requirements,
+    /// Obligations the enforcement point must fulfil when this policy decides
+    pub obligations: Vec<Obligation>,
+    /// Advice the enforcement point may act on when this policy decides
+    pub advice: Vec<Obligation>,
     /// Optional metadata
     pub metadata: Option<Metadata>,
+    /// `let` bindings, already inlined into the triggers and requirements
+    pub bindings: Vec<Definition>,
     /// Source location
     pub location: SourceLocation,
 }
@@ -32,11 +129,26 @@ impl Policy {
             intent,
             triggers,
             requirements,
+            obligations: Vec::new(),
+            advice: Vec::new(),
             metadata: None,
+            bindings: Vec::new(),
             location: SourceLocation::default(),
         }
     }
 
+    /// Attach an obligation to the policy
+    pub fn with_obligation(mut self, obligation: Obligation) -> Self {
+        self.obligations.push(obligation);
+        self
+    }
+
+    /// Attach advice to the policy
+    pub fn with_advice(mut self, advice: Obligation) -> Self {
+        self.advice.push(advice);
+        self
+    }
+
     /// Add metadata to the policy
     pub fn with_metadata(mut self, metadata: Metadata) -> Self {
         self.metadata = Some(metadata);
@@ -50,6 +162,68 @@ impl Policy {
     }
 }
 
+/// A named expression: a module `const` or `predicate`, or a policy `let`
+///
+/// The parser inlines references to definitions, so compiled policies never
+/// see them; they stay in the AST for tooling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub kind: DefinitionKind,
+    pub name: String,
+    /// Parameter names (predicates only)
+    pub params: Vec<String>,
+    /// The body as written
+    pub body: Expression,
+    /// Source location
+    pub location: SourceLocation,
+}
+
+impl Definition {
+    /// Create a definition without parameters
+    pub fn new(kind: DefinitionKind, name: impl Into<String>, body: Expression) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            params: Vec::new(),
+            body,
+            location: SourceLocation::default(),
+        }
+    }
+
+    /// Add parameters
+    pub fn with_params(mut self, params: Vec<String>) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Set source location
+    pub fn with_location(mut self, location: SourceLocation) -> Self {
+        self.location = location;
+        self
+    }
+}
+
+/// Kinds of [`Definition`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionKind {
+    /// `const NAME = value`: a context-free value shared by a module
+    Const,
+    /// `predicate name(params): condition`: a reusable condition
+    Predicate,
+    /// `let name = expr`: a named expression local to one policy
+    Let,
+}
+
+impl fmt::Display for DefinitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefinitionKind::Const => write!(f, "const"),
+            DefinitionKind::Predicate => write!(f, "predicate"),
+            DefinitionKind::Let => write!(f, "let"),
+        }
+    }
+}
+
 /// Policy requirements (requires or denies)
 #[derive(Debug, Clone, PartialEq)]
 pub enum Requirements {
@@ -246,6 +420,16 @@ impl Value {
 pub enum BinaryOp {
     // Comparison
     Comparison(ComparisonOp),
+    /// `+ - * / %` on numbers
+    Arithmetic(ArithmeticOp),
+    /// `a ?? b`: `a` unless it is undefined, otherwise `b`
+    Default,
+    /// `a matches "regex"`: whether the regex matches anywhere in `a`
+    Matches,
+    /// `a like "glob"`: whether the glob matches all of `a`
+    Like,
+    /// `in`, `contains`, `intersects` and `subset_of` on arrays
+    Set(SetOperator),
 }
 
 /// Comparison operators
@@ -280,6 +464,52 @@ pub enum LogicalOp {
     Not,
 }
 
+/// Arithmetic operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithmeticOp {
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Mod, // %
+}
+
+impl fmt::Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticOp::Add => write!(f, "+"),
+            ArithmeticOp::Sub => write!(f, "-"),
+            ArithmeticOp::Mul => write!(f, "*"),
+            ArithmeticOp::Div => write!(f, "/"),
+            ArithmeticOp::Mod => write!(f, "%"),
+        }
+    }
+}
+
+/// Set operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SetOperator {
+    /// `x in roles`: `x` is an element of the array
+    In,
+    /// `roles contains x`: the array has `x` as an element
+    Contains,
+    /// `a intersects b`: the arrays share an element
+    Intersects,
+    /// `a subset_of b`: every element of `a` is in `b`
+    SubsetOf,
+}
+
+impl fmt::Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetOperator::In => write!(f, "in"),
+            SetOperator::Contains => write!(f, "contains"),
+            SetOperator::Intersects => write!(f, "intersects"),
+            SetOperator::SubsetOf => write!(f, "subset_of"),
+        }
+    }
+}
+
 impl fmt::Display for LogicalOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -301,6 +531,29 @@ pub enum AggregateFunc {
     Min,
 }
 
+impl AggregateFunc {
+    /// Parse an aggregate from its name in policy source
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(AggregateFunc::Count),
+            "any" => Some(AggregateFunc::Any),
+            "all" => Some(AggregateFunc::All),
+            "sum" => Some(AggregateFunc::Sum),
+            "max" => Some(AggregateFunc::Max),
+            "min" => Some(AggregateFunc::Min),
+            _ => None,
+        }
+    }
+
+    /// Type of the aggregate's result
+    pub fn result_type(&self) -> Type {
+        match self {
+            AggregateFunc::Any | AggregateFunc::All => Type::Bool,
+            _ => Type::Int,
+        }
+    }
+}
+
 impl fmt::Display for AggregateFunc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -314,6 +567,26 @@ impl fmt::Display for AggregateFunc {
     }
 }
 
+/// An obligation or advice attached to a policy (`with obligation redact(field: "ssn")`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Obligation {
+    pub name: String,
+    pub params: Vec<(String, Value)>,
+}
+
+impl Obligation {
+    /// Create an obligation without parameters
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), params: Vec::new() }
+    }
+
+    /// Add a parameter
+    pub fn with_param(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.params.push((key.into(), value));
+        self
+    }
+}
+
 /// Policy metadata
 #[derive(Debug, Clone, PartialEq)]
 pub struct Metadata {
@@ -344,18 +617,47 @@ impl Default for Metadata {
     }
 }
 
+/// Identifies a source file in a [`SourceMap`](crate::diagnostics::SourceMap)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct FileId(pub u32);
+
 /// Source location for error reporting
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+///
+/// `line` and `column` are 1-indexed (columns count characters); `offset` and
+/// `length` are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct SourceLocation {
     pub line: usize,
     pub column: usize,
     pub length: usize,
+    #[serde(default)]
+    pub file: FileId,
+    #[serde(default)]
+    pub offset: usize,
 }
 
 impl SourceLocation {
     /// Create a new source location
     pub fn new(line: usize, column: usize, length: usize) -> Self {
-        Self { line, column, length }
+        Self {
+            line,
+            column,
+            length,
+            file: FileId::default(),
+            offset: 0,
+        }
+    }
+
+    /// Set the file and byte offset
+    pub fn with_span(mut self, file: FileId, offset: usize) -> Self {
+        self.file = file;
+        self.offset = offset;
+        self
+    }
+
+    /// Byte range covered in the source file
+    pub fn span(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.length
     }
 }
 
@@ -1,7 +1,11 @@
 //! Type system for IPE policies
 
-use super::nodes::{Condition, Expression, Value};
-use std::collections::HashMap;
+use super::nodes::{BinaryOp, Condition, Expression, Path, SetOperator, Value};
+use crate::functions::FunctionRegistry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
 
 /// Type information
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -31,6 +35,37 @@ impl Type {
         }
     }
 
+    /// Whether arithmetic accepts this type
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float | Type::Any)
+    }
+
+    /// Result type of arithmetic on `left` and `right`
+    ///
+    /// A float operand widens the result; an `Any` operand may be either.
+    pub fn arithmetic(left: &Type, right: &Type) -> Type {
+        match (left, right) {
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Float, t) | (t, Type::Float) if t.is_numeric() => Type::Float,
+            _ => Type::Any,
+        }
+    }
+
+    /// Parse a type name as written by [`fmt::Display`] (`int`, `array<string>`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "string" => Some(Type::String),
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "bool" => Some(Type::Bool),
+            "any" => Some(Type::Any),
+            name => {
+                let element = name.strip_prefix("array<")?.strip_suffix('>')?;
+                Some(Type::Array(Box::new(Type::from_name(element)?)))
+            },
+        }
+    }
+
     /// Get type from value
     pub fn from_value(value: &Value) -> Self {
         match value {
@@ -49,16 +84,38 @@ impl Type {
     }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::String => write!(f, "string"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Array(element) => write!(f, "array<{}>", element),
+            Type::Resource(name) => write!(f, "{}", name),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
 /// Type environment for type checking
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
     variables: HashMap<String, Type>,
+    /// Types of known field paths; a trailing `*` matches any last segment
+    fields: HashMap<String, Type>,
+    /// Variables whose fields are all declared in `fields`
+    closed: HashSet<String>,
 }
 
 impl TypeEnv {
     /// Create a new type environment
     pub fn new() -> Self {
-        Self { variables: HashMap::new() }
+        Self {
+            variables: HashMap::new(),
+            fields: HashMap::new(),
+            closed: HashSet::new(),
+        }
     }
 
     /// Add a variable binding
@@ -66,17 +123,87 @@ impl TypeEnv {
         self.variables.insert(name, typ);
     }
 
+    /// Declare the type of a field path (e.g. `action.operation`)
+    ///
+    /// A trailing `*` segment declares every field under that prefix.
+    pub fn bind_field(&mut self, path: &str, typ: Type) {
+        self.fields.insert(path.to_string(), typ);
+    }
+
+    /// Remove a field declaration, including a trailing `*` one
+    pub fn unbind_field(&mut self, path: &str) {
+        self.fields.remove(path);
+    }
+
+    /// Declared variables and their types
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.variables.iter().map(|(name, typ)| (name.as_str(), typ))
+    }
+
+    /// Declared field paths and their types
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.fields.iter().map(|(path, typ)| (path.as_str(), typ))
+    }
+
+    /// Only allow the declared fields of a variable, or of a field path
+    /// such as `request.principal`
+    pub fn close(&mut self, name: &str) {
+        self.closed.insert(name.to_string());
+    }
+
     /// Look up a variable type
     pub fn lookup(&self, name: &str) -> Option<&Type> {
         self.variables.get(name)
     }
 
+    /// Look up the type of a field path
+    ///
+    /// Returns `Ok(None)` for undeclared fields of open variables and for
+    /// objects with declared fields, and the offending field (relative to
+    /// the variable) for undeclared fields of closed ones.
+    pub fn lookup_field(&self, path: &Path) -> Result<Option<&Type>, String> {
+        let full = path.to_string();
+        if let Some(typ) = self.fields.get(&full) {
+            return Ok(Some(typ));
+        }
+
+        let parent = &path.segments[..path.segments.len() - 1];
+        if let Some(typ) = self.fields.get(&format!("{}.*", parent.join("."))) {
+            return Ok(Some(typ));
+        }
+
+        let prefix = format!("{}.", full);
+        if self.fields.keys().any(|field| field.starts_with(&prefix)) {
+            return Ok(None);
+        }
+
+        let closed = (1..path.segments.len())
+            .any(|len| self.closed.contains(&path.segments[..len].join(".")));
+        if closed {
+            Err(path.segments[1..].join("."))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Create standard environment with built-in variables
     pub fn standard() -> Self {
         let mut env = Self::new();
         env.bind("resource".to_string(), Type::Resource("Resource".to_string()));
         env.bind("action".to_string(), Type::Resource("Action".to_string()));
         env.bind("request".to_string(), Type::Resource("Request".to_string()));
+
+        env.bind_field("resource.type", Type::Int);
+
+        env.bind_field("action.operation", Type::String);
+        env.bind_field("action.target", Type::String);
+        env.bind_field("action.attributes.*", Type::Any);
+        env.close("action");
+
+        env.bind_field("request.principal.id", Type::String);
+        env.bind_field("request.principal.roles", Type::Array(Box::new(Type::String)));
+        env.bind_field("request.timestamp", Type::Int);
+        env.bind_field("request.source_ip", Type::String);
         env
     }
 }
@@ -90,13 +217,24 @@ impl Default for TypeEnv {
 /// Type checker for expressions
 pub struct TypeChecker {
     env: TypeEnv,
+    functions: Arc<FunctionRegistry>,
     errors: Vec<TypeError>,
 }
 
 impl TypeChecker {
     /// Create a new type checker
     pub fn new(env: TypeEnv) -> Self {
-        Self { env, errors: Vec::new() }
+        Self {
+            env,
+            functions: FunctionRegistry::builtins(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Check function calls against the given registry instead of the builtins
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = functions;
+        self
     }
 
     /// Check the type of an expression
@@ -105,6 +243,21 @@ impl TypeChecker {
             Expression::Literal(value) => Type::from_value(value),
 
             Expression::Path(path) => {
+                if path.segments.len() > 1 {
+                    // Field access: use the declared field type, if any
+                    return match self.env.lookup_field(path) {
+                        Ok(typ) => typ.cloned().unwrap_or(Type::Any),
+                        Err(field) => {
+                            let base = self.env.lookup(path.root().unwrap_or_default());
+                            self.errors.push(TypeError::InvalidFieldAccess {
+                                base: base.cloned().unwrap_or(Type::Any),
+                                field,
+                            });
+                            Type::Any
+                        },
+                    };
+                }
+
                 // Look up the root in environment
                 if let Some(root) = path.root() {
                     self.env.lookup(root).cloned().unwrap_or(Type::Any)
@@ -113,6 +266,72 @@ impl TypeChecker {
                 }
             },
 
+            Expression::Binary { left, op: BinaryOp::Default, right } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+
+                // The fallback stands in for the value, so their types must agree
+                if !left_type.is_compatible_with(&right_type) {
+                    self.errors.push(TypeError::IncompatibleTypes {
+                        left: left_type.clone(),
+                        right: right_type.clone(),
+                    });
+                }
+
+                if left_type == Type::Any {
+                    right_type
+                } else {
+                    left_type
+                }
+            },
+
+            Expression::Binary { left, op: BinaryOp::Arithmetic(_), right } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+
+                for typ in [&left_type, &right_type] {
+                    if !typ.is_numeric() {
+                        self.errors.push(TypeError::ExpectedNumeric { got: typ.clone() });
+                    }
+                }
+                Type::arithmetic(&left_type, &right_type)
+            },
+
+            Expression::Binary {
+                left,
+                op: BinaryOp::Matches | BinaryOp::Like,
+                right,
+            } => {
+                // Both the text and the pattern must be strings
+                for operand in [left, right] {
+                    let typ = self.check_expression(operand);
+                    if !typ.is_compatible_with(&Type::String) {
+                        self.errors
+                            .push(TypeError::IncompatibleTypes { left: typ, right: Type::String });
+                    }
+                }
+                Type::Bool
+            },
+
+            Expression::Binary { left, op: BinaryOp::Set(set_op), right } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+
+                // `in` and `contains` relate a value to an array, the others two arrays
+                let (left_elem, right_elem) = match set_op {
+                    SetOperator::In => (left_type, self.element_type(right_type)),
+                    SetOperator::Contains => (self.element_type(left_type), right_type),
+                    SetOperator::Intersects | SetOperator::SubsetOf => {
+                        (self.element_type(left_type), self.element_type(right_type))
+                    },
+                };
+                if !left_elem.is_compatible_with(&right_elem) {
+                    self.errors
+                        .push(TypeError::IncompatibleTypes { left: left_elem, right: right_elem });
+                }
+                Type::Bool
+            },
+
             Expression::Binary { left, op: _, right } => {
                 let left_type = self.check_expression(left);
                 let right_type = self.check_expression(right);
@@ -147,9 +366,12 @@ impl TypeChecker {
                 Type::Bool
             },
 
-            Expression::Aggregate { .. } => {
-                // Aggregate functions return their specific type
-                Type::Int // Most aggregates return numbers
+            Expression::Aggregate { func, condition, .. } => {
+                let typ = self.check_condition(condition);
+                if !matches!(typ, Type::Bool | Type::Any) {
+                    self.errors.push(TypeError::ExpectedBool { got: typ });
+                }
+                func.result_type()
             },
 
             Expression::Call { name, args } => {
@@ -159,18 +381,55 @@ impl TypeChecker {
         }
     }
 
+    /// Element type of an array operand, recording an error for non-arrays
+    fn element_type(&mut self, typ: Type) -> Type {
+        match typ {
+            Type::Array(element) => *element,
+            Type::Any => Type::Any,
+            got => {
+                self.errors.push(TypeError::ExpectedArray { got });
+                Type::Any
+            },
+        }
+    }
+
     /// Check a condition
     pub fn check_condition(&mut self, cond: &Condition) -> Type {
         self.check_expression(&cond.expr)
     }
 
-    fn check_function_call(&mut self, _name: &str, args: &[Expression]) -> Type {
-        // Type check arguments
-        for arg in args {
-            self.check_expression(arg);
+    fn check_function_call(&mut self, name: &str, args: &[Expression]) -> Type {
+        if let Some(expanded) = self.functions.expand_clock(name, args) {
+            return self.check_expression(&expanded);
+        }
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.check_expression(arg)).collect();
+
+        let Some(function) = self.functions.lookup(name) else {
+            self.errors.push(TypeError::UnknownFunction { name: name.to_string() });
+            return Type::Any;
+        };
+        let signature = function.signature.clone();
+
+        if arg_types.len() != signature.arity() {
+            self.errors.push(TypeError::ArityMismatch {
+                name: name.to_string(),
+                expected: signature.arity(),
+                got: arg_types.len(),
+            });
+        } else {
+            for (index, (expected, got)) in signature.params.iter().zip(arg_types).enumerate() {
+                if !expected.is_compatible_with(&got) {
+                    self.errors.push(TypeError::ArgumentType {
+                        name: name.to_string(),
+                        index,
+                        expected: expected.clone(),
+                        got,
+                    });
+                }
+            }
         }
-        // Return Any for now - would need function signature database
-        Type::Any
+
+        signature.returns
     }
 
     /// Get collected errors
@@ -185,18 +444,32 @@ impl TypeChecker {
 }
 
 /// Type checking errors
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum TypeError {
+    #[error("Incompatible types: {left} and {right}")]
     IncompatibleTypes { left: Type, right: Type },
+    #[error("Expected bool, got {got}")]
     ExpectedBool { got: Type },
+    #[error("Expected a number, got {got}")]
+    ExpectedNumeric { got: Type },
+    #[error("Expected an array, got {got}")]
+    ExpectedArray { got: Type },
+    #[error("Undefined variable '{name}'")]
     UndefinedVariable { name: String },
+    #[error("{base} has no field '{field}'")]
     InvalidFieldAccess { base: Type, field: String },
+    #[error("Unknown function '{name}'")]
+    UnknownFunction { name: String },
+    #[error("{name}() expects {expected} argument(s), got {got}")]
+    ArityMismatch { name: String, expected: usize, got: usize },
+    #[error("Argument {index} of {name}() must be {expected}, got {got}")]
+    ArgumentType { name: String, index: usize, expected: Type, got: Type },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::nodes::{BinaryOp, ComparisonOp};
+    use crate::ast::nodes::{ArithmeticOp, BinaryOp, ComparisonOp, SetOperator};
 
     #[test]
     fn test_type_compatibility() {
@@ -209,6 +482,23 @@ mod tests {
         assert!(Type::String.is_compatible_with(&Type::Any));
     }
 
+    #[test]
+    fn test_type_names() {
+        let types = [
+            Type::String,
+            Type::Int,
+            Type::Float,
+            Type::Bool,
+            Type::Any,
+            Type::Array(Box::new(Type::Array(Box::new(Type::String)))),
+        ];
+        for typ in types {
+            assert_eq!(Type::from_name(&typ.to_string()), Some(typ));
+        }
+        assert_eq!(Type::from_name("array<nope>"), None);
+        assert_eq!(Type::from_name("Resource"), None);
+    }
+
     #[test]
     fn test_type_from_value() {
         assert_eq!(Type::from_value(&Value::String("test".to_string())), Type::String);
@@ -269,11 +559,76 @@ mod tests {
         let env = TypeEnv::standard();
         let mut checker = TypeChecker::new(env);
 
+        let expr = Expression::path(vec!["resource".to_string()]);
+        assert!(matches!(checker.check_expression(&expr), Type::Resource(_)));
+
         let expr = Expression::path(vec!["resource".to_string(), "type".to_string()]);
-        let typ = checker.check_expression(&expr);
+        assert_eq!(checker.check_expression(&expr), Type::Int);
+
+        // Undeclared resource attributes are dynamically typed
+        let expr = Expression::path(vec!["resource".to_string(), "owner".to_string()]);
+        assert_eq!(checker.check_expression(&expr), Type::Any);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn test_check_action_paths() {
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let path = |p: &str| Expression::path(p.split('.').map(str::to_string).collect());
 
-        assert!(matches!(typ, Type::Resource(_)));
+        assert_eq!(checker.check_expression(&path("action.operation")), Type::String);
+        assert_eq!(checker.check_expression(&path("action.target")), Type::String);
+        assert_eq!(checker.check_expression(&path("action.attributes.reason")), Type::Any);
         assert!(!checker.has_errors());
+
+        checker.check_expression(&path("action.verb"));
+        assert_eq!(
+            checker.errors(),
+            &[TypeError::InvalidFieldAccess {
+                base: Type::Resource("Action".to_string()),
+                field: "verb".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_request_paths() {
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let path = |p: &str| Expression::path(p.split('.').map(str::to_string).collect());
+
+        assert_eq!(
+            checker.check_expression(&path("request.principal.roles")),
+            Type::Array(Box::new(Type::String))
+        );
+        assert_eq!(checker.check_expression(&path("request.timestamp")), Type::Int);
+        assert_eq!(checker.check_expression(&path("request.principal.claims.groups")), Type::Any);
+        assert_eq!(checker.check_expression(&path("resource.labels.team")), Type::Any);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn test_check_closed_field_path() {
+        let mut env = TypeEnv::standard();
+        env.bind_field("request.principal.clearance", Type::Int);
+        env.bind_field("request.principal.groups.*", Type::String);
+        env.close("request.principal");
+        let mut checker = TypeChecker::new(env);
+        let path = |p: &str| Expression::path(p.split('.').map(str::to_string).collect());
+
+        assert_eq!(checker.check_expression(&path("request.principal.clearance")), Type::Int);
+        assert_eq!(checker.check_expression(&path("request.principal.groups.ops")), Type::String);
+        assert_eq!(checker.check_expression(&path("request.principal.groups")), Type::Any);
+        assert_eq!(checker.check_expression(&path("request.trace_id")), Type::Any);
+        assert!(!checker.has_errors());
+
+        checker.check_expression(&path("request.principal.team"));
+        assert_eq!(
+            checker.errors(),
+            &[TypeError::InvalidFieldAccess {
+                base: Type::Resource("Request".to_string()),
+                field: "principal.team".to_string(),
+            }]
+        );
     }
 
     #[test]
@@ -309,6 +664,85 @@ mod tests {
         assert_eq!(checker.errors().len(), 1);
     }
 
+    #[test]
+    fn test_check_arithmetic() {
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let arith =
+            |left, right| Expression::binary(left, BinaryOp::Arithmetic(ArithmeticOp::Add), right);
+
+        let expr = arith(Expression::literal(Value::Int(1)), Expression::literal(Value::Int(2)));
+        assert_eq!(checker.check_expression(&expr), Type::Int);
+        let expr =
+            arith(Expression::literal(Value::Int(1)), Expression::literal(Value::Float(2.0)));
+        assert_eq!(checker.check_expression(&expr), Type::Float);
+        let expr = arith(
+            Expression::path(vec!["resource".to_string(), "replicas".to_string()]),
+            Expression::literal(Value::Int(2)),
+        );
+        assert_eq!(checker.check_expression(&expr), Type::Any);
+        assert!(!checker.has_errors());
+
+        let expr = arith(
+            Expression::literal(Value::String("a".to_string())),
+            Expression::literal(Value::Int(1)),
+        );
+        checker.check_expression(&expr);
+        assert_eq!(checker.errors(), &[TypeError::ExpectedNumeric { got: Type::String }]);
+    }
+
+    #[test]
+    fn test_check_set_operators() {
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let roles = || Expression::path(vec!["request".into(), "principal".into(), "roles".into()]);
+        let set = |left, op, right| Expression::binary(left, BinaryOp::Set(op), right);
+        let strings = Expression::literal(Value::Array(vec![Value::String("admin".into())]));
+
+        let expr =
+            set(Expression::literal(Value::String("admin".into())), SetOperator::In, roles());
+        assert_eq!(checker.check_expression(&expr), Type::Bool);
+        checker.check_expression(&set(roles(), SetOperator::Intersects, strings.clone()));
+        checker.check_expression(&set(strings, SetOperator::SubsetOf, roles()));
+        assert!(!checker.has_errors());
+
+        checker.check_expression(&set(
+            roles(),
+            SetOperator::Contains,
+            Expression::literal(Value::Int(1)),
+        ));
+        assert_eq!(
+            checker.errors(),
+            &[TypeError::IncompatibleTypes { left: Type::String, right: Type::Int }]
+        );
+
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let id = Expression::path(vec!["request".into(), "principal".into(), "id".into()]);
+        checker.check_expression(&set(id, SetOperator::Intersects, roles()));
+        assert_eq!(checker.errors(), &[TypeError::ExpectedArray { got: Type::String }]);
+    }
+
+    #[test]
+    fn test_check_pattern_operators() {
+        let mut checker = TypeChecker::new(TypeEnv::standard());
+        let expr = Expression::binary(
+            Expression::path(vec!["resource".to_string(), "name".to_string()]),
+            BinaryOp::Like,
+            Expression::literal(Value::String("svc-*".to_string())),
+        );
+        assert_eq!(checker.check_expression(&expr), Type::Bool);
+        assert!(!checker.has_errors());
+
+        let expr = Expression::binary(
+            Expression::literal(Value::Int(3)),
+            BinaryOp::Matches,
+            Expression::literal(Value::String("3".to_string())),
+        );
+        checker.check_expression(&expr);
+        assert_eq!(
+            checker.errors(),
+            &[TypeError::IncompatibleTypes { left: Type::Int, right: Type::String }]
+        );
+    }
+
     #[test]
     fn test_check_logical_and() {
         let env = TypeEnv::new();
@@ -392,4 +826,75 @@ mod tests {
         assert!(t1.is_compatible_with(&t2));
         assert!(!t1.is_compatible_with(&t3));
     }
+
+    #[test]
+    fn test_check_function_call() {
+        let mut checker = TypeChecker::new(TypeEnv::new());
+
+        let expr = Expression::Call {
+            name: "len".to_string(),
+            args: vec![Expression::literal(Value::String("abc".to_string()))],
+        };
+        assert_eq!(checker.check_expression(&expr), Type::Int);
+        assert!(!checker.has_errors());
+    }
+
+    #[test]
+    fn test_check_host_function_call() {
+        use crate::functions::{FunctionRegistry, FunctionSignature};
+
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register("is_on_call", FunctionSignature::new(vec![Type::String], Type::Bool), |_| {
+                Ok(crate::bytecode::Value::Bool(true))
+            })
+            .unwrap();
+        let mut checker = TypeChecker::new(TypeEnv::new()).with_functions(Arc::new(registry));
+
+        let call = |arg| Expression::Call {
+            name: "is_on_call".to_string(),
+            args: vec![arg],
+        };
+        assert_eq!(
+            checker.check_expression(&call(Expression::literal(Value::String("a".to_string())))),
+            Type::Bool
+        );
+        assert!(!checker.has_errors());
+
+        checker.check_expression(&call(Expression::literal(Value::Int(1))));
+        assert!(matches!(checker.errors(), [TypeError::ArgumentType { index: 0, .. }]));
+    }
+
+    #[test]
+    fn test_check_function_call_errors() {
+        let mut checker = TypeChecker::new(TypeEnv::new());
+
+        checker.check_expression(&Expression::Call { name: "nope".to_string(), args: vec![] });
+        checker.check_expression(&Expression::Call { name: "lower".to_string(), args: vec![] });
+        checker.check_expression(&Expression::Call {
+            name: "starts_with".to_string(),
+            args: vec![
+                Expression::literal(Value::String("a".to_string())),
+                Expression::literal(Value::Bool(true)),
+            ],
+        });
+
+        assert_eq!(
+            checker.errors(),
+            &[
+                TypeError::UnknownFunction { name: "nope".to_string() },
+                TypeError::ArityMismatch {
+                    name: "lower".to_string(),
+                    expected: 1,
+                    got: 0
+                },
+                TypeError::ArgumentType {
+                    name: "starts_with".to_string(),
+                    index: 1,
+                    expected: Type::String,
+                    got: Type::Bool,
+                },
+            ]
+        );
+    }
 }
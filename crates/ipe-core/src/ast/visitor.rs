@@ -1,9 +1,19 @@
 //! Visitor pattern for traversing AST
 
-use super::nodes::{Condition, Expression, Path, Policy, Requirements, Value};
+use super::nodes::{Condition, Definition, Expression, Module, Path, Policy, Requirements, Value};
 
 /// Visitor trait for AST traversal
 pub trait Visitor: Sized {
+    /// Visit a module
+    fn visit_module(&mut self, module: &Module) {
+        walk_module(self, module);
+    }
+
+    /// Visit a `const`, `predicate` or `let` definition
+    fn visit_definition(&mut self, definition: &Definition) {
+        walk_definition(self, definition);
+    }
+
     /// Visit a policy
     fn visit_policy(&mut self, policy: &Policy) {
         walk_policy(self, policy);
@@ -35,8 +45,28 @@ pub trait Visitor: Sized {
     }
 }
 
+/// Walk a module: its definitions, then every policy, including those in sets
+pub fn walk_module<V: Visitor>(visitor: &mut V, module: &Module) {
+    for definition in &module.definitions {
+        visitor.visit_definition(definition);
+    }
+    for policy in module.policies() {
+        visitor.visit_policy(policy);
+    }
+}
+
+/// Walk a definition's body
+pub fn walk_definition<V: Visitor>(visitor: &mut V, definition: &Definition) {
+    visitor.visit_expression(&definition.body);
+}
+
 /// Walk a policy node
 pub fn walk_policy<V: Visitor>(visitor: &mut V, policy: &Policy) {
+    // Visit `let` bindings
+    for binding in &policy.bindings {
+        visitor.visit_definition(binding);
+    }
+
     // Visit triggers
     for trigger in &policy.triggers {
         visitor.visit_condition(trigger);
@@ -357,4 +387,32 @@ mod tests {
         assert!(collector.paths.contains(&"resource.type".to_string()));
         assert!(collector.paths.contains(&"expected.value".to_string()));
     }
+
+    #[test]
+    fn test_module_definitions() {
+        let source = r#"
+predicate is_prod: resource.env == "prod"
+
+policy P: "definitions are visited before policies"
+  let limit = resource.limit
+  triggers when is_prod
+  requires request.principal.level <= limit
+"#;
+        let module = crate::parser::parse_module(source).unwrap();
+
+        let mut collector = PathCollector::new();
+        collector.visit_module(&module);
+
+        // Bodies are visited where they are defined and again where they are inlined
+        assert_eq!(
+            collector.paths,
+            vec![
+                "resource.env",
+                "resource.limit",
+                "resource.env",
+                "request.principal.level",
+                "resource.limit",
+            ]
+        );
+    }
 }
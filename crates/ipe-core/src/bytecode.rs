@@ -1,4 +1,6 @@
+use crate::ast::SourceLocation;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 
 /// Bytecode instruction set
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -12,18 +14,37 @@ pub enum Instruction {
     /// Compare two values on the stack
     Compare { op: CompOp },
 
+    /// Apply an arithmetic operator to two values on the stack
+    Arith { op: ArithOp },
+
     /// Unconditional jump
     Jump { offset: i16 },
 
     /// Jump if top of stack is false
     JumpIfFalse { offset: i16 },
 
+    /// Jump if top of stack is true
+    JumpIfTrue { offset: i16 },
+
+    /// Jump if top of stack is not `Undefined`
+    JumpIfDefined { offset: i16 },
+
+    /// Duplicate the top of stack
+    Dup,
+
+    /// Discard the top of stack
+    Pop,
+
     /// Call a built-in function
     Call { func: u8, argc: u8 },
 
     /// Return from policy evaluation
     Return { value: bool },
 
+    /// Return from policy evaluation without a decision because the
+    /// policy's `triggers when` guard did not match the request
+    NotApplicable,
+
     /// Logical AND of two boolean values
     And,
 
@@ -32,6 +53,65 @@ pub enum Instruction {
 
     /// Logical NOT of a boolean value
     Not,
+
+    /// Pop an array and start iterating over it
+    ///
+    /// The loop body follows and ends at the matching `AggregateNext`. An
+    /// empty array pushes the aggregate's empty result and jumps `skip`
+    /// instructions ahead, past the `AggregateNext`.
+    AggregateBegin { op: AggregateOp, skip: i16 },
+
+    /// Pop the body's condition, fold the current element into the aggregate
+    /// and jump `offset` back to the body for the next element. After the last
+    /// element (or once the result is known) the result is pushed instead.
+    AggregateNext { offset: i16 },
+
+    /// Push the element of the innermost aggregate loop
+    LoadElement,
+
+    /// Pop a map and push its field named by the string constant `idx`
+    GetField { idx: u16 },
+
+    /// Pop a string and push whether the precompiled pattern `pattern` matches
+    /// it; `Undefined` never matches
+    Match { pattern: u16 },
+
+    /// Pop two values and push the result of a set operator on them
+    SetCompare { op: SetOp },
+
+    /// Pop a value and push whether it is in the precompiled set `set`
+    InSet { set: u16 },
+}
+
+/// Aggregate operations over an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateOp {
+    /// Number of elements matching the condition
+    Count,
+    /// Whether any element matches the condition
+    Any,
+    /// Whether every element matches the condition
+    All,
+    /// Sum of the matching (numeric) elements
+    Sum,
+    /// Largest matching (numeric) element
+    Max,
+    /// Smallest matching (numeric) element
+    Min,
+}
+
+impl AggregateOp {
+    /// Name of the aggregate as written in policies
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateOp::Count => "count",
+            AggregateOp::Any => "any",
+            AggregateOp::All => "all",
+            AggregateOp::Sum => "sum",
+            AggregateOp::Max => "max",
+            AggregateOp::Min => "min",
+        }
+    }
 }
 
 /// Comparison operators
@@ -45,13 +125,66 @@ pub enum CompOp {
     Gte, // >=
 }
 
+/// Arithmetic operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArithOp {
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Mod, // %
+}
+
+impl ArithOp {
+    /// Operator as written in policies
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+            ArithOp::Mod => "%",
+        }
+    }
+}
+
+/// Set operators over arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SetOp {
+    /// Left is an element of the right array
+    In,
+    /// Left array has the right value as an element
+    Contains,
+    /// The arrays share an element
+    Intersects,
+    /// Every element of the left array is in the right one
+    SubsetOf,
+}
+
+impl SetOp {
+    /// Operator as written in policies
+    pub fn name(&self) -> &'static str {
+        match self {
+            SetOp::In => "in",
+            SetOp::Contains => "contains",
+            SetOp::Intersects => "intersects",
+            SetOp::SubsetOf => "subset_of",
+        }
+    }
+}
+
 /// Runtime values
 /// Optimized for cache coherency with small string optimization
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Int(i64),
+    Float(f64),
     Bool(bool),
     String(String),
+    Array(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    /// A missing attribute or field
+    Undefined,
 }
 
 impl Value {
@@ -61,6 +194,12 @@ impl Value {
         Value::Int(i)
     }
 
+    /// Create a Value from a float (inline hint for optimizer)
+    #[inline]
+    pub const fn from_float(f: f64) -> Self {
+        Value::Float(f)
+    }
+
     /// Create a Value from a boolean (inline hint for optimizer)
     #[inline]
     pub const fn from_bool(b: bool) -> Self {
@@ -76,20 +215,73 @@ impl Value {
         match self {
             Value::Bool(b) => *b,
             Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
             Value::String(s) => !s.is_empty(),
+            Value::Array(items) => !items.is_empty(),
+            Value::Map(fields) => !fields.is_empty(),
+            Value::Undefined => false,
+        }
+    }
+
+    /// Check if the value is present (not `Undefined`)
+    #[inline]
+    pub fn is_defined(&self) -> bool {
+        !matches!(self, Value::Undefined)
+    }
+
+    /// Approximate size of the value's payload in bytes
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            Value::Int(_) | Value::Float(_) => 8,
+            Value::Bool(_) => 1,
+            Value::Undefined => 0,
+            Value::String(s) => s.len(),
+            Value::Array(items) => items.iter().map(Value::size_bytes).sum(),
+            Value::Map(fields) => fields.iter().map(|(k, v)| k.len() + v.size_bytes()).sum(),
+        }
+    }
+
+    /// Numeric value as a float, coercing integers
+    #[inline]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
         }
     }
 
     /// Compare two values using the given comparison operator
+    ///
+    /// An `Int` compared with a `Float` is widened to a float, matching
+    /// `Type::is_compatible_with`. Comparisons involving NaN are false
+    /// (except `!=`). `Undefined` only equals itself and never orders, so a
+    /// missing attribute fails every comparison but `!=` instead of erroring.
     /// Marked inline for hot path optimization
     #[inline]
     pub fn compare(&self, other: &Value, op: CompOp) -> Result<bool, String> {
         match (self, other) {
+            (Value::Undefined, _) | (_, Value::Undefined) => Ok(match op {
+                CompOp::Eq => self == other,
+                CompOp::Neq => self != other,
+                _ => false,
+            }),
             (Value::Int(a), Value::Int(b)) => Ok(Self::compare_int(*a, *b, op)),
+            (Value::Float(_), Value::Int(_) | Value::Float(_))
+            | (Value::Int(_), Value::Float(_)) => {
+                let (a, b) =
+                    (self.as_f64().unwrap_or_default(), other.as_f64().unwrap_or_default());
+                Ok(Self::compare_ordered(a, b, op))
+            },
             (Value::String(a), Value::String(b)) => {
                 Ok(Self::compare_ordered(a.as_str(), b.as_str(), op))
             },
             (Value::Bool(a), Value::Bool(b)) => Ok(Self::compare_bools(*a, *b, op)),
+            (Value::Array(_), Value::Array(_)) | (Value::Map(_), Value::Map(_)) => match op {
+                CompOp::Eq => Ok(self == other),
+                CompOp::Neq => Ok(self != other),
+                _ => Err(format!("Cannot order {:?} and {:?}", self, other)),
+            },
             _ => Err(format!("Cannot compare {:?} with {:?}", self, other)),
         }
     }
@@ -130,6 +322,290 @@ impl Value {
             _ => false, // < > <= >= not supported for booleans
         }
     }
+
+    /// Apply an arithmetic operator
+    ///
+    /// `Int` arithmetic is checked: overflow and division or remainder by zero
+    /// are errors rather than wrapping or panicking. Division truncates toward
+    /// zero and `%` takes the sign of the dividend. An `Int` with a `Float` is
+    /// widened to a float. `Undefined` propagates, so a missing attribute fails
+    /// the comparison it feeds (or falls back through `??`).
+    #[inline]
+    pub fn arith(&self, other: &Value, op: ArithOp) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Undefined, _) | (_, Value::Undefined) => Ok(Value::Undefined),
+            (Value::Int(a), Value::Int(b)) => {
+                let (a, b) = (*a, *b);
+                let result = match op {
+                    ArithOp::Add => a.checked_add(b),
+                    ArithOp::Sub => a.checked_sub(b),
+                    ArithOp::Mul => a.checked_mul(b),
+                    ArithOp::Div | ArithOp::Mod if b == 0 => {
+                        return Err(format!("Division by zero in {} {} {}", a, op.symbol(), b))
+                    },
+                    ArithOp::Div => a.checked_div(b),
+                    ArithOp::Mod => a.checked_rem(b),
+                };
+                result
+                    .map(Value::Int)
+                    .ok_or_else(|| format!("Integer overflow in {} {} {}", a, op.symbol(), b))
+            },
+            (Value::Float(_), Value::Int(_) | Value::Float(_))
+            | (Value::Int(_), Value::Float(_)) => {
+                let (a, b) =
+                    (self.as_f64().unwrap_or_default(), other.as_f64().unwrap_or_default());
+                Ok(Value::Float(match op {
+                    ArithOp::Add => a + b,
+                    ArithOp::Sub => a - b,
+                    ArithOp::Mul => a * b,
+                    ArithOp::Div | ArithOp::Mod if b == 0.0 => {
+                        return Err(format!("Division by zero in {} {} {}", a, op.symbol(), b))
+                    },
+                    ArithOp::Div => a / b,
+                    ArithOp::Mod => a % b,
+                }))
+            },
+            _ => Err(format!("Cannot apply {} to {:?} and {:?}", op.symbol(), self, other)),
+        }
+    }
+}
+
+impl Value {
+    /// Apply a set operator
+    ///
+    /// Elements are equal when `==` would hold, so an `Int` element matches an
+    /// equal `Float`. A missing (`Undefined`) operand makes the result false,
+    /// like the comparisons; any other non-array collection is an error.
+    pub fn set_compare(&self, other: &Value, op: SetOp) -> Result<bool, String> {
+        // `in` is `contains` with the operands swapped
+        let (collection, operand) = match op {
+            SetOp::In => (other, self),
+            _ => (self, other),
+        };
+        let Some(items) = collection.elements(op)? else {
+            return Ok(false);
+        };
+
+        match op {
+            SetOp::In | SetOp::Contains => {
+                Ok(operand.is_defined() && items.iter().any(|item| item.set_eq(operand)))
+            },
+            SetOp::Intersects | SetOp::SubsetOf => {
+                let Some(others) = operand.elements(op)? else {
+                    return Ok(false);
+                };
+                // Hash the right-hand array when its elements allow it
+                let lookup = ValueSet::new(others.to_vec()).ok();
+                let member = |item: &Value| match &lookup {
+                    Some(set) => set.contains(item),
+                    None => others.iter().any(|other| other.set_eq(item)),
+                };
+                Ok(match op {
+                    SetOp::Intersects => items.iter().any(member),
+                    _ => items.iter().all(member),
+                })
+            },
+        }
+    }
+
+    /// Elements of an array operand; `None` if it is missing
+    fn elements(&self, op: SetOp) -> Result<Option<&[Value]>, String> {
+        match self {
+            Value::Array(items) => Ok(Some(items)),
+            Value::Undefined => Ok(None),
+            other => Err(format!("'{}' expects an array, got {:?}", op.name(), other)),
+        }
+    }
+
+    /// Element equality for set operators: `==`, but never an error
+    fn set_eq(&self, other: &Value) -> bool {
+        matches!(self.compare(other, CompOp::Eq), Ok(true))
+    }
+}
+
+/// Hashable identity of a scalar, consistent with `==`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SetKey {
+    Int(i64),
+    /// Bits of a non-integral float
+    Float(u64),
+    Bool(bool),
+    String(String),
+}
+
+impl SetKey {
+    /// Key of a scalar; `None` for values that are never set members
+    fn of(value: &Value) -> Option<SetKey> {
+        match value {
+            Value::Int(n) => Some(SetKey::Int(*n)),
+            // Integral floats equal the matching `Int`; NaN equals nothing
+            Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                Some(SetKey::Int(*f as i64))
+            },
+            Value::Float(f) if !f.is_nan() => Some(SetKey::Float(f.to_bits())),
+            Value::Bool(b) => Some(SetKey::Bool(*b)),
+            Value::String(s) => Some(SetKey::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// A set of scalar constants for `InSet`, hashed once with the policy
+///
+/// Serializes as its elements and rehashes on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Value>", into = "Vec<Value>")]
+pub struct ValueSet {
+    items: Vec<Value>,
+    keys: HashSet<SetKey>,
+}
+
+impl ValueSet {
+    /// Build a set; every element must be a number, bool or string
+    pub fn new(items: Vec<Value>) -> Result<Self, String> {
+        let keys = items
+            .iter()
+            .filter(|item| !matches!(item, Value::Float(f) if f.is_nan()))
+            .map(|item| SetKey::of(item).ok_or_else(|| format!("Cannot hash {:?}", item)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { items, keys })
+    }
+
+    /// The elements as written in the policy
+    pub fn items(&self) -> &[Value] {
+        &self.items
+    }
+
+    /// Whether `value` equals an element; `Undefined` never does
+    #[inline]
+    pub fn contains(&self, value: &Value) -> bool {
+        SetKey::of(value).is_some_and(|key| self.keys.contains(&key))
+    }
+}
+
+impl PartialEq for ValueSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+impl TryFrom<Vec<Value>> for ValueSet {
+    type Error = String;
+
+    fn try_from(items: Vec<Value>) -> Result<Self, String> {
+        ValueSet::new(items)
+    }
+}
+
+impl From<ValueSet> for Vec<Value> {
+    fn from(set: ValueSet) -> Self {
+        set.items
+    }
+}
+
+/// Pattern syntax of a [`Pattern`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatternKind {
+    /// Regular expression matching anywhere in the string (`matches`)
+    Regex,
+    /// Glob matching the whole string: `*` any run, `?` one character, `\` escapes (`like`)
+    Glob,
+}
+
+/// A `matches`/`like` pattern, compiled once with the policy
+///
+/// Serializes as its source and recompiles on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "PatternSource", into = "PatternSource")]
+pub struct Pattern {
+    kind: PatternKind,
+    source: String,
+    regex: regex::Regex,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PatternSource {
+    kind: PatternKind,
+    source: String,
+}
+
+impl Pattern {
+    /// Compile a pattern
+    pub fn new(kind: PatternKind, source: impl Into<String>) -> Result<Self, String> {
+        let source = source.into();
+        let regex = match kind {
+            PatternKind::Regex => regex::Regex::new(&source),
+            PatternKind::Glob => regex::Regex::new(&glob_to_regex(&source)?),
+        }
+        .map_err(|e| format!("Invalid pattern {:?}: {}", source, e))?;
+        Ok(Self { kind, source, regex })
+    }
+
+    pub fn kind(&self) -> PatternKind {
+        self.kind
+    }
+
+    /// The pattern as written in the policy
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    #[inline]
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Match a runtime value: strings are matched, `Undefined` never matches
+    #[inline]
+    pub fn matches_value(&self, value: &Value) -> Result<bool, String> {
+        match value {
+            Value::String(text) => Ok(self.is_match(text)),
+            Value::Undefined => Ok(false),
+            other => Err(format!("Cannot match {:?} against {:?}", other, self.source)),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.source == other.source
+    }
+}
+
+impl TryFrom<PatternSource> for Pattern {
+    type Error = String;
+
+    fn try_from(value: PatternSource) -> Result<Self, String> {
+        Pattern::new(value.kind, value.source)
+    }
+}
+
+impl From<Pattern> for PatternSource {
+    fn from(pattern: Pattern) -> Self {
+        PatternSource {
+            kind: pattern.kind,
+            source: pattern.source,
+        }
+    }
+}
+
+/// Translate a glob into an anchored regex
+fn glob_to_regex(glob: &str) -> Result<String, String> {
+    let mut regex = String::from("(?s)^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' => match chars.next() {
+                Some(escaped) => regex.push_str(&regex::escape(&escaped.to_string())),
+                None => return Err(format!("Invalid pattern {:?}: trailing escape", glob)),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
 }
 
 /// Compiled policy header
@@ -143,12 +619,76 @@ pub struct PolicyHeader {
     pub const_size: u32,
 }
 
+/// An obligation or advice returned with a decision (e.g. `redact(field: "ssn")`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obligation {
+    pub name: String,
+    pub params: BTreeMap<String, Value>,
+}
+
+impl Obligation {
+    /// Create an obligation without parameters
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    /// Add a parameter
+    pub fn with_param(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.params.insert(key.into(), value);
+        self
+    }
+}
+
+/// What a policy attaches to its decision besides the outcome
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PolicyEffects {
+    /// Reason reported when the policy denies
+    pub reason: Option<String>,
+    /// Obligations the enforcement point must fulfil
+    pub obligations: Vec<Obligation>,
+    /// Advice the enforcement point may act on
+    pub advice: Vec<Obligation>,
+}
+
+/// Whether a condition guards the policy or is one of its requirements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionKind {
+    Trigger,
+    Requirement,
+}
+
+/// Where a condition came from and where its code ends
+///
+/// The condition's value is on top of the stack when execution reaches `end`;
+/// evaluation traces use this to attribute results to source conditions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionSpan {
+    pub kind: ConditionKind,
+    pub location: SourceLocation,
+    /// Index of the first instruction after the condition's code
+    pub end: usize,
+}
+
 /// Compiled policy bytecode
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompiledPolicy {
     pub header: PolicyHeader,
     pub code: Vec<Instruction>,
     pub constants: Vec<Value>,
+    /// Patterns used by `Match`, precompiled alongside the constants
+    #[serde(default)]
+    pub patterns: Vec<Pattern>,
+    /// Sets used by `InSet`, hashed alongside the constants
+    #[serde(default)]
+    pub sets: Vec<ValueSet>,
+    #[serde(default)]
+    pub effects: PolicyEffects,
+    /// Condition boundaries in code order (empty for hand-built bytecode)
+    #[serde(default)]
+    pub conditions: Vec<ConditionSpan>,
 }
 
 impl CompiledPolicy {
@@ -164,6 +704,10 @@ impl CompiledPolicy {
             },
             code: Vec::new(),
             constants: Vec::new(),
+            patterns: Vec::new(),
+            sets: Vec::new(),
+            effects: PolicyEffects::default(),
+            conditions: Vec::new(),
         }
     }
 
@@ -181,6 +725,24 @@ impl CompiledPolicy {
         idx
     }
 
+    /// Add a pattern, reusing an identical one already in the pool
+    pub fn add_pattern(&mut self, pattern: Pattern) -> u16 {
+        if let Some(idx) = self.patterns.iter().position(|p| *p == pattern) {
+            return idx as u16;
+        }
+        self.patterns.push(pattern);
+        (self.patterns.len() - 1) as u16
+    }
+
+    /// Add a set, reusing an identical one already in the pool
+    pub fn add_set(&mut self, set: ValueSet) -> u16 {
+        if let Some(idx) = self.sets.iter().position(|s| *s == set) {
+            return idx as u16;
+        }
+        self.sets.push(set);
+        (self.sets.len() - 1) as u16
+    }
+
     /// Serialize to bytes (for storage)
     pub fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
         bincode::serialize(self)
@@ -195,15 +757,7 @@ impl CompiledPolicy {
     pub fn size_bytes(&self) -> usize {
         std::mem::size_of::<PolicyHeader>()
             + self.code.len() * std::mem::size_of::<Instruction>()
-            + self
-                .constants
-                .iter()
-                .map(|v| match v {
-                    Value::Int(_) => 8,
-                    Value::Bool(_) => 1,
-                    Value::String(s) => s.len(),
-                })
-                .sum::<usize>()
+            + self.constants.iter().map(Value::size_bytes).sum::<usize>()
     }
 }
 
@@ -358,6 +912,20 @@ mod tests {
         assert!(a.compare(&c, CompOp::Eq).is_err());
     }
 
+    #[test]
+    fn test_value_compare_undefined() {
+        let undefined = Value::Undefined;
+        let one = Value::Int(1);
+
+        assert!(!undefined.is_truthy());
+        assert!(!undefined.is_defined());
+        assert_eq!(undefined.compare(&Value::Undefined, CompOp::Eq), Ok(true));
+        assert_eq!(undefined.compare(&one, CompOp::Eq), Ok(false));
+        assert_eq!(one.compare(&undefined, CompOp::Neq), Ok(true));
+        assert_eq!(undefined.compare(&one, CompOp::Lt), Ok(false));
+        assert_eq!(one.compare(&undefined, CompOp::Gte), Ok(false));
+    }
+
     // CompiledPolicy tests
     #[test]
     fn test_policy_creation() {
@@ -385,4 +953,148 @@ mod tests {
         assert_eq!(policy.header.policy_id, deserialized.header.policy_id);
         assert_eq!(policy.code, deserialized.code);
     }
+
+    #[test]
+    fn test_value_compare_collections() {
+        let a = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        let b = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        let c = Value::Array(vec![]);
+
+        assert!(a.compare(&b, CompOp::Eq).unwrap());
+        assert!(a.compare(&c, CompOp::Neq).unwrap());
+        assert!(a.compare(&b, CompOp::Lt).is_err());
+        assert!(a.is_truthy());
+        assert!(!c.is_truthy());
+    }
+
+    #[test]
+    fn test_value_compare_float() {
+        let a = Value::Float(1.5);
+        let b = Value::Float(2.5);
+
+        assert!(a.compare(&b, CompOp::Lt).unwrap());
+        assert!(!a.compare(&b, CompOp::Eq).unwrap());
+        assert!(Value::Float(0.5).is_truthy());
+        assert!(!Value::Float(0.0).is_truthy());
+
+        // NaN is unordered and unequal to everything
+        let nan = Value::Float(f64::NAN);
+        assert!(!nan.compare(&nan, CompOp::Eq).unwrap());
+        assert!(nan.compare(&nan, CompOp::Neq).unwrap());
+        assert!(!nan.compare(&a, CompOp::Lt).unwrap());
+    }
+
+    #[test]
+    fn test_value_compare_int_float_coercion() {
+        assert!(Value::Int(2).compare(&Value::Float(2.0), CompOp::Eq).unwrap());
+        assert!(Value::Float(1.5).compare(&Value::Int(2), CompOp::Lt).unwrap());
+        assert!(Value::Int(3).compare(&Value::Float(2.5), CompOp::Gte).unwrap());
+        assert!(Value::Float(1.0).compare(&Value::Bool(true), CompOp::Eq).is_err());
+    }
+
+    #[test]
+    fn test_value_arith() {
+        let int = Value::Int;
+        assert_eq!(int(7).arith(&int(2), ArithOp::Add), Ok(int(9)));
+        assert_eq!(int(7).arith(&int(2), ArithOp::Div), Ok(int(3)));
+        assert_eq!(int(-7).arith(&int(2), ArithOp::Div), Ok(int(-3)));
+        assert_eq!(int(-7).arith(&int(2), ArithOp::Mod), Ok(int(-1)));
+        assert_eq!(int(3).arith(&Value::Float(0.5), ArithOp::Mul), Ok(Value::Float(1.5)));
+        assert_eq!(Value::Undefined.arith(&int(1), ArithOp::Add), Ok(Value::Undefined));
+
+        assert!(int(i64::MAX).arith(&int(1), ArithOp::Add).unwrap_err().contains("overflow"));
+        assert!(int(i64::MIN).arith(&int(-1), ArithOp::Div).unwrap_err().contains("overflow"));
+        assert!(int(1).arith(&int(0), ArithOp::Mod).unwrap_err().contains("Division by zero"));
+        assert!(Value::Float(1.0).arith(&int(0), ArithOp::Div).is_err());
+        assert!(Value::String("a".into()).arith(&int(1), ArithOp::Add).is_err());
+    }
+
+    #[test]
+    fn test_value_set_compare() {
+        let s = |text: &str| Value::String(text.to_string());
+        let roles = Value::Array(vec![s("dev"), s("sre")]);
+        let numbers = Value::Array(vec![Value::Int(1), Value::Float(2.5)]);
+
+        assert_eq!(s("sre").set_compare(&roles, SetOp::In), Ok(true));
+        assert_eq!(roles.set_compare(&s("admin"), SetOp::Contains), Ok(false));
+        assert_eq!(Value::Float(1.0).set_compare(&numbers, SetOp::In), Ok(true));
+        // Mismatched element types are simply not members
+        assert_eq!(Value::Int(1).set_compare(&roles, SetOp::In), Ok(false));
+
+        let admins = Value::Array(vec![s("admin"), s("sre")]);
+        assert_eq!(roles.set_compare(&admins, SetOp::Intersects), Ok(true));
+        assert_eq!(roles.set_compare(&admins, SetOp::SubsetOf), Ok(false));
+        assert_eq!(Value::Array(vec![s("sre")]).set_compare(&admins, SetOp::SubsetOf), Ok(true));
+        assert_eq!(Value::Array(vec![]).set_compare(&admins, SetOp::SubsetOf), Ok(true));
+
+        // Missing operands are false, non-arrays errors
+        assert_eq!(Value::Undefined.set_compare(&roles, SetOp::In), Ok(false));
+        assert_eq!(roles.set_compare(&Value::Undefined, SetOp::Intersects), Ok(false));
+        assert!(s("sre")
+            .set_compare(&s("sre"), SetOp::In)
+            .unwrap_err()
+            .contains("expects an array"));
+    }
+
+    #[test]
+    fn test_value_set_pool() {
+        let items =
+            vec![Value::Int(3), Value::Float(0.5), Value::String("a".into()), Value::Bool(true)];
+        let set = ValueSet::new(items).unwrap();
+        assert!(set.contains(&Value::Int(3)));
+        assert!(set.contains(&Value::Float(3.0)));
+        assert!(set.contains(&Value::Float(0.5)));
+        assert!(set.contains(&Value::String("a".into())));
+        assert!(!set.contains(&Value::String("b".into())));
+        assert!(!set.contains(&Value::Int(1)));
+        assert!(!set.contains(&Value::Undefined));
+        assert!(ValueSet::new(vec![Value::Array(vec![])]).is_err());
+
+        let mut policy = CompiledPolicy::new(1);
+        let idx = policy.add_set(set.clone());
+        assert_eq!(policy.add_set(set), idx);
+        let deserialized = CompiledPolicy::from_bytes(&policy.to_bytes().unwrap()).unwrap();
+        assert_eq!(deserialized.sets, policy.sets);
+        assert!(deserialized.sets[idx as usize].contains(&Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_pattern_glob() {
+        let glob = |source: &str| Pattern::new(PatternKind::Glob, source).unwrap();
+
+        assert!(glob("*.log").is_match("a/b.log"));
+        assert!(!glob("*.log").is_match("a.log.gz"));
+        assert!(glob("v?.?").is_match("v1.2"));
+        assert!(!glob("v?.?").is_match("v10.2"));
+        // Regex metacharacters are literal, and `\` escapes glob ones
+        assert!(glob("a+b(c)").is_match("a+b(c)"));
+        assert!(glob(r"what\?").is_match("what?"));
+        assert!(!glob(r"what\?").is_match("whats"));
+        assert!(Pattern::new(PatternKind::Glob, r"trailing\").is_err());
+        assert!(Pattern::new(PatternKind::Regex, "(").unwrap_err().contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn test_pattern_pool_serialization() {
+        let mut policy = CompiledPolicy::new(1);
+        let a = policy.add_pattern(Pattern::new(PatternKind::Regex, "^a+$").unwrap());
+        let b = policy.add_pattern(Pattern::new(PatternKind::Glob, "^a+$").unwrap());
+        assert_eq!(policy.add_pattern(Pattern::new(PatternKind::Regex, "^a+$").unwrap()), a);
+        assert_ne!(a, b);
+
+        let deserialized = CompiledPolicy::from_bytes(&policy.to_bytes().unwrap()).unwrap();
+        assert_eq!(deserialized.patterns, policy.patterns);
+        assert!(deserialized.patterns[a as usize].is_match("aaa"));
+        assert!(!deserialized.patterns[b as usize].is_match("aaa"));
+    }
+
+    #[test]
+    fn test_serialization_float_and_array_constants() {
+        let mut policy = CompiledPolicy::new(1);
+        policy.add_constant(Value::Float(0.25));
+        policy.add_constant(Value::Array(vec![Value::Int(1), Value::Float(2.5)]));
+
+        let deserialized = CompiledPolicy::from_bytes(&policy.to_bytes().unwrap()).unwrap();
+        assert_eq!(deserialized.constants, policy.constants);
+    }
 }
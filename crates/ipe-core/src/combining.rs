@@ -0,0 +1,455 @@
+//! Policy combining algorithms
+//!
+//! Every candidate policy for a request produces an [`Outcome`] (or an
+//! evaluation error). A [`CombiningAlgorithm`] folds those per-policy results
+//! into a single [`Decision`]. Both [`crate::PolicyEngine`] and
+//! [`crate::store::PolicyDataStore`] use [`Combiner`], so the two evaluation
+//! paths always agree for the same algorithm.
+//!
+//! The combined decision also carries the deny reasons, obligations and advice
+//! of the policies that produced it; effects of policies that were overridden
+//! are dropped.
+
+use crate::bytecode::{Obligation, PolicyEffects};
+use crate::engine::{Decision, DecisionKind};
+use crate::interpreter::Outcome;
+use serde::{Deserialize, Serialize};
+
+/// How the results of several applicable policies are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum CombiningAlgorithm {
+    /// Any deny wins; otherwise any allow wins
+    #[default]
+    DenyOverrides,
+    /// Any allow wins; otherwise any deny wins
+    PermitOverrides,
+    /// The first policy (in registration order) that applies decides
+    FirstApplicable,
+    /// Exactly one policy may apply; more than one is indeterminate
+    OnlyOneApplicable,
+}
+
+impl CombiningAlgorithm {
+    /// Parse an algorithm from its kebab-case name (e.g. `deny-overrides`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "deny-overrides" => Some(Self::DenyOverrides),
+            "permit-overrides" => Some(Self::PermitOverrides),
+            "first-applicable" => Some(Self::FirstApplicable),
+            "only-one-applicable" => Some(Self::OnlyOneApplicable),
+            _ => None,
+        }
+    }
+
+    /// Get the kebab-case name of this algorithm
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DenyOverrides => "deny-overrides",
+            Self::PermitOverrides => "permit-overrides",
+            Self::FirstApplicable => "first-applicable",
+            Self::OnlyOneApplicable => "only-one-applicable",
+        }
+    }
+}
+
+/// Reasons, obligations and advice collected for one decision kind
+#[derive(Debug, Default)]
+struct CollectedEffects {
+    reasons: Vec<String>,
+    obligations: Vec<Obligation>,
+    advice: Vec<Obligation>,
+}
+
+impl CollectedEffects {
+    fn merge(
+        &mut self,
+        reason: Option<&String>,
+        obligations: &[Obligation],
+        advice: &[Obligation],
+    ) {
+        if let Some(reason) = reason {
+            if !self.reasons.contains(reason) {
+                self.reasons.push(reason.clone());
+            }
+        }
+        merge_unique(&mut self.obligations, obligations);
+        merge_unique(&mut self.advice, advice);
+    }
+
+    fn apply(self, mut decision: Decision) -> Decision {
+        decision.obligations = self.obligations;
+        decision.advice = self.advice;
+        decision
+    }
+}
+
+fn merge_unique(target: &mut Vec<Obligation>, items: &[Obligation]) {
+    for item in items {
+        if !target.contains(item) {
+            target.push(item.clone());
+        }
+    }
+}
+
+/// Incrementally combines per-policy results into a decision
+#[derive(Debug)]
+pub struct Combiner {
+    algorithm: CombiningAlgorithm,
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    allow_effects: CollectedEffects,
+    deny_effects: CollectedEffects,
+    errors: Vec<String>,
+    /// Number of results that applied (including failures)
+    applicable: usize,
+    /// Result of the first applicable policy (for first-applicable)
+    first: Option<DecisionKind>,
+}
+
+impl Combiner {
+    /// Create a combiner for the given algorithm
+    pub fn new(algorithm: CombiningAlgorithm) -> Self {
+        Self {
+            algorithm,
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            allow_effects: CollectedEffects::default(),
+            deny_effects: CollectedEffects::default(),
+            errors: Vec::new(),
+            applicable: 0,
+            first: None,
+        }
+    }
+
+    /// Record the result of evaluating one policy
+    ///
+    /// Returns `true` once no further result can change the decision, so the
+    /// caller may stop evaluating the remaining policies.
+    pub fn add(&mut self, policy_name: &str, result: std::result::Result<Outcome, String>) -> bool {
+        self.add_policy(policy_name, &PolicyEffects::default(), result)
+    }
+
+    /// Record the result of evaluating one policy along with its effects
+    ///
+    /// The effects only reach the decision if the policy ends up among its
+    /// matched policies. Returns `true` like [`Combiner::add`].
+    pub fn add_policy(
+        &mut self,
+        policy_name: &str,
+        effects: &PolicyEffects,
+        result: std::result::Result<Outcome, String>,
+    ) -> bool {
+        let kind = match result {
+            Ok(Outcome::Allow) => {
+                self.allowed.push(policy_name.to_string());
+                self.allow_effects.merge(None, &effects.obligations, &effects.advice);
+                DecisionKind::Allow
+            },
+            Ok(Outcome::Deny) => {
+                self.denied.push(policy_name.to_string());
+                self.deny_effects.merge(
+                    effects.reason.as_ref(),
+                    &effects.obligations,
+                    &effects.advice,
+                );
+                DecisionKind::Deny
+            },
+            Ok(Outcome::NotApplicable) => return false,
+            Err(e) => {
+                self.errors.push(format!("Policy '{}' evaluation failed: {}", policy_name, e));
+                DecisionKind::Indeterminate
+            },
+        };
+
+        self.record(kind)
+    }
+
+    /// Record the combined decision of a nested policy set
+    ///
+    /// The set counts as a single applicable result; its matched policies are
+    /// carried into the final decision along with its reasons, obligations and
+    /// advice. Returns `true` like [`Combiner::add`].
+    pub fn add_decision(&mut self, decision: Decision) -> bool {
+        match decision.kind {
+            DecisionKind::Allow => {
+                self.allowed.extend(decision.matched_policies);
+                self.allow_effects.merge(None, &decision.obligations, &decision.advice);
+            },
+            DecisionKind::Deny => {
+                self.denied.extend(decision.matched_policies);
+                self.deny_effects.merge(
+                    decision.reason.as_ref(),
+                    &decision.obligations,
+                    &decision.advice,
+                );
+            },
+            DecisionKind::NotApplicable => return false,
+            DecisionKind::Indeterminate => self.errors.push(decision.reason.unwrap_or_default()),
+        }
+
+        self.record(decision.kind)
+    }
+
+    fn record(&mut self, kind: DecisionKind) -> bool {
+        self.applicable += 1;
+        if self.first.is_none() {
+            self.first = Some(kind);
+        }
+
+        match self.algorithm {
+            CombiningAlgorithm::DenyOverrides => kind == DecisionKind::Deny,
+            CombiningAlgorithm::PermitOverrides => kind == DecisionKind::Allow,
+            CombiningAlgorithm::FirstApplicable => true,
+            CombiningAlgorithm::OnlyOneApplicable => self.applicable > 1,
+        }
+    }
+
+    /// Produce the combined decision
+    pub fn finish(self) -> Decision {
+        if self.applicable == 0 {
+            return Decision::not_applicable()
+                .with_reason("No applicable policies for request".to_string());
+        }
+
+        match self.algorithm {
+            CombiningAlgorithm::DenyOverrides => {
+                if !self.denied.is_empty() {
+                    self.deny()
+                } else if !self.errors.is_empty() {
+                    self.indeterminate()
+                } else {
+                    self.allow()
+                }
+            },
+            CombiningAlgorithm::PermitOverrides => {
+                if !self.allowed.is_empty() {
+                    self.allow()
+                } else if !self.errors.is_empty() {
+                    self.indeterminate()
+                } else {
+                    self.deny()
+                }
+            },
+            CombiningAlgorithm::FirstApplicable => match self.first {
+                Some(DecisionKind::Allow) => self.allow(),
+                Some(DecisionKind::Deny) => self.deny(),
+                _ => self.indeterminate(),
+            },
+            CombiningAlgorithm::OnlyOneApplicable => {
+                if self.applicable > 1 {
+                    let mut names = self.allowed.clone();
+                    names.extend(self.denied.iter().cloned());
+                    let mut decision = Decision::indeterminate().with_reason(
+                        "More than one policy applied under only-one-applicable".to_string(),
+                    );
+                    decision.matched_policies = names;
+                    decision
+                } else if !self.allowed.is_empty() {
+                    self.allow()
+                } else if !self.denied.is_empty() {
+                    self.deny()
+                } else {
+                    self.indeterminate()
+                }
+            },
+        }
+    }
+
+    fn allow(self) -> Decision {
+        let mut decision = Decision::allow();
+        decision.matched_policies = self.allowed;
+        self.allow_effects.apply(decision)
+    }
+
+    fn deny(self) -> Decision {
+        let reason = if self.deny_effects.reasons.is_empty() {
+            "One or more policies denied the request".to_string()
+        } else {
+            self.deny_effects.reasons.join("; ")
+        };
+        let mut decision = Decision::deny().with_reason(reason);
+        decision.matched_policies = self.denied;
+        self.deny_effects.apply(decision)
+    }
+
+    fn indeterminate(self) -> Decision {
+        Decision::indeterminate().with_reason(self.errors.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Value;
+
+    fn combine(
+        algorithm: CombiningAlgorithm,
+        results: Vec<(&str, std::result::Result<Outcome, String>)>,
+    ) -> Decision {
+        let mut combiner = Combiner::new(algorithm);
+        for (name, result) in results {
+            if combiner.add(name, result) {
+                break;
+            }
+        }
+        combiner.finish()
+    }
+
+    #[test]
+    fn test_algorithm_names_round_trip() {
+        for alg in [
+            CombiningAlgorithm::DenyOverrides,
+            CombiningAlgorithm::PermitOverrides,
+            CombiningAlgorithm::FirstApplicable,
+            CombiningAlgorithm::OnlyOneApplicable,
+        ] {
+            assert_eq!(CombiningAlgorithm::from_name(alg.name()), Some(alg));
+        }
+        assert_eq!(CombiningAlgorithm::from_name("unknown"), None);
+        assert_eq!(CombiningAlgorithm::default(), CombiningAlgorithm::DenyOverrides);
+    }
+
+    #[test]
+    fn test_no_applicable_policies() {
+        let decision = combine(
+            CombiningAlgorithm::DenyOverrides,
+            vec![("a", Ok(Outcome::NotApplicable)), ("b", Ok(Outcome::NotApplicable))],
+        );
+        assert_eq!(decision.kind, DecisionKind::NotApplicable);
+    }
+
+    #[test]
+    fn test_deny_overrides() {
+        let decision = combine(
+            CombiningAlgorithm::DenyOverrides,
+            vec![("a", Ok(Outcome::Allow)), ("b", Ok(Outcome::Deny))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.matched_policies, vec!["b".to_string()]);
+
+        // A deny also overrides an evaluation error
+        let decision = combine(
+            CombiningAlgorithm::DenyOverrides,
+            vec![("a", Err("boom".to_string())), ("b", Ok(Outcome::Deny))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Deny);
+
+        let decision = combine(
+            CombiningAlgorithm::DenyOverrides,
+            vec![("a", Ok(Outcome::Allow)), ("b", Err("boom".to_string()))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Indeterminate);
+        assert!(decision.reason.unwrap().contains("boom"));
+    }
+
+    #[test]
+    fn test_permit_overrides() {
+        let decision = combine(
+            CombiningAlgorithm::PermitOverrides,
+            vec![("a", Ok(Outcome::Deny)), ("b", Ok(Outcome::Allow))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["b".to_string()]);
+
+        let decision = combine(
+            CombiningAlgorithm::PermitOverrides,
+            vec![("a", Ok(Outcome::Deny)), ("b", Ok(Outcome::NotApplicable))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Deny);
+    }
+
+    #[test]
+    fn test_first_applicable() {
+        let decision = combine(
+            CombiningAlgorithm::FirstApplicable,
+            vec![
+                ("a", Ok(Outcome::NotApplicable)),
+                ("b", Ok(Outcome::Allow)),
+                ("c", Ok(Outcome::Deny)),
+            ],
+        );
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["b".to_string()]);
+
+        let decision = combine(
+            CombiningAlgorithm::FirstApplicable,
+            vec![("a", Err("boom".to_string())), ("b", Ok(Outcome::Allow))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Indeterminate);
+    }
+
+    #[test]
+    fn test_nested_decisions() {
+        let mut inner = Combiner::new(CombiningAlgorithm::PermitOverrides);
+        inner.add("a", Ok(Outcome::Deny));
+        inner.add("b", Ok(Outcome::Allow));
+        let set_decision = inner.finish();
+
+        // The set counts once, so only-one-applicable is still satisfied
+        let mut outer = Combiner::new(CombiningAlgorithm::OnlyOneApplicable);
+        assert!(!outer.add_decision(set_decision));
+        assert!(!outer.add("c", Ok(Outcome::NotApplicable)));
+        let decision = outer.finish();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["b".to_string()]);
+
+        let mut outer = Combiner::new(CombiningAlgorithm::DenyOverrides);
+        outer.add_decision(Decision::not_applicable());
+        assert_eq!(outer.finish().kind, DecisionKind::NotApplicable);
+    }
+
+    #[test]
+    fn test_effects_follow_decision() {
+        let audit = PolicyEffects {
+            obligations: vec![Obligation::new("log_to_audit")],
+            ..Default::default()
+        };
+        let deny = PolicyEffects {
+            reason: Some("Production is frozen".to_string()),
+            obligations: vec![Obligation::new("log_to_audit")],
+            advice: vec![
+                Obligation::new("notify").with_param("channel", Value::String("ops".into()))
+            ],
+        };
+
+        // Overridden allow effects are dropped; duplicate obligations merge
+        let mut combiner = Combiner::new(CombiningAlgorithm::DenyOverrides);
+        combiner.add_policy("a", &audit, Ok(Outcome::Allow));
+        combiner.add_policy("b", &deny, Ok(Outcome::Deny));
+        combiner.add_policy("c", &deny, Ok(Outcome::Deny));
+        let decision = combiner.finish();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.reason.as_deref(), Some("Production is frozen"));
+        assert_eq!(decision.obligations, vec![Obligation::new("log_to_audit")]);
+        assert_eq!(decision.advice.len(), 1);
+
+        let mut combiner = Combiner::new(CombiningAlgorithm::PermitOverrides);
+        combiner.add_policy("a", &audit, Ok(Outcome::Allow));
+        combiner.add_policy("b", &deny, Ok(Outcome::Deny));
+        let decision = combiner.finish();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.obligations, vec![Obligation::new("log_to_audit")]);
+        assert!(decision.advice.is_empty());
+
+        // Nested decisions carry their effects into the outer set
+        let mut outer = Combiner::new(CombiningAlgorithm::DenyOverrides);
+        outer.add_decision(decision);
+        assert_eq!(outer.finish().obligations, vec![Obligation::new("log_to_audit")]);
+    }
+
+    #[test]
+    fn test_only_one_applicable() {
+        let decision = combine(
+            CombiningAlgorithm::OnlyOneApplicable,
+            vec![("a", Ok(Outcome::NotApplicable)), ("b", Ok(Outcome::Deny))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Deny);
+
+        let decision = combine(
+            CombiningAlgorithm::OnlyOneApplicable,
+            vec![("a", Ok(Outcome::Allow)), ("b", Ok(Outcome::Allow))],
+        );
+        assert_eq!(decision.kind, DecisionKind::Indeterminate);
+        assert_eq!(decision.matched_policies.len(), 2);
+    }
+}
@@ -1,10 +1,23 @@
 use crate::ast::nodes::{
-    BinaryOp, ComparisonOp, Condition, Expression, LogicalOp, Policy, Requirements, Value,
+    AggregateFunc, ArithmeticOp, BinaryOp, ComparisonOp, Condition, Expression, LogicalOp,
+    Obligation, Path, Policy, Requirements, SetOperator, Value,
 };
-use crate::bytecode::{CompOp, CompiledPolicy, Instruction, Value as BytecodeValue};
+use crate::ast::types::{Type, TypeChecker, TypeEnv, TypeError};
+use crate::bytecode::{
+    AggregateOp, ArithOp, CompOp, CompiledPolicy, ConditionKind, ConditionSpan, Instruction,
+    Obligation as BytecodeObligation, Pattern, PatternKind, SetOp, Value as BytecodeValue,
+    ValueSet,
+};
+use crate::functions::FunctionRegistry;
+use crate::interpreter::FieldMapping;
 use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
 
+/// Literal `in` lists at least this long compile to a hashed `InSet`; shorter
+/// ones to a chain of comparisons, which the JIT can lower
+const IN_SET_MIN_LEN: usize = 8;
+
 #[derive(Error, Debug)]
 pub enum CompileError {
     #[error("Parse error: {0}")]
@@ -22,18 +35,38 @@ pub enum CompileError {
     #[error("Too many constants (max 65536)")]
     TooManyConstants,
 
-    #[error("Aggregate functions not yet supported: {0}")]
-    UnsupportedAggregate(String),
+    #[error("Jump offset out of range: {0}")]
+    JumpOutOfRange(usize),
+
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("Function {name}() expects {expected} argument(s), got {got}")]
+    ArityMismatch { name: String, expected: usize, got: usize },
+
+    #[error("{0}")]
+    InvalidPattern(String),
+
+    #[error("Type error at line {line}: {error}")]
+    TypeError { line: usize, error: TypeError },
 }
 
 pub type CompileResult<T> = Result<T, CompileError>;
 
+/// Path roots that always load from the evaluation context
+pub(crate) const CONTEXT_ROOTS: &[&str] = &["resource", "action", "request"];
+
+/// Path root naming the current element inside an aggregate condition
+pub(crate) const ELEMENT_ROOT: &str = "item";
+
 /// Context for tracking variables during compilation
 struct CompileContext {
     /// Map from path string to field offset
     field_offsets: HashMap<String, u16>,
     /// Next available field offset
     next_offset: u16,
+    /// Number of aggregate conditions being compiled
+    aggregate_depth: usize,
 }
 
 impl CompileContext {
@@ -41,6 +74,7 @@ impl CompileContext {
         Self {
             field_offsets: HashMap::new(),
             next_offset: 0,
+            aggregate_depth: 0,
         }
     }
 
@@ -60,6 +94,8 @@ impl CompileContext {
 pub struct PolicyCompiler {
     policy: CompiledPolicy,
     context: CompileContext,
+    functions: Arc<FunctionRegistry>,
+    types: Option<TypeEnv>,
 }
 
 impl PolicyCompiler {
@@ -67,18 +103,94 @@ impl PolicyCompiler {
         Self {
             policy: CompiledPolicy::new(policy_id),
             context: CompileContext::new(),
+            functions: FunctionRegistry::builtins(),
+            types: None,
         }
     }
 
+    /// Resolve function calls against the given registry instead of the builtins
+    ///
+    /// The compiled policy must be executed with the same registry.
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Type check every condition against `env` before compiling
+    ///
+    /// See [`Schema::type_env`](crate::schema::Schema::type_env) for the
+    /// environment of a schema.
+    pub fn with_types(mut self, env: TypeEnv) -> Self {
+        self.types = Some(env);
+        self
+    }
+
     /// Compile an AST policy to bytecode
     pub fn compile(mut self, policy: &Policy) -> CompileResult<CompiledPolicy> {
-        // For now, we compile the requirements section
-        // In a full implementation, we'd also handle triggers
+        self.compile_policy(policy)?;
+        Ok(self.policy)
+    }
+
+    /// Compile an AST policy to bytecode along with the field mapping the
+    /// interpreter needs to resolve its `LoadField` offsets
+    pub fn compile_with_field_map(
+        mut self,
+        policy: &Policy,
+    ) -> CompileResult<(CompiledPolicy, FieldMapping)> {
+        self.compile_policy(policy)?;
+        let field_map = self
+            .context
+            .field_offsets
+            .into_iter()
+            .map(|(path, offset)| (offset, path.split('.').map(str::to_string).collect()))
+            .collect();
+        Ok((self.policy, field_map))
+    }
+
+    /// Reject the policy at the first condition with a type error
+    fn check_types(&self, policy: &Policy, env: TypeEnv) -> CompileResult<()> {
+        let mut checker = TypeChecker::new(env).with_functions(Arc::clone(&self.functions));
+        let requirements: Vec<&Condition> = match &policy.requirements {
+            Requirements::Requires { conditions, where_clause } => {
+                conditions.iter().chain(where_clause.iter().flatten()).collect()
+            },
+            Requirements::Denies { .. } => Vec::new(),
+        };
+        for condition in policy.triggers.iter().chain(requirements) {
+            checker.check_condition(condition);
+            if let Some(error) = checker.errors().first() {
+                return Err(CompileError::TypeError {
+                    line: condition.location.line,
+                    error: error.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the code for a whole policy
+    ///
+    /// The generated code starts with a guard prologue for the policy's
+    /// `triggers when` clause: if any trigger evaluates to false the policy
+    /// exits with [`Instruction::NotApplicable`] before any requirement is
+    /// evaluated.
+    fn compile_policy(&mut self, policy: &Policy) -> CompileResult<()> {
+        if let Some(env) = self.types.take() {
+            self.check_types(policy, env)?;
+        }
+
+        // Guard prologue: every trigger must hold for the policy to apply
+        let mut not_applicable_jumps = Vec::with_capacity(policy.triggers.len());
+        for trigger in &policy.triggers {
+            self.compile_traced_condition(trigger, ConditionKind::Trigger)?;
+            not_applicable_jumps.push(self.emit_placeholder_jump());
+        }
+
         match &policy.requirements {
             Requirements::Requires { conditions, where_clause } => {
                 // Compile all conditions with AND logic
                 for (i, condition) in conditions.iter().enumerate() {
-                    self.compile_condition(condition)?;
+                    self.compile_traced_condition(condition, ConditionKind::Requirement)?;
 
                     // If not the last condition, emit AND
                     if i < conditions.len() - 1 {
@@ -89,21 +201,122 @@ impl PolicyCompiler {
                 // If there's a where clause, compile it and AND with main conditions
                 if let Some(where_conds) = where_clause {
                     for condition in where_conds {
-                        self.compile_condition(condition)?;
+                        self.compile_traced_condition(condition, ConditionKind::Requirement)?;
                         self.policy.emit(Instruction::And);
                     }
                 }
 
-                // Return true if all conditions passed
-                self.policy.emit(Instruction::Return { value: true });
+                if conditions.is_empty() {
+                    // Nothing to check - the policy allows unconditionally
+                    self.policy.emit(Instruction::Return { value: true });
+                } else {
+                    // Allow if the combined conditions hold, deny otherwise
+                    self.policy.emit(Instruction::JumpIfFalse { offset: 2 });
+                    self.policy.emit(Instruction::Return { value: true });
+                    self.policy.emit(Instruction::Return { value: false });
+                }
             },
-            Requirements::Denies { .. } => {
+            Requirements::Denies { reason } => {
                 // Denies always returns false
                 self.policy.emit(Instruction::Return { value: false });
+                self.policy.effects.reason = reason.clone();
             },
         }
 
-        Ok(self.policy)
+        self.policy.effects.obligations =
+            policy.obligations.iter().map(bytecode_obligation).collect();
+        self.policy.effects.advice = policy.advice.iter().map(bytecode_obligation).collect();
+
+        if !not_applicable_jumps.is_empty() {
+            let target = self.policy.code.len();
+            self.policy.emit(Instruction::NotApplicable);
+            for jump in not_applicable_jumps {
+                self.patch_jump(jump, target)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile a top-level condition and record its span for evaluation traces
+    fn compile_traced_condition(
+        &mut self,
+        condition: &Condition,
+        kind: ConditionKind,
+    ) -> CompileResult<()> {
+        self.compile_condition(condition)?;
+        self.policy.conditions.push(ConditionSpan {
+            kind,
+            location: condition.location.clone(),
+            end: self.policy.code.len(),
+        });
+        Ok(())
+    }
+
+    /// Emit a `JumpIfFalse` whose target is filled in later by `patch_jump`
+    fn emit_placeholder_jump(&mut self) -> usize {
+        let idx = self.policy.code.len();
+        self.policy.emit(Instruction::JumpIfFalse { offset: 0 });
+        idx
+    }
+
+    /// Point the jump at `jump_idx` to the instruction at `target`
+    fn patch_jump(&mut self, jump_idx: usize, target: usize) -> CompileResult<()> {
+        let offset = i16::try_from(target as isize - jump_idx as isize)
+            .map_err(|_| CompileError::JumpOutOfRange(target))?;
+        match &mut self.policy.code[jump_idx] {
+            Instruction::Jump { offset: o }
+            | Instruction::JumpIfFalse { offset: o }
+            | Instruction::JumpIfTrue { offset: o }
+            | Instruction::JumpIfDefined { offset: o }
+            | Instruction::AggregateBegin { skip: o, .. } => {
+                *o = offset;
+                Ok(())
+            },
+            other => Err(CompileError::UnsupportedExpression(format!(
+                "Cannot patch non-jump instruction: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Compile `and`/`or` operands, skipping the rest once one equals `exit_when`
+    ///
+    /// Every operand but the last is duplicated and tested. If it decides the
+    /// result the copy is left on the stack as the result; otherwise it is
+    /// popped and the next operand is evaluated:
+    ///
+    /// ```text
+    /// a; Dup; JumpIfFalse end; Pop; b; end:
+    /// ```
+    fn compile_short_circuit(
+        &mut self,
+        operands: &[Expression],
+        exit_when: bool,
+    ) -> CompileResult<()> {
+        let (last, rest) = operands.split_last().ok_or_else(|| {
+            CompileError::UnsupportedExpression("AND/OR requires operands".to_string())
+        })?;
+
+        let mut exits = Vec::with_capacity(rest.len());
+        for operand in rest {
+            self.compile_expression(operand)?;
+            self.policy.emit(Instruction::Dup);
+            exits.push(self.policy.code.len());
+            self.policy.emit(if exit_when {
+                Instruction::JumpIfTrue { offset: 0 }
+            } else {
+                Instruction::JumpIfFalse { offset: 0 }
+            });
+            self.policy.emit(Instruction::Pop);
+        }
+        self.compile_expression(last)?;
+
+        let end = self.policy.code.len();
+        for exit in exits {
+            self.patch_jump(exit, end)?;
+        }
+        Ok(())
     }
 
     fn compile_condition(&mut self, condition: &Condition) -> CompileResult<()> {
@@ -114,58 +327,63 @@ impl PolicyCompiler {
         match expr {
             Expression::Literal(value) => self.compile_literal(value),
 
-            Expression::Path(path) => {
-                // Load field from context
-                let path_str = path.to_string();
-                let offset = self.context.get_or_allocate_field(&path_str);
-                self.policy.emit(Instruction::LoadField { offset });
-                Ok(())
-            },
+            Expression::Path(path) => self.compile_path(path),
 
-            Expression::Binary { left, op, right } => {
-                // Compile left and right expressions
-                self.compile_expression(left)?;
-                self.compile_expression(right)?;
+            Expression::Binary { left, op, right } => match op {
+                BinaryOp::Comparison(comp_op) => {
+                    // Compile left and right expressions
+                    self.compile_expression(left)?;
+                    self.compile_expression(right)?;
 
-                // Emit comparison instruction
-                match op {
-                    BinaryOp::Comparison(comp_op) => {
-                        let op = match comp_op {
-                            ComparisonOp::Eq => CompOp::Eq,
-                            ComparisonOp::Neq => CompOp::Neq,
-                            ComparisonOp::Lt => CompOp::Lt,
-                            ComparisonOp::LtEq => CompOp::Lte,
-                            ComparisonOp::Gt => CompOp::Gt,
-                            ComparisonOp::GtEq => CompOp::Gte,
-                        };
-                        self.policy.emit(Instruction::Compare { op });
-                        Ok(())
-                    },
-                }
+                    // Emit comparison instruction
+                    self.policy.emit(Instruction::Compare { op: comp_op_for(*comp_op) });
+                    Ok(())
+                },
+                BinaryOp::Arithmetic(arith_op) => {
+                    self.compile_expression(left)?;
+                    self.compile_expression(right)?;
+                    self.policy.emit(Instruction::Arith { op: arith_op_for(*arith_op) });
+                    Ok(())
+                },
+                BinaryOp::Matches | BinaryOp::Like => {
+                    let pattern =
+                        literal_pattern(*op, right).map_err(CompileError::InvalidPattern)?;
+                    if self.policy.patterns.len() >= 65536 {
+                        return Err(CompileError::InvalidPattern(
+                            "Too many patterns (max 65536)".to_string(),
+                        ));
+                    }
+                    self.compile_expression(left)?;
+                    let pattern = self.policy.add_pattern(pattern);
+                    self.policy.emit(Instruction::Match { pattern });
+                    Ok(())
+                },
+                BinaryOp::Set(set_op) => {
+                    self.compile_expression(left)?;
+                    self.compile_expression(right)?;
+                    self.policy.emit(Instruction::SetCompare { op: set_op_for(*set_op) });
+                    Ok(())
+                },
+                BinaryOp::Default => {
+                    // Only evaluate the fallback if the value is missing:
+                    // value; Dup; JumpIfDefined end; Pop; fallback; end:
+                    self.compile_expression(left)?;
+                    self.policy.emit(Instruction::Dup);
+                    let jump = self.policy.code.len();
+                    self.policy.emit(Instruction::JumpIfDefined { offset: 0 });
+                    self.policy.emit(Instruction::Pop);
+                    self.compile_expression(right)?;
+                    let end = self.policy.code.len();
+                    self.patch_jump(jump, end)
+                },
             },
 
             Expression::Logical { op, operands } => {
                 match op {
-                    LogicalOp::And => {
-                        // Compile all operands and AND them together
-                        for (i, operand) in operands.iter().enumerate() {
-                            self.compile_expression(operand)?;
-                            if i > 0 {
-                                self.policy.emit(Instruction::And);
-                            }
-                        }
-                        Ok(())
-                    },
-                    LogicalOp::Or => {
-                        // Compile all operands and OR them together
-                        for (i, operand) in operands.iter().enumerate() {
-                            self.compile_expression(operand)?;
-                            if i > 0 {
-                                self.policy.emit(Instruction::Or);
-                            }
-                        }
-                        Ok(())
-                    },
+                    // Stop at the first false operand
+                    LogicalOp::And => self.compile_short_circuit(operands, false),
+                    // Stop at the first true operand
+                    LogicalOp::Or => self.compile_short_circuit(operands, true),
                     LogicalOp::Not => {
                         // Compile operand and NOT it
                         if let Some(operand) = operands.first() {
@@ -181,6 +399,22 @@ impl PolicyCompiler {
                 }
             },
 
+            // Large scalar lists are looked up in a hashed set
+            Expression::In { expr, list }
+                if list.len() >= IN_SET_MIN_LEN
+                    && !list.iter().any(|value| matches!(value, Value::Array(_))) =>
+            {
+                let set = ValueSet::new(list.iter().map(bytecode_value).collect())
+                    .map_err(CompileError::UnsupportedExpression)?;
+                if self.policy.sets.len() >= 65536 {
+                    return Err(CompileError::TooManyConstants);
+                }
+                self.compile_expression(expr)?;
+                let set = self.policy.add_set(set);
+                self.policy.emit(Instruction::InSet { set });
+                Ok(())
+            },
+
             Expression::In { expr, list } => {
                 // For IN expressions, we generate comparison logic
                 // expr == list[0] OR expr == list[1] OR ...
@@ -212,53 +446,115 @@ impl PolicyCompiler {
             },
 
             Expression::Call { name, args } => {
-                // Compile arguments
-                for arg in args {
-                    self.compile_expression(arg)?;
+                let functions = Arc::clone(&self.functions);
+                if let Some(expanded) = functions.expand_clock(name, args) {
+                    return self.compile_expression(&expanded);
+                }
+                let func = functions
+                    .resolve(name)
+                    .ok_or_else(|| CompileError::UnknownFunction(name.clone()))?;
+                let signature = &functions.get(func).expect("resolved function exists").signature;
+
+                if args.len() != signature.arity() {
+                    return Err(CompileError::ArityMismatch {
+                        name: name.clone(),
+                        expected: signature.arity(),
+                        got: args.len(),
+                    });
                 }
 
-                // Emit function call
-                // Function ID mapping (simplified for now)
-                let func_id = match name.as_str() {
-                    "count" => 0,
-                    "any" => 1,
-                    "all" => 2,
-                    _ => {
-                        return Err(CompileError::UnsupportedExpression(format!(
-                            "Unknown function: {}",
-                            name
-                        )))
-                    },
-                };
+                // Compile arguments, rejecting those whose type is known to be wrong
+                for (arg, expected) in args.iter().zip(&signature.params) {
+                    let got = self.static_type(arg);
+                    if !expected.is_compatible_with(&got) {
+                        return Err(CompileError::TypeMismatch {
+                            expected: format!("{:?} argument to {}()", expected, name),
+                            got: format!("{:?}", got),
+                        });
+                    }
+                    self.compile_expression(arg)?;
+                }
 
-                self.policy.emit(Instruction::Call { func: func_id, argc: args.len() as u8 });
+                self.policy.emit(Instruction::Call { func, argc: args.len() as u8 });
                 Ok(())
             },
 
-            Expression::Aggregate { .. } => Err(CompileError::UnsupportedAggregate(
-                "Aggregate functions require special handling".to_string(),
-            )),
+            Expression::Aggregate { path, func, condition } => {
+                self.compile_path(path)?;
+
+                // Loop: the condition runs once per element until the result is known
+                let begin = self.policy.code.len();
+                self.policy
+                    .emit(Instruction::AggregateBegin { op: aggregate_op(*func), skip: 0 });
+
+                self.context.aggregate_depth += 1;
+                self.compile_condition(condition)?;
+                self.context.aggregate_depth -= 1;
+
+                let next = self.policy.code.len();
+                let offset = i16::try_from(begin as isize + 1 - next as isize)
+                    .map_err(|_| CompileError::JumpOutOfRange(begin + 1))?;
+                self.policy.emit(Instruction::AggregateNext { offset });
+                self.patch_jump(begin, next + 1)
+            },
         }
     }
 
-    fn compile_literal(&mut self, value: &Value) -> CompileResult<()> {
-        let bytecode_value = match value {
-            Value::Int(n) => BytecodeValue::Int(*n),
-            Value::Bool(b) => BytecodeValue::Bool(*b),
-            Value::String(s) => BytecodeValue::String(s.clone()),
-            Value::Float(_) => {
-                return Err(CompileError::UnsupportedExpression(
-                    "Float literals not yet supported in bytecode".to_string(),
-                ))
+    /// Emit the code to load a path
+    ///
+    /// Inside an aggregate condition, a path that does not start at a context
+    /// root refers to the current element: `item` is the element itself and
+    /// any other path is a field of it (`role` is short for `item.role`).
+    fn compile_path(&mut self, path: &Path) -> CompileResult<()> {
+        let root = path.root().unwrap_or_default();
+        if self.context.aggregate_depth == 0 || CONTEXT_ROOTS.contains(&root) {
+            let offset = self.context.get_or_allocate_field(&path.to_string());
+            self.policy.emit(Instruction::LoadField { offset });
+            return Ok(());
+        }
+
+        self.policy.emit(Instruction::LoadElement);
+        let fields = if root == ELEMENT_ROOT { &path.segments[1..] } else { &path.segments[..] };
+        for field in fields {
+            let idx = self.add_constant(BytecodeValue::String(field.clone()))?;
+            self.policy.emit(Instruction::GetField { idx });
+        }
+        Ok(())
+    }
+
+    /// Type of an expression as far as it is known before evaluation
+    ///
+    /// Field types are only known at runtime, so paths are `Any`.
+    fn static_type(&self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Literal(value) => Type::from_value(value),
+            Expression::Call { name, args } => match self.functions.expand_clock(name, args) {
+                Some(expanded) => self.static_type(&expanded),
+                None => self
+                    .functions
+                    .lookup(name)
+                    .map(|f| f.signature.returns.clone())
+                    .unwrap_or(Type::Any),
             },
-            Value::Array(_) => {
-                return Err(CompileError::UnsupportedExpression(
-                    "Array literals not yet supported in bytecode".to_string(),
-                ))
+            Expression::Binary { left, op: BinaryOp::Default, right } => {
+                match self.static_type(left) {
+                    Type::Any => self.static_type(right),
+                    typ => typ,
+                }
             },
-        };
+            Expression::Binary { left, op: BinaryOp::Arithmetic(_), right } => {
+                Type::arithmetic(&self.static_type(left), &self.static_type(right))
+            },
+            Expression::Binary { .. } | Expression::Logical { .. } | Expression::In { .. } => {
+                Type::Bool
+            },
+            Expression::Aggregate { func, .. } => func.result_type(),
+            Expression::Path(_) => Type::Any,
+        }
+    }
 
-        let idx = self.add_constant(bytecode_value)?;
+    fn compile_literal(&mut self, value: &Value) -> CompileResult<()> {
+        let idx = self.add_constant(bytecode_value(value))?;
         self.policy.emit(Instruction::LoadConst { idx });
         Ok(())
     }
@@ -282,6 +578,85 @@ impl Default for PolicyCompiler {
     }
 }
 
+/// Compile the pattern of a `matches`/`like`, which must be a string literal
+pub(crate) fn literal_pattern(op: BinaryOp, pattern: &Expression) -> Result<Pattern, String> {
+    let kind = match op {
+        BinaryOp::Matches => PatternKind::Regex,
+        BinaryOp::Like => PatternKind::Glob,
+        other => return Err(format!("{:?} is not a pattern operator", other)),
+    };
+    match pattern {
+        Expression::Literal(Value::String(source)) => Pattern::new(kind, source.as_str()),
+        other => Err(format!("Pattern must be a string literal, got {:?}", other)),
+    }
+}
+
+/// Bytecode operator for an AST set operator
+pub(crate) fn set_op_for(op: SetOperator) -> SetOp {
+    match op {
+        SetOperator::In => SetOp::In,
+        SetOperator::Contains => SetOp::Contains,
+        SetOperator::Intersects => SetOp::Intersects,
+        SetOperator::SubsetOf => SetOp::SubsetOf,
+    }
+}
+
+/// Bytecode operator for AST arithmetic
+pub(crate) fn arith_op_for(op: ArithmeticOp) -> ArithOp {
+    match op {
+        ArithmeticOp::Add => ArithOp::Add,
+        ArithmeticOp::Sub => ArithOp::Sub,
+        ArithmeticOp::Mul => ArithOp::Mul,
+        ArithmeticOp::Div => ArithOp::Div,
+        ArithmeticOp::Mod => ArithOp::Mod,
+    }
+}
+
+/// Bytecode operator for an AST comparison
+pub(crate) fn comp_op_for(op: ComparisonOp) -> CompOp {
+    match op {
+        ComparisonOp::Eq => CompOp::Eq,
+        ComparisonOp::Neq => CompOp::Neq,
+        ComparisonOp::Lt => CompOp::Lt,
+        ComparisonOp::LtEq => CompOp::Lte,
+        ComparisonOp::Gt => CompOp::Gt,
+        ComparisonOp::GtEq => CompOp::Gte,
+    }
+}
+
+/// Convert an AST literal to a bytecode constant
+pub(crate) fn bytecode_value(value: &Value) -> BytecodeValue {
+    match value {
+        Value::Int(n) => BytecodeValue::Int(*n),
+        Value::Float(f) => BytecodeValue::Float(*f),
+        Value::Bool(b) => BytecodeValue::Bool(*b),
+        Value::String(s) => BytecodeValue::String(s.clone()),
+        Value::Array(items) => BytecodeValue::Array(items.iter().map(bytecode_value).collect()),
+    }
+}
+
+/// Convert an AST obligation to its runtime form
+fn bytecode_obligation(obligation: &Obligation) -> BytecodeObligation {
+    obligation
+        .params
+        .iter()
+        .fold(BytecodeObligation::new(&obligation.name), |o, (key, value)| {
+            o.with_param(key, bytecode_value(value))
+        })
+}
+
+/// Bytecode operation for an aggregate function
+pub(crate) fn aggregate_op(func: AggregateFunc) -> AggregateOp {
+    match func {
+        AggregateFunc::Count => AggregateOp::Count,
+        AggregateFunc::Any => AggregateOp::Any,
+        AggregateFunc::All => AggregateOp::All,
+        AggregateFunc::Sum => AggregateOp::Sum,
+        AggregateFunc::Max => AggregateOp::Max,
+        AggregateFunc::Min => AggregateOp::Min,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -298,10 +673,12 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have: LoadConst, Return
-        assert_eq!(compiled.code.len(), 2);
+        // Should have: LoadConst, JumpIfFalse, Return(true), Return(false)
+        assert_eq!(compiled.code.len(), 4);
         assert!(matches!(compiled.code[0], Instruction::LoadConst { idx: 0 }));
-        assert!(matches!(compiled.code[1], Instruction::Return { value: true }));
+        assert!(matches!(compiled.code[1], Instruction::JumpIfFalse { offset: 2 }));
+        assert!(matches!(compiled.code[2], Instruction::Return { value: true }));
+        assert!(matches!(compiled.code[3], Instruction::Return { value: false }));
         assert_eq!(compiled.constants.len(), 1);
         assert_eq!(compiled.constants[0], BytecodeValue::Int(42));
     }
@@ -336,8 +713,8 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have: LoadField, Return
-        assert_eq!(compiled.code.len(), 2);
+        // Should have: LoadField, JumpIfFalse, Return(true), Return(false)
+        assert_eq!(compiled.code.len(), 4);
         assert!(matches!(compiled.code[0], Instruction::LoadField { offset: 0 }));
     }
 
@@ -354,12 +731,14 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have: LoadField, LoadConst, Compare, Return
-        assert_eq!(compiled.code.len(), 4);
+        // Should have: LoadField, LoadConst, Compare, JumpIfFalse, Return(true), Return(false)
+        assert_eq!(compiled.code.len(), 6);
         assert!(matches!(compiled.code[0], Instruction::LoadField { offset: 0 }));
         assert!(matches!(compiled.code[1], Instruction::LoadConst { idx: 0 }));
         assert!(matches!(compiled.code[2], Instruction::Compare { op: CompOp::Eq }));
-        assert!(matches!(compiled.code[3], Instruction::Return { value: true }));
+        assert!(matches!(compiled.code[3], Instruction::JumpIfFalse { offset: 2 }));
+        assert!(matches!(compiled.code[4], Instruction::Return { value: true }));
+        assert!(matches!(compiled.code[5], Instruction::Return { value: false }));
     }
 
     #[test]
@@ -403,9 +782,11 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have: LoadConst(true), LoadConst(false), And, Return
-        assert_eq!(compiled.code.len(), 4);
-        assert!(matches!(compiled.code[2], Instruction::And));
+        // LoadConst(true), Dup, JumpIfFalse(+3), Pop, LoadConst(false), JumpIfFalse, Return, Return
+        assert_eq!(compiled.code.len(), 8);
+        assert!(matches!(compiled.code[1], Instruction::Dup));
+        assert!(matches!(compiled.code[2], Instruction::JumpIfFalse { offset: 3 }));
+        assert!(matches!(compiled.code[3], Instruction::Pop));
     }
 
     #[test]
@@ -420,7 +801,9 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        assert!(matches!(compiled.code[2], Instruction::Or));
+        assert!(matches!(compiled.code[1], Instruction::Dup));
+        assert!(matches!(compiled.code[2], Instruction::JumpIfTrue { offset: 3 }));
+        assert!(matches!(compiled.code[3], Instruction::Pop));
     }
 
     #[test]
@@ -433,8 +816,8 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have: LoadConst(true), Not, Return
-        assert_eq!(compiled.code.len(), 3);
+        // Should have: LoadConst(true), Not, JumpIfFalse, Return, Return
+        assert_eq!(compiled.code.len(), 5);
         assert!(matches!(compiled.code[1], Instruction::Not));
     }
 
@@ -457,6 +840,45 @@ mod tests {
         assert!(compiled.constants.contains(&BytecodeValue::String("staging".to_string())));
     }
 
+    #[test]
+    fn test_compile_in_set() {
+        let env = || Expression::path(vec!["env".to_string()]);
+        let names: Vec<_> =
+            (0..IN_SET_MIN_LEN).map(|i| Value::String(format!("env-{}", i))).collect();
+
+        // Long lists become one hashed lookup, shared between identical lists
+        let condition = Condition::new(Expression::or(vec![
+            Expression::in_list(env(), names.clone()),
+            Expression::in_list(env(), names.clone()),
+        ]));
+        let compiled = PolicyCompiler::new(1)
+            .compile(&create_simple_policy(Requirements::requires(vec![condition])))
+            .unwrap();
+        assert_eq!(compiled.sets.len(), 1);
+        assert_eq!(compiled.sets[0].items().len(), IN_SET_MIN_LEN);
+        assert_eq!(
+            &compiled.code[..2],
+            &[Instruction::LoadField { offset: 0 }, Instruction::InSet { set: 0 }]
+        );
+
+        // Short lists stay comparison chains
+        let condition = Condition::new(Expression::in_list(env(), names[..2].to_vec()));
+        let compiled = PolicyCompiler::new(1)
+            .compile(&create_simple_policy(Requirements::requires(vec![condition])))
+            .unwrap();
+        assert!(compiled.sets.is_empty());
+
+        let condition = Condition::new(Expression::binary(
+            Expression::literal(Value::String("admin".to_string())),
+            BinaryOp::Set(SetOperator::In),
+            Expression::path(vec!["roles".to_string()]),
+        ));
+        let compiled = PolicyCompiler::new(1)
+            .compile(&create_simple_policy(Requirements::requires(vec![condition])))
+            .unwrap();
+        assert!(compiled.code.contains(&Instruction::SetCompare { op: SetOp::In }));
+    }
+
     #[test]
     fn test_compile_multiple_conditions() {
         // Two conditions: x == 1 AND y == 2
@@ -494,6 +916,25 @@ mod tests {
         assert!(matches!(compiled.code[0], Instruction::Return { value: false }));
     }
 
+    #[test]
+    fn test_compile_effects() {
+        let policy = create_simple_policy(Requirements::denies(Some("Not allowed".to_string())))
+            .with_obligation(
+                Obligation::new("redact").with_param("field", Value::String("ssn".to_string())),
+            )
+            .with_advice(Obligation::new("notify"));
+
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        assert_eq!(compiled.effects.reason.as_deref(), Some("Not allowed"));
+        assert_eq!(
+            compiled.effects.obligations,
+            vec![BytecodeObligation::new("redact")
+                .with_param("field", BytecodeValue::String("ssn".to_string()))]
+        );
+        assert_eq!(compiled.effects.advice, vec![BytecodeObligation::new("notify")]);
+    }
+
     #[test]
     fn test_compile_with_where_clause() {
         let conditions = vec![Condition::new(Expression::literal(Value::Bool(true)))];
@@ -511,27 +952,37 @@ mod tests {
 
     #[test]
     fn test_compile_function_call() {
-        // count()
-        let condition =
-            Condition::new(Expression::Call { name: "count".to_string(), args: vec![] });
+        // len("abc") == 3
+        let condition = Condition::new(Expression::binary(
+            Expression::Call {
+                name: "len".to_string(),
+                args: vec![Expression::literal(Value::String("abc".to_string()))],
+            },
+            BinaryOp::Comparison(ComparisonOp::Eq),
+            Expression::literal(Value::Int(3)),
+        ));
         let policy = create_simple_policy(Requirements::requires(vec![condition]));
 
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have Call instruction
+        // Should have a Call instruction resolved through the builtin registry
+        let len_id = FunctionRegistry::builtins().resolve("len").unwrap();
         assert!(compiled
             .code
             .iter()
-            .any(|i| matches!(i, Instruction::Call { func: 0, argc: 0 })));
+            .any(|i| matches!(i, Instruction::Call { func, argc: 1 } if *func == len_id)));
     }
 
     #[test]
     fn test_compile_function_call_with_args() {
-        // max(1, 2)
+        // starts_with(resource.name, "prod")
         let condition = Condition::new(Expression::Call {
-            name: "count".to_string(),
-            args: vec![Expression::literal(Value::Int(1)), Expression::literal(Value::Int(2))],
+            name: "starts_with".to_string(),
+            args: vec![
+                Expression::path(vec!["resource".to_string(), "name".to_string()]),
+                Expression::literal(Value::String("prod".to_string())),
+            ],
         });
         let policy = create_simple_policy(Requirements::requires(vec![condition]));
 
@@ -542,6 +993,50 @@ mod tests {
         assert!(compiled.code.iter().any(|i| matches!(i, Instruction::Call { argc: 2, .. })));
     }
 
+    #[test]
+    fn test_error_function_arity() {
+        let condition = Condition::new(Expression::Call {
+            name: "lower".to_string(),
+            args: vec![
+                Expression::literal(Value::String("a".to_string())),
+                Expression::literal(Value::String("b".to_string())),
+            ],
+        });
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let result = PolicyCompiler::new(1).compile(&policy);
+        assert!(matches!(
+            result.unwrap_err(),
+            CompileError::ArityMismatch { expected: 1, got: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_error_function_argument_type() {
+        // utc_hour("noon") - a string literal can never be a timestamp
+        let condition = Condition::new(Expression::Call {
+            name: "utc_hour".to_string(),
+            args: vec![Expression::literal(Value::String("noon".to_string()))],
+        });
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let result = PolicyCompiler::new(1).compile(&policy);
+        assert!(matches!(result.unwrap_err(), CompileError::TypeMismatch { .. }));
+
+        // Nested calls are checked through their return type
+        let condition = Condition::new(Expression::Call {
+            name: "lower".to_string(),
+            args: vec![Expression::Call {
+                name: "len".to_string(),
+                args: vec![Expression::literal(Value::String("abc".to_string()))],
+            }],
+        });
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let result = PolicyCompiler::new(1).compile(&policy);
+        assert!(matches!(result.unwrap_err(), CompileError::TypeMismatch { .. }));
+    }
+
     #[test]
     fn test_compile_complex_expression() {
         // (x == 1 OR y == 2) AND z == 3
@@ -569,9 +1064,9 @@ mod tests {
         let compiler = PolicyCompiler::new(1);
         let compiled = compiler.compile(&policy).unwrap();
 
-        // Should have both AND and OR instructions
-        assert!(compiled.code.iter().any(|i| matches!(i, Instruction::And)));
-        assert!(compiled.code.iter().any(|i| matches!(i, Instruction::Or)));
+        // Both operators short-circuit
+        assert!(compiled.code.iter().any(|i| matches!(i, Instruction::JumpIfFalse { .. })));
+        assert!(compiled.code.iter().any(|i| matches!(i, Instruction::JumpIfTrue { .. })));
     }
 
     #[test]
@@ -597,27 +1092,104 @@ mod tests {
     }
 
     #[test]
-    fn test_error_unsupported_float() {
+    fn test_compile_float_literal() {
         let condition = Condition::new(Expression::literal(Value::Float(3.15)));
         let policy = create_simple_policy(Requirements::requires(vec![condition]));
 
-        let compiler = PolicyCompiler::new(1);
-        let result = compiler.compile(&policy);
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
 
-        assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), CompileError::UnsupportedExpression(_)));
+        assert_eq!(compiled.constants, vec![BytecodeValue::Float(3.15)]);
     }
 
     #[test]
-    fn test_error_unsupported_array() {
-        let condition = Condition::new(Expression::literal(Value::Array(vec![])));
+    fn test_compile_array_literal() {
+        let condition = Condition::new(Expression::literal(Value::Array(vec![
+            Value::Int(1),
+            Value::Array(vec![Value::Float(2.5)]),
+        ])));
         let policy = create_simple_policy(Requirements::requires(vec![condition]));
 
-        let compiler = PolicyCompiler::new(1);
-        let result = compiler.compile(&policy);
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
 
-        assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), CompileError::UnsupportedExpression(_)));
+        assert_eq!(
+            compiled.constants,
+            vec![BytecodeValue::Array(vec![
+                BytecodeValue::Int(1),
+                BytecodeValue::Array(vec![BytecodeValue::Float(2.5)]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_compile_default_operator() {
+        // resource.tier ?? "free"
+        let condition = Condition::new(Expression::binary(
+            Expression::path(vec!["resource".to_string(), "tier".to_string()]),
+            BinaryOp::Default,
+            Expression::literal(Value::String("free".to_string())),
+        ));
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        assert_eq!(
+            &compiled.code[..5],
+            &[
+                Instruction::LoadField { offset: 0 },
+                Instruction::Dup,
+                Instruction::JumpIfDefined { offset: 3 },
+                Instruction::Pop,
+                Instruction::LoadConst { idx: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_pattern_operators() {
+        let matches = |op, pattern: &str| {
+            Condition::new(Expression::binary(
+                Expression::path(vec!["resource".to_string(), "name".to_string()]),
+                op,
+                Expression::literal(Value::String(pattern.to_string())),
+            ))
+        };
+        let policy = create_simple_policy(Requirements::requires(vec![Condition::new(
+            Expression::or(vec![
+                matches(BinaryOp::Matches, "^svc-").expr,
+                matches(BinaryOp::Like, "svc-*").expr,
+                matches(BinaryOp::Matches, "^svc-").expr,
+            ]),
+        )]));
+
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        // Patterns live in the pattern pool, not the constant pool, and are shared
+        assert_eq!(compiled.patterns.len(), 2);
+        assert_eq!(compiled.patterns[1].kind(), PatternKind::Glob);
+        assert!(compiled.constants.is_empty());
+        assert_eq!(
+            &compiled.code[..2],
+            &[Instruction::LoadField { offset: 0 }, Instruction::Match { pattern: 0 }]
+        );
+        assert!(compiled.code.contains(&Instruction::Match { pattern: 1 }));
+
+        let invalid =
+            create_simple_policy(Requirements::requires(vec![matches(BinaryOp::Matches, "(")]));
+        assert!(matches!(
+            PolicyCompiler::new(1).compile(&invalid),
+            Err(CompileError::InvalidPattern(_))
+        ));
+
+        let dynamic =
+            create_simple_policy(Requirements::requires(vec![Condition::new(Expression::binary(
+                Expression::path(vec!["resource".to_string(), "name".to_string()]),
+                BinaryOp::Like,
+                Expression::path(vec!["resource".to_string(), "pattern".to_string()]),
+            ))]));
+        assert!(matches!(
+            PolicyCompiler::new(1).compile(&dynamic),
+            Err(CompileError::InvalidPattern(_))
+        ));
     }
 
     #[test]
@@ -632,7 +1204,34 @@ mod tests {
         let result = compiler.compile(&policy);
 
         assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), CompileError::UnsupportedExpression(_)));
+        assert!(matches!(result.unwrap_err(), CompileError::UnknownFunction(_)));
+    }
+
+    #[test]
+    fn test_error_schema_type_mismatch() {
+        use crate::parser::parse::Parser;
+        use crate::rar::ResourceTypeId;
+        use crate::schema::{ResourceSchema, Schema};
+
+        let schema = Schema::new().with_resource(
+            ResourceTypeId(1),
+            ResourceSchema::new("Deployment").with_attribute("replicas", Type::Int),
+        );
+        let env = schema.type_env(&[ResourceTypeId(1)]);
+        let source = |requirement: &str| {
+            format!("policy P:\n  \"p\"\n  triggers when true\n  requires {}", requirement)
+        };
+
+        let valid = Parser::new(&source("resource.replicas >= 3")).parse_policy().unwrap();
+        assert!(PolicyCompiler::new(1).with_types(env.clone()).compile(&valid).is_ok());
+
+        let invalid = Parser::new(&source("resource.replicas == \"3\"")).parse_policy().unwrap();
+        let error = PolicyCompiler::new(1).with_types(env).compile(&invalid).unwrap_err();
+        assert!(matches!(error, CompileError::TypeError { line: 4, .. }));
+        assert_eq!(error.to_string(), "Type error at line 4: Incompatible types: int and string");
+
+        // Without a type environment only the bytecode is generated
+        assert!(PolicyCompiler::new(1).compile(&invalid).is_ok());
     }
 
     #[test]
@@ -671,4 +1270,124 @@ mod tests {
         assert!(!compiled.code.is_empty());
         assert!(compiled.code.iter().any(|i| matches!(i, Instruction::Return { value: true })));
     }
+
+    #[test]
+    fn test_compile_triggers_guard_prologue() {
+        // triggers when x == 1, requires true
+        let trigger = Condition::new(Expression::binary(
+            Expression::path(vec!["x".to_string()]),
+            BinaryOp::Comparison(ComparisonOp::Eq),
+            Expression::literal(Value::Int(1)),
+        ));
+        let policy = Policy::new(
+            "Guarded".to_string(),
+            "Guarded policy".to_string(),
+            vec![trigger],
+            Requirements::requires(vec![Condition::new(Expression::literal(Value::Bool(true)))]),
+        );
+
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        // LoadField, LoadConst, Compare, JumpIfFalse(-> NotApplicable), <requirements>, NotApplicable
+        assert!(matches!(compiled.code[3], Instruction::JumpIfFalse { .. }));
+        let last = compiled.code.len() - 1;
+        assert!(matches!(compiled.code[last], Instruction::NotApplicable));
+        match compiled.code[3] {
+            Instruction::JumpIfFalse { offset } => assert_eq!(3 + offset as usize, last),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_compile_multiple_triggers_share_exit() {
+        let policy = Policy::new(
+            "Guarded".to_string(),
+            "Guarded policy".to_string(),
+            vec![
+                Condition::new(Expression::literal(Value::Bool(true))),
+                Condition::new(Expression::literal(Value::Bool(true))),
+            ],
+            Requirements::denies(None),
+        );
+
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        // Both guards jump to the single NotApplicable exit
+        let last = compiled.code.len() - 1;
+        assert!(matches!(compiled.code[last], Instruction::NotApplicable));
+        let targets: Vec<usize> = compiled
+            .code
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instr)| match instr {
+                Instruction::JumpIfFalse { offset } => Some(i + *offset as usize),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(targets, vec![last, last]);
+    }
+
+    #[test]
+    fn test_compile_without_triggers_has_no_guard() {
+        let policy = create_simple_policy(Requirements::denies(None));
+        let compiled = PolicyCompiler::new(1).compile(&policy).unwrap();
+
+        assert!(!compiled.code.iter().any(|i| matches!(i, Instruction::NotApplicable)));
+    }
+
+    #[test]
+    fn test_compile_with_field_map() {
+        let condition = Condition::new(Expression::binary(
+            Expression::path(vec!["resource".to_string(), "owner".to_string()]),
+            BinaryOp::Comparison(ComparisonOp::Eq),
+            Expression::path(vec![
+                "request".to_string(),
+                "principal".to_string(),
+                "id".to_string(),
+            ]),
+        ));
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let (_, field_map) = PolicyCompiler::new(1).compile_with_field_map(&policy).unwrap();
+
+        assert_eq!(field_map.len(), 2);
+        assert_eq!(field_map[&0], vec!["resource", "owner"]);
+        assert_eq!(field_map[&1], vec!["request", "principal", "id"]);
+    }
+
+    #[test]
+    fn test_compile_aggregate_loop() {
+        let condition = Condition::new(Expression::binary(
+            Expression::Aggregate {
+                path: Path::new(vec!["resource".to_string(), "approvers".to_string()]),
+                func: AggregateFunc::Count,
+                condition: Box::new(Condition::new(Expression::binary(
+                    Expression::path(vec!["role".to_string()]),
+                    BinaryOp::Comparison(ComparisonOp::Eq),
+                    Expression::literal(Value::String("sre".to_string())),
+                ))),
+            },
+            BinaryOp::Comparison(ComparisonOp::GtEq),
+            Expression::literal(Value::Int(2)),
+        ));
+        let policy = create_simple_policy(Requirements::requires(vec![condition]));
+
+        let (compiled, field_map) = PolicyCompiler::new(1).compile_with_field_map(&policy).unwrap();
+
+        // Element paths are not context fields
+        assert_eq!(field_map.len(), 1);
+        assert_eq!(
+            &compiled.code[..6],
+            &[
+                Instruction::LoadField { offset: 0 },
+                Instruction::AggregateBegin { op: AggregateOp::Count, skip: 6 },
+                Instruction::LoadElement,
+                Instruction::GetField { idx: 0 },
+                Instruction::LoadConst { idx: 1 },
+                Instruction::Compare { op: CompOp::Eq },
+            ]
+        );
+        assert_eq!(compiled.code[6], Instruction::AggregateNext { offset: -4 });
+        assert_eq!(compiled.constants[0], BytecodeValue::String("role".to_string()));
+    }
 }
@@ -0,0 +1,296 @@
+//! Diagnostics for policy sources
+//!
+//! The parser reports every error it recovers from, plus warnings such as
+//! unused definitions, as [`Diagnostic`]s. A diagnostic points into a file
+//! registered in a [`SourceMap`] and renders as a rustc-style snippet:
+//!
+//! ```text
+//! error: Invalid policy structure: Expected 'requires' or 'denies'
+//!  --> deploy.ipe:4:3
+//!   |
+//! 4 |   require resource.approved == true
+//!   |   ^^^^^^^
+//!   |
+//!   = help: did you mean `requires`?
+//! ```
+
+use crate::ast::nodes::SourceLocation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub use crate::ast::nodes::FileId;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning attached to a source location
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: SourceLocation,
+    /// Short text shown next to the underlined span
+    pub label: Option<String>,
+    /// Suggestion shown below the snippet
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// Create an error
+    pub fn error(message: impl Into<String>, location: SourceLocation) -> Self {
+        Self::new(Severity::Error, message.into(), location)
+    }
+
+    /// Create a warning
+    pub fn warning(message: impl Into<String>, location: SourceLocation) -> Self {
+        Self::new(Severity::Warning, message.into(), location)
+    }
+
+    fn new(severity: Severity, message: String, location: SourceLocation) -> Self {
+        Self {
+            severity,
+            message,
+            location,
+            label: None,
+            help: None,
+        }
+    }
+
+    /// Builder: set the label
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Builder: set the help text
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Check if this diagnostic is an error
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Render as a snippet of the source, with the span underlined
+    ///
+    /// Falls back to the message and line number when the file is not in
+    /// `sources`.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let location = &self.location;
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+
+        let Some(file) = sources.get(location.file) else {
+            out.push_str(&format!(" --> line {}:{}\n", location.line, location.column));
+            return out;
+        };
+
+        let line_text = file.line(location.line).unwrap_or_default();
+        let gutter = " ".repeat(location.line.to_string().len());
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter,
+            file.name(),
+            location.line,
+            location.column
+        ));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", location.line, line_text));
+
+        // Underline up to the end of the first line of the span
+        let padding = location.column.saturating_sub(1);
+        let rest = line_text.chars().skip(padding).collect::<String>();
+        let width = rest.char_indices().take_while(|(i, _)| *i < location.length).count().max(1);
+        let marker = match self.severity {
+            Severity::Error => "^",
+            Severity::Warning => "-",
+        };
+        let mut underline = format!("{}{}", " ".repeat(padding), marker.repeat(width));
+        if let Some(label) = &self.label {
+            underline.push(' ');
+            underline.push_str(label);
+        }
+        out.push_str(&format!("{} | {}\n", gutter, underline));
+
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} = help: {}\n", gutter, help));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (line {}, column {})",
+            self.severity, self.message, self.location.line, self.location.column
+        )
+    }
+}
+
+/// A named source file
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    name: String,
+    source: String,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    /// Create a source file
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { name: name.into(), source, line_starts }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Text of a 1-indexed line, without the line break
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self.line_starts.get(line).map_or(self.source.len(), |next| next - 1);
+        Some(self.source[start..end].trim_end_matches('\r'))
+    }
+
+    /// 1-indexed line and column (in characters) of a byte offset
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.source.get(start..offset).map_or(0, |s| s.chars().count());
+        (line, column + 1)
+    }
+}
+
+/// The source files diagnostics refer to
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a file, returning its id
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0 as usize)
+    }
+}
+
+/// The candidate closest to `word`, if it is a plausible misspelling
+pub fn suggest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (word.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != word)
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_file_lines() {
+        let file = SourceFile::new("a.ipe", "policy A:\r\n  \"é\"\nlast");
+        assert_eq!(file.line(1), Some("policy A:"));
+        assert_eq!(file.line(2), Some("  \"é\""));
+        assert_eq!(file.line(3), Some("last"));
+        assert_eq!(file.line(4), None);
+        assert_eq!(file.line(0), None);
+
+        assert_eq!(file.line_column(0), (1, 1));
+        assert_eq!(file.line_column(11), (2, 1));
+        // Columns count characters, not bytes
+        assert_eq!(file.line_column(17), (2, 6));
+        assert_eq!(file.line_column(100), (3, 5));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let keywords = ["requires", "denies", "triggers", "policy"];
+        assert_eq!(suggest("require", keywords), Some("requires"));
+        assert_eq!(suggest("trigers", keywords), Some("triggers"));
+        assert_eq!(suggest("polcy", keywords), Some("policy"));
+        assert_eq!(suggest("resource", keywords), None);
+        assert_eq!(suggest("policy", keywords), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut sources = SourceMap::new();
+        let file =
+            sources.add("deploy.ipe", "policy A: \"a\"\n  triggers when true\n  require x\n");
+        let location = SourceLocation::new(3, 3, 7).with_span(file, 37);
+
+        let diagnostic = Diagnostic::error("Expected 'requires' or 'denies'", location)
+            .with_label("expected a requirement")
+            .with_help("did you mean `requires`?");
+        assert_eq!(
+            diagnostic.render(&sources),
+            "error: Expected 'requires' or 'denies'\n \
+             --> deploy.ipe:3:3\n  \
+             |\n\
+             3 |   require x\n  \
+             |   ^^^^^^^ expected a requirement\n  \
+             |\n  \
+             = help: did you mean `requires`?\n"
+        );
+
+        // Unknown files still report the position
+        let warning = Diagnostic::warning("unused", SourceLocation::new(1, 2, 3));
+        assert_eq!(warning.render(&SourceMap::new()), "warning: unused\n --> line 1:2\n");
+        assert_eq!(warning.to_string(), "warning: unused (line 1, column 2)");
+    }
+}
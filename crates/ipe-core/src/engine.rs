@@ -1,7 +1,14 @@
-use crate::index::PolicyDB;
-use crate::interpreter::Interpreter;
-use crate::{Error, EvaluationContext, Result};
+use crate::bytecode::{Obligation, Value};
+use crate::combining::{Combiner, CombiningAlgorithm};
+use crate::explain::Explanation;
+use crate::functions::{FunctionOptions, FunctionRegistry, FunctionSignature};
+use crate::index::{PolicyDB, StoredPolicy};
+use crate::interpreter::{Interpreter, Outcome};
+use crate::rar::ResourceTypeId;
+use crate::{EvaluationContext, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Policy decision result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +16,12 @@ pub struct Decision {
     pub kind: DecisionKind,
     pub reason: Option<String>,
     pub matched_policies: Vec<String>,
+    /// Obligations the enforcement point must fulfil before acting on the decision
+    #[serde(default)]
+    pub obligations: Vec<Obligation>,
+    /// Advice the enforcement point may act on
+    #[serde(default)]
+    pub advice: Vec<Obligation>,
 }
 
 impl Decision {
@@ -17,6 +30,8 @@ impl Decision {
             kind: if allowed { DecisionKind::Allow } else { DecisionKind::Deny },
             reason: None,
             matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
         }
     }
 
@@ -25,6 +40,8 @@ impl Decision {
             kind: DecisionKind::Allow,
             reason: None,
             matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
         }
     }
 
@@ -33,9 +50,36 @@ impl Decision {
             kind: DecisionKind::Deny,
             reason: None,
             matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
         }
     }
 
+    pub fn not_applicable() -> Self {
+        Self {
+            kind: DecisionKind::NotApplicable,
+            reason: None,
+            matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
+        }
+    }
+
+    pub fn indeterminate() -> Self {
+        Self {
+            kind: DecisionKind::Indeterminate,
+            reason: None,
+            matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
+        }
+    }
+
+    /// Whether access is granted (only `Allow` grants access)
+    pub fn is_allowed(&self) -> bool {
+        self.kind == DecisionKind::Allow
+    }
+
     pub fn with_reason(mut self, reason: String) -> Self {
         self.reason = Some(reason);
         self
@@ -52,23 +96,71 @@ impl Decision {
 pub enum DecisionKind {
     Allow,
     Deny,
+    /// No policy applied to the request
+    NotApplicable,
+    /// A policy failed to evaluate and the result could not be determined
+    Indeterminate,
 }
 
 /// Main policy evaluation engine
 #[derive(Default)]
 pub struct PolicyEngine {
     policy_db: PolicyDB,
+    combining: CombiningAlgorithm,
+    resource_combining: HashMap<ResourceTypeId, CombiningAlgorithm>,
+    functions: Arc<FunctionRegistry>,
 }
 
 impl PolicyEngine {
     /// Create a new empty policy engine
     pub fn new() -> Self {
-        Self { policy_db: PolicyDB::new() }
+        Self::default()
     }
 
     /// Create a policy engine with the given policy database
     pub fn with_policy_db(policy_db: PolicyDB) -> Self {
-        Self { policy_db }
+        Self { policy_db, ..Self::default() }
+    }
+
+    /// Set the default combining algorithm
+    pub fn with_combining_algorithm(mut self, algorithm: CombiningAlgorithm) -> Self {
+        self.combining = algorithm;
+        self
+    }
+
+    /// Override the combining algorithm for a single resource type
+    pub fn set_resource_combining_algorithm(
+        &mut self,
+        resource_type: ResourceTypeId,
+        algorithm: CombiningAlgorithm,
+    ) {
+        self.resource_combining.insert(resource_type, algorithm);
+    }
+
+    /// Get the combining algorithm used for a resource type
+    pub fn combining_algorithm_for(&self, resource_type: ResourceTypeId) -> CombiningAlgorithm {
+        self.resource_combining.get(&resource_type).copied().unwrap_or(self.combining)
+    }
+
+    /// Register a host function callable from policies
+    ///
+    /// Policies that call it must be compiled against [`PolicyEngine::functions`].
+    pub fn register_function<F>(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        options: FunctionOptions,
+        native: F,
+    ) -> Result<u8>
+    where
+        F: Fn(&[Value]) -> std::result::Result<Value, String> + Send + Sync + 'static,
+    {
+        Ok(Arc::make_mut(&mut self.functions).register_with(name, signature, options, native)?)
+    }
+
+    /// Get the function registry used to evaluate policies
+    pub fn functions(&self) -> &Arc<FunctionRegistry> {
+        &self.functions
     }
 
     /// Get a reference to the policy database
@@ -83,53 +175,48 @@ impl PolicyEngine {
 
     /// Evaluate a single policy against the context
     pub fn evaluate(&self, ctx: &EvaluationContext) -> Result<Decision> {
+        Ok(self.evaluate_with(ctx, |stored_policy, interp| interp.run(&stored_policy.policy, ctx)))
+    }
+
+    /// Evaluate like [`PolicyEngine::evaluate`] and explain how each policy
+    /// and condition contributed to the decision
+    pub fn evaluate_explain(&self, ctx: &EvaluationContext) -> Result<(Decision, Explanation)> {
+        let mut explanation = Explanation::default();
+        let decision = self.evaluate_with(ctx, |stored_policy, interp| {
+            let trace = interp.explain(&stored_policy.name, &stored_policy.policy, ctx);
+            let result = trace.result();
+            explanation.policies.push(trace);
+            result
+        });
+        Ok((decision, explanation))
+    }
+
+    fn evaluate_with<F>(&self, ctx: &EvaluationContext, mut run: F) -> Decision
+    where
+        F: FnMut(&StoredPolicy, &mut Interpreter) -> std::result::Result<Outcome, String>,
+    {
         // Get policies for this resource type
         let policies = self.policy_db.get_policies_for_resource(ctx.resource.type_id);
 
+        // Nothing applies, as when no policy's triggers match
         if policies.is_empty() {
-            // No policies found - default deny
-            return Ok(
-                Decision::deny().with_reason("No policies found for resource type".to_string())
-            );
+            return Decision::not_applicable()
+                .with_reason("No policies found for resource type".to_string());
         }
 
-        let mut decision = Decision::deny();
-        let mut any_allow = false;
-        let mut any_deny = false;
+        let mut combiner = Combiner::new(self.combining_algorithm_for(ctx.resource.type_id));
 
-        // Evaluate each policy
+        // Evaluate each policy until the combining algorithm has a final answer
         for stored_policy in policies {
-            let mut interp = Interpreter::new(stored_policy.field_map.clone());
-
-            match interp.evaluate(&stored_policy.policy, ctx) {
-                Ok(result) => {
-                    if result {
-                        // Policy allows
-                        any_allow = true;
-                        decision = decision.add_matched_policy(stored_policy.name.clone());
-                    } else {
-                        // Policy denies
-                        any_deny = true;
-                    }
-                },
-                Err(e) => {
-                    return Err(Error::EvaluationError(format!(
-                        "Policy '{}' evaluation failed: {}",
-                        stored_policy.name, e
-                    )));
-                },
+            let mut interp = Interpreter::new(stored_policy.field_map.clone())
+                .with_functions(Arc::clone(&self.functions));
+            let result = run(stored_policy, &mut interp);
+            if combiner.add_policy(&stored_policy.name, &stored_policy.policy.effects, result) {
+                break;
             }
         }
 
-        // Decision logic: any deny overrides any allow (deny-by-default)
-        if any_allow && !any_deny {
-            decision.kind = DecisionKind::Allow;
-            Ok(decision)
-        } else if any_deny {
-            Ok(Decision::deny().with_reason("One or more policies denied the request".to_string()))
-        } else {
-            Ok(Decision::deny().with_reason("No policies allowed the request".to_string()))
-        }
+        combiner.finish()
     }
 }
 
@@ -168,12 +255,12 @@ mod tests {
     }
 
     #[test]
-    fn test_engine_no_policies_defaults_deny() {
+    fn test_engine_no_policies_not_applicable() {
         let engine = PolicyEngine::new();
         let ctx = EvaluationContext::default();
 
         let decision = engine.evaluate(&ctx).unwrap();
-        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.kind, DecisionKind::NotApplicable);
         assert!(decision.reason.is_some());
     }
 
@@ -377,4 +464,325 @@ mod tests {
         let decision3 = engine.evaluate(&ctx3).unwrap();
         assert_eq!(decision3.kind, DecisionKind::Deny);
     }
+
+    #[test]
+    fn test_engine_excludes_not_applicable_policies() {
+        use crate::testing::{simple_policy, test_context_with_resource};
+        use std::collections::HashMap;
+
+        // A denying policy whose trigger never matches must not override an allow
+        let mut guarded_deny = CompiledPolicy::new(2);
+        let idx = guarded_deny.add_constant(Value::Bool(false));
+        guarded_deny.emit(Instruction::LoadConst { idx });
+        guarded_deny.emit(Instruction::JumpIfFalse { offset: 2 });
+        guarded_deny.emit(Instruction::Return { value: false });
+        guarded_deny.emit(Instruction::NotApplicable);
+
+        let mut db = PolicyDB::new();
+        db.add_policy(
+            "allow-policy".to_string(),
+            simple_policy(1, true),
+            FieldMapping::new(),
+            vec![ResourceTypeId(1)],
+        );
+        db.add_policy(
+            "guarded-deny".to_string(),
+            guarded_deny,
+            FieldMapping::new(),
+            vec![ResourceTypeId(1)],
+        );
+
+        let engine = PolicyEngine::with_policy_db(db);
+        let ctx = test_context_with_resource(ResourceTypeId(1), HashMap::new());
+
+        let decision = engine.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["allow-policy".to_string()]);
+    }
+
+    #[test]
+    fn test_engine_all_not_applicable() {
+        use crate::testing::test_context_with_resource;
+        use std::collections::HashMap;
+
+        let mut policy = CompiledPolicy::new(1);
+        policy.emit(Instruction::NotApplicable);
+
+        let mut db = PolicyDB::new();
+        db.add_policy("skipped".to_string(), policy, FieldMapping::new(), vec![ResourceTypeId(1)]);
+
+        let engine = PolicyEngine::with_policy_db(db);
+        let ctx = test_context_with_resource(ResourceTypeId(1), HashMap::new());
+
+        let decision = engine.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::NotApplicable);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.reason.as_deref(), Some("No applicable policies for request"));
+    }
+
+    #[test]
+    fn test_engine_host_function() {
+        use crate::ast::Type;
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::testing::test_context_with_resource;
+        use std::time::Duration;
+
+        let mut engine = PolicyEngine::new();
+        engine
+            .register_function(
+                "ticket_approved",
+                FunctionSignature::new(vec![Type::Int], Type::Bool),
+                FunctionOptions::default().with_timeout(Duration::from_secs(1)),
+                |args| match args[0] {
+                    Value::Int(ticket) => Ok(Value::Bool(ticket % 2 == 0)),
+                    _ => Err("expected a ticket number".to_string()),
+                },
+            )
+            .unwrap();
+
+        let ast = Parser::new(
+            r#"policy Tickets: "Changes need an approved ticket"
+               triggers when resource.ticket > 0
+               requires ticket_approved(resource.ticket)"#,
+        )
+        .parse_policy()
+        .unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1)
+            .with_functions(Arc::clone(engine.functions()))
+            .compile_with_field_map(&ast)
+            .unwrap();
+        engine.policy_db_mut().add_policy(
+            "tickets".to_string(),
+            policy,
+            field_map,
+            vec![ResourceTypeId(1)],
+        );
+
+        let ctx_for = |ticket: i64| {
+            let attrs = HashMap::from([("ticket".to_string(), AttributeValue::Int(ticket))]);
+            test_context_with_resource(ResourceTypeId(1), attrs)
+        };
+        assert_eq!(engine.evaluate(&ctx_for(42)).unwrap().kind, DecisionKind::Allow);
+        assert_eq!(engine.evaluate(&ctx_for(43)).unwrap().kind, DecisionKind::Deny);
+
+        // Builtin names cannot be shadowed
+        let result = engine.register_function(
+            "lower",
+            FunctionSignature::new(vec![Type::String], Type::String),
+            FunctionOptions::default(),
+            |args| Ok(args[0].clone()),
+        );
+        assert!(matches!(result, Err(crate::Error::FunctionError(_))));
+    }
+
+    fn allow_and_deny_db() -> PolicyDB {
+        use crate::testing::simple_policy;
+
+        let mut db = PolicyDB::new();
+        db.add_policy(
+            "allow-policy".to_string(),
+            simple_policy(1, true),
+            FieldMapping::new(),
+            vec![ResourceTypeId(1), ResourceTypeId(2)],
+        );
+        db.add_policy(
+            "deny-policy".to_string(),
+            simple_policy(2, false),
+            FieldMapping::new(),
+            vec![ResourceTypeId(1), ResourceTypeId(2)],
+        );
+        db
+    }
+
+    #[test]
+    fn test_engine_combining_algorithm() {
+        use crate::testing::test_context_with_resource;
+        use std::collections::HashMap;
+
+        let ctx = test_context_with_resource(ResourceTypeId(1), HashMap::new());
+
+        let engine = PolicyEngine::with_policy_db(allow_and_deny_db());
+        let decision = engine.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.matched_policies, vec!["deny-policy".to_string()]);
+
+        let engine = PolicyEngine::with_policy_db(allow_and_deny_db())
+            .with_combining_algorithm(CombiningAlgorithm::PermitOverrides);
+        let decision = engine.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["allow-policy".to_string()]);
+    }
+
+    #[test]
+    fn test_engine_resource_combining_algorithm() {
+        use crate::testing::test_context_with_resource;
+        use std::collections::HashMap;
+
+        let mut engine = PolicyEngine::with_policy_db(allow_and_deny_db());
+        engine.set_resource_combining_algorithm(
+            ResourceTypeId(2),
+            CombiningAlgorithm::OnlyOneApplicable,
+        );
+
+        let ctx1 = test_context_with_resource(ResourceTypeId(1), HashMap::new());
+        assert_eq!(engine.evaluate(&ctx1).unwrap().kind, DecisionKind::Deny);
+
+        let ctx2 = test_context_with_resource(ResourceTypeId(2), HashMap::new());
+        assert_eq!(engine.evaluate(&ctx2).unwrap().kind, DecisionKind::Indeterminate);
+    }
+
+    #[test]
+    fn test_engine_sparse_context() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::testing::test_context_with_resource;
+
+        let ast = Parser::new(
+            r#"policy Tiers: "Paid tiers, or free resources without an owner"
+               triggers when true
+               requires resource.tier ?? "free" != "free" or not exists(resource.owner)"#,
+        )
+        .parse_policy()
+        .unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut db = PolicyDB::new();
+        db.add_policy("tiers".to_string(), policy, field_map, vec![ResourceTypeId(1)]);
+        let engine = PolicyEngine::with_policy_db(db);
+
+        let ctx_for = |attrs: &[(&str, &str)]| {
+            let attrs = attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), AttributeValue::String(v.to_string())))
+                .collect();
+            test_context_with_resource(ResourceTypeId(1), attrs)
+        };
+
+        // Missing attributes are undefined rather than evaluation errors
+        assert_eq!(engine.evaluate(&ctx_for(&[])).unwrap().kind, DecisionKind::Allow);
+        let ctx = ctx_for(&[("owner", "alice")]);
+        assert_eq!(engine.evaluate(&ctx).unwrap().kind, DecisionKind::Deny);
+        let ctx = ctx_for(&[("owner", "alice"), ("tier", "pro")]);
+        assert_eq!(engine.evaluate(&ctx).unwrap().kind, DecisionKind::Allow);
+    }
+
+    #[test]
+    fn test_engine_decision_effects() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::testing::test_context_with_resource;
+
+        let sources = [
+            r#"policy Audited: "Reads are audited"
+               triggers when true
+               requires resource.public == true
+               with obligation log_to_audit"#,
+            r#"policy Frozen: "Frozen resources cannot be touched"
+               triggers when resource.frozen == true
+               denies with reason "Resource is frozen"
+               with obligation require_mfa(level: 2)
+               with advice notify(channel: "ops")"#,
+        ];
+        let mut db = PolicyDB::new();
+        for (i, source) in sources.iter().enumerate() {
+            let ast = Parser::new(source).parse_policy().unwrap();
+            let (policy, field_map) =
+                PolicyCompiler::new(i as u64).compile_with_field_map(&ast).unwrap();
+            db.add_policy(ast.name.clone(), policy, field_map, vec![ResourceTypeId(1)]);
+        }
+        let engine = PolicyEngine::with_policy_db(db);
+
+        let ctx_for = |frozen: bool| {
+            let mut attrs = HashMap::new();
+            attrs.insert("public".to_string(), AttributeValue::Bool(true));
+            attrs.insert("frozen".to_string(), AttributeValue::Bool(frozen));
+            test_context_with_resource(ResourceTypeId(1), attrs)
+        };
+
+        let decision = engine.evaluate(&ctx_for(false)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.obligations, vec![Obligation::new("log_to_audit")]);
+        assert!(decision.advice.is_empty());
+
+        // Only the denying policy's effects survive deny-overrides
+        let decision = engine.evaluate(&ctx_for(true)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.reason.as_deref(), Some("Resource is frozen"));
+        assert_eq!(
+            decision.obligations,
+            vec![Obligation::new("require_mfa").with_param("level", Value::Int(2))]
+        );
+        assert_eq!(decision.advice[0].name, "notify");
+    }
+
+    #[test]
+    fn test_engine_evaluate_explain() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::testing::test_context_with_resource;
+
+        let sources = [
+            r#"policy Staging: "Only staging"
+               triggers when resource.env == "staging"
+               requires true"#,
+            r#"policy Owners: "Owners may act"
+               triggers when true
+               requires resource.owner == "alice""#,
+        ];
+        let mut db = PolicyDB::new();
+        for (i, source) in sources.iter().enumerate() {
+            let ast = Parser::new(source).parse_policy().unwrap();
+            let (policy, field_map) =
+                PolicyCompiler::new(i as u64).compile_with_field_map(&ast).unwrap();
+            db.add_policy(ast.name.clone(), policy, field_map, vec![ResourceTypeId(1)]);
+        }
+        let engine = PolicyEngine::with_policy_db(db);
+
+        let mut attrs = HashMap::new();
+        attrs.insert("env".to_string(), AttributeValue::String("prod".to_string()));
+        attrs.insert("owner".to_string(), AttributeValue::String("bob".to_string()));
+        let ctx = test_context_with_resource(ResourceTypeId(1), attrs);
+
+        let (decision, explanation) = engine.evaluate_explain(&ctx).unwrap();
+        assert_eq!(decision.kind, engine.evaluate(&ctx).unwrap().kind);
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(explanation.policies.len(), 2);
+
+        let staging = explanation.policy("Staging").unwrap();
+        assert!(!staging.triggers_matched);
+        assert_eq!(staging.outcome, Some(Outcome::NotApplicable));
+
+        let owners = explanation.policy("Owners").unwrap();
+        let failed: Vec<_> = owners.failed_conditions().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].fields[0].value, Value::String("bob".to_string()));
+        assert!(!failed[0].comparisons[0].result);
+    }
+
+    #[test]
+    fn test_engine_evaluation_error_is_indeterminate() {
+        use crate::testing::{simple_policy, test_context_with_resource};
+        use std::collections::HashMap;
+
+        // Loading an unmapped field fails at runtime
+        let mut broken = CompiledPolicy::new(2);
+        broken.emit(Instruction::LoadField { offset: 0 });
+        broken.emit(Instruction::Return { value: true });
+
+        let mut db = PolicyDB::new();
+        db.add_policy(
+            "allow-policy".to_string(),
+            simple_policy(1, true),
+            FieldMapping::new(),
+            vec![ResourceTypeId(1)],
+        );
+        db.add_policy("broken".to_string(), broken, FieldMapping::new(), vec![ResourceTypeId(1)]);
+
+        let engine = PolicyEngine::with_policy_db(db);
+        let ctx = test_context_with_resource(ResourceTypeId(1), HashMap::new());
+
+        let decision = engine.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Indeterminate);
+        assert!(decision.reason.unwrap().contains("Policy 'broken' evaluation failed"));
+    }
 }
@@ -0,0 +1,303 @@
+//! Decision explanations
+//!
+//! [`crate::PolicyEngine::evaluate_explain`] and
+//! [`crate::store::PolicyDataStore::evaluate_explain`] return an
+//! [`Explanation`] next to the decision: for every evaluated policy, whether its
+//! triggers matched and, per condition, the source location, the fields it
+//! loaded, the comparisons it made and what it evaluated to.
+//!
+//! Tracing hooks into the interpreter through [`Tracer`]; the plain evaluation
+//! path uses [`NoTrace`], which compiles away entirely.
+
+use crate::ast::SourceLocation;
+use crate::bytecode::{CompOp, ConditionKind, ConditionSpan, Value};
+use crate::interpreter::{FieldMapping, Outcome, Stack};
+use serde::{Deserialize, Serialize};
+
+/// Why a decision was reached, policy by policy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// Evaluated policies in evaluation order
+    ///
+    /// Policies skipped because the decision was already final are absent.
+    pub policies: Vec<PolicyTrace>,
+}
+
+impl Explanation {
+    /// Find the trace of a policy by name
+    pub fn policy(&self, name: &str) -> Option<&PolicyTrace> {
+        self.policies.iter().find(|p| p.name == name)
+    }
+}
+
+/// How one policy evaluated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyTrace {
+    pub name: String,
+    /// Policy set the policy was evaluated in, if any
+    pub policy_set: Option<String>,
+    /// Whether every trigger held, so the policy applied
+    pub triggers_matched: bool,
+    /// The policy's outcome, or `None` if evaluation failed
+    pub outcome: Option<Outcome>,
+    /// Evaluation error, if any
+    pub error: Option<String>,
+    /// Triggers and requirements in source order
+    pub conditions: Vec<ConditionTrace>,
+}
+
+impl PolicyTrace {
+    /// The outcome as returned by [`crate::interpreter::Interpreter::run`]
+    pub fn result(&self) -> Result<Outcome, String> {
+        match (&self.outcome, &self.error) {
+            (Some(outcome), _) => Ok(*outcome),
+            (None, error) => Err(error.clone().unwrap_or_default()),
+        }
+    }
+
+    /// Conditions that were evaluated and did not hold
+    pub fn failed_conditions(&self) -> impl Iterator<Item = &ConditionTrace> {
+        self.conditions.iter().filter(|c| c.result == Some(false))
+    }
+}
+
+/// How one trigger or requirement evaluated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionTrace {
+    pub kind: ConditionKind,
+    pub location: SourceLocation,
+    /// Whether the condition held; `None` if it was never evaluated (an earlier
+    /// trigger failed or evaluation stopped with an error)
+    pub result: Option<bool>,
+    /// Context fields loaded while evaluating the condition
+    pub fields: Vec<FieldTrace>,
+    /// Comparisons made while evaluating the condition
+    pub comparisons: Vec<ComparisonTrace>,
+}
+
+/// A context field and the value it loaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldTrace {
+    /// Dotted path (e.g. `resource.environment`)
+    pub path: String,
+    pub value: Value,
+}
+
+/// A comparison and its result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonTrace {
+    pub op: CompOp,
+    pub left: Value,
+    pub right: Value,
+    pub result: bool,
+}
+
+/// Observes interpreter execution
+pub(crate) trait Tracer {
+    /// Called before the instruction at `pc` executes
+    fn step(&mut self, _pc: usize, _stack: &Stack) {}
+
+    /// Called when `LoadField` loads a value
+    fn field(&mut self, _offset: u16, _value: &Value) {}
+
+    /// Called when `Compare` produces a result
+    fn compare(&mut self, _op: CompOp, _left: &Value, _right: &Value, _result: bool) {}
+}
+
+/// Tracer that records nothing
+pub(crate) struct NoTrace;
+
+impl Tracer for NoTrace {}
+
+/// Tracer that attributes loads and comparisons to the condition being evaluated
+pub(crate) struct ExplainTracer<'a> {
+    spans: &'a [ConditionSpan],
+    /// Index of the condition currently being evaluated
+    current: usize,
+    results: Vec<Option<bool>>,
+    fields: Vec<(usize, u16, Value)>,
+    comparisons: Vec<(usize, ComparisonTrace)>,
+}
+
+impl<'a> ExplainTracer<'a> {
+    pub(crate) fn new(spans: &'a [ConditionSpan]) -> Self {
+        Self {
+            spans,
+            current: 0,
+            results: vec![None; spans.len()],
+            fields: Vec::new(),
+            comparisons: Vec::new(),
+        }
+    }
+
+    /// Assemble the trace once execution has finished
+    pub(crate) fn finish(
+        self,
+        name: &str,
+        result: Result<Outcome, String>,
+        field_map: &FieldMapping,
+    ) -> PolicyTrace {
+        let mut conditions: Vec<ConditionTrace> = self
+            .spans
+            .iter()
+            .zip(self.results)
+            .map(|(span, result)| ConditionTrace {
+                kind: span.kind,
+                location: span.location.clone(),
+                result,
+                fields: Vec::new(),
+                comparisons: Vec::new(),
+            })
+            .collect();
+
+        for (idx, offset, value) in self.fields {
+            let path = field_map.get(&offset).map(|p| p.join(".")).unwrap_or_default();
+            conditions[idx].fields.push(FieldTrace { path, value });
+        }
+        for (idx, comparison) in self.comparisons {
+            conditions[idx].comparisons.push(comparison);
+        }
+
+        let triggers_matched = conditions
+            .iter()
+            .filter(|c| c.kind == ConditionKind::Trigger)
+            .all(|c| c.result == Some(true));
+        let (outcome, error) = match result {
+            Ok(outcome) => (Some(outcome), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        PolicyTrace {
+            name: name.to_string(),
+            policy_set: None,
+            triggers_matched: triggers_matched && outcome.is_some(),
+            outcome,
+            error,
+            conditions,
+        }
+    }
+}
+
+impl Tracer for ExplainTracer<'_> {
+    fn step(&mut self, pc: usize, stack: &Stack) {
+        // Conditions are laid out in code order; a jump past a condition's end
+        // (a failed trigger) leaves it unevaluated
+        while let Some(span) = self.spans.get(self.current) {
+            if pc < span.end {
+                break;
+            }
+            if pc == span.end {
+                self.results[self.current] = stack.peek().ok().map(Value::is_truthy);
+            }
+            self.current += 1;
+        }
+    }
+
+    fn field(&mut self, offset: u16, value: &Value) {
+        if self.current < self.spans.len() {
+            self.fields.push((self.current, offset, value.clone()));
+        }
+    }
+
+    fn compare(&mut self, op: CompOp, left: &Value, right: &Value, result: bool) {
+        if self.current < self.spans.len() {
+            let comparison = ComparisonTrace {
+                op,
+                left: left.clone(),
+                right: right.clone(),
+                result,
+            };
+            self.comparisons.push((self.current, comparison));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::PolicyCompiler;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::rar::{AttributeValue, ResourceTypeId};
+    use crate::testing::test_context_with_resource;
+    use std::collections::HashMap;
+
+    fn explain(source: &str, attrs: &[(&str, AttributeValue)]) -> PolicyTrace {
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let attrs: HashMap<_, _> = attrs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+        let ctx = test_context_with_resource(ResourceTypeId(1), attrs);
+        Interpreter::new(field_map).explain(&ast.name, &policy, &ctx)
+    }
+
+    const SOURCE: &str = r#"policy Deploy:
+  "Production deploys need two approvals"
+  triggers when resource.env == "prod"
+  requires resource.owner == "platform" and resource.approvals >= 2"#;
+
+    #[test]
+    fn test_explain_failed_requirement() {
+        let trace = explain(
+            SOURCE,
+            &[
+                ("env", AttributeValue::String("prod".into())),
+                ("approvals", AttributeValue::Int(1)),
+                ("owner", AttributeValue::String("platform".into())),
+            ],
+        );
+
+        assert_eq!(trace.outcome, Some(Outcome::Deny));
+        assert!(trace.triggers_matched);
+        assert_eq!(trace.conditions.len(), 2);
+        assert_eq!(trace.conditions[0].kind, ConditionKind::Trigger);
+        assert_eq!(trace.conditions[0].result, Some(true));
+
+        let failed: Vec<_> = trace.failed_conditions().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].kind, ConditionKind::Requirement);
+        assert_eq!(
+            failed[0].location,
+            SourceLocation::new(4, 12, 56).with_span(Default::default(), 107)
+        );
+        assert_eq!(failed[0].fields.len(), 2);
+        assert_eq!(
+            failed[0].fields[1],
+            FieldTrace {
+                path: "resource.approvals".to_string(),
+                value: Value::Int(1)
+            }
+        );
+        assert_eq!(
+            failed[0].comparisons[1],
+            ComparisonTrace {
+                op: CompOp::Gte,
+                left: Value::Int(1),
+                right: Value::Int(2),
+                result: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_explain_unmatched_trigger_and_error() {
+        let trace = explain(SOURCE, &[("env", AttributeValue::String("dev".into()))]);
+        assert_eq!(trace.outcome, Some(Outcome::NotApplicable));
+        assert!(!trace.triggers_matched);
+        assert_eq!(trace.conditions[0].result, Some(false));
+        // Requirements are skipped once a trigger fails
+        assert!(trace.conditions[1..].iter().all(|c| c.result.is_none()));
+
+        let trace = explain(
+            SOURCE,
+            &[
+                ("env", AttributeValue::String("prod".into())),
+                ("owner", AttributeValue::String("platform".into())),
+                ("approvals", AttributeValue::String("two".into())),
+            ],
+        );
+        assert!(trace.error.is_some());
+        assert!(trace.result().is_err());
+        assert_eq!(trace.conditions[0].result, Some(true));
+        assert_eq!(trace.conditions[1].result, None);
+    }
+}
@@ -0,0 +1,873 @@
+//! Function registry
+//!
+//! Functions are resolved by name at compile time to a numeric id that is
+//! embedded in [`Instruction::Call`](crate::bytecode::Instruction::Call). The
+//! interpreter (and the JIT, for scalar functions) dispatch on that id, so
+//! the same registry must be used to compile and to execute a policy. The
+//! builtins are always registered first and in a fixed order, and host
+//! functions are only ever appended, so a policy compiled against a registry
+//! stays valid as more functions are registered.
+
+use crate::ast::nodes::{self, Expression};
+use crate::ast::types::Type;
+use crate::bytecode::Value;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, Timelike};
+use lru::LruCache;
+use parking_lot::Mutex;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Native implementation of a function
+///
+/// Arguments have already been checked against the function's signature.
+pub type NativeFn = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// Maximum number of functions a registry can hold (ids are `u8`)
+pub const MAX_FUNCTIONS: usize = 256;
+
+/// Number of compiled patterns kept by `matches`
+const REGEX_CACHE_SIZE: usize = 128;
+
+/// Number of results memoized per pure host function
+const RESULT_CACHE_SIZE: usize = 1024;
+
+/// Worker threads shared by every host function call with a timeout
+const CALL_WORKERS: usize = 16;
+
+/// Calls with a timeout that may wait for a worker before new ones are rejected
+const CALL_QUEUE_SIZE: usize = 1024;
+
+/// Function registration errors
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum FunctionError {
+    #[error("Function already registered: {0}")]
+    AlreadyRegistered(String),
+
+    #[error("Too many functions (max {MAX_FUNCTIONS})")]
+    TooManyFunctions,
+}
+
+/// Parameter and return types of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSignature {
+    pub params: Vec<Type>,
+    pub returns: Type,
+}
+
+impl FunctionSignature {
+    /// Create a new signature
+    pub fn new(params: Vec<Type>, returns: Type) -> Self {
+        Self { params, returns }
+    }
+
+    /// Number of parameters
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Whether every parameter and the return value fit in a machine word
+    pub fn is_scalar(&self) -> bool {
+        self.params
+            .iter()
+            .chain(std::iter::once(&self.returns))
+            .all(|t| matches!(t, Type::Int | Type::Bool))
+    }
+}
+
+/// How a host function may be called
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FunctionOptions {
+    /// The result depends only on the arguments, so it may be memoized
+    pub pure: bool,
+    /// Fail a call that runs longer than this
+    ///
+    /// Such calls run on a fixed pool of worker threads. A call that times
+    /// out is not cancelled and may still be running afterwards.
+    pub timeout: Option<Duration>,
+}
+
+impl FunctionOptions {
+    /// Mark the function as pure
+    pub fn pure(mut self) -> Self {
+        self.pure = true;
+        self
+    }
+
+    /// Set the per-call timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Memoized results of a pure function, keyed by serialized arguments
+type ResultCache = Mutex<LruCache<Vec<u8>, Value>>;
+
+/// A registered function
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    pub signature: FunctionSignature,
+    pub options: FunctionOptions,
+    native: NativeFn,
+    cache: Option<Arc<ResultCache>>,
+}
+
+impl Function {
+    /// Create a host function
+    ///
+    /// Results of pure functions are memoized per argument list.
+    pub fn new<F>(
+        name: &str,
+        signature: FunctionSignature,
+        options: FunctionOptions,
+        native: F,
+    ) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let cache = options.pure.then(|| {
+            Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(RESULT_CACHE_SIZE).expect("non-zero"),
+            )))
+        });
+
+        Self {
+            name: name.to_string(),
+            signature,
+            options,
+            native: Arc::new(native),
+            cache,
+        }
+    }
+
+    /// Invoke the function, honouring its timeout and result cache
+    fn invoke(&self, args: &[Value]) -> Result<Value, String> {
+        let key = match &self.cache {
+            Some(cache) => {
+                let key = bincode::serialize(args).map_err(|e| e.to_string())?;
+                if let Some(value) = cache.lock().get(&key) {
+                    return Ok(value.clone());
+                }
+                Some(key)
+            },
+            None => None,
+        };
+
+        let result = match self.options.timeout {
+            Some(timeout) => self.invoke_with_timeout(args, timeout),
+            None => (self.native)(args),
+        }?;
+
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.lock().put(key, result.clone());
+        }
+        Ok(result)
+    }
+
+    /// Run the call on the shared [`CallPool`] and stop waiting after `timeout`
+    ///
+    /// A call that times out is abandoned, not cancelled: if it already
+    /// started, it may still be running and keeps its worker until the host
+    /// function returns; its result is discarded.
+    fn invoke_with_timeout(&self, args: &[Value], timeout: Duration) -> Result<Value, String> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let job = {
+            let native = Arc::clone(&self.native);
+            let args = args.to_vec();
+            let abandoned = Arc::clone(&abandoned);
+            Box::new(move || {
+                // Skip a call whose caller gave up while it was queued
+                if !abandoned.load(Ordering::Relaxed) {
+                    let _ = tx.send(native(&args));
+                }
+            })
+        };
+
+        if !CallPool::global().submit(job) {
+            return Err(format!("{}() rejected: too many pending calls", self.name));
+        }
+
+        rx.recv_timeout(timeout).unwrap_or_else(|_| {
+            abandoned.store(true, Ordering::Relaxed);
+            Err(format!("{}() timed out after {:?}", self.name, timeout))
+        })
+    }
+}
+
+/// A call queued on a [`CallPool`]
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of worker threads running host function calls with a timeout
+///
+/// The pool never grows: under load, slow functions make calls wait in a
+/// bounded queue (and time out) or be rejected once it is full, instead of
+/// starting a thread per call.
+struct CallPool {
+    jobs: crossbeam_channel::Sender<Job>,
+}
+
+impl CallPool {
+    /// The process-wide pool
+    fn global() -> &'static CallPool {
+        static POOL: OnceLock<CallPool> = OnceLock::new();
+        POOL.get_or_init(|| CallPool::new(CALL_WORKERS, CALL_QUEUE_SIZE))
+    }
+
+    fn new(workers: usize, queue_size: usize) -> Self {
+        let (jobs, queue) = crossbeam_channel::bounded::<Job>(queue_size);
+        for i in 0..workers {
+            let queue = queue.clone();
+            // Calls still run on the workers that did start
+            let _ = std::thread::Builder::new().name(format!("policy-fn-{}", i)).spawn(move || {
+                for job in queue {
+                    job();
+                }
+            });
+        }
+        Self { jobs }
+    }
+
+    /// Queue `job`, or return false if the queue is full
+    fn submit(&self, job: Job) -> bool {
+        self.jobs.try_send(job).is_ok()
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Registry of callable functions, indexed by compile-time id
+#[derive(Debug, Clone)]
+pub struct FunctionRegistry {
+    functions: Vec<Function>,
+    by_name: HashMap<String, u8>,
+}
+
+impl FunctionRegistry {
+    /// Create a registry with no functions
+    pub fn empty() -> Self {
+        Self {
+            functions: Vec::new(),
+            by_name: HashMap::new(),
+        }
+    }
+
+    /// Create a registry holding the builtin functions
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        for (name, params, returns, native) in BUILTINS {
+            // Builtins are pure but cheap, so they are not memoized
+            let function = Function {
+                name: name.to_string(),
+                signature: FunctionSignature::new(params.to_vec(), returns.clone()),
+                options: FunctionOptions::default().pure(),
+                native: Arc::new(*native),
+                cache: None,
+            };
+            registry.add(function).expect("builtin functions are unique");
+        }
+        registry
+    }
+
+    /// Shared registry holding only the builtins
+    pub fn builtins() -> Arc<FunctionRegistry> {
+        static BUILTIN_REGISTRY: OnceLock<Arc<FunctionRegistry>> = OnceLock::new();
+        Arc::clone(BUILTIN_REGISTRY.get_or_init(|| Arc::new(FunctionRegistry::new())))
+    }
+
+    /// Register a function and return its id
+    pub fn register<F>(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        native: F,
+    ) -> Result<u8, FunctionError>
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.register_with(name, signature, FunctionOptions::default(), native)
+    }
+
+    /// Register a function with call options and return its id
+    pub fn register_with<F>(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        options: FunctionOptions,
+        native: F,
+    ) -> Result<u8, FunctionError>
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.add(Function::new(name, signature, options, native))
+    }
+
+    /// Add a function and return its id
+    pub fn add(&mut self, function: Function) -> Result<u8, FunctionError> {
+        if self.by_name.contains_key(&function.name) {
+            return Err(FunctionError::AlreadyRegistered(function.name));
+        }
+        if self.functions.len() >= MAX_FUNCTIONS {
+            return Err(FunctionError::TooManyFunctions);
+        }
+
+        let id = self.functions.len() as u8;
+        self.by_name.insert(function.name.clone(), id);
+        self.functions.push(function);
+        Ok(id)
+    }
+
+    /// Resolve a function name to its id
+    pub fn resolve(&self, name: &str) -> Option<u8> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Get a function by id
+    pub fn get(&self, id: u8) -> Option<&Function> {
+        self.functions.get(id as usize)
+    }
+
+    /// Get a function by name
+    pub fn lookup(&self, name: &str) -> Option<&Function> {
+        self.resolve(name).and_then(|id| self.get(id))
+    }
+
+    /// Desugar a call that reads the request clock, unless this registry
+    /// defines the function with that many arguments
+    ///
+    /// The clock is the request's: `now()` is `request.timestamp`, and
+    /// `hour_of_day` / `day_of_week` default the timestamp to it and the zone
+    /// to UTC. A host function named `now` is therefore called as registered.
+    pub fn expand_clock(&self, name: &str, args: &[Expression]) -> Option<Expression> {
+        if self.lookup(name).is_some_and(|f| f.signature.arity() == args.len()) {
+            return None;
+        }
+
+        let now = || Expression::path(vec!["request".to_string(), "timestamp".to_string()]);
+        let args = match (name, args) {
+            ("now", []) => return Some(now()),
+            ("hour_of_day" | "day_of_week", []) => {
+                vec![now(), Expression::literal(nodes::Value::String("UTC".to_string()))]
+            },
+            ("hour_of_day" | "day_of_week", [tz]) => vec![now(), tz.clone()],
+            _ => return None,
+        };
+        Some(Expression::Call { name: name.to_string(), args })
+    }
+
+    /// Number of registered functions
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    /// Check if the registry is empty
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// Call a function by id, checking arity and argument types
+    pub fn call(&self, id: u8, args: &[Value]) -> Result<Value, String> {
+        let function = self.get(id).ok_or_else(|| format!("Unknown function id: {}", id))?;
+        let signature = &function.signature;
+
+        if args.len() != signature.arity() {
+            return Err(format!(
+                "{}() expects {} argument(s), got {}",
+                function.name,
+                signature.arity(),
+                args.len()
+            ));
+        }
+
+        for (i, (arg, expected)) in args.iter().zip(&signature.params).enumerate() {
+            let got = value_type(arg);
+            if !expected.is_compatible_with(&got) {
+                return Err(format!(
+                    "{}() argument {} expects {:?}, got {:?}",
+                    function.name,
+                    i + 1,
+                    expected,
+                    got
+                ));
+            }
+        }
+
+        function.invoke(args)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runtime type of a bytecode value
+fn value_type(value: &Value) -> Type {
+    match value {
+        Value::Int(_) => Type::Int,
+        Value::Float(_) => Type::Float,
+        Value::Bool(_) => Type::Bool,
+        Value::String(_) => Type::String,
+        Value::Array(items) => {
+            Type::Array(Box::new(items.first().map(value_type).unwrap_or(Type::Any)))
+        },
+        Value::Map(_) | Value::Undefined => Type::Any,
+    }
+}
+
+type Builtin = (&'static str, &'static [Type], Type, fn(&[Value]) -> Result<Value, String>);
+
+/// Builtin functions, in id order (append only: ids are baked into bytecode)
+const BUILTINS: &[Builtin] = &[
+    ("starts_with", &[Type::String, Type::String], Type::Bool, starts_with),
+    ("ends_with", &[Type::String, Type::String], Type::Bool, ends_with),
+    ("contains", &[Type::String, Type::String], Type::Bool, contains),
+    ("lower", &[Type::String], Type::String, lower),
+    ("len", &[Type::Any], Type::Int, len),
+    ("matches", &[Type::String, Type::String], Type::Bool, matches),
+    ("ip_in_range", &[Type::String, Type::String], Type::Bool, ip_in_range),
+    ("parse_timestamp", &[Type::String], Type::Int, parse_timestamp),
+    ("utc_hour", &[Type::Int], Type::Int, utc_hour),
+    ("utc_weekday", &[Type::Int], Type::Int, utc_weekday),
+    ("exists", &[Type::Any], Type::Bool, exists),
+    ("has", &[Type::Any], Type::Bool, exists),
+    ("hour_of_day", &[Type::Int, Type::String], Type::Int, hour_of_day),
+    ("day_of_week", &[Type::Int, Type::String], Type::Int, day_of_week),
+];
+
+fn string_arg(args: &[Value], i: usize) -> Result<&str, String> {
+    match &args[i] {
+        Value::String(s) => Ok(s),
+        other => Err(format!("Expected string, got {:?}", other)),
+    }
+}
+
+fn int_arg(args: &[Value], i: usize) -> Result<i64, String> {
+    match &args[i] {
+        Value::Int(n) => Ok(*n),
+        other => Err(format!("Expected int, got {:?}", other)),
+    }
+}
+
+fn starts_with(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(string_arg(args, 0)?.starts_with(string_arg(args, 1)?)))
+}
+
+fn ends_with(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(string_arg(args, 0)?.ends_with(string_arg(args, 1)?)))
+}
+
+fn contains(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(string_arg(args, 0)?.contains(string_arg(args, 1)?)))
+}
+
+fn lower(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(string_arg(args, 0)?.to_lowercase()))
+}
+
+fn len(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::String(s) => Ok(Value::Int(s.chars().count() as i64)),
+        Value::Array(items) => Ok(Value::Int(items.len() as i64)),
+        Value::Map(fields) => Ok(Value::Int(fields.len() as i64)),
+        other => Err(format!("len() is not defined for {:?}", other)),
+    }
+}
+
+fn exists(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(args[0].is_defined()))
+}
+
+fn matches(args: &[Value]) -> Result<Value, String> {
+    static CACHE: OnceLock<Mutex<LruCache<String, Regex>>> = OnceLock::new();
+
+    let text = string_arg(args, 0)?;
+    let pattern = string_arg(args, 1)?;
+
+    let cache = CACHE.get_or_init(|| {
+        Mutex::new(LruCache::new(NonZeroUsize::new(REGEX_CACHE_SIZE).expect("non-zero")))
+    });
+    let mut cache = cache.lock();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(Value::Bool(regex.is_match(text)));
+    }
+
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+    let result = regex.is_match(text);
+    cache.put(pattern.to_string(), regex);
+    Ok(Value::Bool(result))
+}
+
+fn ip_in_range(args: &[Value]) -> Result<Value, String> {
+    let ip_str = string_arg(args, 0)?;
+    let cidr = string_arg(args, 1)?;
+
+    let ip: IpAddr = ip_str.parse().map_err(|_| format!("Invalid IP address '{}'", ip_str))?;
+    let (network, prefix) = cidr.split_once('/').unwrap_or((cidr, ""));
+    let network: IpAddr = network.parse().map_err(|_| format!("Invalid CIDR range '{}'", cidr))?;
+
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => (u32::from(a) as u128, u32::from(b) as u128, 32),
+        (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a), u128::from(b), 128),
+        // Different address families never match
+        _ => return Ok(Value::Bool(false)),
+    };
+
+    let prefix: u32 = if prefix.is_empty() {
+        bits
+    } else {
+        prefix
+            .parse()
+            .ok()
+            .filter(|p| *p <= bits)
+            .ok_or_else(|| format!("Invalid CIDR prefix length in '{}'", cidr))?
+    };
+
+    let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) >> (128 - bits) };
+    Ok(Value::Bool(ip & mask == network & mask))
+}
+
+fn parse_timestamp(args: &[Value]) -> Result<Value, String> {
+    let text = string_arg(args, 0)?;
+    DateTime::parse_from_rfc3339(text)
+        .map(|dt| Value::Int(dt.timestamp()))
+        .map_err(|e| format!("Invalid RFC 3339 timestamp '{}': {}", text, e))
+}
+
+fn utc_datetime(seconds: i64) -> Result<DateTime<chrono::Utc>, String> {
+    DateTime::from_timestamp(seconds, 0)
+        .ok_or_else(|| format!("Timestamp out of range: {}", seconds))
+}
+
+fn utc_hour(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(utc_datetime(int_arg(args, 0)?)?.hour() as i64))
+}
+
+/// ISO weekday: Monday = 1 .. Sunday = 7
+fn utc_weekday(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(
+        utc_datetime(int_arg(args, 0)?)?.weekday().number_from_monday() as i64
+    ))
+}
+
+/// Parse a fixed offset time zone: `UTC` or an offset (`+02:00`, `-0530`, `+09`)
+fn fixed_offset(tz: &str) -> Option<FixedOffset> {
+    if matches!(tz, "UTC" | "utc" | "Z" | "GMT") {
+        return FixedOffset::east_opt(0);
+    }
+
+    let (sign, rest) = match tz.as_bytes().first() {
+        Some(b'+') => (1, &tz[1..]),
+        Some(b'-') => (-1, &tz[1..]),
+        _ => return None,
+    };
+    let digits = rest.replacen(':', "", 1);
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().unwrap_or(0);
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Wall-clock time at a timestamp in a time zone: `UTC`, a fixed offset, or
+/// an IANA name (`America/New_York`), which follows daylight saving time
+fn local_datetime(args: &[Value]) -> Result<NaiveDateTime, String> {
+    let utc = utc_datetime(int_arg(args, 0)?)?;
+    let tz = string_arg(args, 1)?;
+    if let Some(offset) = fixed_offset(tz) {
+        return Ok(utc.with_timezone(&offset).naive_local());
+    }
+    match tz.parse::<chrono_tz::Tz>() {
+        Ok(zone) => Ok(utc.with_timezone(&zone).naive_local()),
+        Err(_) => Err(format!(
+            "Unknown time zone '{}': use UTC, an offset like +02:00 or a name like Europe/Berlin",
+            tz
+        )),
+    }
+}
+
+fn hour_of_day(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(local_datetime(args)?.hour() as i64))
+}
+
+/// ISO weekday in the given zone: Monday = 1 .. Sunday = 7
+fn day_of_week(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(local_datetime(args)?.weekday().number_from_monday() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let registry = FunctionRegistry::builtins();
+        let id = registry.resolve(name).unwrap();
+        registry.call(id, &args)
+    }
+
+    fn s(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    #[test]
+    fn test_registry_builtin_ids_are_stable() {
+        let registry = FunctionRegistry::new();
+        assert_eq!(registry.len(), BUILTINS.len());
+        for (i, (name, ..)) in BUILTINS.iter().enumerate() {
+            assert_eq!(registry.resolve(name), Some(i as u8));
+        }
+        assert_eq!(registry.resolve("nope"), None);
+    }
+
+    #[test]
+    fn test_registry_register() {
+        let mut registry = FunctionRegistry::new();
+        let sig = FunctionSignature::new(vec![Type::Int], Type::Int);
+        let id = registry.register("double", sig.clone(), |args| match args[0] {
+            Value::Int(n) => Ok(Value::Int(n * 2)),
+            _ => unreachable!(),
+        });
+        assert_eq!(id, Ok(BUILTINS.len() as u8));
+        assert_eq!(registry.call(id.unwrap(), &[Value::Int(21)]), Ok(Value::Int(42)));
+
+        let duplicate = registry.register("lower", sig, |_| Ok(Value::Bool(true)));
+        assert_eq!(duplicate, Err(FunctionError::AlreadyRegistered("lower".to_string())));
+    }
+
+    #[test]
+    fn test_call_checks_arity_and_types() {
+        let err = call("lower", vec![]).unwrap_err();
+        assert!(err.contains("expects 1 argument(s), got 0"));
+
+        let err = call("starts_with", vec![Value::Int(1), s("a")]).unwrap_err();
+        assert!(err.contains("argument 1"));
+
+        let registry = FunctionRegistry::builtins();
+        assert!(registry.call(250, &[]).is_err());
+    }
+
+    #[test]
+    fn test_string_builtins() {
+        assert_eq!(call("starts_with", vec![s("prod-eu"), s("prod")]), Ok(Value::Bool(true)));
+        assert_eq!(call("ends_with", vec![s("prod-eu"), s("-us")]), Ok(Value::Bool(false)));
+        assert_eq!(call("contains", vec![s("prod-eu"), s("d-e")]), Ok(Value::Bool(true)));
+        assert_eq!(call("lower", vec![s("ProD")]), Ok(s("prod")));
+        assert_eq!(call("len", vec![s("héllo")]), Ok(Value::Int(5)));
+        assert!(call("len", vec![Value::Int(3)]).is_err());
+    }
+
+    #[test]
+    fn test_exists() {
+        assert_eq!(call("exists", vec![Value::Int(0)]), Ok(Value::Bool(true)));
+        assert_eq!(call("exists", vec![Value::Undefined]), Ok(Value::Bool(false)));
+        assert_eq!(call("has", vec![Value::Undefined]), Ok(Value::Bool(false)));
+        assert!(call("lower", vec![Value::Undefined]).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(call("matches", vec![s("svc-042"), s(r"^svc-\d+$")]), Ok(Value::Bool(true)));
+        assert_eq!(call("matches", vec![s("svc-x"), s(r"^svc-\d+$")]), Ok(Value::Bool(false)));
+        assert!(call("matches", vec![s("x"), s("(")]).unwrap_err().contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn test_ip_in_range() {
+        let check = |ip: &str, cidr: &str| call("ip_in_range", vec![s(ip), s(cidr)]);
+
+        assert_eq!(check("10.1.2.3", "10.0.0.0/8"), Ok(Value::Bool(true)));
+        assert_eq!(check("11.1.2.3", "10.0.0.0/8"), Ok(Value::Bool(false)));
+        assert_eq!(check("192.168.1.1", "192.168.1.1"), Ok(Value::Bool(true)));
+        assert_eq!(check("1.2.3.4", "0.0.0.0/0"), Ok(Value::Bool(true)));
+        assert_eq!(check("2001:db8::1", "2001:db8::/32"), Ok(Value::Bool(true)));
+        assert_eq!(check("2001:db9::1", "2001:db8::/32"), Ok(Value::Bool(false)));
+        assert_eq!(check("10.0.0.1", "2001:db8::/32"), Ok(Value::Bool(false)));
+        assert!(check("10.0.0.1", "10.0.0.0/33").is_err());
+        assert!(check("not-an-ip", "10.0.0.0/8").is_err());
+    }
+
+    #[test]
+    fn test_time_builtins() {
+        // 2024-01-15T14:30:00Z was a Monday
+        let ts = call("parse_timestamp", vec![s("2024-01-15T14:30:00Z")]).unwrap();
+        assert_eq!(ts, Value::Int(1_705_329_000));
+        assert_eq!(call("utc_hour", vec![ts.clone()]), Ok(Value::Int(14)));
+        assert_eq!(call("utc_weekday", vec![ts]), Ok(Value::Int(1)));
+        assert!(call("parse_timestamp", vec![s("yesterday")]).is_err());
+    }
+
+    #[test]
+    fn test_time_zone_builtins() {
+        // 2024-01-14T23:30:00Z: Sunday in UTC, already Monday east of it
+        let ts = Value::Int(1_705_275_000);
+        let at = |name: &str, tz: &str| call(name, vec![ts.clone(), s(tz)]);
+        assert_eq!(at("hour_of_day", "UTC"), Ok(Value::Int(23)));
+        assert_eq!(at("day_of_week", "UTC"), Ok(Value::Int(7)));
+        assert_eq!(at("hour_of_day", "+02:00"), Ok(Value::Int(1)));
+        assert_eq!(at("day_of_week", "+02:00"), Ok(Value::Int(1)));
+        assert_eq!(at("hour_of_day", "-0530"), Ok(Value::Int(18)));
+        assert_eq!(at("hour_of_day", "+09"), Ok(Value::Int(8)));
+
+        // Named zones follow daylight saving time: EST in January, EDT in July
+        assert_eq!(at("hour_of_day", "America/New_York"), Ok(Value::Int(18)));
+        assert_eq!(at("day_of_week", "Asia/Tokyo"), Ok(Value::Int(1)));
+        let july = Value::Int(1_720_999_800); // 2024-07-14T23:30:00Z
+        assert_eq!(call("hour_of_day", vec![july, s("America/New_York")]), Ok(Value::Int(19)));
+
+        assert!(at("hour_of_day", "Mars/Olympus_Mons")
+            .unwrap_err()
+            .contains("Unknown time zone"));
+        assert!(at("hour_of_day", "+25:00").is_err());
+        assert!(at("hour_of_day", "+02:75").is_err());
+    }
+
+    #[test]
+    fn test_expand_clock() {
+        let now = Expression::path(vec!["request".to_string(), "timestamp".to_string()]);
+        let tz = Expression::literal(nodes::Value::String("+02:00".to_string()));
+        let registry = FunctionRegistry::new();
+
+        assert_eq!(registry.expand_clock("now", &[]), Some(now.clone()));
+        assert_eq!(
+            registry.expand_clock("day_of_week", std::slice::from_ref(&tz)),
+            Some(Expression::Call {
+                name: "day_of_week".to_string(),
+                args: vec![now.clone(), tz.clone()],
+            })
+        );
+        assert_eq!(registry.expand_clock("hour_of_day", &[now.clone(), tz]), None);
+        assert_eq!(registry.expand_clock("lower", &[now]), None);
+
+        // A host function named `now` is not the request clock
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register("now", FunctionSignature::new(vec![], Type::Int), |_| Ok(Value::Int(7)))
+            .unwrap();
+        assert_eq!(registry.expand_clock("now", &[]), None);
+    }
+
+    #[test]
+    fn test_signature_is_scalar() {
+        let registry = FunctionRegistry::builtins();
+        assert!(registry.lookup("utc_hour").unwrap().signature.is_scalar());
+        assert!(!registry.lookup("lower").unwrap().signature.is_scalar());
+    }
+
+    #[test]
+    fn test_register_closure() {
+        let on_call = ["alice".to_string()];
+        let mut registry = FunctionRegistry::new();
+        let id = registry
+            .register(
+                "is_on_call",
+                FunctionSignature::new(vec![Type::String], Type::Bool),
+                move |args| match &args[0] {
+                    Value::String(user) => Ok(Value::Bool(on_call.contains(user))),
+                    _ => unreachable!(),
+                },
+            )
+            .unwrap();
+
+        assert!(id as usize >= BUILTINS.len());
+        assert_eq!(registry.call(id, &[s("alice")]), Ok(Value::Bool(true)));
+        assert_eq!(registry.call(id, &[s("bob")]), Ok(Value::Bool(false)));
+        assert!(!registry.lookup("is_on_call").unwrap().options.pure);
+    }
+
+    #[test]
+    fn test_pure_function_is_memoized() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut registry = FunctionRegistry::new();
+        let id = registry
+            .register_with(
+                "ticket_approved",
+                FunctionSignature::new(vec![Type::Int], Type::Bool),
+                FunctionOptions::default().pure(),
+                move |args| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Ok(Value::Bool(args[0] == Value::Int(7)))
+                },
+            )
+            .unwrap();
+
+        assert_eq!(registry.call(id, &[Value::Int(7)]), Ok(Value::Bool(true)));
+        assert_eq!(registry.call(id, &[Value::Int(7)]), Ok(Value::Bool(true)));
+        assert_eq!(registry.call(id, &[Value::Int(8)]), Ok(Value::Bool(false)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Clones share the cache
+        let clone = registry.clone();
+        assert_eq!(clone.call(id, &[Value::Int(8)]), Ok(Value::Bool(false)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_function_timeout() {
+        let mut registry = FunctionRegistry::new();
+        let slow = registry
+            .register_with(
+                "slow",
+                FunctionSignature::new(vec![], Type::Bool),
+                FunctionOptions::default().with_timeout(Duration::from_millis(10)),
+                |_| {
+                    std::thread::sleep(Duration::from_millis(500));
+                    Ok(Value::Bool(true))
+                },
+            )
+            .unwrap();
+        let fast = registry
+            .register_with(
+                "fast",
+                FunctionSignature::new(vec![], Type::Bool),
+                FunctionOptions::default().with_timeout(Duration::from_secs(5)),
+                |_| Ok(Value::Bool(true)),
+            )
+            .unwrap();
+
+        let err = registry.call(slow, &[]).unwrap_err();
+        assert!(err.contains("slow() timed out"));
+        assert_eq!(registry.call(fast, &[]), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_call_pool_is_bounded() {
+        let pool = CallPool::new(1, 1);
+        let (started_tx, started) = crossbeam_channel::bounded(1);
+        let (release, release_rx) = crossbeam_channel::bounded::<()>(1);
+
+        // Occupy the only worker, then fill the queue
+        assert!(pool.submit(Box::new(move || {
+            let _ = started_tx.send(());
+            let _ = release_rx.recv();
+        })));
+        started.recv().unwrap();
+        assert!(pool.submit(Box::new(|| {})));
+        assert!(!pool.submit(Box::new(|| {})));
+
+        release.send(()).unwrap();
+    }
+}
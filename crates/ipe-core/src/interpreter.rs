@@ -1,7 +1,8 @@
-#[cfg(test)]
-use crate::bytecode::CompOp;
-use crate::bytecode::{CompiledPolicy, Instruction, Value};
+use crate::bytecode::{AggregateOp, CompOp, CompiledPolicy, Instruction, Value};
+use crate::explain::{ExplainTracer, NoTrace, PolicyTrace, Tracer};
+use crate::functions::FunctionRegistry;
 use crate::rar::EvaluationContext;
+use std::sync::Arc;
 
 /// Maximum stack size to prevent stack overflow
 const MAX_STACK_SIZE: usize = 1024;
@@ -78,42 +79,190 @@ impl Default for Stack {
 }
 
 use crate::rar::AttributeValue;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Field mapping from offset to path
 pub type FieldMapping = HashMap<u16, Vec<String>>;
 
+/// Result of running a compiled policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The policy applied and its requirements were met
+    Allow,
+    /// The policy applied and denied the request
+    Deny,
+    /// The policy's triggers did not match, so it has no say in the decision
+    NotApplicable,
+}
+
+/// State of one aggregate loop
+pub(crate) struct AggregateFrame {
+    op: AggregateOp,
+    items: Vec<Value>,
+    index: usize,
+    /// Matches so far (count) or running result (sum, max, min)
+    acc: Option<Value>,
+}
+
+impl AggregateFrame {
+    pub(crate) fn new(op: AggregateOp, items: Vec<Value>) -> Self {
+        Self { op, items, index: 0, acc: None }
+    }
+
+    /// Fold the current element into the aggregate
+    ///
+    /// Returns the final result once it is known, which for `any`/`all` can
+    /// be before the last element.
+    pub(crate) fn fold(&mut self, matched: bool) -> Result<Option<Value>, String> {
+        let element = &self.items[self.index];
+        match self.op {
+            AggregateOp::Any if matched => return Ok(Some(Value::Bool(true))),
+            AggregateOp::All if !matched => return Ok(Some(Value::Bool(false))),
+            AggregateOp::Count if matched => {
+                let count = match self.acc {
+                    Some(Value::Int(n)) => n + 1,
+                    _ => 1,
+                };
+                self.acc = Some(Value::Int(count));
+            },
+            AggregateOp::Sum | AggregateOp::Max | AggregateOp::Min if matched => {
+                if element.as_f64().is_none() {
+                    return Err(format!("{}() is not defined for {:?}", self.op.name(), element));
+                }
+                self.acc = Some(match self.acc.take() {
+                    None => element.clone(),
+                    Some(acc) => match self.op {
+                        AggregateOp::Sum => Self::add(&acc, element)?,
+                        AggregateOp::Max if element.compare(&acc, CompOp::Gt)? => element.clone(),
+                        AggregateOp::Min if element.compare(&acc, CompOp::Lt)? => element.clone(),
+                        _ => acc,
+                    },
+                });
+            },
+            _ => {},
+        }
+
+        self.index += 1;
+        if self.index < self.items.len() {
+            Ok(None)
+        } else {
+            Self::result(self.op, self.acc.take()).map(Some)
+        }
+    }
+
+    /// Add two numbers, widening to a float if either is one
+    fn add(a: &Value, b: &Value) -> Result<Value, String> {
+        match (a, b) {
+            (Value::Int(a), Value::Int(b)) => a
+                .checked_add(*b)
+                .map(Value::Int)
+                .ok_or_else(|| "Integer overflow in sum()".to_string()),
+            _ => Ok(Value::Float(a.as_f64().unwrap_or_default() + b.as_f64().unwrap_or_default())),
+        }
+    }
+
+    /// Result of the aggregate once every element has been seen
+    pub(crate) fn result(op: AggregateOp, acc: Option<Value>) -> Result<Value, String> {
+        match (op, acc) {
+            (AggregateOp::Any, _) => Ok(Value::Bool(false)),
+            (AggregateOp::All, _) => Ok(Value::Bool(true)),
+            (_, Some(acc)) => Ok(acc),
+            (AggregateOp::Count | AggregateOp::Sum, None) => Ok(Value::Int(0)),
+            // max/min of nothing has no value
+            (_, None) => Ok(Value::Undefined),
+        }
+    }
+}
+
 /// Bytecode interpreter (fallback when JIT not available)
 pub struct Interpreter {
     stack: Stack,
     field_map: FieldMapping,
+    functions: Arc<FunctionRegistry>,
+    aggregates: Vec<AggregateFrame>,
 }
 
 impl Interpreter {
     /// Create a new interpreter with the given field mapping
     pub fn new(field_map: FieldMapping) -> Self {
-        Self { stack: Stack::new(), field_map }
+        Self {
+            stack: Stack::new(),
+            field_map,
+            functions: FunctionRegistry::builtins(),
+            aggregates: Vec::new(),
+        }
+    }
+
+    /// Dispatch `Call` instructions to the given registry instead of the builtins
+    ///
+    /// Must be the registry the policy was compiled with.
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = functions;
+        self
     }
 
     /// Evaluate a compiled policy against an evaluation context
-    /// Hot path - performance critical
+    ///
+    /// Returns `true` only when the policy applies and allows the request;
+    /// use [`Interpreter::run`] to distinguish deny from not-applicable.
     #[inline]
     pub fn evaluate(
         &mut self,
         policy: &CompiledPolicy,
         ctx: &EvaluationContext,
     ) -> Result<bool, String> {
+        self.run(policy, ctx).map(|outcome| outcome == Outcome::Allow)
+    }
+
+    /// Run a compiled policy against an evaluation context
+    /// Hot path - performance critical
+    #[inline]
+    pub fn run(
+        &mut self,
+        policy: &CompiledPolicy,
+        ctx: &EvaluationContext,
+    ) -> Result<Outcome, String> {
+        self.execute(policy, ctx, &mut NoTrace)
+    }
+
+    /// Run a compiled policy and record how each of its conditions evaluated
+    ///
+    /// Slower than [`Interpreter::run`]; meant for explaining decisions.
+    pub fn explain(
+        &mut self,
+        name: &str,
+        policy: &CompiledPolicy,
+        ctx: &EvaluationContext,
+    ) -> PolicyTrace {
+        let mut tracer = ExplainTracer::new(&policy.conditions);
+        let result = self.execute(policy, ctx, &mut tracer);
+        tracer.finish(name, result, &self.field_map)
+    }
+
+    /// The interpreter loop, generic so that tracing compiles away in `run`
+    #[inline]
+    fn execute<T: Tracer>(
+        &mut self,
+        policy: &CompiledPolicy,
+        ctx: &EvaluationContext,
+        tracer: &mut T,
+    ) -> Result<Outcome, String> {
         self.stack.clear();
+        self.aggregates.clear();
         let mut pc = 0; // Program counter
 
         // Main interpreter loop - keep hot path simple
         while pc < policy.code.len() {
+            tracer.step(pc, &self.stack);
+
             // Use unsafe get for performance - we've already bounds checked
             let instr = unsafe { policy.code.get_unchecked(pc) };
 
             match instr {
                 Instruction::LoadField { offset } => {
                     let value = self.load_field(*offset, ctx)?;
+                    tracer.field(*offset, &value);
                     self.stack.push(value)?;
                 },
 
@@ -131,9 +280,16 @@ impl Interpreter {
                     let b = self.stack.pop()?;
                     let a = self.stack.pop()?;
                     let result = a.compare(&b, *op)?;
+                    tracer.compare(*op, &a, &b, result);
                     self.stack.push(Value::Bool(result))?;
                 },
 
+                Instruction::Arith { op } => {
+                    let b = self.stack.pop()?;
+                    let a = self.stack.pop()?;
+                    self.stack.push(a.arith(&b, *op)?)?;
+                },
+
                 Instruction::And => {
                     let b = self.stack.pop()?;
                     let a = self.stack.pop()?;
@@ -155,7 +311,11 @@ impl Interpreter {
                 },
 
                 Instruction::Return { value } => {
-                    return Ok(*value);
+                    return Ok(if *value { Outcome::Allow } else { Outcome::Deny });
+                },
+
+                Instruction::NotApplicable => {
+                    return Ok(Outcome::NotApplicable);
                 },
 
                 Instruction::Jump { offset } => {
@@ -171,11 +331,121 @@ impl Interpreter {
                     }
                 },
 
+                Instruction::JumpIfTrue { offset } => {
+                    let cond = self.stack.pop()?;
+                    if cond.is_truthy() {
+                        pc = (pc as i32 + *offset as i32) as usize;
+                        continue;
+                    }
+                },
+
+                Instruction::JumpIfDefined { offset } => {
+                    if self.stack.pop()?.is_defined() {
+                        pc = (pc as i32 + *offset as i32) as usize;
+                        continue;
+                    }
+                },
+
+                Instruction::Dup => {
+                    let top = self.stack.peek()?.clone();
+                    self.stack.push(top)?;
+                },
+
+                Instruction::Pop => {
+                    self.stack.pop()?;
+                },
+
                 Instruction::Call { func, argc } => {
-                    return Err(format!(
-                        "Function calls not yet supported: func={}, argc={}",
-                        func, argc
-                    ));
+                    let argc = *argc as usize;
+                    if self.stack.len() < argc {
+                        return Err("Stack underflow".to_string());
+                    }
+                    let args = self.stack.values.split_off(self.stack.len() - argc);
+                    let result = self.functions.call(*func, &args)?;
+                    self.stack.push(result)?;
+                },
+
+                Instruction::AggregateBegin { op, skip } => {
+                    let items = match self.stack.pop()? {
+                        Value::Array(items) => items,
+                        // A missing collection aggregates like an empty one
+                        Value::Undefined => Vec::new(),
+                        other => {
+                            return Err(format!(
+                                "{}() expects an array, got {:?}",
+                                op.name(),
+                                other
+                            ))
+                        },
+                    };
+                    if items.is_empty() {
+                        self.stack.push(AggregateFrame::result(*op, None)?)?;
+                        pc = (pc as i32 + *skip as i32) as usize;
+                        continue;
+                    }
+                    self.aggregates.push(AggregateFrame::new(*op, items));
+                },
+
+                Instruction::AggregateNext { offset } => {
+                    let matched = self.stack.pop()?.is_truthy();
+                    let frame = self
+                        .aggregates
+                        .last_mut()
+                        .ok_or_else(|| "AggregateNext outside of an aggregate".to_string())?;
+                    match frame.fold(matched)? {
+                        Some(result) => {
+                            self.aggregates.pop();
+                            self.stack.push(result)?;
+                        },
+                        None => {
+                            pc = (pc as i32 + *offset as i32) as usize;
+                            continue;
+                        },
+                    }
+                },
+
+                Instruction::LoadElement => {
+                    let frame = self
+                        .aggregates
+                        .last()
+                        .ok_or_else(|| "LoadElement outside of an aggregate".to_string())?;
+                    self.stack.push(frame.items[frame.index].clone())?;
+                },
+
+                Instruction::GetField { idx } => {
+                    let name = match policy.constants.get(*idx as usize) {
+                        Some(Value::String(name)) => name,
+                        _ => return Err(format!("Invalid field name constant: {}", idx)),
+                    };
+                    let value = match self.stack.pop()? {
+                        Value::Map(mut fields) => fields.remove(name).unwrap_or(Value::Undefined),
+                        _ => Value::Undefined,
+                    };
+                    self.stack.push(value)?;
+                },
+
+                Instruction::Match { pattern } => {
+                    let pattern = policy
+                        .patterns
+                        .get(*pattern as usize)
+                        .ok_or_else(|| format!("Invalid pattern index: {}", pattern))?;
+                    let result = pattern.matches_value(&self.stack.pop()?)?;
+                    self.stack.push(Value::Bool(result))?;
+                },
+
+                Instruction::SetCompare { op } => {
+                    let b = self.stack.pop()?;
+                    let a = self.stack.pop()?;
+                    self.stack.push(Value::Bool(a.set_compare(&b, *op)?))?;
+                },
+
+                Instruction::InSet { set } => {
+                    let set = policy
+                        .sets
+                        .get(*set as usize)
+                        .ok_or_else(|| format!("Invalid set index: {}", set))?;
+                    let result = set.contains(&self.stack.pop()?);
+                    self.stack.push(Value::Bool(result))?;
                 },
             }
 
@@ -183,7 +453,7 @@ impl Interpreter {
         }
 
         // If we reach here without a Return instruction, default to deny
-        Ok(false)
+        Ok(Outcome::Deny)
     }
 
     /// Load a field value from the evaluation context
@@ -193,7 +463,12 @@ impl Interpreter {
             .field_map
             .get(&offset)
             .ok_or_else(|| format!("Unknown field offset: {}", offset))?;
+        self.load_path(path, ctx)
+    }
 
+    /// Load the value at a path (e.g. `["resource", "owner"]`) from the context
+    #[inline]
+    pub fn load_path(&self, path: &[String], ctx: &EvaluationContext) -> Result<Value, String> {
         // Navigate the path through the context
         if path.is_empty() {
             return Err("Empty field path".to_string());
@@ -220,29 +495,23 @@ impl Interpreter {
         }
 
         match unsafe { path.get_unchecked(0) }.as_str() {
-            "type" => Ok(Value::Int(resource.type_id.0 as i64)),
-            attr_name => {
-                let attr = resource
-                    .attributes
-                    .get(attr_name)
-                    .ok_or_else(|| format!("Attribute not found: {}", attr_name))?;
-                self.attr_to_value(attr)
-            },
+            "type" if path.len() == 1 => Ok(Value::Int(resource.type_id.0 as i64)),
+            _ => self.access_attribute(path, &resource.attributes),
         }
     }
 
     #[inline]
-    fn access_action(
-        &self,
-        path: &[String],
-        _action: &crate::rar::Action,
-    ) -> Result<Value, String> {
+    fn access_action(&self, path: &[String], action: &crate::rar::Action) -> Result<Value, String> {
         if path.is_empty() {
             return Err("Action path cannot be empty".to_string());
         }
 
-        // For now, just return error for unsupported paths
-        Err(format!("Action field not supported: {}", unsafe { path.get_unchecked(0) }))
+        match (unsafe { path.get_unchecked(0) }.as_str(), path.len()) {
+            ("operation", 1) => Ok(Value::String(action.operation.to_string())),
+            ("target", 1) => Ok(Value::String(action.target.clone())),
+            ("attributes", 2..) => self.access_attribute(&path[1..], &action.attributes),
+            _ => Err(format!("Action field not supported: {}", path.join("."))),
+        }
     }
 
     #[inline]
@@ -262,13 +531,12 @@ impl Interpreter {
                 }
                 self.access_principal(&path[1..], &request.principal)
             },
-            attr_name => {
-                let attr = request
-                    .metadata
-                    .get(attr_name)
-                    .ok_or_else(|| format!("Request metadata not found: {}", attr_name))?;
-                self.attr_to_value(attr)
-            },
+            "timestamp" if path.len() == 1 => Ok(Value::Int(request.timestamp)),
+            "source_ip" if path.len() == 1 => Ok(request
+                .source_ip
+                .as_ref()
+                .map_or(Value::Undefined, |ip| Value::String(ip.clone()))),
+            _ => self.access_attribute(path, &request.metadata),
         }
     }
 
@@ -283,25 +551,57 @@ impl Interpreter {
         }
 
         match unsafe { path.get_unchecked(0) }.as_str() {
-            "id" => Ok(Value::String(principal.id.clone())),
-            attr_name => {
-                let attr = principal
-                    .attributes
-                    .get(attr_name)
-                    .ok_or_else(|| format!("Principal attribute not found: {}", attr_name))?;
-                self.attr_to_value(attr)
+            "id" if path.len() == 1 => Ok(Value::String(principal.id.clone())),
+            "roles" if path.len() == 1 => {
+                Ok(Value::Array(principal.roles.iter().cloned().map(Value::String).collect()))
             },
+            _ => self.access_attribute(path, &principal.attributes),
         }
     }
 
+    /// Resolve an attribute path, descending through map-valued attributes
+    ///
+    /// A missing attribute or map key, or a field of a non-map value, is
+    /// `Undefined`.
+    fn access_attribute(
+        &self,
+        path: &[String],
+        attributes: &HashMap<String, AttributeValue>,
+    ) -> Result<Value, String> {
+        let Some(mut attr) = attributes.get(&path[0]) else {
+            return Ok(Value::Undefined);
+        };
+
+        for segment in &path[1..] {
+            attr = match attr {
+                AttributeValue::Map(fields) => match fields.get(segment) {
+                    Some(attr) => attr,
+                    None => return Ok(Value::Undefined),
+                },
+                _ => return Ok(Value::Undefined),
+            };
+        }
+
+        self.attr_to_value(attr)
+    }
+
     #[inline]
     fn attr_to_value(&self, attr: &AttributeValue) -> Result<Value, String> {
-        match attr {
-            AttributeValue::String(s) => Ok(Value::String(s.clone())),
-            AttributeValue::Int(i) => Ok(Value::Int(*i)),
-            AttributeValue::Bool(b) => Ok(Value::Bool(*b)),
-            AttributeValue::Array(_) => Err("Array attributes not yet supported".to_string()),
-        }
+        Ok(match attr {
+            AttributeValue::String(s) => Value::String(s.clone()),
+            AttributeValue::Int(i) => Value::Int(*i),
+            AttributeValue::Float(f) => Value::Float(*f),
+            AttributeValue::Bool(b) => Value::Bool(*b),
+            AttributeValue::Array(items) => {
+                Value::Array(items.iter().map(|a| self.attr_to_value(a)).collect::<Result<_, _>>()?)
+            },
+            AttributeValue::Map(fields) => Value::Map(
+                fields
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.attr_to_value(v)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        })
     }
 }
 
@@ -674,6 +974,632 @@ mod tests {
         assert!(!result); // Should default to deny
     }
 
+    #[test]
+    fn test_interpreter_run_outcomes() {
+        let mut interp = Interpreter::default();
+        let ctx = EvaluationContext::default();
+
+        let mut allow = CompiledPolicy::new(1);
+        allow.emit(Instruction::Return { value: true });
+        assert_eq!(interp.run(&allow, &ctx).unwrap(), Outcome::Allow);
+
+        let mut deny = CompiledPolicy::new(2);
+        deny.emit(Instruction::Return { value: false });
+        assert_eq!(interp.run(&deny, &ctx).unwrap(), Outcome::Deny);
+
+        let mut skipped = CompiledPolicy::new(3);
+        skipped.emit(Instruction::NotApplicable);
+        assert_eq!(interp.run(&skipped, &ctx).unwrap(), Outcome::NotApplicable);
+        // Not applicable is never reported as an allow
+        assert!(!interp.evaluate(&skipped, &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_interpreter_compiled_trigger_guard() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy Guarded: "Only applies to production"
+            triggers when resource.env == "prod"
+            requires resource.reviewed == true
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let ctx_for = |env: &str, reviewed: bool| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource
+                .attributes
+                .insert("env".to_string(), AttributeValue::String(env.to_string()));
+            ctx.resource
+                .attributes
+                .insert("reviewed".to_string(), AttributeValue::Bool(reviewed));
+            ctx
+        };
+
+        assert_eq!(interp.run(&policy, &ctx_for("prod", true)).unwrap(), Outcome::Allow);
+        assert_eq!(interp.run(&policy, &ctx_for("prod", false)).unwrap(), Outcome::Deny);
+        assert_eq!(interp.run(&policy, &ctx_for("dev", true)).unwrap(), Outcome::NotApplicable);
+    }
+
+    #[test]
+    fn test_interpreter_function_calls() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy InternalProd: "Internal production services"
+            triggers when starts_with(lower(resource.name), "prod-")
+            requires ip_in_range(resource.source_ip, "10.0.0.0/8") and len(resource.name) < 12
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let ctx_for = |name: &str, ip: &str| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource
+                .attributes
+                .insert("name".to_string(), AttributeValue::String(name.to_string()));
+            ctx.resource
+                .attributes
+                .insert("source_ip".to_string(), AttributeValue::String(ip.to_string()));
+            ctx
+        };
+
+        assert_eq!(interp.run(&policy, &ctx_for("PROD-api", "10.2.3.4")).unwrap(), Outcome::Allow);
+        assert_eq!(interp.run(&policy, &ctx_for("prod-api", "8.8.8.8")).unwrap(), Outcome::Deny);
+        assert_eq!(
+            interp.run(&policy, &ctx_for("prod-billing-api", "10.2.3.4")).unwrap(),
+            Outcome::Deny
+        );
+        assert_eq!(
+            interp.run(&policy, &ctx_for("dev-api", "10.2.3.4")).unwrap(),
+            Outcome::NotApplicable
+        );
+    }
+
+    #[test]
+    fn test_interpreter_function_call_type_error() {
+        let registry = FunctionRegistry::builtins();
+        let mut policy = CompiledPolicy::new(1);
+        let idx = policy.add_constant(Value::Int(42));
+        policy.emit(Instruction::LoadConst { idx });
+        policy.emit(Instruction::Call {
+            func: registry.resolve("lower").unwrap(),
+            argc: 1,
+        });
+        policy.emit(Instruction::Return { value: true });
+
+        let mut interp = Interpreter::new(HashMap::new());
+        let ctx = EvaluationContext::default();
+        let err = interp.evaluate(&policy, &ctx).unwrap_err();
+        assert!(err.contains("lower() argument 1"));
+
+        // Too few values on the stack for the declared argc
+        let mut policy = CompiledPolicy::new(1);
+        policy.emit(Instruction::Call {
+            func: registry.resolve("contains").unwrap(),
+            argc: 2,
+        });
+        assert!(interp.evaluate(&policy, &ctx).is_err());
+    }
+
+    #[test]
+    fn test_interpreter_aggregates() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let run = |requires: &str, ctx: &EvaluationContext| {
+            let source =
+                format!("policy Agg: \"Aggregates\"\ntriggers when true\nrequires {}", requires);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, ctx)
+        };
+
+        let approver = |name: &str, role: &str| {
+            AttributeValue::Map(HashMap::from([
+                ("name".to_string(), AttributeValue::String(name.to_string())),
+                ("role".to_string(), AttributeValue::String(role.to_string())),
+            ]))
+        };
+        let ints = |values: &[i64]| {
+            AttributeValue::Array(values.iter().map(|v| AttributeValue::Int(*v)).collect())
+        };
+
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.attributes.insert(
+            "approvers".to_string(),
+            AttributeValue::Array(vec![
+                approver("alice", "sre"),
+                approver("bob", "dev"),
+                approver("carol", "sre"),
+            ]),
+        );
+        ctx.resource.attributes.insert("ports".to_string(), ints(&[22, 443, 8080]));
+        ctx.resource.attributes.insert("empty".to_string(), ints(&[]));
+        ctx.resource.attributes.insert("min_sre".to_string(), AttributeValue::Int(2));
+
+        let allowed = |requires: &str| run(requires, &ctx).unwrap() == Outcome::Allow;
+
+        assert!(allowed(r#"count(resource.approvers where role == "sre") >= 2"#));
+        assert!(allowed(r#"count(resource.approvers where role == "sre") == resource.min_sre"#));
+        assert!(!allowed(r#"count(resource.approvers where item.role == "dev") >= 2"#));
+        assert!(allowed("count(resource.approvers) == 3"));
+        assert!(allowed("any(resource.ports where item == 22)"));
+        assert!(!allowed("all(resource.ports where item > 100)"));
+        assert!(allowed("sum(resource.ports where item > 100) == 8523"));
+        assert!(allowed("max(resource.ports) == 8080 and min(resource.ports) == 22"));
+        assert!(allowed(r#"all(resource.approvers where len(name) >= 3)"#));
+
+        // Empty collections
+        assert!(allowed("count(resource.empty) == 0 and all(resource.empty where item > 0)"));
+        assert!(!allowed("any(resource.empty where item > 0)"));
+        assert!(!allowed("max(resource.empty) > 0"));
+        assert!(allowed("(min(resource.empty) ?? 0) == 0"));
+
+        // Aggregating a scalar or summing records is an error
+        assert!(run("count(resource.min_sre) > 0", &ctx).is_err());
+        assert!(run("sum(resource.approvers) > 0", &ctx).is_err());
+    }
+
+    #[test]
+    fn test_interpreter_action_fields() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::rar::{Action, Operation};
+
+        let source = r#"
+            policy Deploys: "Forced production deploys are denied"
+            triggers when action.operation == "deploy" and action.target == "production"
+            requires action.attributes.force == false
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let ctx_for = |operation: Operation, target: &str, force: bool| EvaluationContext {
+            action: Action::new(operation, target)
+                .with_attribute("force", AttributeValue::Bool(force)),
+            ..Default::default()
+        };
+
+        let ctx = ctx_for(Operation::Deploy, "production", false);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Allow);
+        let ctx = ctx_for(Operation::Deploy, "production", true);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Deny);
+        let ctx = ctx_for(Operation::Deploy, "staging", true);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::NotApplicable);
+        let ctx = ctx_for(Operation::Custom(3), "production", true);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::NotApplicable);
+
+        // Unknown action fields are errors, not silently false
+        let mut field_map = FieldMapping::new();
+        field_map.insert(0, vec!["action".to_string(), "verb".to_string()]);
+        let mut bad = CompiledPolicy::new(2);
+        bad.emit(Instruction::LoadField { offset: 0 });
+        bad.emit(Instruction::Return { value: true });
+        let err = Interpreter::new(field_map).run(&bad, &ctx).unwrap_err();
+        assert!(err.contains("verb"));
+    }
+
+    #[test]
+    fn test_interpreter_float_values() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy Scores: "Confident, cheap requests"
+            triggers when resource.score > 0
+            requires resource.score >= 0.75 and resource.cost < 10
+                and sum(resource.weights) == 1 and max(resource.weights) == 0.5
+                and resource.replicas in [1, 2.5]
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let ctx_for = |score: f64, cost: f64| {
+            let mut ctx = EvaluationContext::default();
+            let attrs = &mut ctx.resource.attributes;
+            attrs.insert("score".to_string(), AttributeValue::Float(score));
+            attrs.insert("cost".to_string(), AttributeValue::Float(cost));
+            attrs.insert("replicas".to_string(), AttributeValue::Int(1));
+            attrs.insert(
+                "weights".to_string(),
+                AttributeValue::Array(vec![
+                    AttributeValue::Float(0.5),
+                    AttributeValue::Float(0.25),
+                    AttributeValue::Float(0.25),
+                ]),
+            );
+            ctx
+        };
+
+        assert_eq!(interp.run(&policy, &ctx_for(0.8, 9.99)).unwrap(), Outcome::Allow);
+        assert_eq!(interp.run(&policy, &ctx_for(0.7, 9.99)).unwrap(), Outcome::Deny);
+        assert_eq!(interp.run(&policy, &ctx_for(0.8, 10.0)).unwrap(), Outcome::Deny);
+        assert_eq!(interp.run(&policy, &ctx_for(0.0, 1.0)).unwrap(), Outcome::NotApplicable);
+    }
+
+    #[test]
+    fn test_interpreter_nested_aggregates() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let team = |members: &[&str]| {
+            AttributeValue::Map(HashMap::from([(
+                "members".to_string(),
+                AttributeValue::Array(
+                    members.iter().map(|m| AttributeValue::String(m.to_string())).collect(),
+                ),
+            )]))
+        };
+
+        let source = r#"
+            policy Teams: "Every team has at least two members, one of them alice"
+            triggers when true
+            requires all(resource.teams where count(members) >= 2)
+                and any(resource.teams where any(members where item == "alice"))
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let ctx_for = |teams: Vec<AttributeValue>| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource
+                .attributes
+                .insert("teams".to_string(), AttributeValue::Array(teams));
+            ctx
+        };
+
+        let ctx = ctx_for(vec![team(&["bob", "alice"]), team(&["carol", "dan"])]);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Allow);
+        let ctx = ctx_for(vec![team(&["bob", "erin"]), team(&["carol", "dan"])]);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Deny);
+        let ctx = ctx_for(vec![team(&["alice"]), team(&["carol", "dan"])]);
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Deny);
+    }
+
+    #[test]
+    fn test_interpreter_nested_paths() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::rar::{Principal, Request};
+
+        let source = r#"
+            policy Payments: "Payments SREs from the office network"
+            triggers when resource.labels.team == "payments"
+            requires any(request.principal.roles where item == "sre")
+                and any(request.principal.claims.groups where item == "oncall")
+                and request.source_ip == "10.0.0.7"
+                and request.timestamp > 1700000000
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let claims = AttributeValue::Map(HashMap::from([(
+            "groups".to_string(),
+            AttributeValue::Array(vec![AttributeValue::String("oncall".to_string())]),
+        )]));
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.attributes.insert(
+            "labels".to_string(),
+            AttributeValue::Map(HashMap::from([(
+                "team".to_string(),
+                AttributeValue::String("payments".to_string()),
+            )])),
+        );
+        ctx.request = Request {
+            principal: Principal::new("alice").with_role("sre").with_attribute("claims", claims),
+            timestamp: 1_700_000_100,
+            source_ip: Some("10.0.0.7".to_string()),
+            metadata: HashMap::new(),
+        };
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Allow);
+
+        ctx.request.principal.roles.clear();
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Deny);
+
+        // Missing nested keys and fields of non-map intermediates are undefined
+        ctx.resource.attributes.insert(
+            "labels".to_string(),
+            AttributeValue::Map(HashMap::from([(
+                "owner".to_string(),
+                AttributeValue::String("bob".to_string()),
+            )])),
+        );
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::NotApplicable);
+
+        ctx.resource.attributes.insert("labels".to_string(), AttributeValue::Int(1));
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::NotApplicable);
+    }
+
+    #[test]
+    fn test_interpreter_undefined_values() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.attributes.insert(
+            "approvers".to_string(),
+            AttributeValue::Array(vec![AttributeValue::Map(HashMap::from([(
+                "name".to_string(),
+                AttributeValue::String("alice".to_string()),
+            )]))]),
+        );
+        ctx.resource
+            .attributes
+            .insert("plan".to_string(), AttributeValue::String("gold".to_string()));
+
+        let allowed = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, &ctx).unwrap() == Outcome::Allow
+        };
+
+        // Comparisons against a missing attribute are false, except `!=`
+        assert!(!allowed(r#"resource.tier == "free""#));
+        assert!(!allowed("resource.size > 0 or resource.size <= 0"));
+        assert!(allowed(r#"resource.tier != "free""#));
+
+        assert!(allowed(r#"resource.tier ?? "free" == "free""#));
+        assert!(allowed(r#"request.principal.claims.team ?? "none" == "none""#));
+        assert!(allowed("not exists(resource.tier) and has(resource.approvers)"));
+        assert!(!allowed("exists(request.source_ip)"));
+
+        // Fields of a scalar are missing too, so policies can guard odd shapes
+        assert!(allowed("not exists(resource.plan.level)"));
+        assert!(allowed("resource.plan.level ?? 1 == 1"));
+        assert!(allowed("count(resource.approvers where exists(name.first)) == 0"));
+
+        // Missing element fields and collections
+        assert!(allowed("count(resource.approvers where exists(role)) == 0"));
+        assert!(allowed("count(resource.reviewers) == 0"));
+    }
+
+    #[test]
+    fn test_interpreter_arithmetic() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.timestamp = 10_000;
+        ctx.resource.attributes.insert("replicas".to_string(), AttributeValue::Int(3));
+        ctx.resource
+            .attributes
+            .insert("created_at".to_string(), AttributeValue::Int(8_000));
+
+        let run = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, &ctx)
+        };
+
+        assert_eq!(run("resource.replicas * 2 <= 6"), Ok(Outcome::Allow));
+        assert_eq!(run("request.timestamp - resource.created_at < 3600"), Ok(Outcome::Allow));
+        assert_eq!(run("resource.replicas * 2.5 == 7.5"), Ok(Outcome::Allow));
+
+        // Missing attributes propagate and can be defaulted
+        assert_eq!(run("resource.quota - 1 > 0"), Ok(Outcome::Deny));
+        assert_eq!(run("(resource.quota ?? 10) - resource.replicas == 7"), Ok(Outcome::Allow));
+
+        assert!(run("resource.replicas / (resource.replicas - 3) > 0")
+            .unwrap_err()
+            .contains("Division by zero"));
+        assert!(run("resource.replicas * 9223372036854775807 > 0")
+            .unwrap_err()
+            .contains("overflow"));
+    }
+
+    #[test]
+    fn test_interpreter_set_operators() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::rar::Principal;
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.principal = Principal::user("alice").with_role("dev").with_role("sre");
+        let tags = ["pci", "eu"].map(|t| AttributeValue::String(t.to_string())).to_vec();
+        ctx.resource.attributes.insert("tags".to_string(), AttributeValue::Array(tags));
+        ctx.resource
+            .attributes
+            .insert("env".to_string(), AttributeValue::String("qa".to_string()));
+
+        let run = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, &ctx)
+        };
+
+        assert_eq!(run(r#""sre" in request.principal.roles"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#""admin" not in request.principal.roles"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"request.principal.roles contains "admin""#), Ok(Outcome::Deny));
+        assert_eq!(run(r#"resource.tags intersects ["pci", "hipaa"]"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"resource.tags subset_of ["pci", "hipaa"]"#), Ok(Outcome::Deny));
+        assert_eq!(run(r#"resource.tags subset_of ["pci", "eu", "us"]"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"resource.env not in ["prod", "staging"]"#), Ok(Outcome::Allow));
+
+        // Long literal lists use the hashed set, with the same semantics
+        let envs = r#"["a", "b", "c", "d", "e", "f", "g", "qa"]"#;
+        assert_eq!(run(&format!("resource.env in {}", envs)), Ok(Outcome::Allow));
+        assert_eq!(run(&format!("resource.missing in {}", envs)), Ok(Outcome::Deny));
+        assert_eq!(run(&format!("resource.missing not in {}", envs)), Ok(Outcome::Allow));
+
+        // Missing collections never match
+        assert_eq!(run(r#""pci" in resource.missing"#), Ok(Outcome::Deny));
+        assert!(run(r#""q" in resource.env"#).unwrap_err().contains("expects an array"));
+    }
+
+    #[test]
+    fn test_interpreter_definitions() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::parse_module;
+
+        let source = r#"
+const MAX_REPLICAS = 3 * 2
+predicate owned_by(team): resource.owner == team
+predicate is_prod_deploy: resource.env in ["prod", "prod-eu"]
+
+policy ProdDeploy: "Production deploys stay small"
+  let replicas = resource.replicas ?? 1
+  triggers when is_prod_deploy
+  requires owned_by("sre") and replicas <= MAX_REPLICAS
+"#;
+        let module = parse_module(source).unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1)
+            .compile_with_field_map(module.policies().next().unwrap())
+            .unwrap();
+
+        let mut ctx = EvaluationContext::default();
+        for (key, value) in [("env", "prod-eu"), ("owner", "sre")] {
+            ctx.resource
+                .attributes
+                .insert(key.to_string(), AttributeValue::String(value.to_string()));
+        }
+        let mut interpreter = Interpreter::new(field_map);
+        assert_eq!(interpreter.run(&policy, &ctx), Ok(Outcome::Allow));
+
+        ctx.resource.attributes.insert("replicas".to_string(), AttributeValue::Int(8));
+        assert_eq!(interpreter.run(&policy, &ctx), Ok(Outcome::Deny));
+    }
+
+    #[test]
+    fn test_interpreter_time_windows() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        // Monday 2024-01-15T14:30:00Z
+        let mut ctx = EvaluationContext::default();
+        ctx.request.timestamp = 1_705_329_000;
+        ctx.resource
+            .attributes
+            .insert("approved_at".to_string(), AttributeValue::Int(1_705_329_000 - 3 * 86_400));
+
+        let run = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, &ctx)
+        };
+
+        assert_eq!(run("now() - resource.approved_at < 7d"), Ok(Outcome::Allow));
+        assert_eq!(run("now() - resource.approved_at < 2d"), Ok(Outcome::Deny));
+        assert_eq!(run("now() >= 2024-01-15T00:00:00Z"), Ok(Outcome::Allow));
+        assert_eq!(
+            run("hour_of_day() >= 9 and hour_of_day() < 17 and day_of_week() <= 5"),
+            Ok(Outcome::Allow)
+        );
+        assert_eq!(run(r#"hour_of_day("+10:00") == 0"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"day_of_week(resource.approved_at, "UTC") == 5"#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"hour_of_day("America/New_York") == 9"#), Ok(Outcome::Allow));
+        assert!(run(r#"hour_of_day("Mars/Olympus_Mons") > 0"#).is_err());
+
+        // A host function named `now` replaces the request clock
+        let mut functions = FunctionRegistry::new();
+        functions
+            .register(
+                "now",
+                crate::functions::FunctionSignature::new(vec![], crate::ast::Type::Int),
+                |_| Ok(Value::Int(42)),
+            )
+            .unwrap();
+        let functions = Arc::new(functions);
+        let source = "policy P: \"test\"\ntriggers when true\nrequires now() == 42";
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1)
+            .with_functions(Arc::clone(&functions))
+            .compile_with_field_map(&ast)
+            .unwrap();
+        let outcome = Interpreter::new(field_map).with_functions(functions).run(&policy, &ctx);
+        assert_eq!(outcome, Ok(Outcome::Allow));
+    }
+
+    #[test]
+    fn test_interpreter_pattern_operators() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let mut ctx = EvaluationContext::default();
+        for (key, value) in [("name", "svc-042"), ("path", "/tmp/build/app.log")] {
+            ctx.resource
+                .attributes
+                .insert(key.to_string(), AttributeValue::String(value.to_string()));
+        }
+        ctx.resource.attributes.insert("size".to_string(), AttributeValue::Int(3));
+
+        let run = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+            Interpreter::new(field_map).run(&policy, &ctx)
+        };
+
+        assert_eq!(run(r#"resource.name matches "^svc-[0-9]+$""#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"resource.name matches "[0-9]{4}""#), Ok(Outcome::Deny));
+        assert_eq!(run(r#"resource.path like "/tmp/*.log""#), Ok(Outcome::Allow));
+        assert_eq!(run(r#"resource.path like "/tmp/*.txt""#), Ok(Outcome::Deny));
+        // Globs match the whole string, regexes anywhere in it
+        assert_eq!(run(r#"resource.path like "build""#), Ok(Outcome::Deny));
+        assert_eq!(run(r#"resource.path matches "build""#), Ok(Outcome::Allow));
+        // The builtin still works next to the operator
+        assert_eq!(
+            run(r#"matches(resource.name, "svc") and resource.name like "svc-???""#),
+            Ok(Outcome::Allow)
+        );
+
+        // Missing attributes never match; other types are errors
+        assert_eq!(run(r#"not (resource.owner like "*")"#), Ok(Outcome::Allow));
+        assert!(run(r#"resource.size matches "3""#).unwrap_err().contains("Cannot match"));
+    }
+
+    #[test]
+    fn test_interpreter_short_circuit() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        // `resource.owner` is not a string, so evaluating it would fail
+        let source = r#"
+            policy Public: "Public resources skip the owner check"
+            triggers when true
+            requires resource.public == true or lower(resource.owner) == "alice"
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.attributes.insert("owner".to_string(), AttributeValue::Int(7));
+        ctx.resource.attributes.insert("public".to_string(), AttributeValue::Bool(true));
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Allow);
+
+        ctx.resource
+            .attributes
+            .insert("public".to_string(), AttributeValue::Bool(false));
+        assert!(interp.run(&policy, &ctx).unwrap_err().contains("lower()"));
+
+        let source = r#"
+            policy Private: "Private resources need their owner"
+            triggers when true
+            requires resource.public == false and lower(resource.owner) == "alice"
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(2).compile_with_field_map(&ast).unwrap();
+        let mut interp = Interpreter::new(field_map);
+
+        ctx.resource.attributes.insert("public".to_string(), AttributeValue::Bool(true));
+        assert_eq!(interp.run(&policy, &ctx).unwrap(), Outcome::Deny);
+        assert_eq!(interp.stack.len(), 0);
+    }
+
     // Performance-focused tests for hot path optimizations
     #[test]
     fn test_stack_operations_are_inlineable() {
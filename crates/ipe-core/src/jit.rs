@@ -1,14 +1,156 @@
+use crate::ast::types::Type;
 use crate::bytecode::{CompiledPolicy, Instruction};
-use crate::rar::EvaluationContext;
+use crate::functions::FunctionRegistry;
+use crate::interpreter::{FieldMapping, Outcome};
+use crate::rar::{EvaluationContext, Operation};
 use crate::{Error, Result};
+use cranelift::codegen::ir::FuncRef;
 use cranelift::prelude::*;
 use cranelift_jit::{JITBuilder, JITModule};
 use cranelift_module::{Linkage, Module};
 use parking_lot::RwLock;
 use region::{protect, Protection};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+/// Native return code for a denied request
+const RET_DENY: u8 = 0;
+/// Native return code for an allowed request
+const RET_ALLOW: u8 = 1;
+/// Native return code for a policy whose triggers did not match
+const RET_NOT_APPLICABLE: u8 = 2;
+/// Native return code for a policy that failed to evaluate; the message is
+/// left in [`LAST_ERROR`]
+const RET_ERROR: u8 = 3;
+
+/// Symbol name of the host trampoline for builtin function calls
+const CALL_BUILTIN_SYMBOL: &str = "ipe_call_builtin";
+
+/// Symbol name of the host trampoline for context field loads
+const LOAD_FIELD_SYMBOL: &str = "ipe_load_field";
+
+/// Symbol name of the host trampoline for arithmetic faults
+const ARITH_ERROR_SYMBOL: &str = "ipe_arith_error";
+
+/// Context fields with a native (`i64`) representation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JitField {
+    /// `resource.type`
+    ResourceType = 0,
+    /// `action.operation`, as its [`Operation::code`]
+    ActionOperation = 1,
+    /// `request.timestamp`
+    RequestTimestamp = 2,
+}
+
+impl JitField {
+    fn from_path(path: &[String]) -> Option<Self> {
+        match path {
+            [root, field] if root == "resource" && field == "type" => Some(JitField::ResourceType),
+            [root, field] if root == "action" && field == "operation" => {
+                Some(JitField::ActionOperation)
+            },
+            [root, field] if root == "request" && field == "timestamp" => {
+                Some(JitField::RequestTimestamp)
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Host trampolines and resolved fields available to a compiled function
+struct Natives<'a> {
+    call_builtin: Option<FuncRef>,
+    load_field: Option<FuncRef>,
+    arith_error: Option<FuncRef>,
+    /// Native field per `LoadField` offset; `None` for legacy raw loads
+    fields: Option<&'a HashMap<u16, JitField>>,
+}
+
+/// Host trampoline called by JIT code for `LoadField` instructions
+extern "C" fn load_field(ctx: *const EvaluationContext, field: i64) -> i64 {
+    // SAFETY: JIT code passes on the context pointer it was called with
+    let ctx = unsafe { &*ctx };
+    match field {
+        f if f == JitField::ResourceType as i64 => ctx.resource.type_id.0 as i64,
+        f if f == JitField::ActionOperation as i64 => ctx.action.operation.code(),
+        f if f == JitField::RequestTimestamp as i64 => ctx.request.timestamp,
+        _ => 0,
+    }
+}
+
+thread_local! {
+    /// Error raised by a host trampoline during the current JIT call
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Record `message` as the reason the running JIT code fails
+fn fail(message: String) -> u8 {
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+    RET_ERROR
+}
+
+/// Host trampoline called by JIT code when checked arithmetic faults
+///
+/// Redoes the operation on the operands (`f64` bits if `float` is set) to
+/// record the interpreter's error message, and returns [`RET_ERROR`].
+extern "C" fn arith_error(op: i64, a: i64, b: i64, float: i64) -> u8 {
+    use crate::bytecode::{ArithOp, Value};
+
+    let op = match op {
+        0 => ArithOp::Add,
+        1 => ArithOp::Sub,
+        2 => ArithOp::Mul,
+        3 => ArithOp::Div,
+        _ => ArithOp::Mod,
+    };
+    let (a, b) = if float != 0 {
+        (Value::Float(f64::from_bits(a as u64)), Value::Float(f64::from_bits(b as u64)))
+    } else {
+        (Value::Int(a), Value::Int(b))
+    };
+    match a.arith(&b, op) {
+        Err(e) => fail(e),
+        Ok(_) => fail(format!("Arithmetic fault in {:?} {} {:?}", a, op.symbol(), b)),
+    }
+}
+
+/// Host trampoline called by JIT code for `Call` instructions
+///
+/// Only scalar builtins (see [`crate::functions::FunctionSignature::is_scalar`])
+/// are JIT-compiled, so every argument and the result fit in an `i64`. The
+/// result is written to `result`; the return value is 0 on success and
+/// [`RET_ERROR`] if the function failed, which the generated code returns
+/// as is, since native code cannot unwind.
+extern "C" fn call_builtin(func: i64, args: *const i64, argc: i64, result: *mut i64) -> u8 {
+    let registry = FunctionRegistry::builtins();
+    let Some(function) = registry.get(func as u8) else {
+        return fail(format!("Unknown function id: {}", func));
+    };
+
+    // SAFETY: JIT code passes a stack slot holding `argc` i64 values
+    let raw = unsafe { std::slice::from_raw_parts(args, argc as usize) };
+    let values: Vec<crate::bytecode::Value> = raw
+        .iter()
+        .zip(&function.signature.params)
+        .map(|(raw, typ)| match typ {
+            Type::Bool => crate::bytecode::Value::Bool(*raw != 0),
+            _ => crate::bytecode::Value::Int(*raw),
+        })
+        .collect();
+
+    let value = match registry.call(func as u8, &values) {
+        Ok(crate::bytecode::Value::Int(n)) => n,
+        Ok(crate::bytecode::Value::Bool(b)) => b as i64,
+        Ok(other) => return fail(format!("{}() returned {:?}", function.name, other)),
+        Err(e) => return fail(e),
+    };
+    // SAFETY: JIT code passes a stack slot for the result
+    unsafe { *result = value };
+    0
+}
+
 /// JIT-compiled native code for a policy
 pub struct JitCode {
     /// Function pointer to native code
@@ -28,8 +170,30 @@ impl JitCode {
     /// # Safety
     /// Caller must ensure the context pointer is valid
     pub unsafe fn execute(&self, ctx: *const EvaluationContext) -> bool {
+        matches!(self.execute_outcome(ctx), Ok(Outcome::Allow))
+    }
+
+    /// Execute the JIT-compiled policy, distinguishing deny from
+    /// not-applicable
+    ///
+    /// A failing function call is an error, as in
+    /// [`Interpreter::run`](crate::interpreter::Interpreter::run).
+    ///
+    /// # Safety
+    /// Caller must ensure the context pointer is valid
+    pub unsafe fn execute_outcome(
+        &self,
+        ctx: *const EvaluationContext,
+    ) -> std::result::Result<Outcome, String> {
         let func: extern "C" fn(*const EvaluationContext) -> u8 = std::mem::transmute(self.ptr);
-        func(ctx) != 0
+        match func(ctx) {
+            RET_DENY => Ok(Outcome::Deny),
+            RET_NOT_APPLICABLE => Ok(Outcome::NotApplicable),
+            RET_ERROR => Err(LAST_ERROR
+                .with(|error| error.borrow_mut().take())
+                .unwrap_or_else(|| "JIT evaluation failed".to_string())),
+            _ => Ok(Outcome::Allow),
+        }
     }
 }
 
@@ -68,7 +232,10 @@ impl JitCompiler {
             .finish(settings::Flags::new(flag_builder))
             .map_err(|e| Error::JitError(format!("Failed to create ISA: {}", e)))?;
 
-        let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        builder.symbol(CALL_BUILTIN_SYMBOL, call_builtin as *const u8);
+        builder.symbol(LOAD_FIELD_SYMBOL, load_field as *const u8);
+        builder.symbol(ARITH_ERROR_SYMBOL, arith_error as *const u8);
 
         let module = JITModule::new(builder);
 
@@ -81,6 +248,30 @@ impl JitCompiler {
 
     /// Compile a policy to native code
     pub fn compile(&mut self, policy: &CompiledPolicy, name: &str) -> Result<Arc<JitCode>> {
+        self.compile_inner(policy, None, name)
+    }
+
+    /// Compile a policy whose `LoadField` offsets resolve through `field_map`
+    ///
+    /// Fields are loaded from the context by path. Only fields with a native
+    /// representation are supported (`resource.type`, `request.timestamp`, and
+    /// `action.operation` compared by [`Operation::code`]); any other field is
+    /// a `JitError`, so the policy stays on the interpreter.
+    pub fn compile_with_fields(
+        &mut self,
+        policy: &CompiledPolicy,
+        field_map: &FieldMapping,
+        name: &str,
+    ) -> Result<Arc<JitCode>> {
+        self.compile_inner(policy, Some(field_map), name)
+    }
+
+    fn compile_inner(
+        &mut self,
+        policy: &CompiledPolicy,
+        field_map: Option<&FieldMapping>,
+        name: &str,
+    ) -> Result<Arc<JitCode>> {
         // Check cache
         {
             let cache = self.cache.read();
@@ -105,6 +296,68 @@ impl JitCompiler {
         let mut ctx = self.module.make_context();
         ctx.func.signature = sig;
 
+        // Import the builtin trampoline if the policy calls any functions
+        let call_builtin = if Self::check_calls(policy)? {
+            let mut call_sig = self.module.make_signature();
+            call_sig.params.push(AbiParam::new(types::I64)); // function id
+            call_sig.params.push(AbiParam::new(types::I64)); // argument pointer
+            call_sig.params.push(AbiParam::new(types::I64)); // argument count
+            call_sig.params.push(AbiParam::new(types::I64)); // result pointer
+            call_sig.returns.push(AbiParam::new(types::I8)); // status
+
+            let callee = self
+                .module
+                .declare_function(CALL_BUILTIN_SYMBOL, Linkage::Import, &call_sig)
+                .map_err(|e| Error::JitError(format!("Failed to declare builtin call: {}", e)))?;
+            Some(self.module.declare_func_in_func(callee, &mut ctx.func))
+        } else {
+            None
+        };
+
+        // Resolve every loaded field up front and import the loader if needed
+        let fields = field_map.map(|map| Self::resolve_fields(policy, map)).transpose()?;
+        let load_field = match &fields {
+            Some(fields) if !fields.is_empty() => {
+                let mut load_sig = self.module.make_signature();
+                load_sig.params.push(AbiParam::new(types::I64)); // ctx pointer
+                load_sig.params.push(AbiParam::new(types::I64)); // field
+                load_sig.returns.push(AbiParam::new(types::I64));
+
+                let callee = self
+                    .module
+                    .declare_function(LOAD_FIELD_SYMBOL, Linkage::Import, &load_sig)
+                    .map_err(|e| Error::JitError(format!("Failed to declare field load: {}", e)))?;
+                Some(self.module.declare_func_in_func(callee, &mut ctx.func))
+            },
+            _ => None,
+        };
+        // Import the arithmetic fault trampoline if the policy does arithmetic
+        let arith_error =
+            if policy.code.iter().any(|instr| matches!(instr, Instruction::Arith { .. })) {
+                let mut error_sig = self.module.make_signature();
+                error_sig.params.push(AbiParam::new(types::I64)); // operator
+                error_sig.params.push(AbiParam::new(types::I64)); // left operand
+                error_sig.params.push(AbiParam::new(types::I64)); // right operand
+                error_sig.params.push(AbiParam::new(types::I64)); // float operands
+                error_sig.returns.push(AbiParam::new(types::I8)); // status
+
+                let callee = self
+                    .module
+                    .declare_function(ARITH_ERROR_SYMBOL, Linkage::Import, &error_sig)
+                    .map_err(|e| {
+                        Error::JitError(format!("Failed to declare arithmetic error: {}", e))
+                    })?;
+                Some(self.module.declare_func_in_func(callee, &mut ctx.func))
+            } else {
+                None
+            };
+        let natives = Natives {
+            call_builtin,
+            load_field,
+            arith_error,
+            fields: fields.as_ref(),
+        };
+
         // Build function body
         {
             let mut builder = FunctionBuilder::new(&mut ctx.func, &mut self.builder_ctx);
@@ -118,7 +371,7 @@ impl JitCompiler {
             let ctx_ptr = builder.block_params(entry_block)[0];
 
             // Translate bytecode to IR
-            Self::translate_bytecode(&mut builder, policy, ctx_ptr)?;
+            Self::translate_bytecode(&mut builder, policy, ctx_ptr, &natives)?;
 
             builder.finalize();
         }
@@ -154,21 +407,250 @@ impl JitCompiler {
         Ok(jit_code)
     }
 
+    /// Map each loaded field offset to its native field
+    fn resolve_fields(
+        policy: &CompiledPolicy,
+        field_map: &FieldMapping,
+    ) -> Result<HashMap<u16, JitField>> {
+        let mut fields = HashMap::new();
+        for instr in &policy.code {
+            if let Instruction::LoadField { offset } = instr {
+                let path = field_map
+                    .get(offset)
+                    .ok_or_else(|| Error::JitError(format!("Unknown field offset: {}", offset)))?;
+                let field = JitField::from_path(path).ok_or_else(|| {
+                    Error::JitError(format!("Field {} cannot be JIT-compiled", path.join(".")))
+                })?;
+                fields.insert(*offset, field);
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Check that every called function can run natively
+    ///
+    /// Returns whether the policy calls any function. Functions over strings
+    /// cannot be lowered, so such policies stay on the interpreter.
+    fn check_calls(policy: &CompiledPolicy) -> Result<bool> {
+        let registry = FunctionRegistry::builtins();
+        let mut has_calls = false;
+
+        for instr in &policy.code {
+            if let Instruction::Call { func, .. } = instr {
+                // Host functions are only reachable through the interpreter's registry
+                let function = registry.get(*func).ok_or_else(|| {
+                    Error::JitError(format!("Function id {} is not a builtin", func))
+                })?;
+                if !function.signature.is_scalar() {
+                    return Err(Error::JitError(format!(
+                        "Function {}() has non-scalar arguments and cannot be JIT-compiled",
+                        function.name
+                    )));
+                }
+                has_calls = true;
+            }
+        }
+
+        Ok(has_calls)
+    }
+
+    /// Continue only if `status` is zero; otherwise return it as the result
+    fn return_if_failed(builder: &mut FunctionBuilder, status: Value) {
+        let fail_block = builder.create_block();
+        let ok_block = builder.create_block();
+        builder.ins().brif(status, fail_block, &[], ok_block, &[]);
+        builder.seal_block(fail_block);
+        builder.seal_block(ok_block);
+
+        builder.switch_to_block(fail_block);
+        builder.ins().return_(&[status]);
+
+        builder.switch_to_block(ok_block);
+    }
+
+    /// Continue only if `fault` is false; otherwise fail with the error the
+    /// interpreter raises for `a op b` (overflow, division by zero)
+    fn fail_if(
+        builder: &mut FunctionBuilder,
+        natives: &Natives,
+        fault: Value,
+        op: crate::bytecode::ArithOp,
+        a: Value,
+        b: Value,
+    ) -> Result<()> {
+        let callee = natives.arith_error.ok_or_else(|| {
+            Error::JitError("Arithmetic error trampoline not declared".to_string())
+        })?;
+        let fault_block = builder.create_block();
+        let ok_block = builder.create_block();
+        builder.ins().brif(fault, fault_block, &[], ok_block, &[]);
+        builder.seal_block(fault_block);
+        builder.seal_block(ok_block);
+
+        builder.switch_to_block(fault_block);
+        let float = builder.func.dfg.value_type(a) == types::F64;
+        let (a, b) = if float {
+            (
+                builder.ins().bitcast(types::I64, MemFlags::new(), a),
+                builder.ins().bitcast(types::I64, MemFlags::new(), b),
+            )
+        } else {
+            (a, b)
+        };
+        let op = builder.ins().iconst(types::I64, op as i64);
+        let float = builder.ins().iconst(types::I64, float as i64);
+        let call = builder.ins().call(callee, &[op, a, b, float]);
+        let status = builder.inst_results(call)[0];
+        builder.ins().return_(&[status]);
+
+        builder.switch_to_block(ok_block);
+        Ok(())
+    }
+
+    /// Checked arithmetic, widening an integer operand if the other is a float
+    fn translate_arith(
+        builder: &mut FunctionBuilder,
+        natives: &Natives,
+        op: crate::bytecode::ArithOp,
+        a: Value,
+        b: Value,
+    ) -> Result<Value> {
+        use crate::bytecode::ArithOp;
+
+        let ty = |builder: &FunctionBuilder, v| builder.func.dfg.value_type(v);
+        if ty(builder, a) == types::I8 || ty(builder, b) == types::I8 {
+            return Err(Error::JitError("Arithmetic on booleans".to_string()));
+        }
+
+        if ty(builder, a) == types::I64 && ty(builder, b) == types::I64 {
+            let (result, overflow) = match op {
+                ArithOp::Add => builder.ins().sadd_overflow(a, b),
+                ArithOp::Sub => builder.ins().ssub_overflow(a, b),
+                ArithOp::Mul => builder.ins().smul_overflow(a, b),
+                ArithOp::Div | ArithOp::Mod => {
+                    // Division by zero, and i64::MIN / -1 which overflows
+                    let zero = builder.ins().icmp_imm(IntCC::Equal, b, 0);
+                    let min = builder.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+                    let minus_one = builder.ins().icmp_imm(IntCC::Equal, b, -1);
+                    let overflow = builder.ins().band(min, minus_one);
+                    let fault = builder.ins().bor(zero, overflow);
+                    Self::fail_if(builder, natives, fault, op, a, b)?;
+                    return Ok(if op == ArithOp::Div {
+                        builder.ins().sdiv(a, b)
+                    } else {
+                        builder.ins().srem(a, b)
+                    });
+                },
+            };
+            Self::fail_if(builder, natives, overflow, op, a, b)?;
+            return Ok(result);
+        }
+
+        let a = if ty(builder, a) == types::F64 {
+            a
+        } else {
+            builder.ins().fcvt_from_sint(types::F64, a)
+        };
+        let b = if ty(builder, b) == types::F64 {
+            b
+        } else {
+            builder.ins().fcvt_from_sint(types::F64, b)
+        };
+        Ok(match op {
+            ArithOp::Add => builder.ins().fadd(a, b),
+            ArithOp::Sub => builder.ins().fsub(a, b),
+            ArithOp::Mul => builder.ins().fmul(a, b),
+            ArithOp::Div => {
+                let zero = builder.ins().f64const(0.0);
+                let fault = builder.ins().fcmp(FloatCC::Equal, b, zero);
+                Self::fail_if(builder, natives, fault, op, a, b)?;
+                builder.ins().fdiv(a, b)
+            },
+            ArithOp::Mod => {
+                return Err(Error::JitError("Float remainder cannot be JIT-compiled".to_string()))
+            },
+        })
+    }
+
+    /// Compare two values, widening an integer operand if the other is a float
+    fn translate_compare(
+        builder: &mut FunctionBuilder,
+        op: crate::bytecode::CompOp,
+        a: Value,
+        b: Value,
+    ) -> Value {
+        use crate::bytecode::CompOp;
+
+        let is_float = |builder: &FunctionBuilder, v| builder.func.dfg.value_type(v) == types::F64;
+        if !is_float(builder, a) && !is_float(builder, b) {
+            let cc = match op {
+                CompOp::Eq => IntCC::Equal,
+                CompOp::Neq => IntCC::NotEqual,
+                CompOp::Lt => IntCC::SignedLessThan,
+                CompOp::Lte => IntCC::SignedLessThanOrEqual,
+                CompOp::Gt => IntCC::SignedGreaterThan,
+                CompOp::Gte => IntCC::SignedGreaterThanOrEqual,
+            };
+            return builder.ins().icmp(cc, a, b);
+        }
+
+        let a = if is_float(builder, a) { a } else { builder.ins().fcvt_from_sint(types::F64, a) };
+        let b = if is_float(builder, b) { b } else { builder.ins().fcvt_from_sint(types::F64, b) };
+        // Ordered comparisons are false for NaN; NotEqual is true for it
+        let cc = match op {
+            CompOp::Eq => FloatCC::Equal,
+            CompOp::Neq => FloatCC::NotEqual,
+            CompOp::Lt => FloatCC::LessThan,
+            CompOp::Lte => FloatCC::LessThanOrEqual,
+            CompOp::Gt => FloatCC::GreaterThan,
+            CompOp::Gte => FloatCC::GreaterThanOrEqual,
+        };
+        builder.ins().fcmp(cc, a, b)
+    }
+
+    /// Fail compilation if any of `values` is a string constant
+    ///
+    /// Strings only have a native form as operation names compared against
+    /// `action.operation`; anywhere else the placeholder would be read as a
+    /// number or a boolean, so the policy stays on the interpreter.
+    fn reject_strings(
+        strings: &HashMap<Value, String>,
+        values: &[Value],
+        instruction: &str,
+    ) -> Result<()> {
+        match values.iter().find_map(|v| strings.get(v)) {
+            Some(s) => Err(Error::JitError(format!(
+                "String {:?} in {} cannot be JIT-compiled",
+                s, instruction
+            ))),
+            None => Ok(()),
+        }
+    }
+
     fn translate_bytecode(
         builder: &mut FunctionBuilder,
         policy: &CompiledPolicy,
         ctx_ptr: Value,
+        natives: &Natives,
     ) -> Result<()> {
         // Stack for intermediate values
         let mut value_stack: Vec<Value> = Vec::new();
 
+        // Loaded operations and string constants, so `action.operation == "deploy"`
+        // can compare operation codes
+        let mut operations: HashSet<Value> = HashSet::new();
+        let mut strings: HashMap<Value, String> = HashMap::new();
+
         // Block map for jumps
         let mut block_map: HashMap<usize, Block> = HashMap::new();
 
         // Create blocks for jump targets
         for (idx, instr) in policy.code.iter().enumerate() {
             match instr {
-                Instruction::Jump { offset } | Instruction::JumpIfFalse { offset } => {
+                Instruction::Jump { offset }
+                | Instruction::JumpIfFalse { offset }
+                | Instruction::JumpIfTrue { offset }
+                | Instruction::JumpIfDefined { offset } => {
                     let target = (idx as i16 + offset) as usize;
                     block_map.entry(target).or_insert_with(|| builder.create_block());
                 },
@@ -176,20 +658,60 @@ impl JitCompiler {
             }
         }
 
+        // Whether the current block still needs a terminator
+        let mut block_open = true;
+
+        // Values left on the stack flow into jump targets as block parameters,
+        // typed by the first edge into the block
+        let mut block_params: HashSet<Block> = HashSet::new();
+        let mut edge_args = |builder: &mut FunctionBuilder, block: Block, stack: &[Value]| {
+            if block_params.insert(block) {
+                for &value in stack {
+                    let ty = builder.func.dfg.value_type(value);
+                    builder.append_block_param(block, ty);
+                }
+            }
+            stack.to_vec()
+        };
+
         // Translate instructions
         for (idx, instr) in policy.code.iter().enumerate() {
             // If this is a jump target, seal previous block and switch
             if let Some(&block) = block_map.get(&idx) {
+                // Fall through into the target if the current block is still open
+                if block_open {
+                    Self::reject_strings(&strings, &value_stack, "a jump")?;
+                    let args = edge_args(builder, block, &value_stack);
+                    builder.ins().jump(block, &args);
+                }
                 builder.seal_block(block);
                 builder.switch_to_block(block);
+                value_stack = builder.block_params(block).to_vec();
+                block_open = true;
             }
 
             match instr {
-                Instruction::LoadField { offset } => {
-                    // Load field from context: *(ctx + offset)
-                    let field_addr = builder.ins().iadd_imm(ctx_ptr, *offset as i64);
-                    let value = builder.ins().load(types::I64, MemFlags::trusted(), field_addr, 0);
-                    value_stack.push(value);
+                Instruction::LoadField { offset } => match natives.fields {
+                    Some(fields) => {
+                        let callee = natives.load_field.ok_or_else(|| {
+                            Error::JitError("Field load trampoline not declared".to_string())
+                        })?;
+                        let field = fields[offset];
+                        let code = builder.ins().iconst(types::I64, field as i64);
+                        let call = builder.ins().call(callee, &[ctx_ptr, code]);
+                        let value = builder.inst_results(call)[0];
+                        if field == JitField::ActionOperation {
+                            operations.insert(value);
+                        }
+                        value_stack.push(value);
+                    },
+                    None => {
+                        // Load field from context: *(ctx + offset)
+                        let field_addr = builder.ins().iadd_imm(ctx_ptr, *offset as i64);
+                        let value =
+                            builder.ins().load(types::I64, MemFlags::trusted(), field_addr, 0);
+                        value_stack.push(value);
+                    },
                 },
 
                 Instruction::LoadConst { idx } => {
@@ -197,13 +719,26 @@ impl JitCompiler {
                     let constant = &policy.constants[*idx as usize];
                     let value = match constant {
                         crate::bytecode::Value::Int(i) => builder.ins().iconst(types::I64, *i),
+                        crate::bytecode::Value::Float(f) => builder.ins().f64const(*f),
                         crate::bytecode::Value::Bool(b) => {
                             builder.ins().iconst(types::I8, if *b { 1 } else { 0 })
                         },
-                        crate::bytecode::Value::String(_) => {
+                        crate::bytecode::Value::String(s) => {
                             // For strings, we'd need to store them in data section
                             // For now, just use a placeholder
-                            builder.ins().iconst(types::I64, 0)
+                            let value = builder.ins().iconst(types::I64, 0);
+                            strings.insert(value, s.clone());
+                            value
+                        },
+                        crate::bytecode::Value::Array(_) | crate::bytecode::Value::Map(_) => {
+                            return Err(Error::JitError(
+                                "Collection constants cannot be JIT-compiled".to_string(),
+                            ));
+                        },
+                        crate::bytecode::Value::Undefined => {
+                            return Err(Error::JitError(
+                                "Undefined constants cannot be JIT-compiled".to_string(),
+                            ));
                         },
                     };
                     value_stack.push(value);
@@ -217,48 +752,121 @@ impl JitCompiler {
                         .pop()
                         .ok_or_else(|| Error::JitError("Stack underflow in Compare".to_string()))?;
 
-                    let result = match op {
-                        crate::bytecode::CompOp::Eq => builder.ins().icmp(IntCC::Equal, a, b),
-                        crate::bytecode::CompOp::Neq => builder.ins().icmp(IntCC::NotEqual, a, b),
-                        crate::bytecode::CompOp::Lt => {
-                            builder.ins().icmp(IntCC::SignedLessThan, a, b)
-                        },
-                        crate::bytecode::CompOp::Lte => {
-                            builder.ins().icmp(IntCC::SignedLessThanOrEqual, a, b)
-                        },
-                        crate::bytecode::CompOp::Gt => {
-                            builder.ins().icmp(IntCC::SignedGreaterThan, a, b)
-                        },
-                        crate::bytecode::CompOp::Gte => {
-                            builder.ins().icmp(IntCC::SignedGreaterThanOrEqual, a, b)
+                    // Operation names compare as operation codes
+                    let mut operation_code = |v: Value| match strings.get(&v) {
+                        Some(name) => {
+                            let code = Operation::from_name(name).map_or(-1, |o| o.code());
+                            builder.ins().iconst(types::I64, code)
                         },
+                        None => v,
+                    };
+                    let (a, b) = if operations.contains(&a) {
+                        (a, operation_code(b))
+                    } else if operations.contains(&b) {
+                        (operation_code(a), b)
+                    } else {
+                        Self::reject_strings(&strings, &[a, b], "Compare")?;
+                        (a, b)
                     };
 
+                    let result = Self::translate_compare(builder, *op, a, b);
+                    value_stack.push(result);
+                },
+
+                Instruction::Arith { op } => {
+                    let b = value_stack
+                        .pop()
+                        .ok_or_else(|| Error::JitError("Stack underflow in Arith".to_string()))?;
+                    let a = value_stack
+                        .pop()
+                        .ok_or_else(|| Error::JitError("Stack underflow in Arith".to_string()))?;
+                    if strings.contains_key(&a) || strings.contains_key(&b) {
+                        return Err(Error::JitError("Arithmetic on strings".to_string()));
+                    }
+                    let result = Self::translate_arith(builder, natives, *op, a, b)?;
                     value_stack.push(result);
                 },
 
                 Instruction::Jump { offset } => {
+                    Self::reject_strings(&strings, &value_stack, "Jump")?;
                     let target = (idx as i16 + offset) as usize;
                     let target_block = block_map[&target];
-                    builder.ins().jump(target_block, &[]);
+                    let args = edge_args(builder, target_block, &value_stack);
+                    builder.ins().jump(target_block, &args);
+                    block_open = false;
                 },
 
                 Instruction::JumpIfFalse { offset } => {
                     let cond = value_stack.pop().ok_or_else(|| {
                         Error::JitError("Stack underflow in JumpIfFalse".to_string())
                     })?;
+                    Self::reject_strings(&strings, &[cond], "JumpIfFalse")?;
+                    Self::reject_strings(&strings, &value_stack, "JumpIfFalse")?;
 
                     let target = (idx as i16 + offset) as usize;
                     let target_block = block_map[&target];
+                    let args = edge_args(builder, target_block, &value_stack);
 
                     // Create fallthrough block
                     let fallthrough = builder.create_block();
 
-                    builder.ins().brif(cond, fallthrough, &[], target_block, &[]);
+                    builder.ins().brif(cond, fallthrough, &[], target_block, &args);
                     builder.seal_block(fallthrough);
                     builder.switch_to_block(fallthrough);
                 },
 
+                Instruction::JumpIfTrue { offset } => {
+                    let cond = value_stack.pop().ok_or_else(|| {
+                        Error::JitError("Stack underflow in JumpIfTrue".to_string())
+                    })?;
+                    Self::reject_strings(&strings, &[cond], "JumpIfTrue")?;
+                    Self::reject_strings(&strings, &value_stack, "JumpIfTrue")?;
+
+                    let target = (idx as i16 + offset) as usize;
+                    let target_block = block_map[&target];
+                    let args = edge_args(builder, target_block, &value_stack);
+
+                    let fallthrough = builder.create_block();
+
+                    builder.ins().brif(cond, target_block, &args, fallthrough, &[]);
+                    builder.seal_block(fallthrough);
+                    builder.switch_to_block(fallthrough);
+                },
+
+                // Native values are never undefined, so the jump is always taken;
+                // the fallthrough (the `??` fallback) is unreachable
+                Instruction::JumpIfDefined { offset } => {
+                    let value = value_stack.pop().ok_or_else(|| {
+                        Error::JitError("Stack underflow in JumpIfDefined".to_string())
+                    })?;
+                    Self::reject_strings(&strings, &[value], "JumpIfDefined")?;
+                    Self::reject_strings(&strings, &value_stack, "JumpIfDefined")?;
+
+                    let target = (idx as i16 + offset) as usize;
+                    let target_block = block_map[&target];
+                    let args = edge_args(builder, target_block, &value_stack);
+
+                    let fallthrough = builder.create_block();
+                    let defined = builder.ins().iconst(types::I8, 1);
+
+                    builder.ins().brif(defined, target_block, &args, fallthrough, &[]);
+                    builder.seal_block(fallthrough);
+                    builder.switch_to_block(fallthrough);
+                },
+
+                Instruction::Dup => {
+                    let top = *value_stack
+                        .last()
+                        .ok_or_else(|| Error::JitError("Stack underflow in Dup".to_string()))?;
+                    value_stack.push(top);
+                },
+
+                Instruction::Pop => {
+                    value_stack
+                        .pop()
+                        .ok_or_else(|| Error::JitError("Stack underflow in Pop".to_string()))?;
+                },
+
                 Instruction::And => {
                     let b = value_stack
                         .pop()
@@ -266,6 +874,7 @@ impl JitCompiler {
                     let a = value_stack
                         .pop()
                         .ok_or_else(|| Error::JitError("Stack underflow in And".to_string()))?;
+                    Self::reject_strings(&strings, &[a, b], "And")?;
                     let result = builder.ins().band(a, b);
                     value_stack.push(result);
                 },
@@ -277,6 +886,7 @@ impl JitCompiler {
                     let a = value_stack
                         .pop()
                         .ok_or_else(|| Error::JitError("Stack underflow in Or".to_string()))?;
+                    Self::reject_strings(&strings, &[a, b], "Or")?;
                     let result = builder.ins().bor(a, b);
                     value_stack.push(result);
                 },
@@ -285,30 +895,106 @@ impl JitCompiler {
                     let a = value_stack
                         .pop()
                         .ok_or_else(|| Error::JitError("Stack underflow in Not".to_string()))?;
-                    let result = builder.ins().bnot(a);
+                    Self::reject_strings(&strings, &[a], "Not")?;
+                    // Booleans are 0 or 1, so flipping every bit would keep them truthy
+                    let result = builder.ins().icmp_imm(IntCC::Equal, a, 0);
                     value_stack.push(result);
                 },
 
-                Instruction::Call { func: _, argc: _ } => {
-                    // Built-in function calls
-                    // For now, just push a dummy result
-                    let result = builder.ins().iconst(types::I64, 0);
-                    value_stack.push(result);
+                // Aggregates iterate over heap arrays, which native code cannot see
+                Instruction::AggregateBegin { .. }
+                | Instruction::AggregateNext { .. }
+                | Instruction::LoadElement
+                | Instruction::GetField { .. } => {
+                    return Err(Error::JitError("Aggregates cannot be JIT-compiled".to_string()));
                 },
 
-                Instruction::Return { value } => {
-                    let ret_val = if *value {
-                        builder.ins().iconst(types::I8, 1)
+                // Patterns match heap strings, which native code cannot see
+                Instruction::Match { .. } => {
+                    return Err(Error::JitError(
+                        "Pattern matching cannot be JIT-compiled".to_string(),
+                    ));
+                },
+
+                // Set operators work on arrays, which native code cannot see
+                Instruction::SetCompare { .. } | Instruction::InSet { .. } => {
+                    return Err(Error::JitError(
+                        "Set operators cannot be JIT-compiled".to_string(),
+                    ));
+                },
+
+                Instruction::Call { func, argc } => {
+                    let callee = natives.call_builtin.ok_or_else(|| {
+                        Error::JitError("Builtin call trampoline not declared".to_string())
+                    })?;
+                    let argc = *argc as usize;
+                    if value_stack.len() < argc {
+                        return Err(Error::JitError("Stack underflow in Call".to_string()));
+                    }
+                    let args = value_stack.split_off(value_stack.len() - argc);
+                    Self::reject_strings(&strings, &args, "Call")?;
+
+                    // Spill the arguments to a stack slot as i64s
+                    let slot = builder.create_sized_stack_slot(StackSlotData::new(
+                        StackSlotKind::ExplicitSlot,
+                        (argc.max(1) * 8) as u32,
+                    ));
+                    for (i, arg) in args.into_iter().enumerate() {
+                        let arg = if builder.func.dfg.value_type(arg) == types::I64 {
+                            arg
+                        } else {
+                            builder.ins().uextend(types::I64, arg)
+                        };
+                        builder.ins().stack_store(arg, slot, (i * 8) as i32);
+                    }
+
+                    let result_slot = builder.create_sized_stack_slot(StackSlotData::new(
+                        StackSlotKind::ExplicitSlot,
+                        8,
+                    ));
+
+                    let args_ptr = builder.ins().stack_addr(types::I64, slot, 0);
+                    let result_ptr = builder.ins().stack_addr(types::I64, result_slot, 0);
+                    let func_id = builder.ins().iconst(types::I64, *func as i64);
+                    let argc = builder.ins().iconst(types::I64, argc as i64);
+                    let call = builder.ins().call(callee, &[func_id, args_ptr, argc, result_ptr]);
+                    let status = builder.inst_results(call)[0];
+                    Self::return_if_failed(builder, status);
+                    let result = builder.ins().stack_load(types::I64, result_slot, 0);
+
+                    let returns_bool = FunctionRegistry::builtins()
+                        .get(*func)
+                        .is_some_and(|f| f.signature.returns == Type::Bool);
+                    let result = if returns_bool {
+                        builder.ins().icmp_imm(IntCC::NotEqual, result, 0)
                     } else {
-                        builder.ins().iconst(types::I8, 0)
+                        result
                     };
+                    value_stack.push(result);
+                },
+
+                Instruction::Return { value } => {
+                    Self::reject_strings(&strings, &value_stack, "Return")?;
+                    let code = if *value { RET_ALLOW } else { RET_DENY };
+                    let ret_val = builder.ins().iconst(types::I8, code as i64);
                     builder.ins().return_(&[ret_val]);
+                    block_open = false;
+                },
+
+                Instruction::NotApplicable => {
+                    let ret_val = builder.ins().iconst(types::I8, RET_NOT_APPLICABLE as i64);
+                    builder.ins().return_(&[ret_val]);
+                    block_open = false;
                 },
             }
         }
 
-        // Note: Return instructions are handled in bytecode translation
-        // Each bytecode sequence should end with a Return instruction
+        // Falling off the end without a Return defaults to deny, as in the interpreter
+        if block_open {
+            let ret_val = builder.ins().iconst(types::I8, RET_DENY as i64);
+            builder.ins().return_(&[ret_val]);
+        }
+
         Ok(())
     }
 }
@@ -340,6 +1026,10 @@ mod tests {
             },
             code: vec![Instruction::Return { value: true }],
             constants: vec![],
+            patterns: vec![],
+            sets: vec![],
+            effects: Default::default(),
+            conditions: Vec::new(),
         };
 
         let jit_code = compiler.compile(&policy, "test_policy").unwrap();
@@ -349,4 +1039,399 @@ mod tests {
         let result = unsafe { jit_code.execute(&ctx as *const _) };
         assert!(result);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_not_applicable() {
+        let mut compiler = JitCompiler::new().unwrap();
+
+        // Trigger guard that never matches
+        let mut policy = CompiledPolicy::new(0);
+        let idx = policy.add_constant(crate::bytecode::Value::Bool(false));
+        policy.emit(Instruction::LoadConst { idx });
+        policy.emit(Instruction::JumpIfFalse { offset: 2 });
+        policy.emit(Instruction::Return { value: true });
+        policy.emit(Instruction::NotApplicable);
+
+        let jit_code = compiler.compile(&policy, "not_applicable_policy").unwrap();
+
+        let ctx = EvaluationContext::default();
+        let outcome = unsafe { jit_code.execute_outcome(&ctx as *const _) };
+        assert_eq!(outcome, Ok(Outcome::NotApplicable));
+        assert!(!unsafe { jit_code.execute(&ctx as *const _) });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_scalar_function_call() {
+        let mut compiler = JitCompiler::new().unwrap();
+        let registry = FunctionRegistry::builtins();
+
+        // utc_hour(1705329000) == 14  (2024-01-15T14:30:00Z)
+        let mut policy = CompiledPolicy::new(0);
+        let ts = policy.add_constant(crate::bytecode::Value::Int(1_705_329_000));
+        let hour = policy.add_constant(crate::bytecode::Value::Int(14));
+        policy.emit(Instruction::LoadConst { idx: ts });
+        policy.emit(Instruction::Call {
+            func: registry.resolve("utc_hour").unwrap(),
+            argc: 1,
+        });
+        policy.emit(Instruction::LoadConst { idx: hour });
+        policy.emit(Instruction::Compare { op: crate::bytecode::CompOp::Eq });
+        policy.emit(Instruction::JumpIfFalse { offset: 2 });
+        policy.emit(Instruction::Return { value: true });
+        policy.emit(Instruction::Return { value: false });
+
+        let jit_code = compiler.compile(&policy, "scalar_call_policy").unwrap();
+
+        let ctx = EvaluationContext::default();
+        assert!(unsafe { jit_code.execute(&ctx as *const _) });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_function_error_is_not_false() {
+        let mut compiler = JitCompiler::new().unwrap();
+        let registry = FunctionRegistry::builtins();
+
+        // utc_hour() fails for timestamps chrono cannot represent; the
+        // failure must surface instead of evaluating to a value
+        let mut policy = CompiledPolicy::new(0);
+        let ts = policy.add_constant(crate::bytecode::Value::Int(i64::MAX));
+        let hour = policy.add_constant(crate::bytecode::Value::Int(3));
+        policy.emit(Instruction::LoadConst { idx: ts });
+        policy.emit(Instruction::Call {
+            func: registry.resolve("utc_hour").unwrap(),
+            argc: 1,
+        });
+        policy.emit(Instruction::LoadConst { idx: hour });
+        policy.emit(Instruction::Compare { op: crate::bytecode::CompOp::Neq });
+        policy.emit(Instruction::JumpIfFalse { offset: 2 });
+        policy.emit(Instruction::Return { value: true });
+        policy.emit(Instruction::Return { value: false });
+
+        let jit_code = compiler.compile(&policy, "failing_call_policy").unwrap();
+
+        let ctx = EvaluationContext::default();
+        let outcome = unsafe { jit_code.execute_outcome(&ctx as *const _) };
+        assert!(matches!(outcome, Err(msg) if msg.contains("out of range")));
+        assert!(!unsafe { jit_code.execute(&ctx as *const _) });
+    }
+
+    /// Compile a policy requiring `condition`
+    fn compile_condition(condition: &str) -> (CompiledPolicy, FieldMapping) {
+        let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+        let ast = crate::parser::Parser::new(&source).parse_policy().unwrap();
+        crate::compiler::PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap()
+    }
+
+    /// Run `condition` as a policy's requirement natively and interpreted
+    fn differential(
+        condition: &str,
+        ctx: &EvaluationContext,
+    ) -> (std::result::Result<Outcome, String>, std::result::Result<Outcome, String>) {
+        let (policy, field_map) = compile_condition(condition);
+        let jit = JitCompiler::new()
+            .unwrap()
+            .compile_with_fields(&policy, &field_map, "differential")
+            .unwrap();
+        let native = unsafe { jit.execute_outcome(ctx as *const _) };
+        let interpreted = crate::interpreter::Interpreter::new(field_map).run(&policy, ctx);
+        (native, interpreted)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_function_error() {
+        let mut ctx = EvaluationContext::default();
+
+        // 2024-01-15T14:30:00Z; the negated comparison is false, then true
+        ctx.request.timestamp = 1_705_329_000;
+        for (condition, expected) in [
+            ("not (utc_hour(request.timestamp) == 14)", Outcome::Deny),
+            ("not (utc_hour(request.timestamp) == 3)", Outcome::Allow),
+        ] {
+            let (native, interpreted) = differential(condition, &ctx);
+            assert_eq!(native, Ok(expected), "{}", condition);
+            assert_eq!(native, interpreted, "{}", condition);
+        }
+
+        // A failing call is an error on both paths, even under `not`
+        ctx.request.timestamp = i64::MAX;
+        let (native, interpreted) = differential("not (utc_hour(request.timestamp) == 3)", &ctx);
+        assert_eq!(native, interpreted);
+        assert!(matches!(native, Err(msg) if msg.contains("out of range")));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_string_literals() {
+        use crate::rar::Action;
+
+        let ctx = EvaluationContext {
+            action: Action::new(Operation::Deploy, "production"),
+            ..Default::default()
+        };
+
+        // Only operation names compared with `action.operation` are native;
+        // other string literals keep the policy on the interpreter
+        for (condition, native) in [
+            (r#"action.operation == "deploy""#, true),
+            (r#""deploy" != action.operation"#, true),
+            (r#""a" == "b""#, false),
+            (r#"request.timestamp != "b""#, false),
+            (r#"not ("a" == "a")"#, false),
+            (r#"action.operation == "deploy" and "a" == "a""#, false),
+        ] {
+            let (policy, field_map) = compile_condition(condition);
+            let interpreted =
+                crate::interpreter::Interpreter::new(field_map.clone()).run(&policy, &ctx);
+            match JitCompiler::new().unwrap().compile_with_fields(&policy, &field_map, "strings") {
+                Ok(jit) => {
+                    assert!(native, "{} should stay on the interpreter", condition);
+                    let outcome = unsafe { jit.execute_outcome(&ctx as *const _) };
+                    assert_eq!(outcome, interpreted, "{}", condition);
+                },
+                Err(Error::JitError(msg)) => {
+                    assert!(!native, "{}: {}", condition, msg);
+                    assert!(msg.contains("String"), "{}", msg);
+                },
+                Err(e) => panic!("{}: {}", condition, e),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_rejects_string_function_call() {
+        let mut compiler = JitCompiler::new().unwrap();
+        let registry = FunctionRegistry::builtins();
+
+        let mut policy = CompiledPolicy::new(0);
+        let idx = policy.add_constant(crate::bytecode::Value::String("ABC".to_string()));
+        policy.emit(Instruction::LoadConst { idx });
+        policy.emit(Instruction::Call {
+            func: registry.resolve("lower").unwrap(),
+            argc: 1,
+        });
+        policy.emit(Instruction::Return { value: true });
+
+        let result = compiler.compile(&policy, "string_call_policy");
+        assert!(matches!(result, Err(Error::JitError(msg)) if msg.contains("lower()")));
+    }
+
+    #[test]
+    fn test_jit_rejects_aggregates() {
+        use crate::bytecode::AggregateOp;
+
+        let mut compiler = JitCompiler::new().unwrap();
+
+        let mut policy = CompiledPolicy::new(0);
+        policy.emit(Instruction::LoadField { offset: 0 });
+        policy.emit(Instruction::AggregateBegin { op: AggregateOp::Any, skip: 3 });
+        policy.emit(Instruction::LoadElement);
+        policy.emit(Instruction::AggregateNext { offset: -1 });
+        policy.emit(Instruction::Return { value: true });
+
+        let result = compiler.compile(&policy, "aggregate_policy");
+        assert!(matches!(result, Err(Error::JitError(msg)) if msg.contains("Aggregates")));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_float_comparisons() {
+        use crate::bytecode::{CompOp, Value as BytecodeValue};
+
+        let mut compiler = JitCompiler::new().unwrap();
+        let ctx = EvaluationContext::default();
+
+        let mut check = |a: BytecodeValue, b: BytecodeValue, op: CompOp, name: &str| {
+            let mut policy = CompiledPolicy::new(0);
+            let a = policy.add_constant(a);
+            let b = policy.add_constant(b);
+            policy.emit(Instruction::LoadConst { idx: a });
+            policy.emit(Instruction::LoadConst { idx: b });
+            policy.emit(Instruction::Compare { op });
+            policy.emit(Instruction::JumpIfFalse { offset: 2 });
+            policy.emit(Instruction::Return { value: true });
+            policy.emit(Instruction::Return { value: false });
+
+            let jit_code = compiler.compile(&policy, name).unwrap();
+            unsafe { jit_code.execute(&ctx as *const _) }
+        };
+
+        assert!(check(BytecodeValue::Float(1.5), BytecodeValue::Float(2.5), CompOp::Lt, "f_lt"));
+        assert!(check(BytecodeValue::Int(2), BytecodeValue::Float(2.0), CompOp::Eq, "i_f_eq"));
+        assert!(check(BytecodeValue::Float(2.5), BytecodeValue::Int(2), CompOp::Gt, "f_i_gt"));
+        assert!(!check(BytecodeValue::Float(2.5), BytecodeValue::Int(3), CompOp::Gte, "f_i_gte"));
+
+        let nan = || BytecodeValue::Float(f64::NAN);
+        assert!(!check(nan(), nan(), CompOp::Eq, "nan_eq"));
+        assert!(check(nan(), nan(), CompOp::Neq, "nan_neq"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_action_operation() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+        use crate::rar::Action;
+
+        let source = r#"
+            policy Deploys: "Only deploys are allowed"
+            triggers when true
+            requires action.operation == "deploy"
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+
+        let mut compiler = JitCompiler::new().unwrap();
+        let jit_code = compiler.compile_with_fields(&policy, &field_map, "deploys").unwrap();
+
+        let mut ctx = EvaluationContext {
+            action: Action::new(Operation::Deploy, "production"),
+            ..Default::default()
+        };
+        assert!(unsafe { jit_code.execute(&ctx as *const _) });
+        ctx.action = Action::new(Operation::Delete, "production");
+        assert!(!unsafe { jit_code.execute(&ctx as *const _) });
+
+        // Fields without a native representation stay on the interpreter
+        let source = r#"
+            policy Targets: "Only production"
+            triggers when true
+            requires action.target == "production"
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(2).compile_with_field_map(&ast).unwrap();
+        let result = compiler.compile_with_fields(&policy, &field_map, "targets");
+        assert!(matches!(result, Err(Error::JitError(msg)) if msg.contains("action.target")));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_request_timestamp() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy Fresh: "Requests after the cutover"
+            triggers when true
+            requires request.timestamp >= 1700000000
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+
+        let mut compiler = JitCompiler::new().unwrap();
+        let jit_code = compiler.compile_with_fields(&policy, &field_map, "fresh").unwrap();
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.timestamp = 1_700_000_001;
+        assert!(unsafe { jit_code.execute(&ctx as *const _) });
+        ctx.request.timestamp = 1_600_000_000;
+        assert!(!unsafe { jit_code.execute(&ctx as *const _) });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_short_circuit() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy Window: "Old requests, or new ones on resource type 3 or 4"
+            triggers when true
+            requires request.timestamp < 100
+                or (request.timestamp >= 200 and (resource.type == 3 or resource.type == 4))
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+
+        let mut compiler = JitCompiler::new().unwrap();
+        let jit_code = compiler.compile_with_fields(&policy, &field_map, "window").unwrap();
+
+        let check = |timestamp: i64, type_id: u32| {
+            let mut ctx = EvaluationContext::default();
+            ctx.request.timestamp = timestamp;
+            ctx.resource.type_id = crate::rar::ResourceTypeId(type_id);
+            unsafe { jit_code.execute(&ctx as *const _) }
+        };
+
+        assert!(check(50, 0));
+        assert!(!check(150, 3));
+        assert!(check(250, 3));
+        assert!(check(250, 4));
+        assert!(!check(250, 5));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_default_operator() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let source = r#"
+            policy Fresh: "Native fields are always defined"
+            triggers when true
+            requires request.timestamp ?? 0 >= 100
+        "#;
+        let ast = Parser::new(source).parse_policy().unwrap();
+        let (policy, field_map) = PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap();
+
+        let mut compiler = JitCompiler::new().unwrap();
+        let jit_code = compiler.compile_with_fields(&policy, &field_map, "default").unwrap();
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.timestamp = 150;
+        assert!(unsafe { jit_code.execute(&ctx as *const _) });
+        ctx.request.timestamp = 50;
+        assert!(!unsafe { jit_code.execute(&ctx as *const _) });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "JIT compilation requires pointer operations not supported by Miri")]
+    fn test_jit_arithmetic() {
+        use crate::compiler::PolicyCompiler;
+        use crate::parser::Parser;
+
+        let compile = |condition: &str| {
+            let source = format!("policy P: \"test\"\ntriggers when true\nrequires {}", condition);
+            let ast = Parser::new(&source).parse_policy().unwrap();
+            PolicyCompiler::new(1).compile_with_field_map(&ast).unwrap()
+        };
+        let jit = |condition: &str| {
+            let (policy, field_map) = compile(condition);
+            JitCompiler::new()
+                .unwrap()
+                .compile_with_fields(&policy, &field_map, "arith")
+                .unwrap()
+        };
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.timestamp = 7_200;
+        let allowed = |code: &JitCode| unsafe { code.execute(&ctx as *const _) };
+
+        assert!(allowed(&jit("request.timestamp - 3600 * 2 == 0")));
+        assert!(allowed(&jit("request.timestamp / 7 == 1028 and request.timestamp % 7 == 4")));
+        assert!(allowed(&jit("request.timestamp * 1.5 > 10000.0")));
+        assert!(!allowed(&jit("request.timestamp + 1 < 7200")));
+        // The request clock and time literals stay native
+        assert!(allowed(&jit("now() - 1970-01-01T01:00:00Z == 1h")));
+
+        // Overflow and division by zero fail with the interpreter's error
+        // instead of wrapping or trapping
+        for (condition, error) in [
+            ("request.timestamp * 9223372036854775807 != 0", "Integer overflow"),
+            ("request.timestamp / (request.timestamp - 7200) != 0", "Division by zero"),
+            ("(0 - 9223372036854775807 - 1) / (0 - 1) != 0", "Integer overflow"),
+            ("request.timestamp / 0.0 != 1.0", "Division by zero"),
+        ] {
+            let native = unsafe { jit(condition).execute_outcome(&ctx as *const _) };
+            let (policy, field_map) = compile(condition);
+            let interpreted = crate::interpreter::Interpreter::new(field_map).run(&policy, &ctx);
+            assert_eq!(native, interpreted, "{}", condition);
+            assert!(matches!(native, Err(ref msg) if msg.contains(error)), "{:?}", native);
+            assert!(!allowed(&jit(condition)));
+        }
+    }
 }
@@ -1,11 +1,18 @@
 pub mod ast;
 pub mod bytecode;
+pub mod combining;
 pub mod compiler;
+pub mod diagnostics;
 pub mod engine;
+pub mod explain;
+pub mod functions;
 pub mod index;
 pub mod interpreter;
 pub mod parser;
+pub mod partial;
 pub mod rar;
+pub mod schema;
+pub mod sql;
 pub mod store;
 pub mod tiering;
 
@@ -23,8 +30,10 @@ pub mod relationship;
 pub mod testing;
 
 pub use ast::{Condition, Policy, Requirements};
+pub use combining::CombiningAlgorithm;
 pub use compiler::{CompileError, PolicyCompiler};
 pub use engine::{Decision, DecisionKind, PolicyEngine};
+pub use functions::{FunctionOptions, FunctionRegistry, FunctionSignature};
 pub use rar::{Action, EvaluationContext, Principal, Request, Resource};
 
 use thiserror::Error;
@@ -47,6 +56,9 @@ pub enum Error {
     #[error("JIT compilation error: {0}")]
     JitError(String),
 
+    #[error("Function error: {0}")]
+    FunctionError(#[from] crate::functions::FunctionError),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
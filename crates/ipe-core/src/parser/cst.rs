@@ -0,0 +1,529 @@
+//! Lossless concrete syntax tree
+//!
+//! Unlike the AST, the CST keeps every character of the source. Each token
+//! carries the whitespace, line breaks and comments around it as trivia, so
+//! printing a tree reproduces its input exactly. Nodes group tokens into the
+//! items and clauses of a file (a policy, its `triggers` clause, a metadata
+//! field); expressions stay flat token sequences. The tree is built for any
+//! input, valid or not, and is what the [formatter](super::format) works on.
+
+use super::lexer::Lexer;
+use super::token::TokenKind;
+use std::fmt;
+
+/// Source text between tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace(String),
+    Newline,
+    /// A comment, including the leading `#`
+    Comment(String),
+}
+
+impl fmt::Display for Trivia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trivia::Whitespace(text) | Trivia::Comment(text) => f.write_str(text),
+            Trivia::Newline => f.write_str("\n"),
+        }
+    }
+}
+
+/// A token with the trivia around it
+///
+/// `trailing` holds the whitespace and comment after the token on the same
+/// line; everything else before the next token is that token's `leading`
+/// trivia.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    /// The token exactly as written
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+    /// Byte offset of `text`
+    pub offset: usize,
+}
+
+impl SyntaxToken {
+    /// Check if a line break precedes the token
+    pub fn starts_line(&self) -> bool {
+        self.leading.contains(&Trivia::Newline)
+    }
+
+    /// Check if an empty line precedes the token
+    pub fn follows_blank_line(&self) -> bool {
+        let lines: Vec<_> =
+            self.leading.iter().filter(|t| !matches!(t, Trivia::Whitespace(_))).collect();
+        lines
+            .windows(2)
+            .any(|pair| *pair[0] == Trivia::Newline && *pair[1] == Trivia::Newline)
+    }
+}
+
+/// The kind of a [`SyntaxNode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Module,
+    Package,
+    Const,
+    Predicate,
+    Policy,
+    PolicySet,
+    /// `policy Name:` or `policy_set Name:`
+    Header,
+    Intent,
+    Let,
+    Triggers,
+    Requires,
+    Where,
+    /// `denies`, with an optional `with reason "..."`
+    Denies,
+    /// `with obligation ...` or `with advice ...`
+    With,
+    Combining,
+    Metadata,
+    /// `key: value` inside `metadata`
+    Field,
+    /// The `{ ... }` of a policy set
+    Members,
+}
+
+/// A child of a [`SyntaxNode`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A node of the concrete syntax tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        Self { kind, children: Vec::new() }
+    }
+
+    /// Child nodes
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// All tokens of the node, in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// The first token of the node
+    pub fn first_token(&self) -> Option<&SyntaxToken> {
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.first_token(),
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+}
+
+/// Prints the exact source the tree was built from
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                write!(f, "{}", trivia)?;
+            }
+            f.write_str(&token.text)?;
+            for trivia in &token.trailing {
+                write!(f, "{}", trivia)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build the concrete syntax tree of a source file
+pub fn parse_syntax(source: &str) -> SyntaxNode {
+    let mut builder = Builder { tokens: tokenize(source), position: 0 };
+    builder.module()
+}
+
+/// Lex `source` into tokens with trivia; line breaks become trivia
+fn tokenize(source: &str) -> Vec<SyntaxToken> {
+    let mut tokens: Vec<SyntaxToken> = Vec::new();
+    let mut leading = Vec::new();
+    let mut end = 0;
+
+    for token in Lexer::new(source).tokenize() {
+        // Line breaks are tokens, so the text between two tokens is only
+        // whitespace and comments on one line
+        let trivia = split_trivia(&source[end..token.offset]);
+        match tokens.last_mut() {
+            Some(previous) if leading.is_empty() => previous.trailing = trivia,
+            _ => leading.extend(trivia),
+        }
+        end = token.end();
+
+        if token.kind == TokenKind::Newline {
+            leading.push(Trivia::Newline);
+            continue;
+        }
+        tokens.push(SyntaxToken {
+            text: source[token.offset..token.end()].to_string(),
+            kind: token.kind,
+            leading: std::mem::take(&mut leading),
+            trailing: Vec::new(),
+            offset: token.offset,
+        });
+    }
+    tokens
+}
+
+/// Split whitespace followed by an optional comment into trivia
+fn split_trivia(text: &str) -> Vec<Trivia> {
+    let (space, comment) = text.split_at(text.find('#').unwrap_or(text.len()));
+    let mut trivia = Vec::new();
+    if !space.is_empty() {
+        trivia.push(Trivia::Whitespace(space.to_string()));
+    }
+    if !comment.is_empty() {
+        trivia.push(Trivia::Comment(comment.to_string()));
+    }
+    trivia
+}
+
+/// Groups the token stream into nodes
+struct Builder {
+    tokens: Vec<SyntaxToken>,
+    position: usize,
+}
+
+impl Builder {
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.position].kind
+    }
+
+    fn peek_next(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position + 1).map(|t| &t.kind)
+    }
+
+    fn at_end(&self) -> bool {
+        *self.peek() == TokenKind::Eof
+    }
+
+    fn bump(&mut self, node: &mut SyntaxNode) {
+        node.children.push(SyntaxElement::Token(self.tokens[self.position].clone()));
+        self.position += 1;
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.position == 0 || self.tokens[self.position].starts_line()
+    }
+
+    /// Check if the current token starts a top-level item
+    fn at_item(&self) -> bool {
+        matches!(
+            self.peek(),
+            TokenKind::Package
+                | TokenKind::Const
+                | TokenKind::Predicate
+                | TokenKind::Policy
+                | TokenKind::PolicySet
+        ) && self.at_line_start()
+    }
+
+    fn module(&mut self) -> SyntaxNode {
+        let mut module = SyntaxNode::new(SyntaxKind::Module);
+        while !self.at_end() {
+            let item = match self.peek() {
+                TokenKind::Package => self.simple_item(SyntaxKind::Package),
+                TokenKind::Const => self.simple_item(SyntaxKind::Const),
+                TokenKind::Predicate => self.simple_item(SyntaxKind::Predicate),
+                TokenKind::Policy => self.policy(),
+                TokenKind::PolicySet => self.policy_set(),
+                _ => {
+                    self.bump(&mut module);
+                    continue;
+                },
+            };
+            module.children.push(SyntaxElement::Node(item));
+        }
+        self.bump(&mut module); // end of file
+        module
+    }
+
+    /// An item that runs until the next one
+    fn simple_item(&mut self, kind: SyntaxKind) -> SyntaxNode {
+        let mut node = SyntaxNode::new(kind);
+        self.bump(&mut node);
+        while !self.at_end() && !self.at_item() {
+            self.bump(&mut node);
+        }
+        node
+    }
+
+    /// `policy Name:` or `policy_set Name:` and the intent
+    fn header(&mut self, parent: &mut SyntaxNode) {
+        let mut header = SyntaxNode::new(SyntaxKind::Header);
+        self.bump(&mut header);
+        while !self.at_end() && !self.at_item() {
+            let colon = *self.peek() == TokenKind::Colon;
+            self.bump(&mut header);
+            if colon {
+                break;
+            }
+        }
+        parent.children.push(SyntaxElement::Node(header));
+
+        if matches!(self.peek(), TokenKind::StringLit(_)) {
+            let mut intent = SyntaxNode::new(SyntaxKind::Intent);
+            self.bump(&mut intent);
+            parent.children.push(SyntaxElement::Node(intent));
+        }
+    }
+
+    fn policy(&mut self) -> SyntaxNode {
+        let mut policy = SyntaxNode::new(SyntaxKind::Policy);
+        self.header(&mut policy);
+
+        while !self.at_end() && !self.at_item() && *self.peek() != TokenKind::RBrace {
+            let kind = match self.peek() {
+                TokenKind::Let => SyntaxKind::Let,
+                TokenKind::Triggers => SyntaxKind::Triggers,
+                TokenKind::Requires => SyntaxKind::Requires,
+                TokenKind::Where => SyntaxKind::Where,
+                TokenKind::Denies => SyntaxKind::Denies,
+                TokenKind::With => SyntaxKind::With,
+                TokenKind::Metadata => {
+                    let metadata = self.metadata();
+                    policy.children.push(SyntaxElement::Node(metadata));
+                    continue;
+                },
+                _ => {
+                    self.bump(&mut policy);
+                    continue;
+                },
+            };
+            let clause = self.clause(kind);
+            policy.children.push(SyntaxElement::Node(clause));
+        }
+        policy
+    }
+
+    /// A clause runs until the next clause keyword outside brackets
+    fn clause(&mut self, kind: SyntaxKind) -> SyntaxNode {
+        let mut clause = SyntaxNode::new(kind);
+        self.bump(&mut clause);
+
+        let mut depth = 0usize;
+        while !self.at_end() && !self.at_item() {
+            match self.peek() {
+                TokenKind::LParen | TokenKind::LBracket => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket => depth = depth.saturating_sub(1),
+                TokenKind::RBrace if depth == 0 => break,
+                // `denies with reason` belongs to the `denies` clause
+                TokenKind::With
+                    if depth == 0
+                        && !(kind == SyntaxKind::Denies
+                            && self.peek_next() == Some(&TokenKind::Reason)) =>
+                {
+                    break
+                },
+                TokenKind::Let
+                | TokenKind::Triggers
+                | TokenKind::Requires
+                | TokenKind::Where
+                | TokenKind::Denies
+                | TokenKind::Metadata
+                    if depth == 0 =>
+                {
+                    break
+                },
+                _ => {},
+            }
+            self.bump(&mut clause);
+        }
+        clause
+    }
+
+    /// `metadata` followed by one `key: value` field per line
+    fn metadata(&mut self) -> SyntaxNode {
+        let mut metadata = SyntaxNode::new(SyntaxKind::Metadata);
+        self.bump(&mut metadata);
+
+        while !self.at_end()
+            && (matches!(self.peek(), TokenKind::Ident(_)) || self.peek().is_literal())
+            && self.peek_next() == Some(&TokenKind::Colon)
+        {
+            let mut field = SyntaxNode::new(SyntaxKind::Field);
+            self.bump(&mut field);
+            while !self.at_end() && !self.at_line_start() {
+                self.bump(&mut field);
+            }
+            metadata.children.push(SyntaxElement::Node(field));
+        }
+        metadata
+    }
+
+    fn policy_set(&mut self) -> SyntaxNode {
+        let mut set = SyntaxNode::new(SyntaxKind::PolicySet);
+        self.header(&mut set);
+
+        while !self.at_end() && !self.at_item() {
+            match self.peek() {
+                TokenKind::Combining => {
+                    let mut combining = SyntaxNode::new(SyntaxKind::Combining);
+                    self.bump(&mut combining);
+                    if matches!(self.peek(), TokenKind::StringLit(_)) {
+                        self.bump(&mut combining);
+                    }
+                    set.children.push(SyntaxElement::Node(combining));
+                },
+                TokenKind::Metadata => {
+                    let metadata = self.metadata();
+                    set.children.push(SyntaxElement::Node(metadata));
+                },
+                TokenKind::LBrace => {
+                    let members = self.members();
+                    set.children.push(SyntaxElement::Node(members));
+                    break;
+                },
+                _ => self.bump(&mut set),
+            }
+        }
+        set
+    }
+
+    fn members(&mut self) -> SyntaxNode {
+        let mut members = SyntaxNode::new(SyntaxKind::Members);
+        self.bump(&mut members); // `{`
+
+        while !self.at_end() {
+            match self.peek() {
+                TokenKind::Policy => {
+                    let policy = self.policy();
+                    members.children.push(SyntaxElement::Node(policy));
+                },
+                TokenKind::RBrace => {
+                    self.bump(&mut members);
+                    break;
+                },
+                _ if self.at_item() => break,
+                _ => self.bump(&mut members),
+            }
+        }
+        members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "",
+            "# only a comment",
+            "policy A: \"a\"  # trailing\n\n  triggers when x == -1\t\r\n  denies\n",
+            "const X = [\"a\", \"b\"]\n\n\n# about P\npredicate p(a): a > 1h30m\n",
+            "policy_set S: \"s\" {\n  policy A: \"é\\\"\"\n    triggers when 1\n    denies\n}",
+            "policy A: \"unterminated\n  ) $ \"",
+        ];
+        for source in sources {
+            assert_eq!(parse_syntax(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_trivia() {
+        let tree = parse_syntax("policy A: # note\n  # about the intent\n  \"a\"\n");
+        let tokens = tree.tokens();
+
+        let colon = tokens[2];
+        assert_eq!(
+            colon.trailing,
+            vec![Trivia::Whitespace(" ".into()), Trivia::Comment("# note".into())]
+        );
+        let intent = tokens[3];
+        assert!(intent.starts_line());
+        assert!(!intent.follows_blank_line());
+        assert!(intent.leading.contains(&Trivia::Comment("# about the intent".into())));
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_structure() {
+        let source = r#"
+const LIMIT = 3
+policy A:
+  "a"
+  triggers when resource.kind == "a"
+    and resource.count <= LIMIT
+  requires resource.ok == true
+  where resource.owner != ""
+  with obligation log(level: "info")
+  metadata
+    owner: "sre"
+    severity: high
+
+policy_set S: "s"
+combining "first-applicable"
+{
+  policy B: "b"
+    triggers when true
+    denies with reason "no"
+}
+"#;
+        let tree = parse_syntax(source);
+        let kinds = |node: &SyntaxNode| node.nodes().map(|n| n.kind).collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds(&tree),
+            vec![SyntaxKind::Const, SyntaxKind::Policy, SyntaxKind::PolicySet]
+        );
+        let policy = tree.nodes().nth(1).unwrap();
+        assert_eq!(
+            kinds(policy),
+            vec![
+                SyntaxKind::Header,
+                SyntaxKind::Intent,
+                SyntaxKind::Triggers,
+                SyntaxKind::Requires,
+                SyntaxKind::Where,
+                SyntaxKind::With,
+                SyntaxKind::Metadata,
+            ]
+        );
+        let metadata = policy.nodes().last().unwrap();
+        assert_eq!(kinds(metadata), vec![SyntaxKind::Field, SyntaxKind::Field]);
+
+        let set = tree.nodes().nth(2).unwrap();
+        assert_eq!(
+            kinds(set),
+            vec![
+                SyntaxKind::Header,
+                SyntaxKind::Intent,
+                SyntaxKind::Combining,
+                SyntaxKind::Members
+            ]
+        );
+        let member = set.nodes().last().unwrap().nodes().next().unwrap();
+        assert_eq!(kinds(member).last(), Some(&SyntaxKind::Denies));
+    }
+}
@@ -0,0 +1,451 @@
+//! Canonical formatting of policy source
+//!
+//! [`format_source`] lays a file out again from its [CST](super::cst): one
+//! clause per line with two-space indentation, single spaces around operators,
+//! conditions broken before each top-level `and` / `or`, and metadata fields
+//! sorted by key. Literals are kept as written (`1h30m` stays `1h30m`).
+//!
+//! Comments are preserved: a comment on its own line stays above the token it
+//! preceded, and a trailing comment stays at the end of the line its token
+//! ends up on. Formatting is idempotent and does not change what the file
+//! parses to.
+
+use super::cst::{parse_syntax, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, Trivia};
+use super::parse::{parse_module, ParseResult};
+use super::token::TokenKind;
+
+const INDENT: usize = 2;
+
+/// Format a source file in the canonical layout
+///
+/// Fails with the first parse error if the source is not a valid module.
+pub fn format_source(source: &str) -> ParseResult<String> {
+    parse_module(source)?;
+
+    let mut formatter = Formatter::default();
+    formatter.module(&parse_syntax(source));
+    Ok(formatter.out)
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    /// The line being written, without indentation
+    line: String,
+    indent: usize,
+    /// Comments to append to the line being written
+    comments: Vec<String>,
+    /// The last token on the line being written
+    previous: Option<TokenKind>,
+    /// Whether the last token was a unary `-`
+    after_unary: bool,
+}
+
+impl Formatter {
+    fn module(&mut self, module: &SyntaxNode) {
+        let mut previous: Option<SyntaxKind> = None;
+        for child in &module.children {
+            match child {
+                SyntaxElement::Node(item) => {
+                    // Runs of definitions keep their grouping; anything else
+                    // is separated by an empty line
+                    let grouped = previous.is_some_and(is_definition)
+                        && is_definition(item.kind)
+                        && !item.first_token().is_some_and(SyntaxToken::follows_blank_line);
+                    if grouped {
+                        self.end_line();
+                    } else if previous.is_some() {
+                        self.blank_line();
+                    }
+                    self.item(item, 0);
+                    previous = Some(item.kind);
+                },
+                SyntaxElement::Token(token) if token.kind == TokenKind::Eof => {
+                    if token.follows_blank_line() && has_comments(token) {
+                        self.blank_line();
+                    }
+                    self.end_line();
+                    self.leading(token, 0);
+                },
+                SyntaxElement::Token(token) => {
+                    self.start(0);
+                    self.token(token);
+                },
+            }
+        }
+        self.end_line();
+    }
+
+    fn item(&mut self, item: &SyntaxNode, indent: usize) {
+        match item.kind {
+            SyntaxKind::Policy => self.policy(item, indent),
+            SyntaxKind::PolicySet => self.policy_set(item, indent),
+            _ => self.line_of(item, indent, None),
+        }
+    }
+
+    fn policy(&mut self, policy: &SyntaxNode, indent: usize) {
+        let body = indent + INDENT;
+        for child in &policy.children {
+            let SyntaxElement::Node(clause) = child else {
+                self.stray(child, body);
+                continue;
+            };
+            match clause.kind {
+                SyntaxKind::Header => self.line_of(clause, indent, None),
+                SyntaxKind::Intent => self.line_of(clause, body, None),
+                SyntaxKind::Metadata => self.metadata(clause, body),
+                // `where` qualifies the `requires` clause above it
+                SyntaxKind::Where => self.line_of(clause, body + INDENT, Some(body + 2 * INDENT)),
+                _ => self.line_of(clause, body, Some(body + INDENT)),
+            }
+        }
+    }
+
+    fn policy_set(&mut self, set: &SyntaxNode, indent: usize) {
+        for child in &set.children {
+            let SyntaxElement::Node(node) = child else {
+                self.stray(child, indent);
+                continue;
+            };
+            match node.kind {
+                // `policy_set Name: "intent"` on one line
+                SyntaxKind::Header => {
+                    self.start(indent);
+                    self.tokens(&node.tokens(), None);
+                },
+                SyntaxKind::Intent => {
+                    self.tokens(&node.tokens(), None);
+                    self.end_line();
+                },
+                SyntaxKind::Metadata => self.metadata(node, indent),
+                SyntaxKind::Members => self.members(node, indent),
+                _ => self.line_of(node, indent, None),
+            }
+        }
+    }
+
+    fn members(&mut self, members: &SyntaxNode, indent: usize) {
+        let mut first = true;
+        for child in &members.children {
+            match child {
+                SyntaxElement::Node(policy) => {
+                    if !first {
+                        self.blank_line();
+                    }
+                    self.policy(policy, indent + INDENT);
+                    first = false;
+                },
+                SyntaxElement::Token(token) if token.kind == TokenKind::RBrace => {
+                    // Comments before the brace belong with the members
+                    self.end_line();
+                    self.leading(token, indent + INDENT);
+                    self.start(indent);
+                    self.text(token);
+                    self.end_line();
+                },
+                SyntaxElement::Token(token) => {
+                    self.start(indent);
+                    self.token(token);
+                    self.end_line();
+                },
+            }
+        }
+    }
+
+    fn metadata(&mut self, metadata: &SyntaxNode, indent: usize) {
+        let mut fields = Vec::new();
+        for child in &metadata.children {
+            match child {
+                SyntaxElement::Node(field) => fields.push(field),
+                SyntaxElement::Token(token) => {
+                    self.start(indent);
+                    self.token(token);
+                    self.end_line();
+                },
+            }
+        }
+
+        fields.sort_by_key(|field| field.first_token().map(|key| key.text.as_str()));
+        for field in fields {
+            self.line_of(field, indent + INDENT, None);
+        }
+    }
+
+    /// Write a node on a line of its own, breaking before top-level `and` and
+    /// `or` at `breaks` if given
+    fn line_of(&mut self, node: &SyntaxNode, indent: usize, breaks: Option<usize>) {
+        self.start(indent);
+        self.tokens(&node.tokens(), breaks);
+        self.end_line();
+    }
+
+    fn stray(&mut self, element: &SyntaxElement, indent: usize) {
+        if let SyntaxElement::Token(token) = element {
+            self.start(indent);
+            self.token(token);
+            self.end_line();
+        }
+    }
+
+    fn tokens(&mut self, tokens: &[&SyntaxToken], breaks: Option<usize>) {
+        let mut depth = 0usize;
+        for token in tokens {
+            match token.kind {
+                TokenKind::And | TokenKind::Or if depth == 0 && !self.line.is_empty() => {
+                    if let Some(indent) = breaks {
+                        self.start(indent);
+                    }
+                },
+                TokenKind::RParen | TokenKind::RBracket => depth = depth.saturating_sub(1),
+                _ => {},
+            }
+            self.token(token);
+            if matches!(token.kind, TokenKind::LParen | TokenKind::LBracket) {
+                depth += 1;
+            }
+        }
+    }
+
+    fn token(&mut self, token: &SyntaxToken) {
+        self.leading(token, self.indent);
+        self.text(token);
+    }
+
+    /// Write the comments before `token`: on lines of their own at `indent`
+    /// when nothing has been written on the current line yet, otherwise at
+    /// the end of the current line
+    fn leading(&mut self, token: &SyntaxToken, indent: usize) {
+        for trivia in &token.leading {
+            if let Trivia::Comment(comment) = trivia {
+                if self.line.is_empty() && self.comments.is_empty() {
+                    self.out.push_str(&" ".repeat(indent));
+                    self.out.push_str(comment.trim_end());
+                    self.out.push('\n');
+                } else {
+                    self.comments.push(comment.trim_end().to_string());
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, token: &SyntaxToken) {
+        if let Some(previous) = &self.previous {
+            if !self.after_unary && space_between(previous, &token.kind) {
+                self.line.push(' ');
+            }
+        }
+        self.after_unary =
+            token.kind == TokenKind::Minus && !self.previous.as_ref().is_some_and(ends_operand);
+        self.line.push_str(&token.text);
+        // A keyword after `.` is a path segment: `resource.package - 1`
+        self.previous = Some(match &self.previous {
+            Some(TokenKind::Dot) if token.kind.is_keyword() => TokenKind::Ident(token.text.clone()),
+            _ => token.kind.clone(),
+        });
+
+        for trivia in &token.trailing {
+            if let Trivia::Comment(comment) = trivia {
+                self.comments.push(comment.trim_end().to_string());
+            }
+        }
+    }
+
+    /// Begin a new line at `indent`
+    fn start(&mut self, indent: usize) {
+        self.end_line();
+        self.indent = indent;
+    }
+
+    fn end_line(&mut self) {
+        if self.line.is_empty() && self.comments.is_empty() {
+            return;
+        }
+        let mut line = std::mem::take(&mut self.line);
+        for comment in self.comments.drain(..) {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&comment);
+        }
+        self.out.push_str(&" ".repeat(self.indent));
+        self.out.push_str(&line);
+        self.out.push('\n');
+        self.previous = None;
+        self.after_unary = false;
+    }
+
+    fn blank_line(&mut self) {
+        self.end_line();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+}
+
+fn is_definition(kind: SyntaxKind) -> bool {
+    matches!(kind, SyntaxKind::Const | SyntaxKind::Predicate)
+}
+
+fn has_comments(token: &SyntaxToken) -> bool {
+    token.leading.iter().any(|t| matches!(t, Trivia::Comment(_)))
+}
+
+/// Check if a token can end an operand, so a `-` after it is binary
+fn ends_operand(kind: &TokenKind) -> bool {
+    kind.is_literal()
+        || matches!(kind, TokenKind::Ident(_) | TokenKind::RParen | TokenKind::RBracket)
+}
+
+fn space_between(previous: &TokenKind, next: &TokenKind) -> bool {
+    match (previous, next) {
+        (_, TokenKind::RParen | TokenKind::RBracket | TokenKind::Comma | TokenKind::Dot) => false,
+        (_, TokenKind::Colon) => false,
+        (TokenKind::LParen | TokenKind::LBracket | TokenKind::Dot, _) => false,
+        // A call: `name(...)`, `matches(...)`, `contains(...)`
+        (TokenKind::Ident(_) | TokenKind::Matches | TokenKind::Contains, TokenKind::LParen) => {
+            false
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::nodes::{Item, Metadata};
+    use regex::Regex;
+
+    #[test]
+    fn test_format_layout() {
+        let source = r#"
+package   acme.deploy
+const MAX=6
+predicate owned_by( team ):resource.owner==team
+# Production only
+policy Deploy : "Deploys need an owner"  # why
+  let replicas=resource.replicas??1
+  triggers when resource.kind=="deployment" and resource.env in ["prod","qa"]
+  requires owned_by( "sre" ) and replicas<=MAX or -1>resource.package-1
+    where approver.role=="lead"
+  with obligation log( level:"info" )
+  metadata
+    severity: high
+    # The team to page
+    owner: "sre"
+policy_set Group: "group" combining "deny-overrides" {
+  policy Inner: "inner"
+    triggers when now()-resource.created_at>1h30m
+    denies
+    with reason "too old"
+  # end of group
+}
+"#;
+        let expected = r#"package acme.deploy
+
+const MAX = 6
+predicate owned_by(team): resource.owner == team
+
+# Production only
+policy Deploy:
+  "Deploys need an owner" # why
+  let replicas = resource.replicas ?? 1
+  triggers when resource.kind == "deployment"
+    and resource.env in ["prod", "qa"]
+  requires owned_by("sre")
+    and replicas <= MAX
+    or -1 > resource.package - 1
+    where approver.role == "lead"
+  with obligation log(level: "info")
+  metadata
+    # The team to page
+    owner: "sre"
+    severity: high
+
+policy_set Group: "group"
+combining "deny-overrides"
+{
+  policy Inner:
+    "inner"
+    triggers when now() - resource.created_at > 1h30m
+    denies with reason "too old"
+  # end of group
+}
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_comments_and_errors() {
+        assert_eq!(format_source("").unwrap(), "");
+        assert_eq!(format_source("# notes\n\n\n# more\n").unwrap(), "# notes\n# more\n");
+
+        let source = "const A = 1\n\n# B is big\nconst B = 2\nconst C = 3 # small\n\n# the end";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "const A = 1\n\n# B is big\nconst B = 2\nconst C = 3 # small\n\n# the end\n"
+        );
+
+        assert!(format_source("policy A: \"a\"\n  triggers when\n").is_err());
+    }
+
+    /// Every module in the parser's tests
+    fn corpus() -> Vec<&'static str> {
+        include_str!("parse.rs")
+            .split("r#\"")
+            .skip(1)
+            .filter_map(|rest| rest.split("\"#").next())
+            .filter(|source| parse_module(source).is_ok())
+            .collect()
+    }
+
+    fn comments(source: &str) -> Vec<String> {
+        let mut comments: Vec<_> = parse_syntax(source)
+            .tokens()
+            .into_iter()
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .filter_map(|trivia| match trivia {
+                Trivia::Comment(comment) => Some(comment.trim_end().to_string()),
+                _ => None,
+            })
+            .collect();
+        comments.sort();
+        comments
+    }
+
+    #[test]
+    fn test_format_corpus() {
+        let locations = Regex::new(r"SourceLocation \{[^}]*\}").unwrap();
+        let sort = |metadata: &mut Option<Metadata>| {
+            if let Some(metadata) = metadata {
+                metadata.fields.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+        };
+        // Compare modules up to source locations and metadata order
+        let ast = |source: &str| {
+            let mut module = parse_module(source).unwrap();
+            for item in &mut module.items {
+                if let Item::PolicySet(set) = item {
+                    sort(&mut set.metadata);
+                }
+            }
+            module.policies_mut().for_each(|p| sort(&mut p.metadata));
+            locations.replace_all(&format!("{:?}", module), "_").into_owned()
+        };
+
+        let corpus = corpus();
+        assert!(corpus.len() >= 10);
+        for source in corpus {
+            let formatted = format_source(source).unwrap();
+            assert_eq!(
+                format_source(&formatted).unwrap(),
+                formatted,
+                "not idempotent:\n{}",
+                source
+            );
+            assert_eq!(ast(&formatted), ast(source), "changed meaning:\n{}", source);
+            assert_eq!(comments(&formatted), comments(source), "lost comments:\n{}", source);
+        }
+    }
+}
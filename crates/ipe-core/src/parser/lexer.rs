@@ -10,6 +10,8 @@ pub struct Lexer {
     position: usize,
     line: usize,
     column: usize,
+    /// Byte offset of `position`
+    offset: usize,
 }
 
 impl Lexer {
@@ -20,14 +22,27 @@ impl Lexer {
             position: 0,
             line: 1,
             column: 1,
+            offset: 0,
         }
     }
 
     /// Get the next token
     pub fn next_token(&mut self) -> Token {
-        // Skip whitespace (except newlines)
+        // Skip whitespace (except newlines) and comments
         self.skip_whitespace();
+        while !self.is_at_end() && self.current_char() == '#' {
+            self.skip_comment();
+            self.skip_whitespace();
+        }
+
+        let offset = self.offset;
+        let mut token = self.scan_token();
+        token.offset = offset;
+        token.length = self.offset - offset;
+        token
+    }
 
+    fn scan_token(&mut self) -> Token {
         // Save position for token
         let start_line = self.line;
         let start_column = self.column;
@@ -45,12 +60,6 @@ impl Lexer {
             return Token::new(TokenKind::Newline, "\n".to_string(), start_line, start_column);
         }
 
-        // Handle comments
-        if ch == '#' {
-            self.skip_comment();
-            return self.next_token(); // Get next token after comment
-        }
-
         // Handle strings
         if ch == '"' {
             return self.lex_string();
@@ -103,6 +112,7 @@ impl Lexer {
     fn advance(&mut self) -> char {
         let ch = self.current_char();
         self.position += 1;
+        self.offset += ch.len_utf8();
 
         if ch == '\n' {
             self.line += 1;
@@ -189,7 +199,119 @@ impl Lexer {
         )
     }
 
+    /// Whether an RFC 3339 timestamp (`2024-01-15T...`) starts here
+    fn at_timestamp(&self) -> bool {
+        let shape = "dddd-dd-ddT";
+        self.input.len() >= self.position + shape.len()
+            && shape.chars().zip(&self.input[self.position..]).all(|(s, &c)| match s {
+                'd' => c.is_ascii_digit(),
+                'T' => c == 'T' || c == 't',
+                s => c == s,
+            })
+    }
+
+    fn lex_timestamp(&mut self) -> Token {
+        let start_line = self.line;
+        let start_column = self.column;
+
+        // Date and `T` (checked by `at_timestamp`), then the time with an optional fraction
+        let mut text = String::new();
+        for _ in 0.."dddd-dd-ddT".len() {
+            text.push(self.advance());
+        }
+        while !self.is_at_end()
+            && (self.current_char().is_ascii_digit() || matches!(self.current_char(), ':' | '.'))
+        {
+            text.push(self.advance());
+        }
+
+        // Offset: `Z` or `+HH:MM` / `-HH:MM`
+        if !self.is_at_end() {
+            match self.current_char() {
+                'Z' | 'z' => text.push(self.advance()),
+                '+' | '-' => {
+                    text.push(self.advance());
+                    while !self.is_at_end()
+                        && (self.current_char().is_ascii_digit() || self.current_char() == ':')
+                    {
+                        text.push(self.advance());
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        match chrono::DateTime::parse_from_rfc3339(&text) {
+            Ok(dt) if !self.at_word_char() => {
+                Token::new(TokenKind::TimestampLit(dt.timestamp()), text, start_line, start_column)
+            },
+            _ => Token::new(
+                TokenKind::Error(format!("Invalid RFC 3339 timestamp: {}", text)),
+                text,
+                start_line,
+                start_column,
+            ),
+        }
+    }
+
+    /// Read a duration whose first amount is `text`; the current char is its unit
+    fn lex_duration(&mut self, start_line: usize, start_column: usize, mut text: String) -> Token {
+        let mut seconds = Some(0i64);
+        let mut amount = text.parse::<i64>().ok();
+        let mut complete = true;
+
+        // Each amount needs a unit; `1h30m` chains them
+        loop {
+            let unit = match (!self.is_at_end()).then(|| self.current_char()) {
+                Some('s') => 1,
+                Some('m') => 60,
+                Some('h') => 3_600,
+                Some('d') => 86_400,
+                Some('w') => 604_800,
+                _ => {
+                    complete = false;
+                    break;
+                },
+            };
+            text.push(self.advance());
+            seconds = match (seconds, amount) {
+                (Some(total), Some(n)) => n.checked_mul(unit).and_then(|n| total.checked_add(n)),
+                _ => None,
+            };
+
+            if self.is_at_end() || !self.current_char().is_ascii_digit() {
+                break;
+            }
+            let digits = text.len();
+            while !self.is_at_end() && self.current_char().is_ascii_digit() {
+                text.push(self.advance());
+            }
+            amount = text[digits..].parse().ok();
+        }
+
+        match seconds {
+            Some(seconds) if complete && !self.at_word_char() => {
+                Token::new(TokenKind::DurationLit(seconds), text, start_line, start_column)
+            },
+            _ => Token::new(
+                TokenKind::Error(format!("Invalid duration literal: {}", text)),
+                text,
+                start_line,
+                start_column,
+            ),
+        }
+    }
+
+    /// Whether an identifier character follows
+    fn at_word_char(&self) -> bool {
+        !self.is_at_end() && (self.current_char().is_alphanumeric() || self.current_char() == '_')
+    }
+
     fn lex_number(&mut self) -> Token {
+        if self.at_timestamp() {
+            return self.lex_timestamp();
+        }
+
         let start_line = self.line;
         let start_column = self.column;
 
@@ -201,6 +323,11 @@ impl Lexer {
             number_str.push(self.advance());
         }
 
+        // Duration: digits directly followed by a unit (`30m`, `7d`)
+        if !self.is_at_end() && matches!(self.current_char(), 's' | 'm' | 'h' | 'd' | 'w') {
+            return self.lex_duration(start_line, start_column, number_str);
+        }
+
         // Check for decimal point
         if !self.is_at_end() && self.current_char() == '.' {
             if let Some(next_ch) = self.peek_char() {
@@ -270,6 +397,17 @@ impl Lexer {
             "or" => TokenKind::Or,
             "not" => TokenKind::Not,
             "in" => TokenKind::In,
+            "matches" => TokenKind::Matches,
+            "like" => TokenKind::Like,
+            "contains" => TokenKind::Contains,
+            "intersects" => TokenKind::Intersects,
+            "subset_of" => TokenKind::SubsetOf,
+            "package" => TokenKind::Package,
+            "policy_set" => TokenKind::PolicySet,
+            "combining" => TokenKind::Combining,
+            "const" => TokenKind::Const,
+            "predicate" => TokenKind::Predicate,
+            "let" => TokenKind::Let,
             "true" => TokenKind::BoolLit(true),
             "false" => TokenKind::BoolLit(false),
             _ => TokenKind::Ident(ident.clone()),
@@ -306,6 +444,10 @@ impl Lexer {
                     self.advance();
                     Some(TokenKind::GtEq)
                 },
+                "??" => {
+                    self.advance();
+                    Some(TokenKind::Default)
+                },
                 _ => None,
             };
 
@@ -318,7 +460,13 @@ impl Lexer {
         let kind = match ch {
             '<' => TokenKind::Lt,
             '>' => TokenKind::Gt,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
             ':' => TokenKind::Colon,
+            '=' => TokenKind::Assign,
             ',' => TokenKind::Comma,
             '.' => TokenKind::Dot,
             '(' => TokenKind::LParen,
@@ -377,6 +525,45 @@ mod tests {
         assert_eq!(token_kinds(&tokens), expected);
     }
 
+    #[test]
+    fn test_module_keywords() {
+        let input = "package policy_set combining policy_sets";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+
+        let expected = vec![
+            TokenKind::Package,
+            TokenKind::PolicySet,
+            TokenKind::Combining,
+            TokenKind::Ident("policy_sets".to_string()),
+            TokenKind::Eof,
+        ];
+
+        assert_eq!(token_kinds(&tokens), expected);
+    }
+
+    #[test]
+    fn test_definition_keywords() {
+        let input = "const MAX = 3\nlet x == y";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+
+        let expected = vec![
+            TokenKind::Const,
+            TokenKind::Ident("MAX".to_string()),
+            TokenKind::Assign,
+            TokenKind::IntLit(3),
+            TokenKind::Newline,
+            TokenKind::Let,
+            TokenKind::Ident("x".to_string()),
+            TokenKind::Eq,
+            TokenKind::Ident("y".to_string()),
+            TokenKind::Eof,
+        ];
+
+        assert_eq!(token_kinds(&tokens), expected);
+    }
+
     #[test]
     fn test_operators() {
         let input = "== != < > <= >=";
@@ -396,6 +583,17 @@ mod tests {
         assert_eq!(token_kinds(&tokens), expected);
     }
 
+    #[test]
+    fn test_arithmetic_operators() {
+        let tokens = Lexer::new("a+1 - b*2/c % 3").tokenize();
+        let kinds = token_kinds(&tokens);
+        assert_eq!(kinds[1], TokenKind::Plus);
+        assert_eq!(kinds[3], TokenKind::Minus);
+        assert_eq!(kinds[5], TokenKind::Star);
+        assert_eq!(kinds[7], TokenKind::Slash);
+        assert_eq!(kinds[9], TokenKind::Percent);
+    }
+
     #[test]
     fn test_punctuation() {
         let input = ": , . ( ) [ ] { }";
@@ -647,6 +845,48 @@ mod tests {
         assert_eq!(tokens[2].kind, TokenKind::Ident("field".to_string()));
     }
 
+    #[test]
+    fn test_duration_literals() {
+        let tokens = Lexer::new("30s 15m 1h30m 7d 2w 0s").tokenize();
+        assert_eq!(
+            token_kinds(&tokens),
+            vec![
+                TokenKind::DurationLit(30),
+                TokenKind::DurationLit(900),
+                TokenKind::DurationLit(5_400),
+                TokenKind::DurationLit(604_800),
+                TokenKind::DurationLit(1_209_600),
+                TokenKind::DurationLit(0),
+                TokenKind::Eof,
+            ]
+        );
+
+        for bad in ["5min", "1h30", "99999999999999999w"] {
+            let token = Lexer::new(bad).next_token();
+            assert!(
+                matches!(&token.kind, TokenKind::Error(msg) if msg.contains("Invalid duration")),
+                "{} lexed as {:?}",
+                bad,
+                token.kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_timestamp_literals() {
+        let tokens = Lexer::new("2024-01-15T14:30:00Z 2024-01-15T16:30:00.5+02:00").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::TimestampLit(1_705_329_000));
+        assert_eq!(tokens[0].text, "2024-01-15T14:30:00Z");
+        assert_eq!(tokens[1].kind, TokenKind::TimestampLit(1_705_329_000));
+
+        // A date alone is a subtraction, a malformed timestamp an error
+        let tokens = Lexer::new("2024-01-15").tokenize();
+        assert_eq!(tokens[0].kind, TokenKind::IntLit(2024));
+        assert_eq!(tokens[1].kind, TokenKind::Minus);
+        let token = Lexer::new("2024-13-15T00:00:00Z").next_token();
+        assert!(matches!(token.kind, TokenKind::Error(msg) if msg.contains("RFC 3339")));
+    }
+
     #[test]
     fn test_string_with_newline() {
         let input = "\"line1\nline2\"";
@@ -686,7 +926,7 @@ mod tests {
 
     #[test]
     fn test_multiple_errors() {
-        let input = "@ $ %";
+        let input = "@ $ ^";
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize();
 
@@ -786,4 +1026,16 @@ mod tests {
         assert_eq!(tokens[1].kind, TokenKind::Newline);
         assert_eq!(tokens[2].kind, TokenKind::Requires);
     }
+
+    #[test]
+    fn test_token_offsets() {
+        let input = "# comment\nname == \"é\\\"x\" 42";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+
+        let spans: Vec<_> = tokens.iter().map(|t| &input[t.offset..t.end()]).collect();
+        assert_eq!(spans, vec!["\n", "name", "==", "\"é\\\"x\"", "42", ""]);
+        assert_eq!((tokens[3].line, tokens[3].column), (2, 9));
+        assert_eq!(tokens[4].column, 16);
+    }
 }
@@ -2,10 +2,17 @@
 //!
 //! This module implements parsing for the Idempotent Predicate Engine language.
 
+pub mod cst;
+pub mod format;
 pub mod lexer;
 pub mod parse;
+mod resolve;
 pub mod token;
 
+pub use cst::{parse_syntax, SyntaxNode};
+pub use format::format_source;
 pub use lexer::Lexer;
-pub use parse::{ParseError, ParseResult, Parser};
+pub use parse::{
+    parse_module, parse_module_with_diagnostics, resolve_module, ParseError, ParseResult, Parser,
+};
 pub use token::{Token, TokenKind};
@@ -1,11 +1,16 @@
 //! Parser implementation for IPE policies
 
 use super::lexer::Lexer;
-use super::token::{Token, TokenKind};
+use super::resolve::{error_location, Resolver};
+use super::token::{Token, TokenKind, KEYWORDS};
 use crate::ast::nodes::{
-    BinaryOp, ComparisonOp, Condition, Expression, Metadata, Policy, Requirements, SourceLocation,
-    Value,
+    AggregateFunc, ArithmeticOp, BinaryOp, ComparisonOp, Condition, Definition, DefinitionKind,
+    Expression, FileId, Item, Metadata, Module, Obligation, Policy, PolicySet, Requirements,
+    SetOperator, SourceLocation, Value,
 };
+use crate::combining::CombiningAlgorithm;
+use crate::diagnostics::{suggest, Diagnostic};
+use std::collections::HashSet;
 use thiserror::Error;
 
 #[cfg(test)]
@@ -25,14 +30,69 @@ pub enum ParseError {
 
     #[error("Invalid policy structure: {0}")]
     InvalidPolicy(String),
+
+    #[error("'{name}' on line {line} is already defined on line {previous}")]
+    DuplicateDefinition { name: String, line: usize, previous: usize },
+
+    #[error("Cyclic definition: {0}")]
+    CyclicDefinition(String),
+
+    #[error("Invalid definition '{name}' on line {line}: {message}")]
+    InvalidDefinition { name: String, line: usize, message: String },
 }
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// Parse a source file containing any number of policies and policy sets
+pub fn parse_module(source: &str) -> ParseResult<Module> {
+    Parser::new(source).parse_file()
+}
+
+/// Parse a source file, collecting every error and warning instead of
+/// stopping at the first error
+pub fn parse_module_with_diagnostics(source: &str, file: FileId) -> (Module, Vec<Diagnostic>) {
+    Parser::new(source).with_file(file).parse_file_with_diagnostics()
+}
+
+/// Inline definitions into a module built by another front-end and check
+/// that policy names are unique, as [`parse_module`] does
+///
+/// Each policy's `let` bindings must be in its `bindings`, not yet inlined,
+/// and policy set members must already carry the set's metadata.
+pub fn resolve_module(mut module: Module) -> ParseResult<Module> {
+    let mut parser = Parser::new("");
+    for policy in module.policies_mut() {
+        let bindings = std::mem::take(&mut policy.bindings);
+        parser.resolve_bindings(policy, bindings)?;
+    }
+
+    parser.check_module(&mut module);
+    match parser.first_error.take() {
+        Some(error) => Err(error),
+        None => Ok(module),
+    }
+}
+
+/// Tokens a top-level item starts with, where parsing resumes after an error
+const ITEM_KEYWORDS: &[TokenKind] = &[
+    TokenKind::Package,
+    TokenKind::Policy,
+    TokenKind::PolicySet,
+    TokenKind::Const,
+    TokenKind::Predicate,
+];
+
 /// Parser for IPE policies
 pub struct Parser {
     tokens: Vec<Token>,
     position: usize,
+    file: FileId,
+    /// Errors and warnings reported so far
+    diagnostics: Vec<Diagnostic>,
+    /// The first error reported, returned by `parse_file`
+    first_error: Option<ParseError>,
+    /// Where the pending error points, when that is not the current token
+    error_location: Option<SourceLocation>,
 }
 
 impl Parser {
@@ -40,7 +100,292 @@ impl Parser {
     pub fn new(source: &str) -> Self {
         let mut lexer = Lexer::new(source);
         let tokens = lexer.tokenize();
-        Self { tokens, position: 0 }
+        Self {
+            tokens,
+            position: 0,
+            file: FileId::default(),
+            diagnostics: Vec::new(),
+            first_error: None,
+            error_location: None,
+        }
+    }
+
+    /// Builder: set the file source locations refer to
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Parse a complete source file
+    ///
+    /// A file starts with an optional `package` declaration followed by any
+    /// number of `const` and `predicate` definitions and `policy` and
+    /// `policy_set` blocks. Policy names must be unique within the file, and
+    /// definitions are inlined into every policy.
+    pub fn parse_file(&mut self) -> ParseResult<Module> {
+        let module = self.parse_items();
+        match self.first_error.take() {
+            Some(error) => Err(error),
+            None => Ok(module),
+        }
+    }
+
+    /// Parse a complete source file, recovering from errors
+    ///
+    /// After an error the parser skips to the next top-level item (or the
+    /// next policy of a `policy_set`), so a single pass reports every error.
+    /// The module holds everything that parsed.
+    pub fn parse_file_with_diagnostics(&mut self) -> (Module, Vec<Diagnostic>) {
+        let module = self.parse_items();
+        (module, std::mem::take(&mut self.diagnostics))
+    }
+
+    fn parse_items(&mut self) -> Module {
+        let mut module = Module::default();
+        self.skip_newlines();
+
+        if self.check_keyword(TokenKind::Package) {
+            let start = self.position;
+            self.advance(); // consume 'package'
+            match self.parse_qualified_name() {
+                Ok(name) => module.package = Some(name),
+                Err(error) => self.recover(error, start, ITEM_KEYWORDS),
+            }
+        }
+
+        loop {
+            self.skip_newlines();
+            if self.is_at_end() {
+                break;
+            }
+
+            let start = self.position;
+            let result = match self.current().kind {
+                TokenKind::PolicySet => {
+                    self.parse_policy_set().map(|s| module.items.push(Item::PolicySet(s)))
+                },
+                TokenKind::Policy => {
+                    self.parse_policy().map(|p| module.items.push(Item::Policy(p)))
+                },
+                TokenKind::Const => {
+                    self.parse_binding(TokenKind::Const).map(|d| module.definitions.push(d))
+                },
+                TokenKind::Predicate => self.parse_predicate().map(|d| module.definitions.push(d)),
+                _ => Err(ParseError::UnexpectedToken {
+                    expected: "'policy', 'policy_set', 'const' or 'predicate'".to_string(),
+                    got: format!("{}", self.current().kind),
+                }),
+            };
+            if let Err(error) = result {
+                self.recover(error, start, ITEM_KEYWORDS);
+            }
+        }
+
+        self.check_module(&mut module);
+        module
+    }
+
+    /// Inline definitions and reject duplicate policy names
+    fn check_module(&mut self, module: &mut Module) {
+        self.resolve_module(module);
+
+        let mut names = HashSet::new();
+        for policy in module.policies() {
+            if !names.insert(policy.name.as_str()) {
+                let error =
+                    ParseError::InvalidPolicy(format!("Duplicate policy name '{}'", policy.name));
+                self.report(error, policy.location.clone());
+            }
+        }
+    }
+
+    /// Inline the module's definitions into every policy
+    fn resolve_module(&mut self, module: &mut Module) {
+        if let Err(error) = Resolver::new(&module.definitions).and_then(|mut r| r.check()) {
+            let location = error_location(&error, &module.definitions).unwrap_or_default();
+            self.report(error, location);
+            return;
+        }
+
+        let mut used = HashSet::new();
+        for policy in module.items.iter_mut().flat_map(Item::policies_mut) {
+            // A `let` may not shadow a module definition
+            let bindings = std::mem::take(&mut policy.bindings);
+            let result = Resolver::new(module.definitions.iter().chain(&bindings)).and_then(
+                |mut resolver| {
+                    resolver.resolve_policy(policy)?;
+                    used.extend(resolver.used().map(str::to_string));
+                    Ok(())
+                },
+            );
+            if let Err(error) = result {
+                let location = error_location(&error, module.definitions.iter().chain(&bindings))
+                    .unwrap_or_else(|| policy.location.clone());
+                self.report(error, location);
+            }
+            policy.bindings = bindings;
+        }
+
+        // Policies that failed to parse may have used the other definitions
+        if self.first_error.is_none() {
+            for definition in &module.definitions {
+                if !used.contains(&definition.name) {
+                    self.diagnostics.push(Self::unused(definition));
+                }
+            }
+        }
+    }
+
+    fn unused(definition: &Definition) -> Diagnostic {
+        Diagnostic::warning(
+            format!("Unused {} '{}'", definition.kind, definition.name),
+            definition.location.clone(),
+        )
+        .with_label("never referenced")
+    }
+
+    /// Parse `const NAME = expr` or `let name = expr`
+    fn parse_binding(&mut self, keyword: TokenKind) -> ParseResult<Definition> {
+        let start = self.current().clone();
+        let kind = match keyword {
+            TokenKind::Const => DefinitionKind::Const,
+            _ => DefinitionKind::Let,
+        };
+        self.expect_keyword(keyword)?;
+
+        let name = self.expect_identifier()?;
+        self.expect_token(TokenKind::Assign)?;
+        let body = self.parse_expression()?;
+
+        Ok(Definition::new(kind, name, body).with_location(self.location(&start, start.length)))
+    }
+
+    /// Parse `predicate name: expr` or `predicate name(a, b): expr`
+    fn parse_predicate(&mut self) -> ParseResult<Definition> {
+        let start = self.current().clone();
+        self.expect_keyword(TokenKind::Predicate)?;
+
+        let name = self.expect_identifier()?;
+        let mut params = Vec::new();
+        if self.check_token(TokenKind::LParen) {
+            self.advance();
+            while !self.check_token(TokenKind::RParen) {
+                params.push(self.expect_identifier()?);
+                if !self.check_token(TokenKind::RParen) {
+                    self.expect_token(TokenKind::Comma)?;
+                }
+            }
+            self.advance(); // consume ')'
+        }
+
+        self.expect_token(TokenKind::Colon)?;
+        self.skip_newlines();
+        let body = self.parse_expression()?;
+
+        Ok(Definition::new(DefinitionKind::Predicate, name, body)
+            .with_params(params)
+            .with_location(self.location(&start, start.length)))
+    }
+
+    /// Parse a `policy_set` block
+    ///
+    /// ```text
+    /// policy_set Name: "intent"
+    /// combining "first-applicable"
+    /// metadata
+    ///   owner: "platform"
+    /// {
+    ///   policy ...
+    /// }
+    /// ```
+    fn parse_policy_set(&mut self) -> ParseResult<PolicySet> {
+        let start = self.current().clone();
+        self.expect_keyword(TokenKind::PolicySet)?;
+
+        let name = self.expect_identifier()?;
+        self.expect_token(TokenKind::Colon)?;
+        self.skip_newlines();
+        let intent = self.expect_string()?;
+        self.skip_newlines();
+
+        let combining = if self.check_keyword(TokenKind::Combining) {
+            self.advance(); // consume 'combining'
+            let algorithm = self.expect_string()?;
+            self.skip_newlines();
+            if CombiningAlgorithm::from_name(&algorithm).is_none() {
+                return Err(ParseError::InvalidPolicy(format!(
+                    "Unknown combining algorithm '{}'",
+                    algorithm
+                )));
+            }
+            Some(algorithm)
+        } else {
+            None
+        };
+
+        let metadata = if self.check_keyword(TokenKind::Metadata) {
+            Some(self.parse_metadata()?)
+        } else {
+            None
+        };
+
+        self.skip_newlines();
+        self.expect_token(TokenKind::LBrace)?;
+
+        let mut policies = Vec::new();
+        loop {
+            self.skip_newlines();
+
+            if self.check_token(TokenKind::RBrace) {
+                self.advance();
+                break;
+            } else if self.is_at_end() {
+                return Err(ParseError::UnexpectedEof);
+            }
+
+            let member_start = self.position;
+            match self.parse_policy() {
+                Ok(mut policy) => {
+                    if let Some(shared) = &metadata {
+                        Self::inherit_metadata(&mut policy, shared);
+                    }
+                    policies.push(policy);
+                },
+                Err(error) => {
+                    self.recover(error, member_start, &[TokenKind::Policy, TokenKind::RBrace])
+                },
+            }
+        }
+
+        Ok(PolicySet {
+            name,
+            intent,
+            combining,
+            metadata,
+            policies,
+            location: self.location(&start, start.length),
+        })
+    }
+
+    /// Copy shared fields into a member policy's metadata (the policy's own fields win)
+    fn inherit_metadata(policy: &mut Policy, shared: &Metadata) {
+        let metadata = policy.metadata.get_or_insert_with(Metadata::new);
+        for (key, value) in &shared.fields {
+            if metadata.get(key).is_none() {
+                metadata.fields.push((key.clone(), value.clone()));
+            }
+        }
+    }
+
+    /// Parse a dotted name such as `acme.deployments`
+    fn parse_qualified_name(&mut self) -> ParseResult<String> {
+        let mut name = self.expect_identifier()?;
+        while self.check_token(TokenKind::Dot) {
+            self.advance();
+            name.push('.');
+            name.push_str(&self.expect_identifier()?);
+        }
+        Ok(name)
     }
 
     /// Parse a complete policy
@@ -49,6 +394,7 @@ impl Parser {
         self.skip_newlines();
 
         // Expect "policy"
+        let start = self.current().clone();
         self.expect_keyword(TokenKind::Policy)?;
 
         // Parse name
@@ -66,6 +412,13 @@ impl Parser {
         // Skip newlines
         self.skip_newlines();
 
+        // Parse optional let bindings
+        let mut bindings = Vec::new();
+        while self.check_keyword(TokenKind::Let) {
+            bindings.push(self.parse_binding(TokenKind::Let)?);
+            self.skip_newlines();
+        }
+
         // Parse triggers
         let triggers = self.parse_triggers()?;
 
@@ -78,6 +431,9 @@ impl Parser {
         // Skip newlines
         self.skip_newlines();
 
+        // Parse optional obligations and advice
+        let (obligations, advice) = self.parse_obligations()?;
+
         // Parse optional metadata
         let metadata = if self.check_keyword(TokenKind::Metadata) {
             Some(self.parse_metadata()?)
@@ -85,14 +441,46 @@ impl Parser {
             None
         };
 
-        Ok(Policy {
+        let mut policy = Policy {
             name,
             intent,
             triggers,
             requirements,
+            obligations,
+            advice,
             metadata,
-            location: SourceLocation::default(),
-        })
+            bindings: Vec::new(),
+            location: self.location(&start, start.length),
+        };
+        self.resolve_bindings(&mut policy, bindings)?;
+
+        Ok(policy)
+    }
+
+    /// Inline a policy's `let` bindings, warning about unused ones
+    fn resolve_bindings(
+        &mut self,
+        policy: &mut Policy,
+        bindings: Vec<Definition>,
+    ) -> ParseResult<()> {
+        let resolved = Resolver::new(&bindings).and_then(|mut resolver| {
+            resolver.check()?;
+            resolver.resolve_policy(policy)?;
+            Ok(bindings
+                .iter()
+                .filter(|binding| !resolver.is_used(&binding.name))
+                .map(Self::unused)
+                .collect::<Vec<_>>())
+        });
+        match resolved {
+            Ok(warnings) => self.diagnostics.extend(warnings),
+            Err(error) => {
+                self.error_location = error_location(&error, &bindings);
+                return Err(error);
+            },
+        }
+        policy.bindings = bindings;
+        Ok(())
     }
 
     fn parse_triggers(&mut self) -> ParseResult<Vec<Condition>> {
@@ -103,8 +491,7 @@ impl Parser {
         let mut triggers = Vec::new();
 
         loop {
-            let expr = self.parse_expression()?;
-            triggers.push(Condition::new(expr));
+            triggers.push(self.parse_condition()?);
 
             self.skip_newlines();
 
@@ -125,6 +512,18 @@ impl Parser {
         Ok(triggers)
     }
 
+    /// Parse a trigger or requirement, recording where it appears in the source
+    fn parse_condition(&mut self) -> ParseResult<Condition> {
+        let start = self.current().clone();
+        let expr = self.parse_expression()?;
+
+        // Span up to the end of the last token when the condition fits on one line
+        let last = &self.tokens[self.position.saturating_sub(1)];
+        let length = if last.line == start.line { last.end() - start.offset } else { start.length };
+
+        Ok(Condition::new(expr).with_location(self.location(&start, length)))
+    }
+
     fn parse_requirements(&mut self) -> ParseResult<Requirements> {
         if self.check_keyword(TokenKind::Requires) {
             self.advance(); // consume 'requires'
@@ -133,8 +532,7 @@ impl Parser {
             let mut conditions = Vec::new();
 
             loop {
-                let expr = self.parse_expression()?;
-                conditions.push(Condition::new(expr));
+                conditions.push(self.parse_condition()?);
 
                 self.skip_newlines();
 
@@ -149,8 +547,7 @@ impl Parser {
 
                     let mut where_conditions = Vec::new();
                     loop {
-                        let expr = self.parse_expression()?;
-                        where_conditions.push(Condition::new(expr));
+                        where_conditions.push(self.parse_condition()?);
 
                         self.skip_newlines();
 
@@ -174,13 +571,14 @@ impl Parser {
             self.skip_newlines();
 
             // Check for optional "with reason"
-            let reason = if self.check_keyword(TokenKind::With) {
-                self.advance();
-                self.expect_keyword(TokenKind::Reason)?;
-                Some(self.expect_string()?)
-            } else {
-                None
-            };
+            let reason =
+                if self.check_keyword(TokenKind::With) && self.check_next(TokenKind::Reason) {
+                    self.advance();
+                    self.expect_keyword(TokenKind::Reason)?;
+                    Some(self.expect_string()?)
+                } else {
+                    None
+                };
 
             Ok(Requirements::denies(reason))
         } else {
@@ -188,6 +586,48 @@ impl Parser {
         }
     }
 
+    /// Parse `with obligation <name>[(key: value, ...)]` and `with advice ...` clauses
+    fn parse_obligations(&mut self) -> ParseResult<(Vec<Obligation>, Vec<Obligation>)> {
+        let mut obligations = Vec::new();
+        let mut advice = Vec::new();
+
+        while self.check_keyword(TokenKind::With) {
+            self.advance();
+            let kind = self.expect_identifier()?;
+            let target = match kind.as_str() {
+                "obligation" => &mut obligations,
+                "advice" => &mut advice,
+                _ => {
+                    return Err(ParseError::UnexpectedToken {
+                        expected: "obligation or advice".to_string(),
+                        got: kind,
+                    })
+                },
+            };
+
+            let mut obligation = Obligation::new(self.expect_identifier()?);
+            if self.check_token(TokenKind::LParen) {
+                self.advance();
+                while !self.check_token(TokenKind::RParen) {
+                    let key = self.expect_identifier()?;
+                    self.expect_token(TokenKind::Colon)?;
+                    obligation = obligation.with_param(key, self.parse_value()?);
+
+                    if !self.check_token(TokenKind::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect_token(TokenKind::RParen)?;
+            }
+            target.push(obligation);
+
+            self.skip_newlines();
+        }
+
+        Ok((obligations, advice))
+    }
+
     fn parse_metadata(&mut self) -> ParseResult<Metadata> {
         self.expect_keyword(TokenKind::Metadata)?;
         self.skip_newlines();
@@ -256,38 +696,113 @@ impl Parser {
         let left = self.parse_in_expression()?;
 
         // Check for comparison operator
-        if let Some(op) = self.parse_comparison_op() {
-            self.advance();
-            let right = self.parse_in_expression()?;
-            Ok(Expression::binary(left, BinaryOp::Comparison(op), right))
-        } else {
-            Ok(left)
-        }
+        let op = match self.current().kind {
+            TokenKind::Matches => BinaryOp::Matches,
+            TokenKind::Like => BinaryOp::Like,
+            _ => match self.parse_comparison_op() {
+                Some(op) => BinaryOp::Comparison(op),
+                None => return Ok(left),
+            },
+        };
+        self.advance();
+        let right = self.parse_in_expression()?;
+        Ok(Expression::binary(left, op, right))
     }
 
+    /// Parse the set operators: `in`, `not in`, `contains`, `intersects`, `subset_of`
     fn parse_in_expression(&mut self) -> ParseResult<Expression> {
-        let expr = self.parse_primary()?;
+        let expr = self.parse_additive()?;
 
-        if self.check_keyword(TokenKind::In) {
+        // `x not in y` is `not (x in y)`
+        if self.check_keyword(TokenKind::Not) && self.check_next(TokenKind::In) {
             self.advance();
-            self.expect_token(TokenKind::LBracket)?;
+            return Ok(Expression::logical_not(self.parse_in_rest(expr)?));
+        }
 
-            let mut values = Vec::new();
-            loop {
-                values.push(self.parse_value()?);
+        let op = match self.current().kind {
+            TokenKind::In => return self.parse_in_rest(expr),
+            TokenKind::Contains => SetOperator::Contains,
+            TokenKind::Intersects => SetOperator::Intersects,
+            TokenKind::SubsetOf => SetOperator::SubsetOf,
+            _ => return Ok(expr),
+        };
+        self.advance();
+        let right = self.parse_additive()?;
+        Ok(Expression::binary(expr, BinaryOp::Set(op), right))
+    }
 
-                if self.check_token(TokenKind::Comma) {
-                    self.advance();
-                } else {
-                    break;
-                }
+    /// Parse `in <collection>` after `expr`
+    ///
+    /// A literal list stays an [`Expression::In`]; any other collection (e.g.
+    /// `request.principal.roles`) is tested at runtime.
+    fn parse_in_rest(&mut self, expr: Expression) -> ParseResult<Expression> {
+        self.expect_keyword(TokenKind::In)?;
+
+        if !self.check_token(TokenKind::LBracket) {
+            let collection = self.parse_additive()?;
+            return Ok(Expression::binary(expr, BinaryOp::Set(SetOperator::In), collection));
+        }
+
+        self.advance();
+        let mut values = Vec::new();
+        loop {
+            values.push(self.parse_value()?);
+
+            if self.check_token(TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
             }
+        }
 
-            self.expect_token(TokenKind::RBracket)?;
-            Ok(Expression::in_list(expr, values))
-        } else {
-            Ok(expr)
+        self.expect_token(TokenKind::RBracket)?;
+        Ok(Expression::in_list(expr, values))
+    }
+
+    /// Parse `a + b` and `a - b`, left to right
+    fn parse_additive(&mut self) -> ParseResult<Expression> {
+        let mut left = self.parse_multiplicative()?;
+
+        loop {
+            let op = match self.current().kind {
+                TokenKind::Plus => ArithmeticOp::Add,
+                TokenKind::Minus => ArithmeticOp::Sub,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            left = Expression::binary(left, BinaryOp::Arithmetic(op), right);
+        }
+    }
+
+    /// Parse `a * b`, `a / b` and `a % b`, left to right
+    fn parse_multiplicative(&mut self) -> ParseResult<Expression> {
+        let mut left = self.parse_default()?;
+
+        loop {
+            let op = match self.current().kind {
+                TokenKind::Star => ArithmeticOp::Mul,
+                TokenKind::Slash => ArithmeticOp::Div,
+                TokenKind::Percent => ArithmeticOp::Mod,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.parse_default()?;
+            left = Expression::binary(left, BinaryOp::Arithmetic(op), right);
+        }
+    }
+
+    /// Parse `a ?? b`, which binds tighter than arithmetic, comparisons and `in`
+    fn parse_default(&mut self) -> ParseResult<Expression> {
+        let mut left = self.parse_primary()?;
+
+        while self.check_token(TokenKind::Default) {
+            self.advance();
+            let right = self.parse_primary()?;
+            left = Expression::binary(left, BinaryOp::Default, right);
         }
+
+        Ok(left)
     }
 
     fn parse_primary(&mut self) -> ParseResult<Expression> {
@@ -299,7 +814,8 @@ impl Parser {
                 self.advance();
                 Ok(Expression::literal(Value::String(s)))
             },
-            TokenKind::IntLit(n) => {
+            // Durations and timestamps are seconds
+            TokenKind::IntLit(n) | TokenKind::DurationLit(n) | TokenKind::TimestampLit(n) => {
                 self.advance();
                 Ok(Expression::literal(Value::Int(n)))
             },
@@ -315,6 +831,16 @@ impl Parser {
             // Identifiers and paths
             TokenKind::Ident(_) => self.parse_path_or_call(),
 
+            // The `matches(text, pattern)` and `contains(text, part)` builtins, as
+            // opposed to the operators
+            TokenKind::Matches | TokenKind::Contains if self.check_next(TokenKind::LParen) => {
+                self.advance();
+                self.parse_call(token_kind.to_string())
+            },
+
+            // Array literals: `resource.tags intersects ["pci", "hipaa"]`
+            TokenKind::LBracket => Ok(Expression::literal(self.parse_value()?)),
+
             // Parenthesized expressions
             TokenKind::LParen => {
                 self.advance();
@@ -323,6 +849,22 @@ impl Parser {
                 Ok(expr)
             },
 
+            // Negation: `-2` is a literal, `-x` is `0 - x`
+            TokenKind::Minus => {
+                self.advance();
+                match self.parse_primary()? {
+                    Expression::Literal(Value::Int(n)) => Ok(Expression::literal(Value::Int(-n))),
+                    Expression::Literal(Value::Float(f)) => {
+                        Ok(Expression::literal(Value::Float(-f)))
+                    },
+                    operand => Ok(Expression::binary(
+                        Expression::literal(Value::Int(0)),
+                        BinaryOp::Arithmetic(ArithmeticOp::Sub),
+                        operand,
+                    )),
+                }
+            },
+
             // NOT operator
             TokenKind::Not => {
                 self.advance();
@@ -340,7 +882,7 @@ impl Parser {
         // Parse path segments
         while self.check_token(TokenKind::Dot) {
             self.advance();
-            segments.push(self.expect_identifier()?);
+            segments.push(self.expect_path_segment()?);
         }
 
         // Check for function call
@@ -350,27 +892,76 @@ impl Parser {
                     "Function calls cannot have path segments".to_string(),
                 ));
             }
+            self.parse_call(segments.remove(0))
+        } else {
+            Ok(Expression::path(segments))
+        }
+    }
 
-            self.advance();
-            let mut args = Vec::new();
+    /// Parse the `(args)` of a call to `name`
+    fn parse_call(&mut self, name: String) -> ParseResult<Expression> {
+        self.expect_token(TokenKind::LParen)?;
+        let mut args = Vec::new();
 
-            if !self.check_token(TokenKind::RParen) {
-                loop {
-                    args.push(self.parse_expression()?);
+        if !self.check_token(TokenKind::RParen) {
+            loop {
+                args.push(self.parse_expression()?);
 
-                    if self.check_token(TokenKind::Comma) {
-                        self.advance();
-                    } else {
-                        break;
-                    }
+                // `count(resource.items where <condition>)`
+                if args.len() == 1 && self.check_keyword(TokenKind::Where) {
+                    return self.parse_aggregate(&name, args.remove(0));
+                }
+
+                if self.check_token(TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
                 }
             }
+        }
 
-            self.expect_token(TokenKind::RParen)?;
-            Ok(Expression::Call { name: segments[0].clone(), args })
-        } else {
-            Ok(Expression::path(segments))
+        self.expect_token(TokenKind::RParen)?;
+
+        // An aggregate over a bare path counts every element: `count(resource.items)`
+        if let (Some(func), [Expression::Path(path)]) =
+            (AggregateFunc::from_name(&name), args.as_slice())
+        {
+            return Ok(Expression::Aggregate {
+                path: path.clone(),
+                func,
+                condition: Box::new(Condition::new(Expression::literal(Value::Bool(true)))),
+            });
         }
+
+        Ok(Expression::Call { name, args })
+    }
+
+    /// Parse the `where <condition>)` rest of an aggregate over `collection`
+    fn parse_aggregate(&mut self, name: &str, collection: Expression) -> ParseResult<Expression> {
+        let func = AggregateFunc::from_name(name).ok_or_else(|| {
+            ParseError::InvalidExpression(format!(
+                "{}() is not an aggregate and takes no 'where'",
+                name
+            ))
+        })?;
+        let Expression::Path(path) = collection else {
+            return Err(ParseError::InvalidExpression(format!(
+                "Aggregate {}() expects a collection path",
+                name
+            )));
+        };
+
+        self.expect_keyword(TokenKind::Where)?;
+        self.skip_newlines();
+        let condition = self.parse_expression()?;
+        self.skip_newlines();
+        self.expect_token(TokenKind::RParen)?;
+
+        Ok(Expression::Aggregate {
+            path,
+            func,
+            condition: Box::new(Condition::new(condition)),
+        })
     }
 
     fn parse_value(&mut self) -> ParseResult<Value> {
@@ -381,7 +972,7 @@ impl Parser {
                 self.advance();
                 Ok(Value::String(s))
             },
-            TokenKind::IntLit(n) => {
+            TokenKind::IntLit(n) | TokenKind::DurationLit(n) | TokenKind::TimestampLit(n) => {
                 self.advance();
                 Ok(Value::Int(n))
             },
@@ -393,6 +984,16 @@ impl Parser {
                 self.advance();
                 Ok(Value::Bool(b))
             },
+            TokenKind::Minus => {
+                self.advance();
+                match self.parse_value()? {
+                    Value::Int(n) => Ok(Value::Int(-n)),
+                    Value::Float(f) => Ok(Value::Float(-f)),
+                    other => {
+                        Err(ParseError::InvalidExpression(format!("Cannot negate {:?}", other)))
+                    },
+                }
+            },
             TokenKind::Ident(s) => {
                 self.advance();
                 Ok(Value::String(s))
@@ -438,6 +1039,56 @@ impl Parser {
         &self.tokens[self.position]
     }
 
+    /// Location of `length` bytes starting at `start`
+    fn location(&self, start: &Token, length: usize) -> SourceLocation {
+        SourceLocation::new(start.line, start.column, length).with_span(self.file, start.offset)
+    }
+
+    /// Report `error` and skip to the next line that starts with one of
+    /// `resume`, past the item that began at token `start`
+    fn recover(&mut self, error: ParseError, start: usize, resume: &[TokenKind]) {
+        let location = match self.error_location.take() {
+            Some(location) => location,
+            None => self.location(self.current(), self.current().length),
+        };
+        self.report(error, location);
+
+        while !self.is_at_end() {
+            if self.position > start
+                && self.tokens[self.position - 1].kind == TokenKind::Newline
+                && resume.contains(&self.current().kind)
+            {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    fn report(&mut self, error: ParseError, location: SourceLocation) {
+        let mut diagnostic = Diagnostic::error(error.to_string(), location);
+        if let ParseError::UnexpectedToken { expected, .. } = &error {
+            diagnostic = diagnostic.with_label(format!("expected {}", expected));
+        }
+        if let Some(keyword) = self.suggest_keyword(&error) {
+            diagnostic = diagnostic.with_help(format!("did you mean `{}`?", keyword));
+        }
+        self.diagnostics.push(diagnostic);
+        self.first_error.get_or_insert(error);
+    }
+
+    /// A keyword the error asks for that the current identifier looks like a typo of
+    fn suggest_keyword(&self, error: &ParseError) -> Option<&'static str> {
+        let TokenKind::Ident(word) = &self.current().kind else {
+            return None;
+        };
+        let message = error.to_string();
+        let wanted = KEYWORDS.iter().copied().filter(|keyword| {
+            message.contains(&format!("'{}'", keyword))
+                || matches!(error, ParseError::UnexpectedToken { expected, .. } if expected == keyword)
+        });
+        suggest(word, wanted)
+    }
+
     fn advance(&mut self) {
         if !self.is_at_end() {
             self.position += 1;
@@ -456,6 +1107,11 @@ impl Parser {
         self.check_token(kind)
     }
 
+    /// Check the token after the current one
+    fn check_next(&self, kind: TokenKind) -> bool {
+        self.tokens.get(self.position + 1).is_some_and(|t| t.kind == kind)
+    }
+
     fn skip_newlines(&mut self) {
         while self.check_token(TokenKind::Newline) {
             self.advance();
@@ -492,6 +1148,17 @@ impl Parser {
         }
     }
 
+    /// A path segment after `.`, where keywords are plain names: `resource.package`
+    fn expect_path_segment(&mut self) -> ParseResult<String> {
+        let kind = &self.current().kind;
+        if kind.is_keyword() {
+            let segment = kind.to_string();
+            self.advance();
+            return Ok(segment);
+        }
+        self.expect_identifier()
+    }
+
     fn expect_string(&mut self) -> ParseResult<String> {
         match &self.current().kind {
             TokenKind::StringLit(s) => {
@@ -573,6 +1240,95 @@ mod tests {
         }
     }
 
+    /// `resource.<keyword>.name` parses as a plain path
+    fn assert_keyword_segment(keyword: &str) {
+        let source = format!("resource.{}.name == 1", keyword);
+        match Parser::new(&source).parse_expression().unwrap() {
+            Expression::Binary { left, .. } => match *left {
+                Expression::Path(path) => assert_eq!(path.segments, ["resource", keyword, "name"]),
+                other => panic!("Expected path, got {:?}", other),
+            },
+            other => panic!("Expected binary expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_package_path_segment() {
+        assert_keyword_segment("package");
+    }
+
+    #[test]
+    fn test_parse_policy_set_path_segment() {
+        assert_keyword_segment("policy_set");
+    }
+
+    #[test]
+    fn test_parse_combining_path_segment() {
+        assert_keyword_segment("combining");
+    }
+
+    #[test]
+    fn test_parse_matches_path_segment() {
+        assert_keyword_segment("matches");
+    }
+
+    #[test]
+    fn test_parse_like_path_segment() {
+        assert_keyword_segment("like");
+    }
+
+    #[test]
+    fn test_parse_contains_path_segment() {
+        assert_keyword_segment("contains");
+    }
+
+    #[test]
+    fn test_parse_intersects_path_segment() {
+        assert_keyword_segment("intersects");
+    }
+
+    #[test]
+    fn test_parse_subset_of_path_segment() {
+        assert_keyword_segment("subset_of");
+    }
+
+    #[test]
+    fn test_parse_const_path_segment() {
+        assert_keyword_segment("const");
+    }
+
+    #[test]
+    fn test_parse_predicate_path_segment() {
+        assert_keyword_segment("predicate");
+    }
+
+    #[test]
+    fn test_parse_let_path_segment() {
+        assert_keyword_segment("let");
+    }
+
+    #[test]
+    fn test_parse_file_keyword_path_segments() {
+        let source = r#"
+package acme
+policy_set Modules: "Module policies"
+combining "deny-overrides"
+{
+  policy Core:
+    "Core packages need review"
+    triggers when resource.package == "core"
+    requires resource.policy_set.combining != "permit-overrides"
+}
+"#;
+        let module = parse_module(source).unwrap();
+        let policy = module.policies().next().unwrap();
+        assert!(matches!(
+            &policy.triggers[0].expr,
+            Expression::Binary { left, .. }
+                if matches!(&**left, Expression::Path(p) if p.segments == ["resource", "package"])
+        ));
+    }
+
     #[test]
     fn test_parse_binary_eq() {
         let mut parser = Parser::new("x == 42");
@@ -648,6 +1404,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_set_operators() {
+        let parse = |source: &str| Parser::new(source).parse_expression().unwrap();
+        let set_op = |expr: Expression| match expr {
+            Expression::Binary { op: BinaryOp::Set(op), right, .. } => (op, *right),
+            other => panic!("Expected set operator, got {:?}", other),
+        };
+
+        let (op, right) = set_op(parse(r#""admin" in request.principal.roles"#));
+        assert_eq!(op, SetOperator::In);
+        assert!(matches!(right, Expression::Path(_)));
+        let (op, _) = set_op(parse(r#"request.principal.roles contains "admin""#));
+        assert_eq!(op, SetOperator::Contains);
+        let (op, right) = set_op(parse(r#"resource.tags intersects ["pci", "hipaa"]"#));
+        assert_eq!(op, SetOperator::Intersects);
+        assert!(matches!(right, Expression::Literal(Value::Array(items)) if items.len() == 2));
+        let (op, _) = set_op(parse("resource.scopes subset_of request.principal.scopes"));
+        assert_eq!(op, SetOperator::SubsetOf);
+
+        // `not in` negates the membership, for literal lists and collections alike
+        let Expression::Logical { op: LogicalOp::Not, operands } = parse(r#"env not in ["dev"]"#)
+        else {
+            panic!("Expected NOT");
+        };
+        assert!(matches!(operands[0], Expression::In { .. }));
+        assert!(matches!(
+            parse(r#""x" not in resource.tags"#),
+            Expression::Logical { op: LogicalOp::Not, .. }
+        ));
+
+        // The `contains(text, part)` builtin is still a call
+        assert!(matches!(
+            parse(r#"contains(resource.name, "svc")"#),
+            Expression::Call { name, .. } if name == "contains"
+        ));
+    }
+
     #[test]
     fn test_parse_parenthesized() {
         let mut parser = Parser::new("(42)");
@@ -681,6 +1474,153 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_aggregate() {
+        let mut parser = Parser::new(r#"count(resource.approvers where role == "sre") >= 2"#);
+        let expr = parser.parse_expression().unwrap();
+        let Expression::Binary { left, .. } = expr else {
+            panic!("Expected comparison");
+        };
+        match *left {
+            Expression::Aggregate { path, func, condition } => {
+                assert_eq!(path.to_string(), "resource.approvers");
+                assert_eq!(func, AggregateFunc::Count);
+                assert!(matches!(condition.expr, Expression::Binary { .. }));
+            },
+            other => panic!("Expected aggregate, got {:?}", other),
+        }
+
+        // Without a condition every element is selected
+        let expr = Parser::new("any(resource.flags)").parse_expression().unwrap();
+        assert!(matches!(expr, Expression::Aggregate { func: AggregateFunc::Any, .. }));
+
+        assert!(Parser::new("lower(resource.name where x)").parse_expression().is_err());
+        assert!(Parser::new("count(\"x\" where true)").parse_expression().is_err());
+    }
+
+    #[test]
+    fn test_parse_clock_builtins() {
+        // The clock defaults are filled in against the function registry, so
+        // the calls parse as written
+        let call = |source: &str| match Parser::new(source).parse_expression().unwrap() {
+            Expression::Call { name, args } => (name, args.len()),
+            other => panic!("Expected call, got {:?}", other),
+        };
+        assert_eq!(call("now()"), ("now".to_string(), 0));
+        assert_eq!(call("hour_of_day()"), ("hour_of_day".to_string(), 0));
+        assert_eq!(call(r#"day_of_week("+02:00")"#), ("day_of_week".to_string(), 1));
+        assert_eq!(
+            call(r#"hour_of_day(resource.created_at, "UTC")"#),
+            ("hour_of_day".to_string(), 2)
+        );
+
+        // Durations and timestamps are plain seconds
+        let expr = Parser::new("now() - 2024-01-01T00:00:00Z < 1h30m").parse_expression().unwrap();
+        let Expression::Binary { right, .. } = expr else {
+            panic!("Expected comparison");
+        };
+        assert_eq!(*right, Expression::literal(Value::Int(5_400)));
+    }
+
+    #[test]
+    fn test_parse_default_operator() {
+        // `??` binds tighter than the comparison
+        let expr = Parser::new(r#"resource.tier ?? "free" == "free""#).parse_expression().unwrap();
+        let Expression::Binary { left, op, .. } = expr else {
+            panic!("Expected comparison");
+        };
+        assert_eq!(op, BinaryOp::Comparison(ComparisonOp::Eq));
+        assert!(matches!(*left, Expression::Binary { op: BinaryOp::Default, .. }));
+
+        // and chains left to right
+        let expr = Parser::new("a ?? b ?? 1").parse_expression().unwrap();
+        let Expression::Binary { left, op: BinaryOp::Default, right } = expr else {
+            panic!("Expected default");
+        };
+        assert!(matches!(*left, Expression::Binary { op: BinaryOp::Default, .. }));
+        assert_eq!(*right, Expression::literal(Value::Int(1)));
+    }
+
+    #[test]
+    fn test_parse_arithmetic_precedence() {
+        let arith = |left, op, right| Expression::binary(left, BinaryOp::Arithmetic(op), right);
+        let path = |name: &str| Expression::path(vec!["resource".to_string(), name.to_string()]);
+        let int = |n| Expression::literal(Value::Int(n));
+
+        // `*` binds tighter than `+`, which binds tighter than comparisons
+        let expr = Parser::new("resource.replicas * 2 + 1 <= resource.max")
+            .parse_expression()
+            .unwrap();
+        assert_eq!(
+            expr,
+            Expression::binary(
+                arith(
+                    arith(path("replicas"), ArithmeticOp::Mul, int(2)),
+                    ArithmeticOp::Add,
+                    int(1)
+                ),
+                BinaryOp::Comparison(ComparisonOp::LtEq),
+                path("max"),
+            )
+        );
+
+        // Left associative, with parentheses and `??` grouping first
+        let expr = Parser::new("10 - resource.a - (2 % 3) / resource.b ?? 1")
+            .parse_expression()
+            .unwrap();
+        assert_eq!(
+            expr,
+            arith(
+                arith(int(10), ArithmeticOp::Sub, path("a")),
+                ArithmeticOp::Sub,
+                arith(
+                    arith(int(2), ArithmeticOp::Mod, int(3)),
+                    ArithmeticOp::Div,
+                    Expression::binary(path("b"), BinaryOp::Default, int(1)),
+                ),
+            )
+        );
+
+        // Unary minus
+        assert_eq!(Parser::new("-5").parse_expression().unwrap(), int(-5));
+        assert_eq!(
+            Parser::new("-resource.a").parse_expression().unwrap(),
+            arith(int(0), ArithmeticOp::Sub, path("a"))
+        );
+        assert_eq!(
+            Parser::new("resource.a in [-1, 2]").parse_expression().unwrap(),
+            Expression::in_list(path("a"), vec![Value::Int(-1), Value::Int(2)])
+        );
+    }
+
+    #[test]
+    fn test_parse_pattern_operators() {
+        let expr = Parser::new(r#"resource.name matches "^svc-" and resource.path like "/tmp/*""#)
+            .parse_expression()
+            .unwrap();
+        let Expression::Logical { operands, .. } = expr else {
+            panic!("Expected and");
+        };
+        assert!(matches!(operands[0], Expression::Binary { op: BinaryOp::Matches, .. }));
+        assert!(matches!(operands[1], Expression::Binary { op: BinaryOp::Like, .. }));
+
+        // `matches(...)` is still the builtin
+        let expr = Parser::new(r#"matches(resource.name, "^svc-")"#).parse_expression().unwrap();
+        assert!(
+            matches!(expr, Expression::Call { ref name, ref args } if name == "matches" && args.len() == 2)
+        );
+
+        // After a dot, the operators are attribute names
+        let expr = Parser::new(r#"resource.like like resource.matches"#)
+            .parse_expression()
+            .unwrap();
+        let Expression::Binary { left, op: BinaryOp::Like, right } = expr else {
+            panic!("Expected like");
+        };
+        assert!(matches!(*left, Expression::Path(ref p) if p.segments == ["resource", "like"]));
+        assert!(matches!(*right, Expression::Path(ref p) if p.segments == ["resource", "matches"]));
+    }
+
     #[test]
     fn test_parse_complex_expression() {
         let mut parser = Parser::new("resource.type == \"Deployment\" and count >= 2");
@@ -1029,4 +1969,464 @@ policy DenyWithReason:
             _ => panic!("Expected denies clause"),
         }
     }
+
+    #[test]
+    fn test_parse_obligations() {
+        let source = r#"
+policy ProtectPii:
+  "Reads of customer records are audited and redacted"
+  triggers when resource.type == "Customer"
+  requires principal.department == "support"
+  with obligation log_to_audit
+  with obligation redact(field: "ssn", mask: "***")
+  with advice notify(channel: "security")
+  metadata
+    owner: "privacy"
+"#;
+        let policy = Parser::new(source).parse_policy().unwrap();
+
+        assert_eq!(policy.obligations.len(), 2);
+        assert_eq!(policy.obligations[0], Obligation::new("log_to_audit"));
+        assert_eq!(
+            policy.obligations[1],
+            Obligation::new("redact")
+                .with_param("field", Value::String("ssn".to_string()))
+                .with_param("mask", Value::String("***".to_string()))
+        );
+        assert_eq!(policy.advice.len(), 1);
+        assert_eq!(policy.advice[0].name, "notify");
+        assert!(policy.metadata.is_some());
+
+        let source = r#"
+policy Frozen:
+  "Frozen environments reject deployments"
+  triggers when resource.frozen == true
+  denies with reason "Environment is frozen"
+  with obligation require_mfa
+"#;
+        let policy = Parser::new(source).parse_policy().unwrap();
+        assert_eq!(
+            policy.requirements,
+            Requirements::Denies {
+                reason: Some("Environment is frozen".to_string())
+            }
+        );
+        assert_eq!(policy.obligations, vec![Obligation::new("require_mfa")]);
+
+        let source = r#"
+policy Bad:
+  "Unknown effect kind"
+  triggers when resource.frozen == true
+  denies
+  with penalty fine
+"#;
+        assert!(Parser::new(source).parse_policy().is_err());
+    }
+
+    #[test]
+    fn test_parse_file_multiple_policies() {
+        let source = r#"
+# Deployment policies
+package acme.deployments
+
+policy RequireApproval:
+  "Deployments need approval"
+  triggers when resource.kind == "deployment"
+  requires resource.approved == true
+
+policy DenyFrozen:
+  "Frozen environments reject deployments"
+  triggers when resource.frozen == true
+  denies with reason "Environment is frozen"
+  metadata
+    severity: "high"
+"#;
+        let module = parse_module(source).unwrap();
+
+        assert_eq!(module.package.as_deref(), Some("acme.deployments"));
+        let policies: Vec<_> = module.policies().collect();
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0].name, "RequireApproval");
+        assert_eq!(policies[1].name, "DenyFrozen");
+        assert!(policies[1].metadata.is_some());
+        assert_eq!(module.qualified_name("DenyFrozen"), "acme.deployments.DenyFrozen");
+    }
+
+    #[test]
+    fn test_parse_file_policy_set() {
+        let source = r#"
+policy_set Production: "Production deployment controls"
+combining "first-applicable"
+metadata
+  owner: "platform"
+{
+  policy AllowOnCall:
+    "On-call engineers may deploy"
+    triggers when resource.kind == "deployment"
+    requires principal.on_call == true
+    metadata
+      owner: "sre"
+
+  policy DenyOthers:
+    "Everyone else is denied"
+    triggers when resource.kind == "deployment"
+    denies
+}
+
+policy Standalone:
+  "Outside of any set"
+  triggers when resource.kind == "service"
+  requires resource.approved == true
+"#;
+        let module = parse_module(source).unwrap();
+
+        assert_eq!(module.package, None);
+        assert_eq!(module.policy_count(), 3);
+        assert_eq!(module.items.len(), 2);
+        assert!(matches!(&module.items[1], Item::Policy(p) if p.name == "Standalone"));
+
+        let set = module.policy_sets().next().unwrap();
+        assert_eq!(set.name, "Production");
+        assert_eq!(set.combining.as_deref(), Some("first-applicable"));
+        assert_eq!(set.policies.len(), 2);
+        assert_eq!(set.location.line, 2);
+
+        // Shared metadata is inherited, but a policy's own fields win
+        let own = set.policies[0].metadata.as_ref().unwrap();
+        assert_eq!(own.get("owner"), Some(&Value::String("sre".to_string())));
+        let inherited = set.policies[1].metadata.as_ref().unwrap();
+        assert_eq!(inherited.get("owner"), Some(&Value::String("platform".to_string())));
+    }
+
+    #[test]
+    fn test_parse_file_empty() {
+        let module = parse_module("\n# nothing here\n").unwrap();
+        assert_eq!(module.policy_count(), 0);
+    }
+
+    #[test]
+    fn test_parse_file_duplicate_policy_name() {
+        let source = r#"
+policy Same: "first"
+  triggers when resource.kind == "a"
+  requires resource.ok == true
+
+policy_set Group: "group" {
+  policy Same: "second"
+    triggers when resource.kind == "b"
+    denies
+}
+"#;
+        let result = parse_module(source);
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::InvalidPolicy("Duplicate policy name 'Same'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_file_errors() {
+        let unknown_algorithm = r#"
+policy_set Group: "group"
+combining "most-restrictive"
+{
+}
+"#;
+        assert!(matches!(
+            parse_module(unknown_algorithm),
+            Err(ParseError::InvalidPolicy(msg)) if msg.contains("most-restrictive")
+        ));
+
+        let unterminated = r#"
+policy_set Group: "group" {
+  policy A: "a"
+    triggers when resource.kind == "a"
+    denies
+"#;
+        assert_eq!(parse_module(unterminated), Err(ParseError::UnexpectedEof));
+
+        assert!(matches!(
+            parse_module("requires resource.ok == true"),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_definitions() {
+        let source = r#"
+const PROD_ENVS = ["prod", "prod-eu"]
+const MAX_REPLICAS = 10
+predicate is_prod_deploy: resource.type == "Deployment" and resource.env in PROD_ENVS
+predicate owned_by(team): resource.owner == team
+
+policy ProdDeploy: "Production deploys need an owner"
+  let replicas = resource.replicas ?? 1
+  triggers when is_prod_deploy
+  requires owned_by(request.principal.team) and replicas <= MAX_REPLICAS
+"#;
+        let module = parse_module(source).unwrap();
+        assert_eq!(module.definitions.len(), 4);
+        assert_eq!(module.definitions[3].kind, DefinitionKind::Predicate);
+        assert_eq!(module.definitions[3].params, vec!["team".to_string()]);
+
+        let policy = module.policies().next().unwrap();
+        assert_eq!(policy.bindings.len(), 1);
+        assert_eq!(policy.bindings[0].kind, DefinitionKind::Let);
+
+        // References are replaced by the definition bodies
+        let expected =
+            Parser::new(r#"resource.type == "Deployment" and resource.env in ["prod", "prod-eu"]"#)
+                .parse_expression()
+                .unwrap();
+        assert_eq!(policy.triggers[0].expr, expected);
+        assert_eq!(policy.triggers[0].location.line, 9);
+
+        let Requirements::Requires { conditions, .. } = &policy.requirements else {
+            panic!("Expected requires");
+        };
+        let expected = Parser::new(
+            "resource.owner == request.principal.team and (resource.replicas ?? 1) <= 10",
+        )
+        .parse_expression()
+        .unwrap();
+        assert_eq!(conditions[0].expr, expected);
+    }
+
+    #[test]
+    fn test_parse_definition_errors() {
+        let policy = "\npolicy P: \"p\"\n  triggers when a\n  denies\n";
+
+        assert_eq!(
+            parse_module(&format!("predicate a: b\npredicate b: a\n{}", policy)).unwrap_err(),
+            ParseError::CyclicDefinition("a -> b -> a".to_string())
+        );
+        assert_eq!(
+            parse_module(&format!("const a = 1\nconst a = 2\n{}", policy)).unwrap_err(),
+            ParseError::DuplicateDefinition {
+                name: "a".to_string(),
+                line: 2,
+                previous: 1
+            }
+        );
+
+        let err = parse_module("const LIMIT = resource.limit").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid definition 'LIMIT' on line 1: a const cannot depend on the request, but it \
+             reads 'resource.limit'"
+        );
+
+        let err = parse_module(
+            &format!("predicate owned_by(team): team == 1\n{}", policy)
+                .replace("when a", "when owned_by()"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("predicate expects 1 argument(s), got 0"));
+
+        // A let may not shadow a module definition
+        let shadowed =
+            format!("const a = 1\n{}", policy.replace("triggers", "let a = 2\n  triggers"));
+        assert!(matches!(
+            parse_module(&shadowed),
+            Err(ParseError::DuplicateDefinition { name, .. }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn test_parse_definition_path_prefixes() {
+        let source = r#"
+predicate owns(r): r.owner == request.principal.id
+predicate has_admin(roles): any(roles where name == "admin")
+
+policy P: "p"
+  let target = resource.spec
+  triggers when owns(resource) and has_admin(request.principal.roles)
+  requires target.replicas <= 3
+"#;
+        let module = parse_module(source).unwrap();
+        let policy = module.policies().next().unwrap();
+
+        let expected = Parser::new(
+            r#"resource.owner == request.principal.id and any(request.principal.roles where name == "admin")"#,
+        )
+        .parse_expression()
+        .unwrap();
+        assert_eq!(policy.triggers[0].expr, expected);
+
+        let Requirements::Requires { conditions, .. } = &policy.requirements else {
+            panic!("Expected requires");
+        };
+        let expected = Parser::new("resource.spec.replicas <= 3").parse_expression().unwrap();
+        assert_eq!(conditions[0].expr, expected);
+
+        // Values that are not attribute paths cannot be extended or aggregated
+        let policy = "\npolicy P: \"p\"\n  triggers when a\n  denies\n";
+        let err = parse_module(&format!(
+            "predicate owns(r): r.owner == 1\n{}",
+            policy.replace("when a", "when owns(1 + 1)")
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid definition 'owns' on line 1: the argument for 'r' is read as a path in \
+             'r.owner', so it must be an attribute path"
+        );
+
+        let err = parse_module(&format!(
+            "predicate has_admin(roles): any(roles where name == \"admin\")\n{}",
+            policy.replace("when a", r#"when has_admin(["admin"])"#)
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("the argument for 'roles' is aggregated over"));
+
+        let err = parse_module(&format!(
+            "const LIMITS = 3\n{}",
+            policy.replace("when a", "when LIMITS.cpu")
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid definition 'LIMITS' on line 1: 'LIMITS' is read as a path in 'LIMITS.cpu', \
+             so it must be an attribute path"
+        );
+    }
+
+    #[test]
+    fn test_parse_definition_reserved_names() {
+        let policy = "\npolicy P: \"p\"\n  triggers when request.principal.id == \"a\"\n  denies\n";
+        for (definitions, name) in [
+            ("const item = \"z\"", "item"),
+            ("predicate resource: true", "resource"),
+            ("predicate owned_by(action): action == 1", "action"),
+        ] {
+            let err = parse_module(&format!("{}\n{}", definitions, policy)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Invalid definition '{}' on line 1: '{}' is a reserved path root and \
+                     cannot be defined",
+                    definitions.split([' ', ':', '(']).nth(1).unwrap(),
+                    name
+                )
+            );
+        }
+
+        // A `let` cannot rebind a context root either
+        let shadowed = policy.replace("triggers", "let request = resource.meta\n  triggers");
+        assert!(matches!(
+            parse_module(&shadowed),
+            Err(ParseError::InvalidDefinition { name, .. }) if name == "request"
+        ));
+    }
+
+    #[test]
+    fn test_parse_predicate_argument_capture() {
+        let source = r#"
+predicate has_role(x): any(request.principal.roles where item == x)
+
+policy P: "p"
+  triggers when any(resource.tags where has_role(item))
+  denies
+"#;
+        let err = parse_module(source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid definition 'has_role' on line 2: the argument for 'x' reads the aggregate \
+             element 'item', which an aggregate in the predicate would capture"
+        );
+
+        // Arguments from the request context are not captured
+        let module =
+            parse_module(&source.replace("has_role(item)", "has_role(resource.owner)")).unwrap();
+        let expected = Parser::new(
+            "any(resource.tags where any(request.principal.roles where item == resource.owner))",
+        )
+        .parse_expression()
+        .unwrap();
+        assert_eq!(module.policies().next().unwrap().triggers[0].expr, expected);
+    }
+
+    #[test]
+    fn test_parse_file_recovers_errors() {
+        let source = r#"
+policy A: "a"
+  triggers when resource.kind == "a"
+  require resource.ok == true
+
+policy_set Group: "group" {
+  policy B: "b"
+    triggers resource.kind == "b"
+    denies
+
+  policy C: "c"
+    triggers when resource.kind == "c"
+    denies
+}
+
+polcy D: "d"
+  triggers when true
+  denies
+
+policy E: "e"
+  triggers when resource.kind == "e"
+  denies
+"#;
+        let (module, diagnostics) = parse_module_with_diagnostics(source, FileId(3));
+
+        // Everything after an error still parses
+        let names: Vec<_> = module.policies().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["C", "E"]);
+        assert_eq!(module.policy_sets().next().unwrap().policies.len(), 1);
+
+        assert_eq!(diagnostics.len(), 3);
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| d.location.line).collect();
+        assert_eq!(lines, vec![4, 8, 16]);
+        assert_eq!(diagnostics[0].location.file, FileId(3));
+        assert_eq!(&source[diagnostics[0].location.span()], "require");
+        assert_eq!(diagnostics[0].help.as_deref(), Some("did you mean `requires`?"));
+        assert_eq!(diagnostics[1].label.as_deref(), Some("expected when"));
+        assert_eq!(diagnostics[2].help.as_deref(), Some("did you mean `policy`?"));
+
+        // parse_file reports the first error
+        assert_eq!(
+            parse_module(source),
+            Err(ParseError::InvalidPolicy("Expected 'requires' or 'denies'".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_file_warnings() {
+        let source = r#"
+const UNUSED = 1
+predicate is_prod: resource.env == "prod"
+
+policy P: "p"
+  let limit = 5
+  triggers when is_prod
+  denies
+"#;
+        let (module, diagnostics) = parse_module_with_diagnostics(source, FileId::default());
+        assert_eq!(module.policy_count(), 1);
+
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "warning: Unused let 'limit' (line 6, column 3)",
+                "warning: Unused const 'UNUSED' (line 2, column 1)",
+            ]
+        );
+
+        // Warnings do not fail parsing
+        assert!(parse_module(source).is_ok());
+
+        // Definition errors point at the definition
+        let cyclic = "\nconst A = B + 1\nconst B = A\n";
+        let (_, diagnostics) = parse_module_with_diagnostics(cyclic, FileId::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Cyclic definition: A -> B -> A");
+        assert_eq!(diagnostics[0].location.line, 2);
+    }
 }
@@ -0,0 +1,451 @@
+//! Inlining of `const`, `predicate` and `let` definitions
+//!
+//! A definition is referenced by its name as a one-segment path
+//! (`MAX_REPLICAS`, `is_prod_deploy`) or, for a predicate, as a call
+//! (`owned_by(request.principal.id)`). The resolver replaces every reference
+//! with the definition's body, substituting predicate arguments for their
+//! parameters, so the compiler, the partial evaluator and the SQL translator
+//! only ever see plain expressions. Names that are not definitions (such as
+//! aggregate element fields) are left alone.
+//!
+//! A parameter or definition may also start a longer path (`r.owner`) or be
+//! the source of an aggregate (`any(roles where ...)`); its value must then
+//! itself be an attribute path, which the remaining segments extend.
+//!
+//! Definitions and parameters cannot be named after a context root or the
+//! aggregate element, and an argument that reads the enclosing aggregate's
+//! element cannot be passed to a parameter used inside an aggregate in the
+//! predicate body, where it would read that aggregate's element instead.
+
+use super::parse::{ParseError, ParseResult};
+use crate::ast::nodes::{
+    BinaryOp, Condition, Definition, DefinitionKind, Expression, Path, Policy, Requirements,
+    SetOperator, SourceLocation, Value,
+};
+use crate::ast::visitor::{walk_expression, Visitor};
+use crate::compiler::{CONTEXT_ROOTS, ELEMENT_ROOT};
+use std::collections::{HashMap, HashSet};
+
+/// Inlines the definitions of one scope
+pub(crate) struct Resolver<'a> {
+    definitions: HashMap<&'a str, &'a Definition>,
+    /// Definitions being expanded, innermost last, to report cycles
+    expanding: Vec<&'a str>,
+    /// Definitions referenced by resolved policies
+    used: HashSet<&'a str>,
+    /// Whether definitions are being checked with placeholder arguments
+    checking: bool,
+}
+
+impl<'a> Resolver<'a> {
+    /// Create a resolver, rejecting duplicate names
+    pub(crate) fn new(definitions: impl IntoIterator<Item = &'a Definition>) -> ParseResult<Self> {
+        let mut scope: HashMap<&str, &Definition> = HashMap::new();
+        for definition in definitions {
+            if let Some(previous) = scope.insert(definition.name.as_str(), definition) {
+                return Err(duplicate(definition, previous));
+            }
+            let mut names = std::iter::once(&definition.name).chain(&definition.params);
+            if let Some(name) = names.find(|name| is_reserved(name)) {
+                return Err(invalid(
+                    definition,
+                    format!("'{}' is a reserved path root and cannot be defined", name),
+                ));
+            }
+            for (i, param) in definition.params.iter().enumerate() {
+                if definition.params[..i].contains(param) {
+                    return Err(invalid(definition, format!("duplicate parameter '{}'", param)));
+                }
+            }
+        }
+        Ok(Self {
+            definitions: scope,
+            expanding: Vec::new(),
+            used: HashSet::new(),
+            checking: false,
+        })
+    }
+
+    /// The definition named `name`, if any
+    pub(crate) fn get(&self, name: &str) -> Option<&'a Definition> {
+        self.definitions.get(name).copied()
+    }
+
+    /// Check every definition: bodies must expand without cycles, and consts
+    /// must not read the request context
+    pub(crate) fn check(&mut self) -> ParseResult<()> {
+        let mut definitions: Vec<_> = self.definitions.values().copied().collect();
+        definitions.sort_by_key(|d| (d.location.line, d.location.column));
+
+        // Placeholder arguments are free names, not aggregate elements
+        self.checking = true;
+        let result = self.check_definitions(definitions);
+        self.checking = false;
+        result?;
+
+        // Only references from policies count as uses
+        self.used.clear();
+        Ok(())
+    }
+
+    fn check_definitions(&mut self, definitions: Vec<&'a Definition>) -> ParseResult<()> {
+        for definition in definitions {
+            // Parameters stand in for themselves while checking the body
+            let params = definition
+                .params
+                .iter()
+                .map(|p| (p.as_str(), Expression::path(vec![p.clone()])))
+                .collect();
+            let body = self.expand(definition, &params)?;
+
+            if definition.kind == DefinitionKind::Const {
+                let mut finder = ContextFinder::default();
+                finder.visit_expression(&body);
+                if let Some(path) = finder.path {
+                    return Err(invalid(
+                        definition,
+                        format!("a const cannot depend on the request, but it reads '{}'", path),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the definitions referenced so far
+    pub(crate) fn used(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.used.iter().copied()
+    }
+
+    pub(crate) fn is_used(&self, name: &str) -> bool {
+        self.used.contains(name)
+    }
+
+    /// Inline definitions into a policy's triggers and requirements
+    pub(crate) fn resolve_policy(&mut self, policy: &mut Policy) -> ParseResult<()> {
+        for trigger in &mut policy.triggers {
+            self.resolve_condition(trigger)?;
+        }
+        if let Requirements::Requires { conditions, where_clause } = &mut policy.requirements {
+            for condition in conditions.iter_mut().chain(where_clause.iter_mut().flatten()) {
+                self.resolve_condition(condition)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_condition(&mut self, condition: &mut Condition) -> ParseResult<()> {
+        condition.expr = self.resolve(&condition.expr, &HashMap::new())?;
+        Ok(())
+    }
+
+    /// Inline definitions into `expr`; `params` binds the parameters of the
+    /// predicate whose body this is
+    fn resolve(
+        &mut self,
+        expr: &Expression,
+        params: &HashMap<&str, Expression>,
+    ) -> ParseResult<Expression> {
+        Ok(match expr {
+            Expression::Path(path) => self.resolve_path(path, params)?,
+
+            Expression::Literal(_) => expr.clone(),
+
+            Expression::Binary { left, op, right } => {
+                let resolved_left = self.resolve(left, params)?;
+                let resolved_right = self.resolve(right, params)?;
+                match (op, right.as_ref(), resolved_right) {
+                    // `env in PROD_ENVS` with a literal list is an ordinary `in`
+                    (
+                        BinaryOp::Set(SetOperator::In),
+                        Expression::Path(_),
+                        Expression::Literal(Value::Array(items)),
+                    ) => Expression::in_list(resolved_left, items),
+                    (_, _, resolved_right) => {
+                        Expression::binary(resolved_left, *op, resolved_right)
+                    },
+                }
+            },
+
+            Expression::Logical { op, operands } => Expression::Logical {
+                op: *op,
+                operands: operands
+                    .iter()
+                    .map(|operand| self.resolve(operand, params))
+                    .collect::<ParseResult<_>>()?,
+            },
+
+            Expression::In { expr, list } => {
+                Expression::in_list(self.resolve(expr, params)?, list.clone())
+            },
+
+            Expression::Aggregate { path, func, condition } => Expression::Aggregate {
+                path: match self.resolve_path(path, params)? {
+                    Expression::Path(source) => source,
+                    _ => return Err(self.not_a_path(path, params, "aggregated over")),
+                },
+                func: *func,
+                condition: Box::new(Condition {
+                    expr: self.resolve(&condition.expr, params)?,
+                    location: condition.location.clone(),
+                }),
+            },
+
+            Expression::Call { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.resolve(arg, params))
+                    .collect::<ParseResult<Vec<_>>>()?;
+                match self.get(name) {
+                    Some(definition) if definition.kind == DefinitionKind::Predicate => {
+                        self.inline(definition, args)?
+                    },
+                    Some(definition) => {
+                        return Err(invalid(
+                            definition,
+                            format!(
+                                "a {} is not a predicate and cannot be called",
+                                definition.kind
+                            ),
+                        ))
+                    },
+                    None => Expression::Call { name: name.clone(), args },
+                }
+            },
+        })
+    }
+
+    /// Substitute the parameter or definition that `path` starts with, if any
+    fn resolve_path(
+        &mut self,
+        path: &Path,
+        params: &HashMap<&str, Expression>,
+    ) -> ParseResult<Expression> {
+        let Some((name, rest)) = path.segments.split_first() else {
+            return Ok(Expression::Path(path.clone()));
+        };
+        let value = match (params.get(name.as_str()), self.get(name)) {
+            (Some(arg), _) => arg.clone(),
+            (None, Some(definition)) => self.inline(definition, Vec::new())?,
+            (None, None) => return Ok(Expression::Path(path.clone())),
+        };
+        match value {
+            value if rest.is_empty() => Ok(value),
+            Expression::Path(mut prefix) => {
+                prefix.segments.extend_from_slice(rest);
+                Ok(Expression::Path(prefix))
+            },
+            _ => Err(self.not_a_path(path, params, "read as a path")),
+        }
+    }
+
+    /// Error for a parameter or definition at the start of `path` whose value
+    /// is not an attribute path
+    fn not_a_path(
+        &self,
+        path: &Path,
+        params: &HashMap<&str, Expression>,
+        usage: &str,
+    ) -> ParseError {
+        let name = path.segments[0].as_str();
+        if params.contains_key(name) {
+            // Parameters belong to the predicate being expanded
+            if let Some(predicate) = self.expanding.last().and_then(|n| self.get(n)) {
+                return invalid(
+                    predicate,
+                    format!(
+                        "the argument for '{}' is {} in '{}', so it must be an attribute path",
+                        name, usage, path
+                    ),
+                );
+            }
+        }
+        match self.get(name) {
+            Some(definition) => invalid(
+                definition,
+                format!("'{}' is {} in '{}', so it must be an attribute path", name, usage, path),
+            ),
+            None => ParseError::InvalidExpression(format!("'{}' is not an attribute path", path)),
+        }
+    }
+
+    /// Expand a reference to `definition` with already resolved arguments
+    fn inline(
+        &mut self,
+        definition: &'a Definition,
+        args: Vec<Expression>,
+    ) -> ParseResult<Expression> {
+        if args.len() != definition.params.len() {
+            return Err(invalid(
+                definition,
+                format!(
+                    "predicate expects {} argument(s), got {}",
+                    definition.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        if !self.checking {
+            check_capture(definition, &args)?;
+        }
+        let params = definition.params.iter().map(String::as_str).zip(args).collect();
+        self.used.insert(definition.name.as_str());
+        self.expand(definition, &params)
+    }
+
+    fn expand(
+        &mut self,
+        definition: &'a Definition,
+        params: &HashMap<&str, Expression>,
+    ) -> ParseResult<Expression> {
+        let name = definition.name.as_str();
+        if let Some(start) = self.expanding.iter().position(|n| *n == name) {
+            let mut cycle = self.expanding[start..].to_vec();
+            cycle.push(name);
+            self.expanding.clear();
+            return Err(ParseError::CyclicDefinition(cycle.join(" -> ")));
+        }
+
+        self.expanding.push(name);
+        let body = self.resolve(&definition.body, params)?;
+        self.expanding.pop();
+        Ok(body)
+    }
+}
+
+/// Where the definition an error from [`Resolver`] is about was defined
+pub(crate) fn error_location<'a>(
+    error: &ParseError,
+    definitions: impl IntoIterator<Item = &'a Definition>,
+) -> Option<SourceLocation> {
+    let (name, line) = match error {
+        ParseError::DuplicateDefinition { name, line, .. }
+        | ParseError::InvalidDefinition { name, line, .. } => (name.as_str(), Some(*line)),
+        ParseError::CyclicDefinition(cycle) => (cycle.split(" -> ").next()?, None),
+        _ => return None,
+    };
+    definitions
+        .into_iter()
+        .filter(|d| d.name == name && line.is_none_or(|line| d.location.line == line))
+        .last()
+        .map(|d| d.location.clone())
+}
+
+fn duplicate(definition: &Definition, previous: &Definition) -> ParseError {
+    ParseError::DuplicateDefinition {
+        name: definition.name.clone(),
+        line: definition.location.line,
+        previous: previous.location.line,
+    }
+}
+
+fn invalid(definition: &Definition, message: String) -> ParseError {
+    ParseError::InvalidDefinition {
+        name: definition.name.clone(),
+        line: definition.location.line,
+        message,
+    }
+}
+
+/// Whether `name` is a context root or the aggregate element
+fn is_reserved(name: &str) -> bool {
+    CONTEXT_ROOTS.contains(&name) || name == ELEMENT_ROOT
+}
+
+/// Reject arguments that the aggregates in a predicate body would capture
+///
+/// A path outside the request context reads the element of the aggregate it
+/// appears in. Substituted into an aggregate condition in the body, it would
+/// read that aggregate's element instead of the caller's.
+fn check_capture(definition: &Definition, args: &[Expression]) -> ParseResult<()> {
+    for (param, arg) in definition.params.iter().zip(args) {
+        let mut element = ElementPathFinder::default();
+        element.visit_expression(arg);
+        let Some(path) = element.path else {
+            continue;
+        };
+
+        let mut usage = AggregateUseFinder { param, depth: 0, found: false };
+        usage.visit_expression(&definition.body);
+        if usage.found {
+            return Err(invalid(
+                definition,
+                format!(
+                    "the argument for '{}' reads the aggregate element '{}', which an \
+                     aggregate in the predicate would capture",
+                    param, path
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the first path that reads an aggregate element rather than the
+/// request context, outside of any aggregate of its own
+#[derive(Default)]
+struct ElementPathFinder {
+    path: Option<Path>,
+}
+
+impl Visitor for ElementPathFinder {
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            // The condition reads the nested aggregate's own element
+            Expression::Aggregate { path, .. } => self.visit_path(path),
+            _ => walk_expression(self, expr),
+        }
+    }
+
+    fn visit_path(&mut self, path: &Path) {
+        let root = path.segments.first().map(String::as_str).unwrap_or_default();
+        if self.path.is_none() && !CONTEXT_ROOTS.contains(&root) {
+            self.path = Some(path.clone());
+        }
+    }
+}
+
+/// Finds a use of `param` inside an aggregate condition
+struct AggregateUseFinder<'a> {
+    param: &'a str,
+    depth: usize,
+    found: bool,
+}
+
+impl Visitor for AggregateUseFinder<'_> {
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Aggregate { path, condition, .. } => {
+                self.visit_path(path);
+                self.depth += 1;
+                self.visit_expression(&condition.expr);
+                self.depth -= 1;
+            },
+            _ => walk_expression(self, expr),
+        }
+    }
+
+    fn visit_path(&mut self, path: &Path) {
+        if self.depth > 0 && path.segments.first().is_some_and(|root| root == self.param) {
+            self.found = true;
+        }
+    }
+}
+
+/// Finds the first path into the request context
+#[derive(Default)]
+struct ContextFinder {
+    path: Option<Path>,
+}
+
+impl Visitor for ContextFinder {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if self.path.is_none() {
+            walk_expression(self, expr);
+        }
+    }
+
+    fn visit_path(&mut self, path: &Path) {
+        self.path.get_or_insert_with(|| path.clone());
+    }
+}
@@ -13,15 +13,60 @@ pub struct Token {
     pub line: usize,
     /// Column number (1-indexed)
     pub column: usize,
+    /// Byte offset of the first character
+    pub offset: usize,
+    /// Length in bytes of the source text, escapes and quotes included
+    pub length: usize,
 }
 
 impl Token {
     /// Create a new token
     pub fn new(kind: TokenKind, text: String, line: usize, column: usize) -> Self {
-        Self { kind, text, line, column }
+        let length = text.len();
+        Self {
+            kind,
+            text,
+            line,
+            column,
+            offset: 0,
+            length,
+        }
+    }
+
+    /// Byte offset just past the last character
+    pub fn end(&self) -> usize {
+        self.offset + self.length
     }
 }
 
+/// Keywords of the language, for suggestions in diagnostics
+pub const KEYWORDS: &[&str] = &[
+    "policy",
+    "triggers",
+    "when",
+    "requires",
+    "denies",
+    "with",
+    "reason",
+    "where",
+    "metadata",
+    "and",
+    "or",
+    "not",
+    "in",
+    "matches",
+    "like",
+    "contains",
+    "intersects",
+    "subset_of",
+    "package",
+    "policy_set",
+    "combining",
+    "const",
+    "predicate",
+    "let",
+];
+
 /// The kind of token
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
@@ -39,6 +84,17 @@ pub enum TokenKind {
     Or,
     Not,
     In,
+    Matches,
+    Like,
+    Contains,
+    Intersects,
+    SubsetOf,
+    Package,
+    PolicySet,
+    Combining,
+    Const,
+    Predicate,
+    Let,
 
     // Comparison operators
     Eq,   // ==
@@ -48,10 +104,24 @@ pub enum TokenKind {
     LtEq, // <=
     GtEq, // >=
 
+    // Arithmetic operators
+    Plus,    // +
+    Minus,   // -
+    Star,    // *
+    Slash,   // /
+    Percent, // %
+
+    // Missing-value operator
+    Default, // ??
+
     // Literals
     StringLit(String),
     IntLit(i64),
     FloatLit(f64),
+    /// Duration in seconds (`30m`, `1h30m`)
+    DurationLit(i64),
+    /// RFC 3339 timestamp in seconds since the Unix epoch
+    TimestampLit(i64),
     BoolLit(bool),
 
     // Identifiers
@@ -59,6 +129,7 @@ pub enum TokenKind {
 
     // Punctuation
     Colon,    // :
+    Assign,   // =
     Comma,    // ,
     Dot,      // .
     LParen,   // (
@@ -90,18 +161,41 @@ impl fmt::Display for TokenKind {
             TokenKind::Or => write!(f, "or"),
             TokenKind::Not => write!(f, "not"),
             TokenKind::In => write!(f, "in"),
+            TokenKind::Matches => write!(f, "matches"),
+            TokenKind::Like => write!(f, "like"),
+            TokenKind::Contains => write!(f, "contains"),
+            TokenKind::Intersects => write!(f, "intersects"),
+            TokenKind::SubsetOf => write!(f, "subset_of"),
+            TokenKind::Package => write!(f, "package"),
+            TokenKind::PolicySet => write!(f, "policy_set"),
+            TokenKind::Combining => write!(f, "combining"),
+            TokenKind::Const => write!(f, "const"),
+            TokenKind::Predicate => write!(f, "predicate"),
+            TokenKind::Let => write!(f, "let"),
             TokenKind::Eq => write!(f, "=="),
             TokenKind::Neq => write!(f, "!="),
             TokenKind::Lt => write!(f, "<"),
             TokenKind::Gt => write!(f, ">"),
             TokenKind::LtEq => write!(f, "<="),
             TokenKind::GtEq => write!(f, ">="),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Default => write!(f, "??"),
             TokenKind::StringLit(s) => write!(f, "\"{}\"", s),
             TokenKind::IntLit(n) => write!(f, "{}", n),
             TokenKind::FloatLit(n) => write!(f, "{}", n),
+            TokenKind::DurationLit(n) => write!(f, "{}s", n),
+            TokenKind::TimestampLit(n) => match chrono::DateTime::from_timestamp(*n, 0) {
+                Some(dt) => write!(f, "{}", dt.format("%Y-%m-%dT%H:%M:%SZ")),
+                None => write!(f, "{}", n),
+            },
             TokenKind::BoolLit(b) => write!(f, "{}", b),
             TokenKind::Ident(s) => write!(f, "{}", s),
             TokenKind::Colon => write!(f, ":"),
+            TokenKind::Assign => write!(f, "="),
             TokenKind::Comma => write!(f, ","),
             TokenKind::Dot => write!(f, "."),
             TokenKind::LParen => write!(f, "("),
@@ -135,6 +229,17 @@ impl TokenKind {
                 | TokenKind::Or
                 | TokenKind::Not
                 | TokenKind::In
+                | TokenKind::Matches
+                | TokenKind::Like
+                | TokenKind::Contains
+                | TokenKind::Intersects
+                | TokenKind::SubsetOf
+                | TokenKind::Package
+                | TokenKind::PolicySet
+                | TokenKind::Combining
+                | TokenKind::Const
+                | TokenKind::Predicate
+                | TokenKind::Let
         )
     }
 
@@ -148,6 +253,12 @@ impl TokenKind {
                 | TokenKind::Gt
                 | TokenKind::LtEq
                 | TokenKind::GtEq
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::Slash
+                | TokenKind::Percent
+                | TokenKind::Default
         )
     }
 
@@ -158,6 +269,8 @@ impl TokenKind {
             TokenKind::StringLit(_)
                 | TokenKind::IntLit(_)
                 | TokenKind::FloatLit(_)
+                | TokenKind::DurationLit(_)
+                | TokenKind::TimestampLit(_)
                 | TokenKind::BoolLit(_)
         )
     }
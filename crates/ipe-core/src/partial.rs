@@ -0,0 +1,756 @@
+//! Partial evaluation of policies
+//!
+//! List-filtering endpoints need the constraint on resource attributes under
+//! which a request would be allowed, rather than a decision for one resource.
+//! [`PartialEvaluator`] evaluates policies against a context in which some
+//! paths (by default everything under `resource`) are unknown. Known fields are
+//! substituted, constant subexpressions are folded and what remains is a
+//! residual [`Expression`] over the unknown paths only:
+//!
+//! ```text
+//! requires resource.owner == request.principal.id
+//!       or any(request.principal.roles where item == "admin")
+//!
+//! principal alice, roles []        =>  resource.owner == "alice"
+//! principal bob, roles ["admin"]   =>  true
+//! ```
+//!
+//! A residual can be translated into a query, or checked against concrete
+//! resources with [`PartialEvaluator::matches`]. Evaluation errors, which make
+//! a decision indeterminate at runtime, are not modelled.
+
+use crate::ast::nodes::{
+    AggregateFunc, BinaryOp, Condition, Expression, LogicalOp, Path, Policy, Requirements,
+    Value as AstValue,
+};
+use crate::bytecode::{CompOp, Value};
+use crate::combining::CombiningAlgorithm;
+use crate::compiler::{
+    aggregate_op, arith_op_for, bytecode_value, comp_op_for, literal_pattern, set_op_for,
+    CONTEXT_ROOTS, ELEMENT_ROOT,
+};
+use crate::functions::FunctionRegistry;
+use crate::interpreter::{AggregateFrame, Interpreter};
+use crate::rar::EvaluationContext;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PartialError {
+    #[error("Evaluation error: {0}")]
+    Evaluation(String),
+
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("Cannot partially evaluate {0}")]
+    Unsupported(String),
+
+    #[error("Value {0} cannot appear in a residual expression")]
+    Unrepresentable(String),
+}
+
+pub type PartialResult<T> = Result<T, PartialError>;
+
+/// Residual conditions of a single policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyResidual {
+    /// When the policy applies (its triggers hold)
+    pub applies: Expression,
+    /// When the policy allows
+    pub allow: Expression,
+    /// When the policy denies
+    pub deny: Expression,
+}
+
+/// Result of evaluating an expression as far as the known fields allow
+#[derive(Clone)]
+enum Partial {
+    Known(Value),
+    Residual(Expression),
+}
+
+impl Partial {
+    fn bool(value: bool) -> Self {
+        Partial::Known(Value::Bool(value))
+    }
+
+    fn into_expression(self) -> PartialResult<Expression> {
+        match self {
+            Partial::Known(value) => Ok(Expression::Literal(ast_value(&value)?)),
+            Partial::Residual(expr) => Ok(expr),
+        }
+    }
+}
+
+/// How paths that do not start at a context root resolve
+#[derive(Clone, Copy)]
+enum Scope<'v> {
+    /// Outside any aggregate
+    Context,
+    /// Inside an aggregate over a known collection
+    Element(&'v Value),
+    /// Inside an aggregate over an unknown collection
+    UnknownElement,
+}
+
+/// Evaluates policies with some context paths left unknown
+pub struct PartialEvaluator<'a> {
+    ctx: &'a EvaluationContext,
+    unknowns: Vec<Vec<String>>,
+    functions: Arc<FunctionRegistry>,
+    loader: Interpreter,
+}
+
+impl<'a> PartialEvaluator<'a> {
+    /// Create an evaluator that treats every `resource` path as unknown
+    pub fn new(ctx: &'a EvaluationContext) -> Self {
+        Self {
+            ctx,
+            unknowns: vec![vec!["resource".to_string()]],
+            functions: FunctionRegistry::builtins(),
+            loader: Interpreter::default(),
+        }
+    }
+
+    /// Replace the unknown path prefixes (e.g. `["resource.owner", "request.timestamp"]`)
+    pub fn with_unknowns<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.unknowns = prefixes
+            .into_iter()
+            .map(|p| p.as_ref().split('.').map(str::to_string).collect())
+            .collect();
+        self
+    }
+
+    /// Resolve function calls in the given registry instead of the builtins
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Simplify an expression, leaving only the unknown paths
+    pub fn expression(&self, expr: &Expression) -> PartialResult<Expression> {
+        self.eval(expr, Scope::Context)?.into_expression()
+    }
+
+    /// Residual conditions under which a policy applies, allows and denies
+    pub fn policy(&self, policy: &Policy) -> PartialResult<PolicyResidual> {
+        let (applies, allow, deny) = self.policy_partials(policy)?;
+        Ok(PolicyResidual {
+            applies: applies.into_expression()?,
+            allow: allow.into_expression()?,
+            deny: deny.into_expression()?,
+        })
+    }
+
+    /// Residual condition under which the combined decision is `Allow`
+    ///
+    /// `policies` are the candidates for the request, in evaluation order.
+    pub fn policies(
+        &self,
+        policies: &[Policy],
+        algorithm: CombiningAlgorithm,
+    ) -> PartialResult<Expression> {
+        let residuals = policies
+            .iter()
+            .map(|p| self.policy_partials(p))
+            .collect::<PartialResult<Vec<_>>>()?;
+
+        let allowed = match algorithm {
+            CombiningAlgorithm::DenyOverrides => {
+                let mut allows = Vec::with_capacity(residuals.len());
+                let mut denies = Vec::with_capacity(residuals.len());
+                for (_, allow, deny) in residuals {
+                    allows.push(allow);
+                    denies.push(deny);
+                }
+                let any_deny = combine(LogicalOp::Or, denies.into_iter().map(Ok))?;
+                combine(
+                    LogicalOp::And,
+                    [Ok(combine(LogicalOp::Or, allows.into_iter().map(Ok))?), Ok(negate(any_deny))],
+                )?
+            },
+            CombiningAlgorithm::PermitOverrides => {
+                combine(LogicalOp::Or, residuals.into_iter().map(|(_, allow, _)| Ok(allow)))?
+            },
+            CombiningAlgorithm::FirstApplicable => {
+                // allow_1 or (not applies_1 and (allow_2 or (not applies_2 and ...)))
+                let mut acc = Partial::bool(false);
+                for (applies, allow, _) in residuals.into_iter().rev() {
+                    let skip = combine(LogicalOp::And, [Ok(negate(applies)), Ok(acc)])?;
+                    acc = combine(LogicalOp::Or, [Ok(allow), Ok(skip)])?;
+                }
+                acc
+            },
+            CombiningAlgorithm::OnlyOneApplicable => {
+                // Some policy allows and no other policy applies
+                let cases = (0..residuals.len()).map(|i| {
+                    let others = residuals
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .map(|(_, (applies, _, _))| Ok(negate(applies.clone())));
+                    combine(
+                        LogicalOp::And,
+                        std::iter::once(Ok(residuals[i].1.clone())).chain(others),
+                    )
+                });
+                combine(LogicalOp::Or, cases)?
+            },
+        };
+
+        allowed.into_expression()
+    }
+
+    /// Check a residual against a context in which every path is known
+    pub fn matches(residual: &Expression, ctx: &EvaluationContext) -> PartialResult<bool> {
+        let evaluator = PartialEvaluator::new(ctx).with_unknowns(Vec::<String>::new());
+        match evaluator.eval(residual, Scope::Context)? {
+            Partial::Known(value) => Ok(value.is_truthy()),
+            Partial::Residual(expr) => Err(PartialError::Unsupported(format!("{:?}", expr))),
+        }
+    }
+
+    fn policy_partials(&self, policy: &Policy) -> PartialResult<(Partial, Partial, Partial)> {
+        let conditions = |conds: &[Condition]| {
+            conds.iter().map(|c| self.eval(&c.expr, Scope::Context)).collect::<Vec<_>>()
+        };
+
+        let applies = combine(LogicalOp::And, conditions(&policy.triggers))?;
+        let (allow, deny) = match &policy.requirements {
+            Requirements::Requires { conditions: required, where_clause } => {
+                let mut all = conditions(required);
+                if let Some(where_conds) = where_clause {
+                    all.extend(conditions(where_conds));
+                }
+                let required = combine(LogicalOp::And, all)?;
+                (
+                    combine(LogicalOp::And, [Ok(applies.clone()), Ok(required.clone())])?,
+                    combine(LogicalOp::And, [Ok(applies.clone()), Ok(negate(required))])?,
+                )
+            },
+            Requirements::Denies { .. } => (Partial::bool(false), applies.clone()),
+        };
+
+        Ok((applies, allow, deny))
+    }
+
+    fn eval(&self, expr: &Expression, scope: Scope<'_>) -> PartialResult<Partial> {
+        match expr {
+            Expression::Literal(value) => Ok(Partial::Known(bytecode_value(value))),
+
+            Expression::Path(path) => self.path(path, scope),
+
+            Expression::Binary { left, op, right } => match op {
+                BinaryOp::Comparison(comp_op) => {
+                    let left = self.eval(left, scope)?;
+                    let right = self.eval(right, scope)?;
+                    match (left, right) {
+                        (Partial::Known(a), Partial::Known(b)) => a
+                            .compare(&b, comp_op_for(*comp_op))
+                            .map(Partial::bool)
+                            .map_err(PartialError::Evaluation),
+                        (left, right) => Ok(Partial::Residual(Expression::binary(
+                            left.into_expression()?,
+                            *op,
+                            right.into_expression()?,
+                        ))),
+                    }
+                },
+                BinaryOp::Arithmetic(arith_op) => {
+                    let left = self.eval(left, scope)?;
+                    let right = self.eval(right, scope)?;
+                    match (left, right) {
+                        (Partial::Known(a), Partial::Known(b)) => a
+                            .arith(&b, arith_op_for(*arith_op))
+                            .map(Partial::Known)
+                            .map_err(PartialError::Evaluation),
+                        (left, right) => Ok(Partial::Residual(Expression::binary(
+                            left.into_expression()?,
+                            *op,
+                            right.into_expression()?,
+                        ))),
+                    }
+                },
+                BinaryOp::Set(set_op) => {
+                    let left = self.eval(left, scope)?;
+                    let right = self.eval(right, scope)?;
+                    match (left, right) {
+                        (Partial::Known(a), Partial::Known(b)) => a
+                            .set_compare(&b, set_op_for(*set_op))
+                            .map(Partial::bool)
+                            .map_err(PartialError::Evaluation),
+                        (left, right) => Ok(Partial::Residual(Expression::binary(
+                            left.into_expression()?,
+                            *op,
+                            right.into_expression()?,
+                        ))),
+                    }
+                },
+                BinaryOp::Matches | BinaryOp::Like => {
+                    let pattern = literal_pattern(*op, right).map_err(PartialError::Evaluation)?;
+                    match self.eval(left, scope)? {
+                        Partial::Known(value) => pattern
+                            .matches_value(&value)
+                            .map(Partial::bool)
+                            .map_err(PartialError::Evaluation),
+                        Partial::Residual(left) => {
+                            Ok(Partial::Residual(Expression::binary(left, *op, (**right).clone())))
+                        },
+                    }
+                },
+                BinaryOp::Default => match self.eval(left, scope)? {
+                    Partial::Known(value) if value.is_defined() => Ok(Partial::Known(value)),
+                    Partial::Known(_) => self.eval(right, scope),
+                    Partial::Residual(left) => Ok(Partial::Residual(Expression::binary(
+                        left,
+                        BinaryOp::Default,
+                        self.eval(right, scope)?.into_expression()?,
+                    ))),
+                },
+            },
+
+            Expression::Logical { op: LogicalOp::Not, operands } => match operands.first() {
+                Some(operand) => Ok(negate(self.eval(operand, scope)?)),
+                None => Err(PartialError::Unsupported("NOT without an operand".to_string())),
+            },
+
+            Expression::Logical { op, operands } => {
+                combine(*op, operands.iter().map(|operand| self.eval(operand, scope)))
+            },
+
+            Expression::In { expr, list } => match self.eval(expr, scope)? {
+                Partial::Known(value) => {
+                    let mut found = false;
+                    for item in list {
+                        found |= value
+                            .compare(&bytecode_value(item), CompOp::Eq)
+                            .map_err(PartialError::Evaluation)?;
+                    }
+                    Ok(Partial::bool(found))
+                },
+                Partial::Residual(expr) => {
+                    Ok(Partial::Residual(Expression::in_list(expr, list.clone())))
+                },
+            },
+
+            Expression::Aggregate { path, func, condition } => {
+                self.aggregate(path, *func, condition, scope)
+            },
+
+            Expression::Call { name, args } => {
+                if let Some(expanded) = self.functions.expand_clock(name, args) {
+                    return self.eval(&expanded, scope);
+                }
+                let id = self
+                    .functions
+                    .resolve(name)
+                    .ok_or_else(|| PartialError::UnknownFunction(name.clone()))?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, scope))
+                    .collect::<PartialResult<Vec<_>>>()?;
+
+                if args.iter().all(|arg| matches!(arg, Partial::Known(_))) {
+                    let values: Vec<Value> = args
+                        .into_iter()
+                        .map(|arg| match arg {
+                            Partial::Known(value) => value,
+                            Partial::Residual(_) => Value::Undefined,
+                        })
+                        .collect();
+                    return self
+                        .functions
+                        .call(id, &values)
+                        .map(Partial::Known)
+                        .map_err(PartialError::Evaluation);
+                }
+
+                let args =
+                    args.into_iter().map(Partial::into_expression).collect::<PartialResult<_>>()?;
+                Ok(Partial::Residual(Expression::Call { name: name.clone(), args }))
+            },
+        }
+    }
+
+    fn path(&self, path: &Path, scope: Scope<'_>) -> PartialResult<Partial> {
+        let root = path.root().unwrap_or_default();
+        if matches!(scope, Scope::Context) || CONTEXT_ROOTS.contains(&root) {
+            if self.unknowns.iter().any(|prefix| path.segments.starts_with(prefix)) {
+                return Ok(Partial::Residual(Expression::Path(path.clone())));
+            }
+            return self
+                .loader
+                .load_path(&path.segments, self.ctx)
+                .map(Partial::Known)
+                .map_err(PartialError::Evaluation);
+        }
+
+        // A field of the current element, as in the compiler
+        let element = match scope {
+            Scope::Element(element) => element,
+            _ => return Ok(Partial::Residual(Expression::Path(path.clone()))),
+        };
+        let fields = if root == ELEMENT_ROOT { &path.segments[1..] } else { &path.segments[..] };
+        let mut value = element.clone();
+        for field in fields {
+            value = match value {
+                Value::Map(mut entries) => entries.remove(field).unwrap_or(Value::Undefined),
+                _ => Value::Undefined,
+            };
+        }
+        Ok(Partial::Known(value))
+    }
+
+    fn aggregate(
+        &self,
+        path: &Path,
+        func: AggregateFunc,
+        condition: &Condition,
+        scope: Scope<'_>,
+    ) -> PartialResult<Partial> {
+        let items = match self.path(path, scope)? {
+            Partial::Known(Value::Array(items)) => items,
+            Partial::Known(Value::Undefined) => Vec::new(),
+            Partial::Known(other) => {
+                return Err(PartialError::Evaluation(format!(
+                    "{}() expects an array, got {:?}",
+                    func, other
+                )))
+            },
+            Partial::Residual(_) => {
+                // The collection itself is unknown; only the context paths of
+                // the condition can be substituted
+                let residual = self.eval(&condition.expr, Scope::UnknownElement)?;
+                return Ok(Partial::Residual(Expression::Aggregate {
+                    path: path.clone(),
+                    func,
+                    condition: Box::new(Condition::new(residual.into_expression()?)),
+                }));
+            },
+        };
+
+        let parts = items
+            .iter()
+            .map(|item| self.eval(&condition.expr, Scope::Element(item)))
+            .collect::<PartialResult<Vec<_>>>()?;
+
+        if parts.iter().all(|part| matches!(part, Partial::Known(_))) {
+            let op = aggregate_op(func);
+            if items.is_empty() {
+                return AggregateFrame::result(op, None)
+                    .map(Partial::Known)
+                    .map_err(PartialError::Evaluation);
+            }
+            let mut frame = AggregateFrame::new(op, items);
+            for part in parts {
+                let matched = matches!(part, Partial::Known(value) if value.is_truthy());
+                if let Some(result) = frame.fold(matched).map_err(PartialError::Evaluation)? {
+                    return Ok(Partial::Known(result));
+                }
+            }
+            unreachable!("the aggregate finishes at its last element");
+        }
+
+        match func {
+            AggregateFunc::Any => combine(LogicalOp::Or, parts.into_iter().map(Ok)),
+            AggregateFunc::All => combine(LogicalOp::And, parts.into_iter().map(Ok)),
+            _ => Err(PartialError::Unsupported(format!("{}() over unknown fields", func))),
+        }
+    }
+}
+
+/// Fold `and`/`or` operands, dropping known operands that do not decide the result
+fn combine<I>(op: LogicalOp, operands: I) -> PartialResult<Partial>
+where
+    I: IntoIterator<Item = PartialResult<Partial>>,
+{
+    let decisive = op == LogicalOp::Or;
+    let mut residuals = Vec::new();
+
+    for operand in operands {
+        match operand? {
+            Partial::Known(value) if value.is_truthy() == decisive => {
+                return Ok(Partial::bool(decisive))
+            },
+            Partial::Known(_) => {},
+            Partial::Residual(expr) => residuals.push(expr),
+        }
+    }
+
+    Ok(join(op, residuals))
+}
+
+/// Join residual operands, flattening nested `and`/`or` of the same kind and
+/// removing duplicates; an operand next to its own negation decides the result
+fn join(op: LogicalOp, operands: Vec<Expression>) -> Partial {
+    let decisive = op == LogicalOp::Or;
+    let mut joined: Vec<Expression> = Vec::with_capacity(operands.len());
+
+    for operand in operands {
+        let flattened = match operand {
+            Expression::Logical { op: inner, operands } if inner == op => operands,
+            other => vec![other],
+        };
+        for expr in flattened {
+            if let Partial::Residual(complement) = negate(Partial::Residual(expr.clone())) {
+                if joined.contains(&complement) {
+                    return Partial::bool(decisive);
+                }
+            }
+            if !joined.contains(&expr) {
+                joined.push(expr);
+            }
+        }
+    }
+
+    match joined.len() {
+        0 => Partial::bool(!decisive),
+        1 => Partial::Residual(joined.remove(0)),
+        _ => Partial::Residual(Expression::Logical { op, operands: joined }),
+    }
+}
+
+/// Logical negation, removing double negations and pushing `not` through
+/// `and`/`or` (De Morgan)
+fn negate(partial: Partial) -> Partial {
+    match partial {
+        Partial::Known(value) => Partial::bool(!value.is_truthy()),
+        Partial::Residual(Expression::Logical { op: LogicalOp::Not, mut operands })
+            if operands.len() == 1 =>
+        {
+            Partial::Residual(operands.remove(0))
+        },
+        Partial::Residual(Expression::Logical {
+            op: op @ (LogicalOp::And | LogicalOp::Or),
+            operands,
+        }) => {
+            let flipped = if op == LogicalOp::And { LogicalOp::Or } else { LogicalOp::And };
+            let decisive = flipped == LogicalOp::Or;
+            let mut negated = Vec::with_capacity(operands.len());
+            for operand in operands {
+                match negate(Partial::Residual(operand)) {
+                    Partial::Known(value) if value.is_truthy() == decisive => {
+                        return Partial::bool(decisive)
+                    },
+                    Partial::Known(_) => {},
+                    Partial::Residual(expr) => negated.push(expr),
+                }
+            }
+            join(flipped, negated)
+        },
+        Partial::Residual(expr) => Partial::Residual(Expression::logical_not(expr)),
+    }
+}
+
+/// Convert a runtime value back into an AST literal
+fn ast_value(value: &Value) -> PartialResult<AstValue> {
+    Ok(match value {
+        Value::Int(n) => AstValue::Int(*n),
+        Value::Float(f) => AstValue::Float(*f),
+        Value::Bool(b) => AstValue::Bool(*b),
+        Value::String(s) => AstValue::String(s.clone()),
+        Value::Array(items) => {
+            AstValue::Array(items.iter().map(ast_value).collect::<PartialResult<_>>()?)
+        },
+        other => return Err(PartialError::Unrepresentable(format!("{:?}", other))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::nodes::{ComparisonOp, SetOperator};
+    use crate::parser::Parser;
+    use crate::rar::{AttributeValue, Principal};
+
+    fn parse(source: &str) -> Policy {
+        Parser::new(source).parse_policy().unwrap()
+    }
+
+    fn ctx_for(principal: Principal) -> EvaluationContext {
+        let mut ctx = EvaluationContext::default();
+        ctx.request.principal = principal;
+        ctx
+    }
+
+    fn path(dotted: &str) -> Expression {
+        Expression::path(dotted.split('.').map(str::to_string).collect())
+    }
+
+    fn eq(left: Expression, value: &str) -> Expression {
+        Expression::binary(
+            left,
+            BinaryOp::Comparison(ComparisonOp::Eq),
+            Expression::literal(AstValue::String(value.to_string())),
+        )
+    }
+
+    const OWNERS: &str = r#"policy Owners: "Owners and admins may read"
+  triggers when true
+  requires resource.owner == request.principal.id or any(request.principal.roles where item == "admin")"#;
+
+    #[test]
+    fn test_residual_substitutes_known_fields() {
+        let policy = parse(OWNERS);
+
+        let ctx = ctx_for(Principal::new("alice"));
+        let residual = PartialEvaluator::new(&ctx).policy(&policy).unwrap();
+        assert_eq!(residual.applies, Expression::literal(AstValue::Bool(true)));
+        assert_eq!(residual.allow, eq(path("resource.owner"), "alice"));
+        assert_eq!(residual.deny, Expression::logical_not(eq(path("resource.owner"), "alice")));
+
+        let ctx = ctx_for(Principal::new("bob").with_role("admin"));
+        let residual = PartialEvaluator::new(&ctx).policy(&policy).unwrap();
+        assert_eq!(residual.allow, Expression::literal(AstValue::Bool(true)));
+        assert_eq!(residual.deny, Expression::literal(AstValue::Bool(false)));
+    }
+
+    #[test]
+    fn test_residual_expands_known_collections() {
+        let policy = parse(
+            r#"policy Teams: "Members see their teams' resources"
+  triggers when true
+  requires any(request.principal.roles where resource.team == item) and resource.archived ?? false == false"#,
+        );
+        let ctx = ctx_for(Principal::new("alice").with_role("red").with_role("blue"));
+        let residual = PartialEvaluator::new(&ctx).policy(&policy).unwrap();
+
+        let Expression::Logical { op: LogicalOp::And, operands } = &residual.allow else {
+            panic!("expected a conjunction, got {:?}", residual.allow);
+        };
+        assert_eq!(
+            operands[0],
+            Expression::or(vec![
+                eq(path("resource.team"), "red"),
+                eq(path("resource.team"), "blue")
+            ])
+        );
+
+        // An aggregate over an unknown collection keeps its shape
+        let policy = parse(
+            r#"policy Tags: "Tagged for the principal"
+  triggers when true
+  requires any(resource.tags where item == request.principal.id)"#,
+        );
+        let residual = PartialEvaluator::new(&ctx).policy(&policy).unwrap();
+        let Expression::Aggregate { condition, .. } = &residual.allow else {
+            panic!("expected an aggregate, got {:?}", residual.allow);
+        };
+        assert_eq!(condition.expr, eq(path("item"), "alice"));
+
+        let policy = parse(
+            r#"policy Count: "Counts over unknown fields"
+  triggers when true
+  requires count(request.principal.roles where item == resource.team) > 1"#,
+        );
+        assert!(matches!(
+            PartialEvaluator::new(&ctx).policy(&policy),
+            Err(PartialError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_residual_fields_of_scalars_are_undefined() {
+        let policy = parse(
+            r#"policy Shapes: "Defensive against odd shapes"
+  triggers when true
+  requires not exists(request.principal.claims.team) and any(request.principal.roles where item.level ?? 1 == 1)"#,
+        );
+        let principal = Principal::new("alice")
+            .with_role("admin")
+            .with_attribute("claims", AttributeValue::String("opaque".to_string()));
+        let residual = PartialEvaluator::new(&ctx_for(principal)).policy(&policy).unwrap();
+        assert_eq!(residual.allow, Expression::literal(AstValue::Bool(true)));
+    }
+
+    #[test]
+    fn test_residual_set_operators() {
+        let ctx = ctx_for(Principal::new("alice").with_role("red"));
+        let evaluator = PartialEvaluator::new(&ctx);
+        let residual = |source: &str| {
+            evaluator.expression(&Parser::new(source).parse_expression().unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            residual(r#""red" in request.principal.roles"#),
+            Expression::literal(AstValue::Bool(true))
+        );
+        assert_eq!(
+            residual(r#"request.principal.roles intersects ["blue", "green"]"#),
+            Expression::literal(AstValue::Bool(false))
+        );
+        // Known collections become literal arrays in the residual
+        assert_eq!(
+            residual("resource.team in request.principal.roles"),
+            Expression::binary(
+                path("resource.team"),
+                BinaryOp::Set(SetOperator::In),
+                Expression::literal(AstValue::Array(vec![AstValue::String("red".to_string())]))
+            )
+        );
+    }
+
+    #[test]
+    fn test_residual_combining() {
+        let policies = vec![
+            parse(OWNERS),
+            parse(
+                r#"policy Archived: "Archived resources are hidden"
+  triggers when resource.archived == true
+  denies"#,
+            ),
+        ];
+        let ctx = ctx_for(Principal::new("alice"));
+        let evaluator = PartialEvaluator::new(&ctx);
+
+        let archived = Expression::binary(
+            path("resource.archived"),
+            BinaryOp::Comparison(ComparisonOp::Eq),
+            Expression::literal(AstValue::Bool(true)),
+        );
+        assert_eq!(
+            evaluator.policies(&policies, CombiningAlgorithm::DenyOverrides).unwrap(),
+            Expression::and(vec![
+                eq(path("resource.owner"), "alice"),
+                Expression::logical_not(archived)
+            ])
+        );
+        assert_eq!(
+            evaluator.policies(&policies, CombiningAlgorithm::PermitOverrides).unwrap(),
+            eq(path("resource.owner"), "alice")
+        );
+        assert_eq!(
+            evaluator.policies(&[], CombiningAlgorithm::DenyOverrides).unwrap(),
+            Expression::literal(AstValue::Bool(false))
+        );
+    }
+
+    #[test]
+    fn test_residual_matches_agree_with_interpreter() {
+        use crate::compiler::PolicyCompiler;
+        use crate::interpreter::Outcome;
+
+        let policy = parse(OWNERS);
+        let principal = Principal::new("alice");
+        let residual = PartialEvaluator::new(&ctx_for(principal.clone())).policy(&policy).unwrap();
+        let (compiled, field_map) = PolicyCompiler::new(1).compile_with_field_map(&policy).unwrap();
+
+        for owner in ["alice", "bob"] {
+            let mut ctx = ctx_for(principal.clone());
+            ctx.resource
+                .attributes
+                .insert("owner".to_string(), AttributeValue::String(owner.to_string()));
+
+            let allowed = Interpreter::new(field_map.clone()).run(&compiled, &ctx).unwrap();
+            assert_eq!(
+                PartialEvaluator::matches(&residual.allow, &ctx).unwrap(),
+                allowed == Outcome::Allow
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
 #[cfg(feature = "approvals")]
 use std::sync::Arc;
@@ -205,6 +206,48 @@ pub enum Operation {
     Custom(u32),
 }
 
+impl Operation {
+    /// Parse an operation from its name in policy source (e.g. `deploy`, `custom:7`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "create" => Some(Operation::Create),
+            "read" => Some(Operation::Read),
+            "update" => Some(Operation::Update),
+            "delete" => Some(Operation::Delete),
+            "deploy" => Some(Operation::Deploy),
+            "execute" => Some(Operation::Execute),
+            _ => name.strip_prefix("custom:")?.parse().ok().map(Operation::Custom),
+        }
+    }
+
+    /// Stable integer code for the operation, used by native code
+    pub fn code(&self) -> i64 {
+        match self {
+            Operation::Create => 0,
+            Operation::Read => 1,
+            Operation::Update => 2,
+            Operation::Delete => 3,
+            Operation::Deploy => 4,
+            Operation::Execute => 5,
+            Operation::Custom(n) => 256 + *n as i64,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Create => write!(f, "create"),
+            Operation::Read => write!(f, "read"),
+            Operation::Update => write!(f, "update"),
+            Operation::Delete => write!(f, "delete"),
+            Operation::Deploy => write!(f, "deploy"),
+            Operation::Execute => write!(f, "execute"),
+            Operation::Custom(n) => write!(f, "custom:{}", n),
+        }
+    }
+}
+
 /// Request metadata
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Request {
@@ -255,8 +298,10 @@ impl Principal {
 pub enum AttributeValue {
     String(String),
     Int(i64),
+    Float(f64),
     Bool(bool),
     Array(Vec<AttributeValue>),
+    Map(HashMap<String, AttributeValue>),
 }
 
 #[cfg(test)]
@@ -272,4 +317,15 @@ mod tests {
 
         assert_eq!(ctx.resource.attributes.len(), 1);
     }
+
+    #[test]
+    fn test_operation_names() {
+        for op in [Operation::Create, Operation::Deploy, Operation::Custom(7)] {
+            assert_eq!(Operation::from_name(&op.to_string()), Some(op));
+        }
+        assert_eq!(Operation::Custom(7).to_string(), "custom:7");
+        assert_eq!(Operation::from_name("custom:x"), None);
+        assert_eq!(Operation::from_name("Deploy"), None);
+        assert_ne!(Operation::Custom(0).code(), Operation::Execute.code());
+    }
 }
@@ -0,0 +1,356 @@
+//! Attribute schemas for type checking policies
+//!
+//! A [`Schema`] declares the typed attributes of each resource type, of
+//! actions and of principals. [`Schema::type_env`] turns it into the
+//! [`TypeEnv`] for policies that apply to a set of resource types, which
+//! [`PolicyCompiler::with_types`](crate::compiler::PolicyCompiler::with_types)
+//! checks every condition against before compiling, so that for example
+//! `resource.replicas == "3"` is rejected when `replicas` is an `int`.
+//!
+//! Schemas are usually loaded from JSON, with attribute types written as
+//! [`Type::from_name`] spells them:
+//!
+//! ```json
+//! {
+//!   "resources": [
+//!     { "id": 1, "name": "Deployment",
+//!       "attributes": { "replicas": "int", "env": "string", "labels.*": "string" } }
+//!   ],
+//!   "action": { "ticket": "string" },
+//!   "principal": { "clearance": "int" }
+//! }
+//! ```
+//!
+//! Resource attributes are paths under `resource`, action attributes under
+//! `action.attributes` and principal attributes under `request.principal`. A
+//! trailing `*` declares every field of a map-valued attribute. Once a
+//! section declares anything, fields it does not declare are type errors.
+
+use crate::ast::types::{Type, TypeEnv};
+use crate::rar::ResourceTypeId;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Errors loading a schema
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("Invalid schema: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unknown type '{name}' for {path}")]
+    UnknownType { path: String, name: String },
+
+    #[error("Resource type {0} is declared more than once")]
+    DuplicateResourceType(u32),
+}
+
+/// Typed attributes of a single resource type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceSchema {
+    /// Resource type name, shown in type errors
+    pub name: String,
+    /// Attribute paths (relative to `resource`) and their types
+    pub attributes: HashMap<String, Type>,
+}
+
+impl ResourceSchema {
+    /// Create a resource type with no attributes
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            attributes: HashMap::new(),
+        }
+    }
+
+    /// Declare an attribute
+    pub fn with_attribute(mut self, path: impl Into<String>, typ: Type) -> Self {
+        self.attributes.insert(path.into(), typ);
+        self
+    }
+}
+
+/// Typed attributes of resources, actions and principals
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "SchemaFile")]
+pub struct Schema {
+    resources: HashMap<ResourceTypeId, ResourceSchema>,
+    action: HashMap<String, Type>,
+    principal: HashMap<String, Type>,
+}
+
+impl Schema {
+    /// Create an empty schema, which declares nothing beyond the standard
+    /// environment
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a schema from JSON
+    pub fn from_json(json: &str) -> Result<Self, SchemaError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Declare the attributes of a resource type
+    pub fn with_resource(
+        mut self,
+        resource_type: ResourceTypeId,
+        resource: ResourceSchema,
+    ) -> Self {
+        self.resources.insert(resource_type, resource);
+        self
+    }
+
+    /// Declare an action attribute (`action.attributes.<path>`)
+    pub fn with_action_attribute(mut self, path: impl Into<String>, typ: Type) -> Self {
+        self.action.insert(path.into(), typ);
+        self
+    }
+
+    /// Declare a principal attribute (`request.principal.<path>`)
+    pub fn with_principal_attribute(mut self, path: impl Into<String>, typ: Type) -> Self {
+        self.principal.insert(path.into(), typ);
+        self
+    }
+
+    /// Get the declared attributes of a resource type
+    pub fn resource(&self, resource_type: ResourceTypeId) -> Option<&ResourceSchema> {
+        self.resources.get(&resource_type)
+    }
+
+    /// Type environment for policies applying to `resource_types`
+    ///
+    /// `resource` may use any attribute declared by one of the types; an
+    /// attribute declared with different types is `any`. An empty list means
+    /// every declared type. If one of the types is not declared, resource
+    /// attributes are left unchecked.
+    pub fn type_env(&self, resource_types: &[ResourceTypeId]) -> TypeEnv {
+        let mut env = TypeEnv::standard();
+
+        let resources: Vec<&ResourceSchema> = if resource_types.is_empty() {
+            self.resources.values().collect()
+        } else {
+            resource_types
+                .iter()
+                .map(|id| self.resources.get(id))
+                .collect::<Option<_>>()
+                .unwrap_or_default()
+        };
+        if !resources.is_empty() {
+            let mut attributes: HashMap<&str, Type> = HashMap::new();
+            for resource in &resources {
+                for (path, typ) in &resource.attributes {
+                    attributes
+                        .entry(path)
+                        .and_modify(|declared| {
+                            if declared != typ {
+                                *declared = Type::Any;
+                            }
+                        })
+                        .or_insert_with(|| typ.clone());
+                }
+            }
+            for (path, typ) in attributes {
+                env.bind_field(&format!("resource.{}", path), typ);
+            }
+            if let [resource] = resources.as_slice() {
+                env.bind("resource".to_string(), Type::Resource(resource.name.clone()));
+            }
+            env.close("resource");
+        }
+
+        if !self.action.is_empty() {
+            env.unbind_field("action.attributes.*");
+            for (path, typ) in &self.action {
+                env.bind_field(&format!("action.attributes.{}", path), typ.clone());
+            }
+        }
+
+        if !self.principal.is_empty() {
+            for (path, typ) in &self.principal {
+                env.bind_field(&format!("request.principal.{}", path), typ.clone());
+            }
+            env.close("request.principal");
+        }
+
+        env
+    }
+}
+
+/// The JSON form of a [`Schema`], with types spelled out
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaFile {
+    #[serde(default)]
+    resources: Vec<ResourceFile>,
+    #[serde(default)]
+    action: HashMap<String, String>,
+    #[serde(default)]
+    principal: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResourceFile {
+    id: u32,
+    name: String,
+    #[serde(default)]
+    attributes: HashMap<String, String>,
+}
+
+impl TryFrom<SchemaFile> for Schema {
+    type Error = SchemaError;
+
+    fn try_from(file: SchemaFile) -> Result<Self, SchemaError> {
+        let mut schema = Schema {
+            resources: HashMap::new(),
+            action: parse_types("action.attributes", file.action)?,
+            principal: parse_types("request.principal", file.principal)?,
+        };
+        for resource in file.resources {
+            let attributes = parse_types("resource", resource.attributes)?;
+            let declared = ResourceSchema { name: resource.name, attributes };
+            if schema.resources.insert(ResourceTypeId(resource.id), declared).is_some() {
+                return Err(SchemaError::DuplicateResourceType(resource.id));
+            }
+        }
+        Ok(schema)
+    }
+}
+
+fn parse_types(
+    prefix: &str,
+    names: HashMap<String, String>,
+) -> Result<HashMap<String, Type>, SchemaError> {
+    names
+        .into_iter()
+        .map(|(path, name)| match Type::from_name(&name) {
+            Some(typ) => Ok((path, typ)),
+            None => Err(SchemaError::UnknownType {
+                path: format!("{}.{}", prefix, path),
+                name,
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::types::{TypeChecker, TypeError};
+    use crate::parser::parse::Parser;
+
+    const SCHEMA: &str = r#"{
+        "resources": [
+            { "id": 1, "name": "Deployment",
+              "attributes": { "replicas": "int", "env": "string", "labels.*": "string" } },
+            { "id": 2, "name": "Database",
+              "attributes": { "env": "string", "replicas": "string", "engine": "string" } }
+        ],
+        "action": { "ticket": "string" },
+        "principal": { "clearance": "int" }
+    }"#;
+
+    /// Type errors in `source` when checked against `env`
+    fn check(env: &TypeEnv, source: &str) -> Vec<TypeError> {
+        let expr = Parser::new(source).parse_expression().unwrap();
+        let mut checker = TypeChecker::new(env.clone());
+        checker.check_expression(&expr);
+        checker.errors().to_vec()
+    }
+
+    #[test]
+    fn test_from_json() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        let deployment = ResourceSchema::new("Deployment")
+            .with_attribute("replicas", Type::Int)
+            .with_attribute("env", Type::String)
+            .with_attribute("labels.*", Type::String);
+        assert_eq!(schema.resource(ResourceTypeId(1)), Some(&deployment));
+        assert_eq!(schema.resource(ResourceTypeId(3)), None);
+
+        let built = Schema::new()
+            .with_resource(ResourceTypeId(1), deployment)
+            .with_resource(
+                ResourceTypeId(2),
+                ResourceSchema::new("Database")
+                    .with_attribute("env", Type::String)
+                    .with_attribute("replicas", Type::String)
+                    .with_attribute("engine", Type::String),
+            )
+            .with_action_attribute("ticket", Type::String)
+            .with_principal_attribute("clearance", Type::Int);
+        assert_eq!(schema, built);
+        assert_eq!(Schema::from_json("{}").unwrap(), Schema::new());
+    }
+
+    #[test]
+    fn test_invalid_schemas() {
+        let error = Schema::from_json(r#"{"principal": {"clearance": "integer"}}"#).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Unknown type 'integer' for request.principal.clearance"));
+
+        let duplicate = r#"{"resources": [{"id": 1, "name": "A"}, {"id": 1, "name": "B"}]}"#;
+        assert!(Schema::from_json(duplicate).unwrap_err().to_string().contains("more than once"));
+
+        assert!(matches!(Schema::from_json(r#"{"resource": []}"#), Err(SchemaError::Json(_))));
+    }
+
+    #[test]
+    fn test_resource_attributes() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        let env = schema.type_env(&[ResourceTypeId(1)]);
+        assert_eq!(env.lookup("resource"), Some(&Type::Resource("Deployment".to_string())));
+
+        assert!(
+            check(&env, "resource.replicas >= 3 and resource.labels.team == \"ops\"").is_empty()
+        );
+        assert_eq!(
+            check(&env, "resource.replicas == \"3\""),
+            vec![TypeError::IncompatibleTypes { left: Type::Int, right: Type::String }]
+        );
+        assert_eq!(
+            check(&env, "resource.engine == \"postgres\""),
+            vec![TypeError::InvalidFieldAccess {
+                base: Type::Resource("Deployment".to_string()),
+                field: "engine".to_string(),
+            }]
+        );
+        assert!(check(&env, "resource.type == 1").is_empty());
+    }
+
+    #[test]
+    fn test_multiple_resource_types() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        for env in [schema.type_env(&[ResourceTypeId(1), ResourceTypeId(2)]), schema.type_env(&[])]
+        {
+            // Declared by either type; `replicas` has conflicting types
+            assert!(check(&env, "resource.engine == \"postgres\"").is_empty());
+            assert!(check(&env, "resource.replicas == \"3\"").is_empty());
+            assert_eq!(check(&env, "resource.env == 1").len(), 1);
+            assert_eq!(check(&env, "resource.owner == \"alice\"").len(), 1);
+        }
+
+        // Undeclared resource types leave resource attributes unchecked
+        let env = schema.type_env(&[ResourceTypeId(1), ResourceTypeId(9)]);
+        assert!(check(&env, "resource.owner == 1 and resource.env == 1").is_empty());
+        assert!(check(&Schema::new().type_env(&[]), "resource.replicas == \"3\"").is_empty());
+    }
+
+    #[test]
+    fn test_action_and_principal_attributes() {
+        let env = Schema::from_json(SCHEMA).unwrap().type_env(&[]);
+
+        let source = "action.attributes.ticket == \"OPS-1\" and request.principal.clearance > 2";
+        assert!(check(&env, source).is_empty());
+        assert!(check(&env, "request.principal.id == \"alice\"").is_empty());
+        assert_eq!(check(&env, "action.attributes.priority == 1").len(), 1);
+        assert_eq!(check(&env, "request.principal.clearance == \"top\"").len(), 1);
+        assert_eq!(check(&env, "request.principal.team == \"ops\"").len(), 1);
+
+        // Without declarations both stay open
+        let env = Schema::new().type_env(&[]);
+        assert!(check(&env, "action.attributes.priority == request.principal.team").is_empty());
+    }
+}
@@ -0,0 +1,742 @@
+//! Translate residual policy conditions into SQL `WHERE` filters
+//!
+//! Data services can push authorization into their queries instead of
+//! filtering rows after the fact. [`SqlTranslator`] turns an expression over
+//! `resource.*` paths, typically the residual produced by
+//! [`crate::partial::PartialEvaluator`], into a parameterized fragment:
+//!
+//! ```text
+//! resource.owner == "alice" and not (resource.archived == true)
+//!
+//! Postgres:  ("owner_id" = $1 AND "archived" IS DISTINCT FROM $2)   ["alice", true]
+//! ```
+//!
+//! Values always travel as parameters; only mapped column names appear in the
+//! SQL text. `like` globs become `LIKE` patterns on Postgres and `GLOB`
+//! patterns on SQLite, whose `LIKE` ignores case, and `matches` becomes `~`
+//! on Postgres or `REGEXP` on SQLite, which needs a `regexp()` function loaded.
+//! Membership in a literal array is an `IN` list; set operators on array
+//! columns use Postgres array operators (`= ANY`, `&&`, `<@`). A missing
+//! attribute behaves like `NULL`, and negated conditions are rewritten so that
+//! rows with `NULL` columns match exactly when the policy would treat the
+//! attribute as undefined.
+
+use crate::ast::nodes::{
+    BinaryOp, ComparisonOp, Expression, LogicalOp, Path, Policy, SetOperator, Value,
+};
+use crate::combining::CombiningAlgorithm;
+use crate::partial::{PartialError, PartialEvaluator};
+use crate::rar::EvaluationContext;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SqlError {
+    #[error(transparent)]
+    Partial(#[from] PartialError),
+
+    #[error("No column mapped for {0}")]
+    UnmappedPath(String),
+
+    #[error("Cannot translate to SQL: {0}")]
+    Unsupported(String),
+
+    #[error("Invalid pattern {0:?}: trailing escape")]
+    InvalidPattern(String),
+}
+
+pub type SqlResult<T> = Result<T, SqlError>;
+
+/// SQL flavour, which decides placeholders and null-safe comparisons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SqlDialect {
+    /// `$1` placeholders, `IS [NOT] DISTINCT FROM`
+    #[default]
+    Postgres,
+    /// `?` placeholders, `IS [NOT]`
+    Sqlite,
+}
+
+/// A parameterized `WHERE` fragment
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlFilter {
+    pub sql: String,
+    /// Parameters in placeholder order
+    pub params: Vec<Value>,
+}
+
+/// One side of a comparison
+struct Operand {
+    sql: String,
+    /// Whether the operand can be `NULL` (a column or an expression over one)
+    nullable: bool,
+}
+
+/// Converts expressions over `resource.*` paths into SQL
+#[derive(Debug, Clone, Default)]
+pub struct SqlTranslator {
+    dialect: SqlDialect,
+    columns: HashMap<String, String>,
+    attribute_columns: bool,
+    param_offset: usize,
+}
+
+impl SqlTranslator {
+    /// Create a translator for the given dialect with no column mappings
+    pub fn new(dialect: SqlDialect) -> Self {
+        Self { dialect, ..Default::default() }
+    }
+
+    /// Map a path (e.g. `resource.owner`) to a column expression (e.g. `d.owner_id`)
+    ///
+    /// The column is inserted verbatim, so it must come from trusted configuration.
+    pub fn with_column(mut self, path: impl Into<String>, column: impl Into<String>) -> Self {
+        self.columns.insert(path.into(), column.into());
+        self
+    }
+
+    /// Map unmapped `resource.<name>` paths to the quoted column `"<name>"`
+    pub fn with_attribute_columns(mut self) -> Self {
+        self.attribute_columns = true;
+        self
+    }
+
+    /// Number placeholders after `offset` existing query parameters (Postgres)
+    pub fn with_param_offset(mut self, offset: usize) -> Self {
+        self.param_offset = offset;
+        self
+    }
+
+    /// Translate an expression whose only paths are mapped columns
+    pub fn translate(&self, expr: &Expression) -> SqlResult<SqlFilter> {
+        let mut params = Vec::new();
+        let sql = self.condition(expr, false, &mut params)?;
+        Ok(SqlFilter { sql, params })
+    }
+
+    /// Translate the condition under which `policy` allows requests like `ctx`
+    ///
+    /// Principal, action and request values are substituted from `ctx`; the
+    /// `resource` fields become columns.
+    pub fn translate_policy(
+        &self,
+        policy: &Policy,
+        ctx: &EvaluationContext,
+    ) -> SqlResult<SqlFilter> {
+        self.translate(&PartialEvaluator::new(ctx).policy(policy)?.allow)
+    }
+
+    /// Translate the condition under which the combined decision over `policies` is `Allow`
+    pub fn translate_policies(
+        &self,
+        policies: &[Policy],
+        algorithm: CombiningAlgorithm,
+        ctx: &EvaluationContext,
+    ) -> SqlResult<SqlFilter> {
+        self.translate(&PartialEvaluator::new(ctx).policies(policies, algorithm)?)
+    }
+
+    /// Translate a boolean condition; `negated` pushes a pending `NOT` down to the leaves
+    fn condition(
+        &self,
+        expr: &Expression,
+        negated: bool,
+        params: &mut Vec<Value>,
+    ) -> SqlResult<String> {
+        match expr {
+            Expression::Literal(value) => Ok(constant(value.is_truthy() != negated)),
+
+            Expression::Logical { op: LogicalOp::Not, operands } => match operands.as_slice() {
+                [operand] => self.condition(operand, !negated, params),
+                _ => Err(SqlError::Unsupported("NOT without exactly one operand".to_string())),
+            },
+
+            Expression::Logical { op, operands } => {
+                // De Morgan: a negated AND is an OR of negations and vice versa
+                let joiner = match (op, negated) {
+                    (LogicalOp::And, false) | (LogicalOp::Or, true) => " AND ",
+                    _ => " OR ",
+                };
+                if operands.is_empty() {
+                    return Ok(constant((joiner == " AND ") != negated));
+                }
+                let parts = operands
+                    .iter()
+                    .map(|operand| self.condition(operand, negated, params))
+                    .collect::<SqlResult<Vec<_>>>()?;
+                Ok(format!("({})", parts.join(joiner)))
+            },
+
+            Expression::Binary {
+                left,
+                op: BinaryOp::Comparison(op),
+                right,
+            } => {
+                let left = self.operand(left, params)?;
+                let right = self.operand(right, params)?;
+                Ok(self.comparison(&left, *op, &right, negated))
+            },
+
+            Expression::Binary {
+                left,
+                op: op @ (BinaryOp::Matches | BinaryOp::Like),
+                right,
+            } => {
+                let Expression::Literal(Value::String(source)) = right.as_ref() else {
+                    return Err(SqlError::Unsupported("non-literal pattern".to_string()));
+                };
+                let operand = self.operand(left, params)?;
+                let sql = match (op, self.dialect) {
+                    (BinaryOp::Like, _) => self.glob(&operand, source, params)?,
+                    (_, SqlDialect::Postgres) => {
+                        format!(
+                            "{} ~ {}",
+                            operand.sql,
+                            self.param(Value::String(source.clone()), params)?
+                        )
+                    },
+                    (_, SqlDialect::Sqlite) => {
+                        format!(
+                            "{} REGEXP {}",
+                            operand.sql,
+                            self.param(Value::String(source.clone()), params)?
+                        )
+                    },
+                };
+                Ok(if negated { or_null(format!("NOT ({})", sql), &[&operand]) } else { sql })
+            },
+
+            Expression::Binary { left, op: BinaryOp::Set(op), right } => {
+                self.set_condition(left, *op, right, negated, params)
+            },
+
+            Expression::In { expr, list } => {
+                let operand = self.operand(expr, params)?;
+                if list.is_empty() {
+                    return Ok(constant(negated));
+                }
+                let placeholders = list
+                    .iter()
+                    .map(|value| self.param(value.clone(), params))
+                    .collect::<SqlResult<Vec<_>>>()?
+                    .join(", ");
+                Ok(if negated {
+                    or_null(format!("{} NOT IN ({})", operand.sql, placeholders), &[&operand])
+                } else {
+                    format!("{} IN ({})", operand.sql, placeholders)
+                })
+            },
+
+            // A bare value used as a condition must be true
+            Expression::Path(_) | Expression::Binary { op: BinaryOp::Default, .. } => {
+                let operand = self.operand(expr, params)?;
+                let truth = Operand {
+                    sql: self.param(Value::Bool(true), params)?,
+                    nullable: false,
+                };
+                Ok(self.comparison(&operand, ComparisonOp::Eq, &truth, negated))
+            },
+
+            Expression::Call { name, args } => self.call_condition(name, args, negated, params),
+
+            Expression::Binary { op: BinaryOp::Arithmetic(op), .. } => {
+                Err(SqlError::Unsupported(format!("{} as a condition", op)))
+            },
+
+            Expression::Aggregate { path, func, .. } => {
+                Err(SqlError::Unsupported(format!("{}() over {}", func, path)))
+            },
+        }
+    }
+
+    /// Comparison with the policy's treatment of missing values
+    ///
+    /// `==` on two columns is null-safe because two undefined values are equal;
+    /// negated ordering comparisons also match `NULL`s, since a comparison with
+    /// an undefined value is false and its negation true.
+    fn comparison(
+        &self,
+        left: &Operand,
+        op: ComparisonOp,
+        right: &Operand,
+        negated: bool,
+    ) -> String {
+        let (same, distinct) = match self.dialect {
+            SqlDialect::Postgres => ("IS NOT DISTINCT FROM", "IS DISTINCT FROM"),
+            SqlDialect::Sqlite => ("IS", "IS NOT"),
+        };
+        let (l, r) = (&left.sql, &right.sql);
+
+        match (op, negated) {
+            (ComparisonOp::Eq, false) | (ComparisonOp::Neq, true) => {
+                if left.nullable && right.nullable {
+                    format!("{} {} {}", l, same, r)
+                } else {
+                    format!("{} = {}", l, r)
+                }
+            },
+            (ComparisonOp::Neq, false) | (ComparisonOp::Eq, true) => {
+                if left.nullable || right.nullable {
+                    format!("{} {} {}", l, distinct, r)
+                } else {
+                    format!("{} <> {}", l, r)
+                }
+            },
+            (op, false) => format!("{} {} {}", l, sql_operator(op), r),
+            (op, true) => {
+                or_null(format!("{} {} {}", l, sql_operator(inverse(op)), r), &[left, right])
+            },
+        }
+    }
+
+    /// Set operators: an `IN` list for literal arrays, array operators otherwise
+    fn set_condition(
+        &self,
+        left: &Expression,
+        op: SetOperator,
+        right: &Expression,
+        negated: bool,
+        params: &mut Vec<Value>,
+    ) -> SqlResult<String> {
+        match (op, left, right) {
+            (SetOperator::In, value, Expression::Literal(Value::Array(items)))
+            | (SetOperator::Contains, Expression::Literal(Value::Array(items)), value) => {
+                let membership = Expression::in_list(value.clone(), items.clone());
+                return self.condition(&membership, negated, params);
+            },
+            _ => {},
+        }
+        if self.dialect == SqlDialect::Sqlite {
+            return Err(SqlError::Unsupported(format!("'{}' on array columns in SQLite", op)));
+        }
+
+        let left = self.array_operand(left, params)?;
+        let right = self.array_operand(right, params)?;
+        let sql = match op {
+            SetOperator::In => format!("{} = ANY({})", left.sql, right.sql),
+            SetOperator::Contains => format!("{} = ANY({})", right.sql, left.sql),
+            SetOperator::Intersects => format!("{} && {}", left.sql, right.sql),
+            SetOperator::SubsetOf => format!("{} <@ {}", left.sql, right.sql),
+        };
+        Ok(if negated { or_null(format!("NOT ({})", sql), &[&left, &right]) } else { sql })
+    }
+
+    /// A value or, for a literal array, a Postgres `ARRAY[...]` of parameters
+    fn array_operand(&self, expr: &Expression, params: &mut Vec<Value>) -> SqlResult<Operand> {
+        let Expression::Literal(Value::Array(items)) = expr else {
+            return self.operand(expr, params);
+        };
+        if items.is_empty() {
+            return Err(SqlError::Unsupported("empty array literal".to_string()));
+        }
+        let placeholders = items
+            .iter()
+            .map(|value| self.param(value.clone(), params))
+            .collect::<SqlResult<Vec<_>>>()?;
+        Ok(Operand {
+            sql: format!("ARRAY[{}]", placeholders.join(", ")),
+            nullable: false,
+        })
+    }
+
+    /// Builtins with a direct SQL equivalent, used as conditions
+    ///
+    /// A negated builtin also matches rows where its subject is `NULL`, like
+    /// the negated operators.
+    fn call_condition(
+        &self,
+        name: &str,
+        args: &[Expression],
+        negated: bool,
+        params: &mut Vec<Value>,
+    ) -> SqlResult<String> {
+        let (sql, subject) = match (name, args) {
+            ("exists" | "has", [arg]) => {
+                let operand = self.operand(arg, params)?;
+                let check = if negated { "IS NULL" } else { "IS NOT NULL" };
+                return Ok(format!("{} {}", operand.sql, check));
+            },
+            (
+                "starts_with" | "ends_with" | "contains",
+                [subject, Expression::Literal(Value::String(s))],
+            ) => {
+                let subject = self.operand(subject, params)?;
+                let escaped = s.replace('\\', "\\\\").replace('*', "\\*").replace('?', "\\?");
+                let glob = match name {
+                    "starts_with" => format!("{}*", escaped),
+                    "ends_with" => format!("*{}", escaped),
+                    _ => format!("*{}*", escaped),
+                };
+                (self.glob(&subject, &glob, params)?, subject)
+            },
+            _ => return Err(SqlError::Unsupported(format!("{}() as a condition", name))),
+        };
+        Ok(if negated { or_null(format!("NOT ({})", sql), &[&subject]) } else { sql })
+    }
+
+    /// Case-sensitive match of `subject` against a `like` glob
+    ///
+    /// SQLite's `LIKE` ignores ASCII case, so SQLite uses `GLOB` instead.
+    fn glob(&self, subject: &Operand, glob: &str, params: &mut Vec<Value>) -> SqlResult<String> {
+        Ok(match self.dialect {
+            SqlDialect::Postgres => {
+                let pattern = self.param(Value::String(glob_to_like(glob)?), params)?;
+                format!("{} LIKE {} ESCAPE '\\'", subject.sql, pattern)
+            },
+            SqlDialect::Sqlite => {
+                let pattern = self.param(Value::String(glob_to_sqlite_glob(glob)?), params)?;
+                format!("{} GLOB {}", subject.sql, pattern)
+            },
+        })
+    }
+
+    /// Translate a value-producing expression
+    fn operand(&self, expr: &Expression, params: &mut Vec<Value>) -> SqlResult<Operand> {
+        match expr {
+            Expression::Literal(value) => Ok(Operand {
+                sql: self.param(value.clone(), params)?,
+                nullable: false,
+            }),
+            Expression::Path(path) => Ok(Operand { sql: self.column(path)?, nullable: true }),
+            Expression::Binary { left, op: BinaryOp::Default, right } => {
+                let left = self.operand(left, params)?;
+                let right = self.operand(right, params)?;
+                Ok(Operand {
+                    sql: format!("COALESCE({}, {})", left.sql, right.sql),
+                    nullable: right.nullable,
+                })
+            },
+            Expression::Binary {
+                left,
+                op: BinaryOp::Arithmetic(op),
+                right,
+            } => {
+                let left = self.operand(left, params)?;
+                let right = self.operand(right, params)?;
+                Ok(Operand {
+                    sql: format!("({} {} {})", left.sql, op, right.sql),
+                    nullable: left.nullable || right.nullable,
+                })
+            },
+            Expression::Call { name, args } if name == "lower" && args.len() == 1 => {
+                let arg = self.operand(&args[0], params)?;
+                Ok(Operand {
+                    sql: format!("LOWER({})", arg.sql),
+                    nullable: arg.nullable,
+                })
+            },
+            other => Err(SqlError::Unsupported(format!("{:?} as a value", other))),
+        }
+    }
+
+    fn column(&self, path: &Path) -> SqlResult<String> {
+        let dotted = path.to_string();
+        if let Some(column) = self.columns.get(&dotted) {
+            return Ok(column.clone());
+        }
+        match path.segments.as_slice() {
+            [root, name] if self.attribute_columns && root == "resource" => {
+                Ok(format!("\"{}\"", name.replace('"', "\"\"")))
+            },
+            _ => Err(SqlError::UnmappedPath(dotted)),
+        }
+    }
+
+    /// Add a parameter and return its placeholder
+    fn param(&self, value: Value, params: &mut Vec<Value>) -> SqlResult<String> {
+        if let Value::Array(_) = value {
+            return Err(SqlError::Unsupported("array parameters".to_string()));
+        }
+        params.push(value);
+        Ok(match self.dialect {
+            SqlDialect::Postgres => format!("${}", self.param_offset + params.len()),
+            SqlDialect::Sqlite => "?".to_string(),
+        })
+    }
+}
+
+/// Translate a `like` glob into a `LIKE` pattern escaped with `\`
+fn glob_to_like(glob: &str) -> SqlResult<String> {
+    let mut like = String::with_capacity(glob.len());
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => like.push('%'),
+            '?' => like.push('_'),
+            '\\' => {
+                let escaped =
+                    chars.next().ok_or_else(|| SqlError::InvalidPattern(glob.to_string()))?;
+                if matches!(escaped, '%' | '_' | '\\') {
+                    like.push('\\');
+                }
+                like.push(escaped);
+            },
+            '%' | '_' => {
+                like.push('\\');
+                like.push(c);
+            },
+            c => like.push(c),
+        }
+    }
+    Ok(like)
+}
+
+/// Translate a `like` glob into a SQLite `GLOB` pattern, where literal
+/// metacharacters are bracketed (`[*]`)
+fn glob_to_sqlite_glob(glob: &str) -> SqlResult<String> {
+    let mut pattern = String::with_capacity(glob.len());
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' => pattern.push(c),
+            '\\' => {
+                let escaped =
+                    chars.next().ok_or_else(|| SqlError::InvalidPattern(glob.to_string()))?;
+                push_glob_literal(&mut pattern, escaped);
+            },
+            c => push_glob_literal(&mut pattern, c),
+        }
+    }
+    Ok(pattern)
+}
+
+fn push_glob_literal(pattern: &mut String, c: char) {
+    if matches!(c, '*' | '?' | '[') {
+        pattern.push('[');
+        pattern.push(c);
+        pattern.push(']');
+    } else {
+        pattern.push(c);
+    }
+}
+
+/// A condition that is always true or always false, in portable SQL
+fn constant(value: bool) -> String {
+    if value { "1 = 1" } else { "1 = 0" }.to_string()
+}
+
+/// Extend a condition to also match rows where any nullable operand is `NULL`
+fn or_null(sql: String, operands: &[&Operand]) -> String {
+    let nulls: Vec<String> = operands
+        .iter()
+        .filter(|o| o.nullable)
+        .map(|o| format!("{} IS NULL", o.sql))
+        .collect();
+    if nulls.is_empty() {
+        sql
+    } else {
+        format!("({} OR {})", sql, nulls.join(" OR "))
+    }
+}
+
+fn sql_operator(op: ComparisonOp) -> &'static str {
+    match op {
+        ComparisonOp::Eq => "=",
+        ComparisonOp::Neq => "<>",
+        ComparisonOp::Lt => "<",
+        ComparisonOp::LtEq => "<=",
+        ComparisonOp::Gt => ">",
+        ComparisonOp::GtEq => ">=",
+    }
+}
+
+/// The ordering comparison that holds exactly when `op` does not (for defined values)
+fn inverse(op: ComparisonOp) -> ComparisonOp {
+    match op {
+        ComparisonOp::Eq => ComparisonOp::Neq,
+        ComparisonOp::Neq => ComparisonOp::Eq,
+        ComparisonOp::Lt => ComparisonOp::GtEq,
+        ComparisonOp::LtEq => ComparisonOp::Gt,
+        ComparisonOp::Gt => ComparisonOp::LtEq,
+        ComparisonOp::GtEq => ComparisonOp::Lt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::rar::Principal;
+
+    fn expr(source: &str) -> Expression {
+        Parser::new(source).parse_expression().unwrap()
+    }
+
+    fn s(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_translate_comparisons() {
+        let translator = SqlTranslator::new(SqlDialect::Postgres)
+            .with_column("resource.owner", "d.owner_id")
+            .with_attribute_columns();
+
+        let filter = translator
+            .translate(&expr(r#"resource.owner == "alice" and resource.priority >= 3"#))
+            .unwrap();
+        assert_eq!(filter.sql, r#"(d.owner_id = $1 AND "priority" >= $2)"#);
+        assert_eq!(filter.params, vec![s("alice"), Value::Int(3)]);
+
+        let filter = translator
+            .translate(&expr(
+                r#"resource.env in ["dev", "staging"] or resource.team == resource.owner"#,
+            ))
+            .unwrap();
+        assert_eq!(filter.sql, r#"("env" IN ($1, $2) OR "team" IS NOT DISTINCT FROM d.owner_id)"#);
+
+        let filter = translator.translate(&expr(r#"resource.tier ?? "free" != "free""#)).unwrap();
+        assert_eq!(filter.sql, r#"COALESCE("tier", $1) <> $2"#);
+
+        let translator = translator.with_param_offset(2);
+        let filter = translator.translate(&expr("resource.priority < 3")).unwrap();
+        assert_eq!(filter.sql, r#""priority" < $3"#);
+    }
+
+    #[test]
+    fn test_translate_negation_matches_missing_values() {
+        let translator = SqlTranslator::new(SqlDialect::Sqlite).with_attribute_columns();
+
+        // A missing attribute is not equal to anything, so the negation matches NULL
+        let filter = translator.translate(&expr(r#"not (resource.env == "prod")"#)).unwrap();
+        assert_eq!(filter.sql, r#""env" IS NOT ?"#);
+
+        let filter = translator
+            .translate(&expr(r#"not (resource.priority > 3 or resource.env in ["prod"])"#))
+            .unwrap();
+        assert_eq!(
+            filter.sql,
+            r#"(("priority" <= ? OR "priority" IS NULL) AND ("env" NOT IN (?) OR "env" IS NULL))"#
+        );
+        assert_eq!(filter.params, vec![Value::Int(3), s("prod")]);
+
+        let filter = translator.translate(&expr("not exists(resource.owner)")).unwrap();
+        assert_eq!(filter.sql, r#""owner" IS NULL"#);
+
+        let filter = translator
+            .translate(&expr(r#"not starts_with(resource.path, "/tmp")"#))
+            .unwrap();
+        assert_eq!(filter.sql, r#"(NOT ("path" GLOB ?) OR "path" IS NULL)"#);
+    }
+
+    #[test]
+    fn test_translate_functions_and_errors() {
+        let translator = SqlTranslator::new(SqlDialect::Postgres).with_attribute_columns();
+
+        let filter =
+            translator.translate(&expr(r#"starts_with(resource.path, "/tmp_%")"#)).unwrap();
+        assert_eq!(filter.sql, r#""path" LIKE $1 ESCAPE '\'"#);
+        assert_eq!(filter.params, vec![s("/tmp\\_\\%%")]);
+
+        let filter = translator
+            .translate(&expr(
+                r#"resource.path like "/tmp/*_?" and not (resource.name matches "^x")"#,
+            ))
+            .unwrap();
+        assert_eq!(
+            filter.sql,
+            r#"("path" LIKE $1 ESCAPE '\' AND (NOT ("name" ~ $2) OR "name" IS NULL))"#
+        );
+        assert_eq!(filter.params, vec![s("/tmp/%\\__"), s("^x")]);
+
+        let filter = translator.translate(&expr("resource.used + 2 <= resource.quota")).unwrap();
+        assert_eq!(filter.sql, r#"("used" + $1) <= "quota""#);
+
+        let filter = translator.translate(&expr(r#"lower(resource.name) == "ops""#)).unwrap();
+        assert_eq!(filter.sql, r#"LOWER("name") = $1"#);
+
+        assert_eq!(
+            SqlTranslator::new(SqlDialect::Postgres).translate(&expr("resource.owner == 1")),
+            Err(SqlError::UnmappedPath("resource.owner".to_string()))
+        );
+        assert!(matches!(
+            translator.translate(&expr("request.principal.id == resource.owner")),
+            Err(SqlError::UnmappedPath(_))
+        ));
+        assert!(matches!(
+            translator.translate(&expr(r#"any(resource.tags where item == "x")"#)),
+            Err(SqlError::Unsupported(_))
+        ));
+        assert_eq!(
+            translator.translate(&expr(r#"resource.path like "/tmp\\""#)),
+            Err(SqlError::InvalidPattern("/tmp\\".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_translate_sqlite_globs_are_case_sensitive() {
+        let translator = SqlTranslator::new(SqlDialect::Sqlite).with_attribute_columns();
+
+        // `like` is case-sensitive, so "prod-eu" must not match "Prod-*"
+        let filter = translator.translate(&expr(r#"resource.name like "Prod-*""#)).unwrap();
+        assert_eq!(filter.sql, r#""name" GLOB ?"#);
+        assert_eq!(filter.params, vec![s("Prod-*")]);
+
+        // Literal metacharacters are bracketed
+        let filter = translator
+            .translate(&expr(
+                r#"resource.name like "Q\\*[1]?" and ends_with(resource.path, "*.Md")"#,
+            ))
+            .unwrap();
+        assert_eq!(filter.sql, r#"("name" GLOB ? AND "path" GLOB ?)"#);
+        assert_eq!(filter.params, vec![s("Q[*][[]1]?"), s("*[*].Md")]);
+    }
+
+    #[test]
+    fn test_translate_set_operators() {
+        let policy = Parser::new(
+            r#"policy Teams: "Team members see unrestricted resources"
+  triggers when true
+  requires resource.team in request.principal.roles and not (resource.labels intersects ["secret"])"#,
+        )
+        .parse_policy()
+        .unwrap();
+        let mut ctx = EvaluationContext::default();
+        ctx.request.principal = Principal::new("alice").with_role("red").with_role("blue");
+
+        // Known roles turn the membership into an `IN` list
+        let translator = SqlTranslator::new(SqlDialect::Postgres).with_attribute_columns();
+        let filter = translator.translate_policy(&policy, &ctx).unwrap();
+        assert_eq!(
+            filter.sql,
+            r#"("team" IN ($1, $2) AND (NOT ("labels" && ARRAY[$3]) OR "labels" IS NULL))"#
+        );
+        assert_eq!(filter.params, vec![s("red"), s("blue"), s("secret")]);
+
+        let filter = translator.translate(&expr(r#"resource.labels contains "pii""#)).unwrap();
+        assert_eq!(filter.sql, r#"$1 = ANY("labels")"#);
+
+        let sqlite = SqlTranslator::new(SqlDialect::Sqlite).with_attribute_columns();
+        let filter = sqlite.translate(&expr(r#"["a", "b"] contains resource.team"#)).unwrap();
+        assert_eq!(filter.sql, r#""team" IN (?, ?)"#);
+        assert!(matches!(sqlite.translate_policy(&policy, &ctx), Err(SqlError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_translate_policies() {
+        let policies: Vec<Policy> = [
+            r#"policy Owners: "Owners may read"
+  triggers when true
+  requires resource.owner == request.principal.id"#,
+            r#"policy Archived: "Archived resources are hidden"
+  triggers when resource.archived == true
+  denies"#,
+        ]
+        .iter()
+        .map(|source| Parser::new(source).parse_policy().unwrap())
+        .collect();
+
+        let mut ctx = EvaluationContext::default();
+        ctx.request.principal = Principal::new("alice");
+        let translator = SqlTranslator::new(SqlDialect::Postgres).with_attribute_columns();
+
+        let filter = translator
+            .translate_policies(&policies, CombiningAlgorithm::DenyOverrides, &ctx)
+            .unwrap();
+        assert_eq!(filter.sql, r#"("owner" = $1 AND "archived" IS DISTINCT FROM $2)"#);
+        assert_eq!(filter.params, vec![s("alice"), Value::Bool(true)]);
+
+        let filter = translator.translate_policy(&policies[1], &ctx).unwrap();
+        assert_eq!(filter.sql, "1 = 0");
+    }
+}
@@ -27,11 +27,17 @@
 //! └─────────────┘
 //! ```
 
+use crate::ast::{Item, Policy};
 use crate::bytecode::CompiledPolicy;
+use crate::bytecode::Value;
+use crate::combining::{Combiner, CombiningAlgorithm};
 use crate::compiler::PolicyCompiler;
-use crate::interpreter::{FieldMapping, Interpreter};
-use crate::parser::parse::Parser;
+use crate::explain::Explanation;
+use crate::functions::{Function, FunctionOptions, FunctionRegistry, FunctionSignature};
+use crate::interpreter::{FieldMapping, Interpreter, Outcome};
+use crate::parser::parse::{parse_module, Parser};
 use crate::rar::{EvaluationContext, ResourceTypeId};
+use crate::schema::Schema;
 use crate::{Decision, Result};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use std::collections::HashMap;
@@ -50,6 +56,18 @@ pub struct PolicySnapshot {
 
     /// Index: resource_type_id -> policy indices
     index: HashMap<ResourceTypeId, Vec<usize>>,
+
+    /// Default combining algorithm
+    combining: CombiningAlgorithm,
+
+    /// Per-resource-type combining algorithm overrides
+    resource_combining: HashMap<ResourceTypeId, CombiningAlgorithm>,
+
+    /// Functions the policies were compiled against
+    functions: Arc<FunctionRegistry>,
+
+    /// Schema policies are type checked against when loaded
+    schema: Option<Arc<Schema>>,
 }
 
 /// Pre-compiled policy entry
@@ -66,6 +84,28 @@ pub struct PolicyEntry {
 
     /// Resource types this policy applies to
     pub resource_types: Vec<ResourceTypeId>,
+
+    /// Package the policy was loaded from, if any
+    pub package: Option<String>,
+
+    /// Policy set the policy belongs to, if any
+    pub policy_set: Option<Arc<PolicySetInfo>>,
+
+    /// Parsed policy, kept to recheck it when the schema changes
+    pub ast: Option<Arc<Policy>>,
+}
+
+/// Policy set shared by its member entries
+///
+/// Members of a set are stored contiguously and are combined with the set's
+/// algorithm before the set's decision is combined with everything else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicySetInfo {
+    /// Policy set name (qualified with its package)
+    pub name: String,
+
+    /// Algorithm used to combine the member policies
+    pub combining: CombiningAlgorithm,
 }
 
 impl PolicySnapshot {
@@ -75,6 +115,10 @@ impl PolicySnapshot {
             version: 0,
             policies: Vec::new(),
             index: HashMap::new(),
+            combining: CombiningAlgorithm::default(),
+            resource_combining: HashMap::new(),
+            functions: FunctionRegistry::builtins(),
+            schema: None,
         }
     }
 
@@ -88,7 +132,39 @@ impl PolicySnapshot {
             }
         }
 
-        Self { version, policies, index }
+        Self {
+            version,
+            policies,
+            index,
+            combining: CombiningAlgorithm::default(),
+            resource_combining: HashMap::new(),
+            functions: FunctionRegistry::builtins(),
+            schema: None,
+        }
+    }
+
+    /// Set the default combining algorithm
+    pub fn with_combining_algorithm(mut self, algorithm: CombiningAlgorithm) -> Self {
+        self.combining = algorithm;
+        self
+    }
+
+    /// Get the combining algorithm used for a resource type
+    #[inline]
+    pub fn combining_algorithm_for(&self, resource_type: ResourceTypeId) -> CombiningAlgorithm {
+        self.resource_combining.get(&resource_type).copied().unwrap_or(self.combining)
+    }
+
+    /// Get the function registry used to compile and evaluate policies
+    #[inline]
+    pub fn functions(&self) -> &Arc<FunctionRegistry> {
+        &self.functions
+    }
+
+    /// Get the schema policies are type checked against, if any
+    #[inline]
+    pub fn schema(&self) -> Option<&Arc<Schema>> {
+        self.schema.as_ref()
     }
 
     /// Get all policies that apply to a resource type
@@ -130,6 +206,28 @@ pub enum UpdateRequest {
 
     /// Replace all policies
     ReplaceAll { policies: Vec<(String, String, Vec<ResourceTypeId>)> },
+
+    /// Load every policy and policy set in a source file as a unit
+    ///
+    /// Names are qualified with the file's `package`. If the file declares a
+    /// package, all policies previously loaded from that package are replaced.
+    LoadModule { source: String, resource_types: Vec<ResourceTypeId> },
+
+    /// Remove every policy loaded from a package
+    RemovePackage { package: String },
+
+    /// Set the combining algorithm, either the default (`resource_type: None`)
+    /// or an override for a single resource type
+    SetCombiningAlgorithm { resource_type: Option<ResourceTypeId>, algorithm: CombiningAlgorithm },
+
+    /// Register a host function for policies loaded afterwards
+    RegisterFunction { function: Function },
+
+    /// Type check policies against a schema
+    ///
+    /// Loaded policies are recompiled against it first; if any of them fails
+    /// to type check, the update fails and the store is left unchanged.
+    SetSchema { schema: Schema },
 }
 
 /// Result of an update operation
@@ -208,43 +306,98 @@ impl PolicyDataStore {
     #[inline]
     pub fn evaluate(&self, ctx: &EvaluationContext) -> Result<Decision> {
         let snap = self.snapshot();
+        Ok(Self::evaluate_with(&snap, ctx, |policy_entry| {
+            Self::interpreter(policy_entry, &snap.functions).run(&policy_entry.bytecode, ctx)
+        }))
+    }
+
+    /// Evaluate like [`PolicyDataStore::evaluate`] and explain how each policy
+    /// and condition contributed to the decision
+    pub fn evaluate_explain(&self, ctx: &EvaluationContext) -> Result<(Decision, Explanation)> {
+        let snap = self.snapshot();
+        let mut explanation = Explanation::default();
+        let decision = Self::evaluate_with(&snap, ctx, |policy_entry| {
+            let mut trace = Self::interpreter(policy_entry, &snap.functions).explain(
+                &policy_entry.name,
+                &policy_entry.bytecode,
+                ctx,
+            );
+            trace.policy_set = policy_entry.policy_set.as_ref().map(|set| set.name.clone());
+            let result = trace.result();
+            explanation.policies.push(trace);
+            result
+        });
+        Ok((decision, explanation))
+    }
+
+    #[inline]
+    fn evaluate_with<F>(snap: &PolicySnapshot, ctx: &EvaluationContext, mut run: F) -> Decision
+    where
+        F: FnMut(&PolicyEntry) -> std::result::Result<Outcome, String>,
+    {
         let policies = snap.policies_for_resource(ctx.resource.type_id);
 
+        // Nothing applies, as when no policy's triggers match
         if policies.is_empty() {
-            return Ok(
-                Decision::deny().with_reason("No policies found for resource type".to_string())
-            );
+            return Decision::not_applicable()
+                .with_reason("No policies found for resource type".to_string());
         }
 
-        // Evaluate all applicable policies
-        let mut allow = false;
-        let mut matched_policies = Vec::new();
-
-        for policy_entry in policies {
-            let mut interp = Interpreter::new(policy_entry.field_mapping.clone());
-            match interp.evaluate(&policy_entry.bytecode, ctx) {
-                Ok(result) => {
-                    if result {
-                        allow = true;
-                        matched_policies.push(policy_entry.name.clone());
+        let mut combiner = Combiner::new(snap.combining_algorithm_for(ctx.resource.type_id));
+
+        // Policy set members are contiguous, so each set is one chunk
+        let chunks = policies.chunk_by(|a, b| match (&a.policy_set, &b.policy_set) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        });
+
+        for chunk in chunks {
+            let done = match &chunk[0].policy_set {
+                Some(set) => {
+                    let mut set_combiner = Combiner::new(set.combining);
+                    for policy_entry in chunk {
+                        let result = run(policy_entry);
+                        let effects = &policy_entry.bytecode.effects;
+                        if set_combiner.add_policy(&policy_entry.name, effects, result) {
+                            break;
+                        }
                     }
+                    combiner.add_decision(set_combiner.finish())
                 },
-                Err(e) => {
-                    return Err(crate::Error::EvaluationError(format!(
-                        "Policy '{}' failed: {}",
-                        policy_entry.name, e
-                    )));
+                None => {
+                    combiner.add_policy(&chunk[0].name, &chunk[0].bytecode.effects, run(chunk[0]))
                 },
+            };
+
+            if done {
+                break;
             }
         }
 
-        if allow {
-            let mut decision = Decision::allow();
-            decision.matched_policies = matched_policies;
-            Ok(decision)
-        } else {
-            Ok(Decision::deny().with_reason("No policies allowed access".to_string()))
-        }
+        combiner.finish()
+    }
+
+    /// Interpreter for a single policy entry
+    #[inline]
+    fn interpreter(policy_entry: &PolicyEntry, functions: &Arc<FunctionRegistry>) -> Interpreter {
+        Interpreter::new(policy_entry.field_mapping.clone()).with_functions(Arc::clone(functions))
+    }
+
+    /// Register a host function callable from policies (blocking)
+    ///
+    /// Only policies added after registration can call the function.
+    pub fn register_function<F>(
+        &self,
+        name: &str,
+        signature: FunctionSignature,
+        options: FunctionOptions,
+        native: F,
+    ) -> UpdateResult
+    where
+        F: Fn(&[Value]) -> std::result::Result<Value, String> + Send + Sync + 'static,
+    {
+        let function = Function::new(name, signature, options, native);
+        self.update_sync(UpdateRequest::RegisterFunction { function })
     }
 
     /// Request an update (non-blocking)
@@ -295,10 +448,21 @@ impl PolicyDataStore {
         let current = Arc::clone(&*snapshot.read().unwrap());
         let new_version = current.version + 1;
 
+        let mut combining = current.combining;
+        let mut resource_combining = current.resource_combining.clone();
+        let mut functions = Arc::clone(&current.functions);
+        let mut schema = current.schema.clone();
+
         let new_policies = match request {
             UpdateRequest::AddPolicy { name, source, resource_types } => {
                 // Compile the policy
-                let entry = Self::compile_policy(&name, &source, resource_types)?;
+                let entry = Self::compile_policy(
+                    &name,
+                    &source,
+                    resource_types,
+                    &functions,
+                    schema.as_deref(),
+                )?;
 
                 // Add to existing policies
                 let mut policies = current.policies.clone();
@@ -315,15 +479,86 @@ impl PolicyDataStore {
                 // Compile all new policies
                 let mut policies = Vec::with_capacity(new_policy_specs.len());
                 for (name, source, resource_types) in new_policy_specs {
-                    let entry = Self::compile_policy(&name, &source, resource_types)?;
+                    let entry = Self::compile_policy(
+                        &name,
+                        &source,
+                        resource_types,
+                        &functions,
+                        schema.as_deref(),
+                    )?;
                     policies.push(entry);
                 }
                 policies
             },
+
+            UpdateRequest::LoadModule { source, resource_types } => {
+                let (package, entries) =
+                    Self::compile_module(&source, resource_types, &functions, schema.as_deref())?;
+
+                // Replace the package as a whole so a reload never leaves stale policies
+                let mut policies: Vec<PolicyEntry> = current
+                    .policies
+                    .iter()
+                    .filter(|p| package.is_none() || p.package != package)
+                    .cloned()
+                    .collect();
+                policies.extend(entries);
+                policies
+            },
+
+            UpdateRequest::RemovePackage { package } => current
+                .policies
+                .iter()
+                .filter(|p| p.package.as_deref() != Some(package.as_str()))
+                .cloned()
+                .collect(),
+
+            UpdateRequest::SetCombiningAlgorithm { resource_type, algorithm } => {
+                match resource_type {
+                    Some(resource_type) => {
+                        resource_combining.insert(resource_type, algorithm);
+                    },
+                    None => combining = algorithm,
+                }
+                current.policies.clone()
+            },
+
+            UpdateRequest::RegisterFunction { function } => {
+                Arc::make_mut(&mut functions).add(function)?;
+                current.policies.clone()
+            },
+
+            UpdateRequest::SetSchema { schema: new_schema } => {
+                let policies = current
+                    .policies
+                    .iter()
+                    .map(|entry| match &entry.ast {
+                        Some(ast) => Ok(PolicyEntry {
+                            package: entry.package.clone(),
+                            policy_set: entry.policy_set.clone(),
+                            ..Self::compile_ast(
+                                &entry.name,
+                                ast,
+                                entry.resource_types.clone(),
+                                &functions,
+                                Some(&new_schema),
+                            )?
+                        }),
+                        None => Ok(entry.clone()),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                schema = Some(Arc::new(new_schema));
+                policies
+            },
         };
 
-        // Create new snapshot
-        let new_snapshot = Arc::new(PolicySnapshot::new(new_version, new_policies));
+        // Create new snapshot, carrying over the combining configuration
+        let mut new_snapshot = PolicySnapshot::new(new_version, new_policies);
+        new_snapshot.combining = combining;
+        new_snapshot.resource_combining = resource_combining;
+        new_snapshot.functions = functions;
+        new_snapshot.schema = schema;
+        let new_snapshot = Arc::new(new_snapshot);
 
         // Atomic swap
         *snapshot.write().unwrap() = new_snapshot;
@@ -336,30 +571,98 @@ impl PolicyDataStore {
         name: &str,
         source: &str,
         resource_types: Vec<ResourceTypeId>,
+        functions: &Arc<FunctionRegistry>,
+        schema: Option<&Schema>,
     ) -> Result<PolicyEntry> {
         let mut parser = Parser::new(source);
         let ast = parser.parse_policy().map_err(|e| {
             crate::Error::ParseError(format!("Failed to parse policy '{}': {}", name, e))
         })?;
 
+        Self::compile_ast(name, &ast, resource_types, functions, schema)
+    }
+
+    /// Compile every policy in a source file, keeping policy sets together
+    ///
+    /// Entries follow source order, which first-applicable combining relies
+    /// on. Returns the file's package along with the compiled entries.
+    fn compile_module(
+        source: &str,
+        resource_types: Vec<ResourceTypeId>,
+        functions: &Arc<FunctionRegistry>,
+        schema: Option<&Schema>,
+    ) -> Result<(Option<String>, Vec<PolicyEntry>)> {
+        let module = parse_module(source)
+            .map_err(|e| crate::Error::ParseError(format!("Failed to parse module: {}", e)))?;
+
+        let mut entries = Vec::with_capacity(module.policy_count());
+
+        for item in &module.items {
+            let info = match item {
+                Item::PolicySet(set) => {
+                    let combining = match &set.combining {
+                        Some(name) => CombiningAlgorithm::from_name(name).ok_or_else(|| {
+                            crate::Error::ParseError(format!(
+                                "Unknown combining algorithm '{}'",
+                                name
+                            ))
+                        })?,
+                        None => CombiningAlgorithm::default(),
+                    };
+                    Some(Arc::new(PolicySetInfo {
+                        name: module.qualified_name(&set.name),
+                        combining,
+                    }))
+                },
+                Item::Policy(_) => None,
+            };
+
+            for policy in item.policies() {
+                let mut entry = Self::compile_ast(
+                    &module.qualified_name(&policy.name),
+                    policy,
+                    resource_types.clone(),
+                    functions,
+                    schema,
+                )?;
+                entry.policy_set = info.clone();
+                entries.push(entry);
+            }
+        }
+
+        for entry in &mut entries {
+            entry.package = module.package.clone();
+        }
+
+        Ok((module.package, entries))
+    }
+
+    /// Compile a parsed policy, type checking it first if there is a schema
+    fn compile_ast(
+        name: &str,
+        ast: &Policy,
+        resource_types: Vec<ResourceTypeId>,
+        functions: &Arc<FunctionRegistry>,
+        schema: Option<&Schema>,
+    ) -> Result<PolicyEntry> {
         // Use a random policy ID (or could hash the name)
         let policy_id = 0; // TODO: use proper ID generation
-        let compiler = PolicyCompiler::new(policy_id);
-        let bytecode = compiler.compile(&ast).map_err(|e| {
+        let mut compiler = PolicyCompiler::new(policy_id).with_functions(Arc::clone(functions));
+        if let Some(schema) = schema {
+            compiler = compiler.with_types(schema.type_env(&resource_types));
+        }
+        let (bytecode, field_mapping) = compiler.compile_with_field_map(ast).map_err(|e| {
             crate::Error::CompilationError(format!("Failed to compile policy '{}': {}", name, e))
         })?;
-        let field_mapping = bytecode
-            .constants
-            .iter()
-            .enumerate()
-            .map(|(idx, _)| (idx as u16, vec![]))
-            .collect();
 
         Ok(PolicyEntry {
             name: name.to_string(),
             bytecode: Arc::new(bytecode),
             field_mapping,
             resource_types,
+            package: None,
+            policy_set: None,
+            ast: Some(Arc::new(ast.clone())),
         })
     }
 
@@ -402,6 +705,9 @@ mod tests {
             bytecode: Arc::new(CompiledPolicy::new(1)),
             field_mapping: HashMap::new(),
             resource_types: vec![ResourceTypeId(1)],
+            package: None,
+            policy_set: None,
+            ast: None,
         };
 
         let snap = PolicySnapshot::new(1, vec![entry]);
@@ -417,6 +723,9 @@ mod tests {
             bytecode: Arc::new(CompiledPolicy::new(1)),
             field_mapping: HashMap::new(),
             resource_types: vec![ResourceTypeId(1)],
+            package: None,
+            policy_set: None,
+            ast: None,
         };
 
         let snap = PolicySnapshot::new(1, vec![entry]);
@@ -431,6 +740,9 @@ mod tests {
             bytecode: Arc::new(CompiledPolicy::new(1)),
             field_mapping: HashMap::new(),
             resource_types: vec![ResourceTypeId(1)],
+            package: None,
+            policy_set: None,
+            ast: None,
         };
 
         let entry2 = PolicyEntry {
@@ -438,6 +750,9 @@ mod tests {
             bytecode: Arc::new(CompiledPolicy::new(2)),
             field_mapping: HashMap::new(),
             resource_types: vec![ResourceTypeId(2)],
+            package: None,
+            policy_set: None,
+            ast: None,
         };
 
         let snap = PolicySnapshot::new(1, vec![entry1, entry2]);
@@ -462,6 +777,16 @@ mod tests {
         assert!(snap.is_empty());
     }
 
+    #[test]
+    fn test_data_store_no_policies_not_applicable() {
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let decision = store.evaluate(&EvaluationContext::default()).unwrap();
+        assert_eq!(decision.kind, DecisionKind::NotApplicable);
+        assert!(decision.reason.is_some());
+    }
+
     #[test]
     fn test_data_store_stats() {
         let store = PolicyDataStore::new(1);
@@ -726,4 +1051,410 @@ mod tests {
         assert_eq!(stats.update_failures, 0);
         assert_eq!(stats.current_version, 2);
     }
+
+    #[test]
+    fn test_data_store_evaluate_excludes_non_applicable() {
+        use crate::rar::AttributeValue;
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+
+        let source = r#"
+            policy ApprovedDeployments: "Deployments must be approved"
+            triggers when resource.kind == "deployment"
+            requires resource.approved == true
+        "#;
+
+        let _ = store.update_sync(UpdateRequest::AddPolicy {
+            name: "approved_deployments".to_string(),
+            source: source.to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+
+        let ctx_for = |kind: &str, approved: bool| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource.type_id = ResourceTypeId(1);
+            ctx.resource
+                .attributes
+                .insert("kind".to_string(), AttributeValue::String(kind.to_string()));
+            ctx.resource
+                .attributes
+                .insert("approved".to_string(), AttributeValue::Bool(approved));
+            ctx
+        };
+
+        let decision = store.evaluate(&ctx_for("deployment", true)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["approved_deployments".to_string()]);
+
+        let decision = store.evaluate(&ctx_for("deployment", false)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.reason.as_deref(), Some("One or more policies denied the request"));
+
+        // Trigger does not match: the policy is not applicable rather than evaluated
+        let decision = store.evaluate(&ctx_for("service", true)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::NotApplicable);
+        assert!(decision.matched_policies.is_empty());
+        assert_eq!(decision.reason.as_deref(), Some("No applicable policies for request"));
+    }
+
+    #[test]
+    fn test_data_store_combining_algorithm() {
+        use crate::rar::AttributeValue;
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let allow = r#"
+            policy AllowDeployments: "Deployments are allowed"
+            triggers when resource.kind == "deployment"
+            requires resource.kind == "deployment"
+        "#;
+        let deny = r#"
+            policy DenyDeployments: "Deployments are denied"
+            triggers when resource.kind == "deployment"
+            denies with reason "frozen"
+        "#;
+
+        let _ = store.update_sync(UpdateRequest::ReplaceAll {
+            policies: vec![
+                (
+                    "allow".to_string(),
+                    allow.to_string(),
+                    vec![ResourceTypeId(1), ResourceTypeId(2)],
+                ),
+                ("deny".to_string(), deny.to_string(), vec![ResourceTypeId(1), ResourceTypeId(2)]),
+            ],
+        });
+
+        let ctx_for = |type_id: u32| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource.type_id = ResourceTypeId(type_id);
+            ctx.resource
+                .attributes
+                .insert("kind".to_string(), AttributeValue::String("deployment".to_string()));
+            ctx
+        };
+
+        // Deny-overrides by default
+        assert_eq!(store.evaluate(&ctx_for(1)).unwrap().kind, DecisionKind::Deny);
+
+        let _ = store.update_sync(UpdateRequest::SetCombiningAlgorithm {
+            resource_type: None,
+            algorithm: CombiningAlgorithm::PermitOverrides,
+        });
+        let _ = store.update_sync(UpdateRequest::SetCombiningAlgorithm {
+            resource_type: Some(ResourceTypeId(2)),
+            algorithm: CombiningAlgorithm::FirstApplicable,
+        });
+        assert_eq!(store.evaluate(&ctx_for(1)).unwrap().kind, DecisionKind::Allow);
+
+        // Configuration survives later policy updates
+        let _ = store.update_sync(UpdateRequest::RemovePolicy { name: "allow".to_string() });
+        let snap = store.snapshot();
+        assert_eq!(
+            snap.combining_algorithm_for(ResourceTypeId(1)),
+            CombiningAlgorithm::PermitOverrides
+        );
+        assert_eq!(
+            snap.combining_algorithm_for(ResourceTypeId(2)),
+            CombiningAlgorithm::FirstApplicable
+        );
+        assert_eq!(store.evaluate(&ctx_for(2)).unwrap().kind, DecisionKind::Deny);
+    }
+
+    #[test]
+    fn test_data_store_load_module() {
+        use crate::rar::AttributeValue;
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let source = r#"
+package acme
+
+policy_set Deployments: "Deployment controls"
+combining "first-applicable"
+{
+  policy AllowApproved: "Approved deployments are allowed"
+    triggers when resource.kind == "deployment"
+    requires resource.approved == true
+
+  policy DenyAll: "Everything else is denied"
+    triggers when resource.kind == "deployment"
+    denies
+}
+
+policy Services: "Services are always allowed"
+  triggers when resource.kind == "service"
+  requires resource.kind == "service"
+"#;
+
+        let result = store.update_sync(UpdateRequest::LoadModule {
+            source: source.to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+        assert!(matches!(result, UpdateResult::Success { version: 1 }));
+
+        let snap = store.snapshot();
+        assert_eq!(snap.len(), 3);
+        let member = snap.get_policy("acme.AllowApproved").unwrap();
+        assert_eq!(member.package.as_deref(), Some("acme"));
+        assert_eq!(member.policy_set.as_ref().unwrap().name, "acme.Deployments");
+        assert!(snap.get_policy("acme.Services").unwrap().policy_set.is_none());
+
+        let ctx_for = |kind: &str, approved: bool| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource.type_id = ResourceTypeId(1);
+            ctx.resource
+                .attributes
+                .insert("kind".to_string(), AttributeValue::String(kind.to_string()));
+            ctx.resource
+                .attributes
+                .insert("approved".to_string(), AttributeValue::Bool(approved));
+            ctx
+        };
+
+        // The set's first-applicable algorithm lets the allow win over the later deny
+        let decision = store.evaluate(&ctx_for("deployment", true)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["acme.AllowApproved".to_string()]);
+
+        let decision = store.evaluate(&ctx_for("deployment", false)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+
+        let decision = store.evaluate(&ctx_for("service", false)).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Allow);
+        assert_eq!(decision.matched_policies, vec!["acme.Services".to_string()]);
+
+        // Reloading the package replaces it as a unit
+        let reload = r#"
+package acme
+
+policy Services: "Services are always allowed"
+  triggers when resource.kind == "service"
+  requires resource.kind == "service"
+"#;
+        let _ = store.update_sync(UpdateRequest::LoadModule {
+            source: reload.to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+        assert_eq!(store.snapshot().len(), 1);
+
+        // A broken module is rejected without touching the snapshot
+        let result = store.update_sync(UpdateRequest::LoadModule {
+            source: "package acme\npolicy Broken".to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+        assert!(matches!(result, UpdateResult::Error { .. }));
+        assert_eq!(store.snapshot().len(), 1);
+
+        let _ = store.update_sync(UpdateRequest::RemovePackage { package: "acme".to_string() });
+        assert!(store.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_data_store_load_module_source_order() {
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let _ = store.update_sync(UpdateRequest::SetCombiningAlgorithm {
+            resource_type: None,
+            algorithm: CombiningAlgorithm::FirstApplicable,
+        });
+        let source = r#"
+policy Freeze: "Deployments are frozen"
+  triggers when true
+  denies
+
+policy_set Deployments: "Deployment controls" {
+  policy AllowAll: "Deployments are allowed"
+    triggers when true
+    requires true
+}
+"#;
+        let _ = store.update_sync(UpdateRequest::LoadModule {
+            source: source.to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+
+        // The standalone policy comes first in the file, so it applies first
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.type_id = ResourceTypeId(1);
+        let decision = store.evaluate(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.matched_policies, vec!["Freeze".to_string()]);
+    }
+
+    #[test]
+    fn test_data_store_evaluate_explain() {
+        use crate::explain::FieldTrace;
+        use crate::interpreter::Outcome;
+        use crate::rar::AttributeValue;
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let source = r#"
+package acme
+
+policy_set Deployments: "Deployment controls"
+combining "first-applicable"
+{
+  policy AllowApproved: "Approved deployments are allowed"
+    triggers when resource.kind == "deployment"
+    requires resource.approved == true
+
+  policy DenyAll: "Everything else is denied"
+    triggers when resource.kind == "deployment"
+    denies
+}
+"#;
+        let _ = store.update_sync(UpdateRequest::LoadModule {
+            source: source.to_string(),
+            resource_types: vec![ResourceTypeId(1)],
+        });
+
+        let mut ctx = EvaluationContext::default();
+        ctx.resource.type_id = ResourceTypeId(1);
+        ctx.resource
+            .attributes
+            .insert("kind".to_string(), AttributeValue::String("deployment".to_string()));
+        ctx.resource
+            .attributes
+            .insert("approved".to_string(), AttributeValue::Bool(false));
+
+        let (decision, explanation) = store.evaluate_explain(&ctx).unwrap();
+        assert_eq!(decision.kind, DecisionKind::Deny);
+        assert_eq!(decision.matched_policies, store.evaluate(&ctx).unwrap().matched_policies);
+
+        // First-applicable stops at the first member, so DenyAll never runs
+        assert_eq!(explanation.policies.len(), 1);
+        let trace = explanation.policy("acme.AllowApproved").unwrap();
+        assert_eq!(trace.policy_set.as_deref(), Some("acme.Deployments"));
+        assert_eq!(trace.outcome, Some(Outcome::Deny));
+        assert!(trace.triggers_matched);
+
+        let failed: Vec<_> = trace.failed_conditions().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].location.line, 9);
+        assert_eq!(
+            failed[0].fields,
+            vec![FieldTrace {
+                path: "resource.approved".to_string(),
+                value: Value::Bool(false)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_data_store_host_function() {
+        use crate::ast::Type;
+        use crate::rar::AttributeValue;
+        use crate::DecisionKind;
+
+        let store = PolicyDataStore::new(1);
+        let add_policy = || {
+            store.update_sync(UpdateRequest::AddPolicy {
+                name: "OnCall".to_string(),
+                source: r#"
+                    policy OnCall: "Only on-call engineers may page"
+                    triggers when resource.kind == "pager"
+                    requires is_on_call(resource.owner)
+                "#
+                .to_string(),
+                resource_types: vec![ResourceTypeId(1)],
+            })
+        };
+
+        // Unknown until registered
+        assert!(matches!(add_policy(), UpdateResult::Error { .. }));
+
+        let result = store.register_function(
+            "is_on_call",
+            FunctionSignature::new(vec![Type::String], Type::Bool),
+            FunctionOptions::default().pure(),
+            |args| Ok(Value::Bool(args[0] == Value::String("alice".to_string()))),
+        );
+        assert!(matches!(result, UpdateResult::Success { .. }));
+        assert!(matches!(add_policy(), UpdateResult::Success { .. }));
+
+        let ctx_for = |owner: &str| {
+            let mut ctx = EvaluationContext::default();
+            ctx.resource.type_id = ResourceTypeId(1);
+            ctx.resource
+                .attributes
+                .insert("kind".to_string(), AttributeValue::String("pager".to_string()));
+            ctx.resource
+                .attributes
+                .insert("owner".to_string(), AttributeValue::String(owner.to_string()));
+            ctx
+        };
+
+        assert_eq!(store.evaluate(&ctx_for("alice")).unwrap().kind, DecisionKind::Allow);
+        assert_eq!(store.evaluate(&ctx_for("bob")).unwrap().kind, DecisionKind::Deny);
+
+        // Registering the same name twice is rejected
+        let result = store.register_function(
+            "is_on_call",
+            FunctionSignature::new(vec![Type::String], Type::Bool),
+            FunctionOptions::default(),
+            |_| Ok(Value::Bool(true)),
+        );
+        assert!(matches!(result, UpdateResult::Error { .. }));
+    }
+
+    #[test]
+    fn test_data_store_schema() {
+        let store = PolicyDataStore::new(1);
+        let schema = Schema::from_json(
+            r#"{"resources": [{"id": 1, "name": "Deployment", "attributes": {"replicas": "int"}}]}"#,
+        )
+        .unwrap();
+        let add_policy = |requirement: &str, resource_type: u32| {
+            store.update_sync(UpdateRequest::AddPolicy {
+                name: "Replicas".to_string(),
+                source: format!(
+                    "policy Replicas: \"r\"\n  triggers when true\n  requires {}",
+                    requirement
+                ),
+                resource_types: vec![ResourceTypeId(resource_type)],
+            })
+        };
+
+        // Accepted until the schema is set
+        assert!(matches!(
+            add_policy("resource.replicas == \"3\"", 1),
+            UpdateResult::Success { .. }
+        ));
+
+        // A schema the loaded policies do not type check against is rejected
+        match store.update_sync(UpdateRequest::SetSchema { schema: schema.clone() }) {
+            UpdateResult::Error { message } => {
+                assert!(message.contains("Incompatible types: int and string"), "{}", message)
+            },
+            UpdateResult::Success { .. } => panic!("expected a type error"),
+        }
+        assert!(store.snapshot().schema().is_none());
+        assert_eq!(store.snapshot().len(), 1);
+
+        store.update_sync(UpdateRequest::RemovePolicy { name: "Replicas".to_string() });
+        assert!(matches!(add_policy("resource.replicas >= 3", 1), UpdateResult::Success { .. }));
+        let result = store.update_sync(UpdateRequest::SetSchema { schema });
+        assert!(matches!(result, UpdateResult::Success { .. }));
+        assert!(store.snapshot().schema().is_some());
+
+        match add_policy("resource.replicas == \"3\"", 1) {
+            UpdateResult::Error { message } => {
+                assert!(message.contains("Incompatible types: int and string"), "{}", message)
+            },
+            UpdateResult::Success { .. } => panic!("expected a type error"),
+        }
+        assert!(matches!(add_policy("resource.replcas >= 3", 1), UpdateResult::Error { .. }));
+        assert!(matches!(add_policy("resource.replicas >= 3", 1), UpdateResult::Success { .. }));
+
+        // Resource types the schema does not declare are not checked
+        assert!(matches!(
+            add_policy("resource.replicas == \"3\"", 2),
+            UpdateResult::Success { .. }
+        ));
+        assert_eq!(store.snapshot().len(), 3);
+    }
 }
@@ -137,10 +137,15 @@ impl TieredPolicy {
         #[cfg(feature = "jit")]
         {
             if let Some(ref jit) = *self.jit_code.read() {
-                let result = unsafe { jit.execute(ctx as *const _) };
+                let outcome = unsafe { jit.execute_outcome(ctx as *const _) };
                 let latency = start.elapsed();
                 self.stats.record_evaluation(latency);
-                return Ok(Decision::from_bool(result));
+                return Ok(match outcome {
+                    Ok(crate::interpreter::Outcome::Allow) => Decision::allow(),
+                    Ok(crate::interpreter::Outcome::Deny) => Decision::deny(),
+                    Ok(crate::interpreter::Outcome::NotApplicable) => Decision::not_applicable(),
+                    Err(e) => Decision::indeterminate().with_reason(e),
+                });
             }
         }
 
@@ -169,6 +174,8 @@ impl TieredPolicy {
             kind: crate::engine::DecisionKind::Allow,
             reason: None,
             matched_policies: vec![],
+            obligations: vec![],
+            advice: vec![],
         })
     }
 
@@ -0,0 +1,506 @@
+//! Language features computed from a single document
+//!
+//! Everything here is a pure function of the document text and the schema,
+//! so it can be tested without a client connection.
+
+use std::collections::BTreeMap;
+
+use ipe_core::ast::types::{Type, TypeChecker, TypeEnv};
+use ipe_core::ast::{Condition, Definition, DefinitionKind, Module, Path, Policy, Requirements};
+use ipe_core::diagnostics::{self, FileId, Severity};
+use ipe_core::parser::token::KEYWORDS;
+use ipe_core::parser::{parse_module_with_diagnostics, Lexer, Token, TokenKind};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Position, Range,
+    SemanticToken, SemanticTokenType,
+};
+
+/// Semantic token types, in the order their indices are reported
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::COMMENT,
+];
+
+const KEYWORD: u32 = 0;
+const STRING: u32 = 1;
+const NUMBER: u32 = 2;
+const OPERATOR: u32 = 3;
+const VARIABLE: u32 = 4;
+const PROPERTY: u32 = 5;
+const FUNCTION: u32 = 6;
+const COMMENT: u32 = 7;
+
+/// An open policy file, lexed and parsed once per change
+pub struct Document {
+    text: String,
+    line_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    module: Module,
+    parse_diagnostics: Vec<diagnostics::Diagnostic>,
+}
+
+impl Document {
+    /// Analyze `text`
+    pub fn new(text: String) -> Self {
+        let line_starts =
+            std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        let tokens = Lexer::new(&text).tokenize();
+        let (module, parse_diagnostics) = parse_module_with_diagnostics(&text, FileId::default());
+        Self {
+            text,
+            line_starts,
+            tokens,
+            module,
+            parse_diagnostics,
+        }
+    }
+
+    /// Syntax errors, then type errors in every policy condition
+    pub fn diagnostics(&self, schema: &TypeEnv) -> Vec<Diagnostic> {
+        let mut result: Vec<Diagnostic> = self
+            .parse_diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let mut message = diagnostic.message.clone();
+                if let Some(help) = &diagnostic.help {
+                    message = format!("{}\nhelp: {}", message, help);
+                }
+                Diagnostic {
+                    range: self.range(diagnostic.location.span()),
+                    severity: Some(match diagnostic.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    source: Some("ipe".to_string()),
+                    message,
+                    ..Diagnostic::default()
+                }
+            })
+            .collect();
+
+        // The compiler does not type check, so these never stop a policy
+        // from loading; they flag conditions that cannot behave as written.
+        for condition in self.policies().flat_map(conditions) {
+            let mut checker = TypeChecker::new(schema.clone());
+            checker.check_condition(condition);
+            result.extend(checker.errors().iter().map(|error| Diagnostic {
+                range: self.range(condition.location.span()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("ipe".to_string()),
+                message: error.to_string(),
+                ..Diagnostic::default()
+            }));
+        }
+        result
+    }
+
+    /// Markdown describing the identifier at `position`
+    pub fn hover(&self, schema: &TypeEnv, position: Position) -> Option<(String, Range)> {
+        let index = self.identifier_at(self.offset(position))?;
+        let token = &self.tokens[index];
+        let segments = self.path_to(index);
+
+        let description = if let [name] = segments.as_slice() {
+            if let Some(definition) = self.definition_named(name, token.offset) {
+                let start = definition.location.offset;
+                let line = self.text[start..].lines().next().unwrap_or_default();
+                line.trim_end().to_string()
+            } else {
+                format!("{}: {}", name, schema.lookup(name)?)
+            }
+        } else {
+            schema.lookup(&segments[0])?;
+            let path = Path::new(segments.clone());
+            match schema.lookup_field(&path) {
+                Ok(Some(typ)) => format!("{}: {}", path, typ),
+                Ok(None) => format!("{}: any (not in schema)", path),
+                Err(field) => format!("{}: unknown field '{}'", path, field),
+            }
+        };
+        Some((format!("```ipe\n{}\n```", description), self.range(token.offset..token.end())))
+    }
+
+    /// Range of the name in the definition the identifier at `position` refers to
+    pub fn definition(&self, position: Position) -> Option<Range> {
+        let index = self.identifier_at(self.offset(position))?;
+        if self.path_to(index).len() != 1 {
+            return None;
+        }
+        let token = &self.tokens[index];
+        let definition = self.definition_named(&token.text, token.offset)?;
+        let keyword = self.tokens.iter().position(|t| t.offset == definition.location.offset)?;
+        let name = self.tokens.get(keyword + 1).filter(|t| t.text == definition.name)?;
+        Some(self.range(name.offset..name.end()))
+    }
+
+    /// Completions at `position`: field paths after a `.`, otherwise
+    /// keywords, variables and definitions
+    pub fn completions(&self, schema: &TypeEnv, position: Position) -> Vec<CompletionItem> {
+        let offset = self.offset(position);
+        let before = &self.text[..offset];
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map_or(0, |i| i + before[i..].chars().next().map_or(1, char::len_utf8));
+        let word = &before[start..];
+
+        if let Some((parent, _)) = word.rsplit_once('.') {
+            let parent: Vec<&str> = parent.split('.').collect();
+            let mut children: BTreeMap<&str, Option<&Type>> = BTreeMap::new();
+            for (path, typ) in schema.fields() {
+                let segments: Vec<&str> = path.split('.').collect();
+                if segments.len() <= parent.len() || segments[..parent.len()] != parent[..] {
+                    continue;
+                }
+                let child = segments[parent.len()];
+                if child == "*" {
+                    continue;
+                }
+                let leaf = segments.len() == parent.len() + 1;
+                let entry = children.entry(child).or_default();
+                if leaf {
+                    *entry = Some(typ);
+                }
+            }
+            return children
+                .into_iter()
+                .map(|(name, typ)| CompletionItem {
+                    label: name.to_string(),
+                    kind: Some(CompletionItemKind::FIELD),
+                    detail: typ.map(ToString::to_string),
+                    ..CompletionItem::default()
+                })
+                .collect();
+        }
+
+        let keywords = KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..CompletionItem::default()
+        });
+        let mut variables: Vec<_> = schema.variables().collect();
+        variables.sort_by_key(|(name, _)| *name);
+        let variables = variables.into_iter().map(|(name, typ)| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some(typ.to_string()),
+            ..CompletionItem::default()
+        });
+        let bindings = self
+            .enclosing_policy(offset)
+            .map(|policy| policy.bindings.as_slice())
+            .unwrap_or_default();
+        let definitions =
+            self.module.definitions.iter().chain(bindings).map(|definition| CompletionItem {
+                label: definition.name.clone(),
+                kind: Some(match definition.kind {
+                    DefinitionKind::Const => CompletionItemKind::CONSTANT,
+                    DefinitionKind::Predicate => CompletionItemKind::FUNCTION,
+                    DefinitionKind::Let => CompletionItemKind::VARIABLE,
+                }),
+                detail: Some(definition.kind.to_string()),
+                ..CompletionItem::default()
+            });
+        keywords.chain(variables).chain(definitions).collect()
+    }
+
+    /// Semantic tokens, delta-encoded as the protocol expects
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let mut spans = Vec::new();
+        let mut previous_end = 0;
+        for (index, token) in self.tokens.iter().enumerate() {
+            spans.extend(self.comment_in(previous_end..token.offset));
+            previous_end = token.end();
+
+            let next = self.tokens.get(index + 1).map(|t| &t.kind);
+            let after_dot = index > 0 && matches!(self.tokens[index - 1].kind, TokenKind::Dot);
+            let token_type = match &token.kind {
+                kind if kind.is_keyword() && after_dot => PROPERTY,
+                kind if kind.is_keyword() => KEYWORD,
+                kind if kind.is_operator() => OPERATOR,
+                TokenKind::BoolLit(_) => KEYWORD,
+                TokenKind::StringLit(_) => STRING,
+                TokenKind::IntLit(_)
+                | TokenKind::FloatLit(_)
+                | TokenKind::DurationLit(_)
+                | TokenKind::TimestampLit(_) => NUMBER,
+                TokenKind::Ident(_) if after_dot => PROPERTY,
+                TokenKind::Ident(_) if matches!(next, Some(TokenKind::LParen)) => FUNCTION,
+                TokenKind::Ident(_) => VARIABLE,
+                _ => continue,
+            };
+            spans.push((token.offset..token.end(), token_type));
+        }
+
+        let mut result = Vec::with_capacity(spans.len());
+        let mut previous = Position::new(0, 0);
+        for (span, token_type) in spans {
+            let start = self.position(span.start);
+            let line_end = self.line_end(start.line as usize);
+            let end = self.position(span.end.min(line_end));
+            let delta_line = start.line - previous.line;
+            let delta_start = if delta_line == 0 {
+                start.character - previous.character
+            } else {
+                start.character
+            };
+            result.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: end.character - start.character,
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            previous = start;
+        }
+        result
+    }
+
+    /// A `#` comment in the gap between two tokens
+    fn comment_in(&self, gap: std::ops::Range<usize>) -> Option<(std::ops::Range<usize>, u32)> {
+        let start = gap.start + self.text.get(gap.clone())?.find('#')?;
+        let end = self.line_end(self.position(start).line as usize).min(gap.end);
+        Some((start..end, COMMENT))
+    }
+
+    /// Index of the identifier token touching `offset`
+    fn identifier_at(&self, offset: usize) -> Option<usize> {
+        self.tokens.iter().position(|token| {
+            matches!(token.kind, TokenKind::Ident(_))
+                && token.offset <= offset
+                && offset <= token.end()
+        })
+    }
+
+    /// Segments of the dotted path ending at the identifier token `index`
+    fn path_to(&self, index: usize) -> Vec<String> {
+        let mut start = index;
+        while start >= 2
+            && matches!(self.tokens[start - 1].kind, TokenKind::Dot)
+            && matches!(self.tokens[start - 2].kind, TokenKind::Ident(_))
+        {
+            start -= 2;
+        }
+        self.tokens[start..=index]
+            .iter()
+            .step_by(2)
+            .map(|token| token.text.clone())
+            .collect()
+    }
+
+    /// The `let` binding or module definition `name` refers to at `offset`
+    fn definition_named(&self, name: &str, offset: usize) -> Option<&Definition> {
+        let bindings = self.enclosing_policy(offset).map(|policy| policy.bindings.as_slice());
+        bindings
+            .unwrap_or_default()
+            .iter()
+            .chain(&self.module.definitions)
+            .find(|definition| definition.name == name)
+    }
+
+    /// The last policy starting before `offset`
+    fn enclosing_policy(&self, offset: usize) -> Option<&Policy> {
+        self.policies()
+            .filter(|policy| policy.location.offset <= offset)
+            .max_by_key(|policy| policy.location.offset)
+    }
+
+    fn policies(&self) -> impl Iterator<Item = &Policy> {
+        self.module.policies()
+    }
+
+    /// Byte offset of the end of `line`, before its newline
+    fn line_end(&self, line: usize) -> usize {
+        self.line_starts.get(line + 1).map_or(self.text.len(), |next| next - 1)
+    }
+
+    /// LSP position (UTF-16 columns) of a byte offset
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self.text[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    /// Byte offset of an LSP position, clamped to its line
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn range(&self, span: std::ops::Range<usize>) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+/// Triggers, requirements and `where` clauses of a policy
+fn conditions(policy: &Policy) -> impl Iterator<Item = &Condition> {
+    let (requirements, where_clause) = match &policy.requirements {
+        Requirements::Requires { conditions, where_clause } => {
+            (conditions.as_slice(), where_clause.as_deref())
+        },
+        Requirements::Denies { .. } => (&[][..], None),
+    };
+    policy
+        .triggers
+        .iter()
+        .chain(requirements)
+        .chain(where_clause.into_iter().flatten())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"# Deployments
+predicate owned_by(team): resource.owner == team
+
+policy RequireApproval:
+  "Production deployments need two approvals"
+  triggers when
+    resource.env == "prod"
+    and owned_by("platform")
+  requires
+    resource.approvals >= 2
+"#;
+
+    fn schema() -> TypeEnv {
+        let mut schema = TypeEnv::standard();
+        schema.bind_field("resource.env", Type::String);
+        schema.bind_field("resource.approvals", Type::Int);
+        schema
+    }
+
+    /// Position of the `nth` occurrence of `needle` in `source`, plus `shift`
+    fn position_of(document: &Document, needle: &str, nth: usize, shift: usize) -> Position {
+        let offset = document.text.match_indices(needle).nth(nth).unwrap().0;
+        document.position(offset + shift)
+    }
+
+    #[test]
+    fn test_positions() {
+        let document = Document::new("a\n  é𝄞x\n".to_string());
+        assert_eq!(document.position(0), Position::new(0, 0));
+        assert_eq!(document.position(2), Position::new(1, 0));
+        // é is 2 bytes and 1 UTF-16 unit; 𝄞 is 4 bytes and 2 units
+        assert_eq!(document.position(10), Position::new(1, 5));
+        assert_eq!(document.offset(Position::new(1, 5)), 10);
+        assert_eq!(document.offset(Position::new(1, 99)), 11);
+        assert_eq!(document.offset(Position::new(9, 0)), document.text.len());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let document = Document::new(SOURCE.to_string());
+        assert!(document.diagnostics(&schema()).is_empty());
+
+        let source = SOURCE.replace("resource.approvals >= 2", "resource.env >= 2");
+        let diagnostics = Document::new(source).diagnostics(&schema());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].range.start, Position::new(9, 4));
+
+        let source = SOURCE.replace("requires\n", "requires requires\n");
+        let diagnostics = Document::new(source).diagnostics(&schema());
+        assert!(!diagnostics.is_empty());
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    }
+
+    #[test]
+    fn test_hover() {
+        let document = Document::new(SOURCE.to_string());
+        let hover = |needle, shift| {
+            let position = position_of(&document, needle, 0, shift);
+            document.hover(&schema(), position).map(|(text, _)| text)
+        };
+
+        assert_eq!(hover("env", 1).unwrap(), "```ipe\nresource.env: string\n```");
+        assert_eq!(hover("approvals >=", 0).unwrap(), "```ipe\nresource.approvals: int\n```");
+        assert!(hover("owner", 0).unwrap().contains("resource.owner: any (not in schema)"));
+        assert!(hover("resource.env", 0).unwrap().contains("resource: Resource"));
+        assert!(hover("owned_by(\"", 0)
+            .unwrap()
+            .contains("predicate owned_by(team): resource.owner == team"));
+        assert_eq!(hover("prod", 0), None);
+    }
+
+    #[test]
+    fn test_definition() {
+        let document = Document::new(SOURCE.to_string());
+        let use_site = position_of(&document, "owned_by", 1, 2);
+        let range = document.definition(use_site).unwrap();
+        assert_eq!(range.start, position_of(&document, "owned_by", 0, 0));
+        assert_eq!(range.end, position_of(&document, "owned_by", 0, "owned_by".len()));
+
+        assert_eq!(document.definition(position_of(&document, "env", 0, 0)), None);
+    }
+
+    #[test]
+    fn test_completions() {
+        let document =
+            Document::new("policy A: \"a\" triggers when request.principal.".to_string());
+        let end = document.position(document.text.len());
+        let labels: Vec<_> = document
+            .completions(&schema(), end)
+            .into_iter()
+            .map(|item| (item.label, item.detail))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("id".to_string(), Some("string".to_string())),
+                ("roles".to_string(), Some("array<string>".to_string())),
+            ]
+        );
+
+        let document = Document::new(SOURCE.replace("2\n", "2 and act"));
+        let end = document.position(document.text.len());
+        let items = document.completions(&schema(), end);
+        for item in items.iter().filter(|item| item.kind == Some(CompletionItemKind::KEYWORD)) {
+            assert!(Lexer::new(&item.label).next_token().kind.is_keyword(), "{}", item.label);
+        }
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert!(labels.contains(&"action"));
+        assert!(labels.contains(&"owned_by"));
+        assert!(labels.contains(&"policy_set"));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let document = Document::new("# c\nconst é = a.b + f(\"x\") # d".to_string());
+        let tokens: Vec<_> = document
+            .semantic_tokens()
+            .into_iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, 0, 3, COMMENT),
+                (1, 0, 5, KEYWORD),
+                (0, 6, 1, VARIABLE),
+                (0, 4, 1, VARIABLE),
+                (0, 2, 1, PROPERTY),
+                (0, 2, 1, OPERATOR),
+                (0, 2, 1, FUNCTION),
+                (0, 2, 3, STRING),
+                (0, 5, 3, COMMENT),
+            ]
+        );
+    }
+}
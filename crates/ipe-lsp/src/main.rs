@@ -0,0 +1,19 @@
+//! IPE language server
+//!
+//! Speaks the Language Server Protocol over stdio. Attribute types for hover,
+//! completion and type diagnostics come from the `schema` object in the
+//! client's `initializationOptions`.
+
+mod analysis;
+mod server;
+
+use std::error::Error;
+
+use lsp_server::Connection;
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+    server::run(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
@@ -0,0 +1,327 @@
+//! Request and notification dispatch over an LSP connection
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use ipe_core::ast::types::TypeEnv;
+use ipe_core::schema::Schema;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, HoverRequest, Request as _, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, InitializeParams, Location, MarkupContent, MarkupKind, OneOf,
+    PublishDiagnosticsParams, SaveOptions, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url,
+};
+
+use crate::analysis::{Document, TOKEN_TYPES};
+
+/// Open documents and the schema they are checked against
+pub struct Server {
+    schema: TypeEnv,
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    /// Create a server checking against `schema`
+    pub fn new(schema: TypeEnv) -> Self {
+        Self { schema, documents: HashMap::new() }
+    }
+
+    /// What this server supports, as sent in the `initialize` response
+    pub fn capabilities() -> ServerCapabilities {
+        ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::FULL),
+                    save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                        include_text: Some(true),
+                    })),
+                    ..TextDocumentSyncOptions::default()
+                },
+            )),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec![".".to_string()]),
+                ..CompletionOptions::default()
+            }),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    legend: SemanticTokensLegend {
+                        token_types: TOKEN_TYPES.to_vec(),
+                        token_modifiers: Vec::new(),
+                    },
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                    ..SemanticTokensOptions::default()
+                }),
+            ),
+            ..ServerCapabilities::default()
+        }
+    }
+
+    /// Handle a request from the client
+    pub fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            SemanticTokensFullRequest::METHOD => {
+                self.respond::<SemanticTokensFullRequest>(request, Self::semantic_tokens)
+            },
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled method {}", method),
+            ),
+        }
+    }
+
+    /// Handle a notification from the client, returning any to send back
+    ///
+    /// Diagnostics are published when a document is opened or saved, and
+    /// cleared when it is closed.
+    pub fn handle_notification(&mut self, notification: Notification) -> Vec<Notification> {
+        let publish = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = params::<DidOpenTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), Document::new(document.text));
+                Some(document.uri)
+            },
+            DidChangeTextDocument::METHOD => {
+                let Some(mut params) = params::<DidChangeTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                if let Some(change) = params.content_changes.pop() {
+                    let document = Document::new(change.text);
+                    self.documents.insert(params.text_document.uri, document);
+                }
+                None
+            },
+            DidSaveTextDocument::METHOD => {
+                let Some(params) = params::<DidSaveTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                if let Some(text) = params.text {
+                    self.documents.insert(uri.clone(), Document::new(text));
+                }
+                Some(uri)
+            },
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = params::<DidCloseTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                Some(uri)
+            },
+            _ => None,
+        };
+
+        publish
+            .map(|uri| {
+                let diagnostics = self
+                    .documents
+                    .get(&uri)
+                    .map(|document| document.diagnostics(&self.schema))
+                    .unwrap_or_default();
+                let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+                Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn respond<R: lsp_types::request::Request>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(error) => {
+                Response::new_err(request.id, ErrorCode::InvalidParams as i32, error.to_string())
+            },
+        }
+    }
+
+    fn hover(&self, params: lsp_types::HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let (value, range) = document.hover(&self.schema, position.position)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(range),
+        })
+    }
+
+    fn definition(
+        &self,
+        params: lsp_types::GotoDefinitionParams,
+    ) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let range = self.documents.get(&uri)?.definition(position.position)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+    }
+
+    fn completion(&self, params: lsp_types::CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let items = document.completions(&self.schema, position.position);
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn semantic_tokens(
+        &self,
+        params: lsp_types::SemanticTokensParams,
+    ) -> Option<SemanticTokensResult> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: document.semantic_tokens(),
+        }))
+    }
+}
+
+fn params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    serde_json::from_value(notification.params).ok()
+}
+
+/// Type environment for the `schema` in `initializationOptions`
+///
+/// The schema uses the JSON format of [`Schema`]; documents are checked
+/// against every resource type it declares.
+pub fn schema(options: Option<&serde_json::Value>) -> Result<TypeEnv, serde_json::Error> {
+    let schema = match options.and_then(|options| options.get("schema")) {
+        Some(schema) => serde_json::from_value(schema.clone())?,
+        None => Schema::new(),
+    };
+    Ok(schema.type_env(&[]))
+}
+
+/// Initialize `connection` and serve it until the client shuts down
+pub fn run(connection: Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let capabilities = serde_json::to_value(Server::capabilities())?;
+    let params: InitializeParams = serde_json::from_value(connection.initialize(capabilities)?)?;
+    let mut server = Server::new(schema(params.initialization_options.as_ref())?);
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(Message::Response(server.handle_request(request)))?;
+            },
+            Message::Notification(notification) => {
+                for reply in server.handle_notification(notification) {
+                    connection.sender.send(Message::Notification(reply))?;
+                }
+            },
+            Message::Response(_) => {},
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipe_core::ast::types::Type;
+    use lsp_server::RequestId;
+    use lsp_types::request::{Initialize, Shutdown};
+    use serde_json::json;
+
+    fn request(id: i32, method: &str, params: serde_json::Value) -> Message {
+        Message::Request(Request::new(RequestId::from(id), method.to_string(), params))
+    }
+
+    fn notification(method: &str, params: serde_json::Value) -> Message {
+        Message::Notification(Notification::new(method.to_string(), params))
+    }
+
+    #[test]
+    fn test_schema() {
+        let options = json!({"schema": {
+            "resources": [{"id": 1, "name": "Deployment", "attributes": {"env": "string"}}],
+            "principal": {"clearance": "int"}
+        }});
+        let schema = schema(Some(&options)).unwrap();
+        let field = |path: &str| schema.fields().find(|(field, _)| *field == path).map(|f| f.1);
+        assert_eq!(field("resource.env"), Some(&Type::String));
+        assert_eq!(field("request.principal.clearance"), Some(&Type::Int));
+        assert_eq!(schema.lookup("resource"), Some(&Type::Resource("Deployment".to_string())));
+
+        let unknown = json!({"schema": {"principal": {"clearance": "integer"}}});
+        assert!(super::schema(Some(&unknown)).is_err());
+        assert!(super::schema(None).is_ok());
+    }
+
+    #[test]
+    fn test_session() {
+        let (server, client) = Connection::memory();
+        let thread = std::thread::spawn(move || run(server).unwrap());
+        let uri = "file:///policies/deploy.ipe";
+
+        let resource = json!({"id": 1, "name": "Deployment", "attributes": {"env": "string"}});
+        let options = json!({"schema": {"resources": [resource]}});
+        let params = json!({"capabilities": {}, "initializationOptions": options});
+        client.sender.send(request(1, Initialize::METHOD, params)).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected the initialize response");
+        };
+        assert!(response.result.unwrap()["capabilities"]["semanticTokensProvider"].is_object());
+        client.sender.send(notification("initialized", json!({}))).unwrap();
+
+        let text = "policy A: \"a\"\n  triggers when resource.env == 1\n  requires true\n";
+        let params =
+            json!({"textDocument": {"uri": uri, "languageId": "ipe", "version": 1, "text": text}});
+        client.sender.send(notification(DidOpenTextDocument::METHOD, params)).unwrap();
+        let Message::Notification(published) = client.receiver.recv().unwrap() else {
+            panic!("expected diagnostics");
+        };
+        assert_eq!(published.method, PublishDiagnostics::METHOD);
+        let published: PublishDiagnosticsParams = serde_json::from_value(published.params).unwrap();
+        assert_eq!(published.diagnostics.len(), 1);
+        assert!(published.diagnostics[0].message.contains("Incompatible types"));
+
+        let position =
+            json!({"textDocument": {"uri": uri}, "position": {"line": 1, "character": 26}});
+        client.sender.send(request(2, HoverRequest::METHOD, position)).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected the hover response");
+        };
+        let hover: Hover = serde_json::from_value(response.result.unwrap()).unwrap();
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markdown");
+        };
+        assert!(contents.value.contains("resource.env: string"));
+
+        client.sender.send(request(3, "ipe/unknown", json!({}))).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected an error response");
+        };
+        assert_eq!(response.error.unwrap().code, ErrorCode::MethodNotFound as i32);
+
+        client.sender.send(request(4, Shutdown::METHOD, json!(null))).unwrap();
+        client.receiver.recv().unwrap();
+        client.sender.send(notification("exit", json!(null))).unwrap();
+        thread.join().unwrap();
+    }
+}
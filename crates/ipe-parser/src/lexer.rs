@@ -0,0 +1,446 @@
+//! Lexer for the predicate language, generated by `logos`
+//!
+//! Produces the same tokens as the hand-written lexer in `ipe_core::parser`:
+//! spaces, tabs, carriage returns and `#` comments are skipped, newlines are
+//! tokens, and durations and timestamps are seconds.
+
+use crate::ParseError;
+use ipe_core::diagnostics::SourceFile;
+use logos::{Lexer, Logos};
+use std::fmt;
+
+/// The kind of token
+// Variants are named after the text they match
+#[allow(missing_docs)]
+#[derive(Logos, Debug, Clone, PartialEq)]
+#[logos(skip r"([ \t\r]|#[^\n]*)+")]
+pub enum TokenKind {
+    // Keywords
+    #[token("policy")]
+    Policy,
+    #[token("triggers")]
+    Triggers,
+    #[token("when")]
+    When,
+    #[token("requires")]
+    Requires,
+    #[token("denies")]
+    Denies,
+    #[token("with")]
+    With,
+    #[token("reason")]
+    Reason,
+    #[token("where")]
+    Where,
+    #[token("metadata")]
+    Metadata,
+    #[token("and")]
+    And,
+    #[token("or")]
+    Or,
+    #[token("not")]
+    Not,
+    #[token("in")]
+    In,
+    #[token("matches")]
+    Matches,
+    #[token("like")]
+    Like,
+    #[token("contains")]
+    Contains,
+    #[token("intersects")]
+    Intersects,
+    #[token("subset_of")]
+    SubsetOf,
+    #[token("package")]
+    Package,
+    #[token("policy_set")]
+    PolicySet,
+    #[token("combining")]
+    Combining,
+    #[token("const")]
+    Const,
+    #[token("predicate")]
+    Predicate,
+    #[token("let")]
+    Let,
+
+    // Comparison operators
+    #[token("==")]
+    Eq,
+    #[token("!=")]
+    Neq,
+    #[token("<")]
+    Lt,
+    #[token(">")]
+    Gt,
+    #[token("<=")]
+    LtEq,
+    #[token(">=")]
+    GtEq,
+
+    // Arithmetic operators
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*")]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("%")]
+    Percent,
+
+    // Missing-value operator
+    #[token("??")]
+    Default,
+
+    // Literals
+    #[regex(r#""([^"\\\n]|\\(.|\n))*""#, string)]
+    StringLit(String),
+    #[regex("[0-9]+", |lex| lex.slice().parse().ok())]
+    IntLit(i64),
+    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse().ok())]
+    FloatLit(f64),
+    /// Duration in seconds (`30m`, `1h30m`)
+    #[regex(r"[0-9]+[smhdw][\p{Alphabetic}\p{N}_]*", duration)]
+    DurationLit(i64),
+    /// RFC 3339 timestamp in seconds since the Unix epoch
+    #[regex(
+        r"[0-9]{4}-[0-9]{2}-[0-9]{2}[Tt][0-9:.]*([Zz]|[+-][0-9:]*)?[\p{Alphabetic}\p{N}_]*",
+        timestamp
+    )]
+    TimestampLit(i64),
+    #[token("true", |_| true)]
+    #[token("false", |_| false)]
+    BoolLit(bool),
+
+    // Identifiers
+    #[regex(r"[\p{Alphabetic}_][\p{Alphabetic}\p{N}_]*", |lex| lex.slice().to_string())]
+    Ident(String),
+
+    // Punctuation
+    #[token(":")]
+    Colon,
+    #[token("=")]
+    Assign,
+    #[token(",")]
+    Comma,
+    #[token(".")]
+    Dot,
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+    #[token("{")]
+    LBrace,
+    #[token("}")]
+    RBrace,
+
+    // Special
+    #[token("\n")]
+    Newline,
+    Eof,
+}
+
+impl TokenKind {
+    /// Check if this token is a keyword
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Policy
+                | TokenKind::Triggers
+                | TokenKind::When
+                | TokenKind::Requires
+                | TokenKind::Denies
+                | TokenKind::With
+                | TokenKind::Reason
+                | TokenKind::Where
+                | TokenKind::Metadata
+                | TokenKind::And
+                | TokenKind::Or
+                | TokenKind::Not
+                | TokenKind::In
+                | TokenKind::Matches
+                | TokenKind::Like
+                | TokenKind::Contains
+                | TokenKind::Intersects
+                | TokenKind::SubsetOf
+                | TokenKind::Package
+                | TokenKind::PolicySet
+                | TokenKind::Combining
+                | TokenKind::Const
+                | TokenKind::Predicate
+                | TokenKind::Let
+        )
+    }
+
+    /// Check if this token is a literal
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::StringLit(_)
+                | TokenKind::IntLit(_)
+                | TokenKind::FloatLit(_)
+                | TokenKind::DurationLit(_)
+                | TokenKind::TimestampLit(_)
+                | TokenKind::BoolLit(_)
+        )
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::StringLit(s) => write!(f, "\"{}\"", s),
+            TokenKind::IntLit(n) => write!(f, "{}", n),
+            TokenKind::FloatLit(n) => write!(f, "{}", n),
+            TokenKind::DurationLit(n) => write!(f, "{}s", n),
+            TokenKind::TimestampLit(n) => write!(f, "timestamp {}", n),
+            TokenKind::BoolLit(b) => write!(f, "{}", b),
+            TokenKind::Ident(s) => write!(f, "{}", s),
+            TokenKind::Newline => write!(f, "\\n"),
+            TokenKind::Eof => write!(f, "EOF"),
+            TokenKind::Policy => write!(f, "policy"),
+            TokenKind::Triggers => write!(f, "triggers"),
+            TokenKind::When => write!(f, "when"),
+            TokenKind::Requires => write!(f, "requires"),
+            TokenKind::Denies => write!(f, "denies"),
+            TokenKind::With => write!(f, "with"),
+            TokenKind::Reason => write!(f, "reason"),
+            TokenKind::Where => write!(f, "where"),
+            TokenKind::Metadata => write!(f, "metadata"),
+            TokenKind::And => write!(f, "and"),
+            TokenKind::Or => write!(f, "or"),
+            TokenKind::Not => write!(f, "not"),
+            TokenKind::In => write!(f, "in"),
+            TokenKind::Matches => write!(f, "matches"),
+            TokenKind::Like => write!(f, "like"),
+            TokenKind::Contains => write!(f, "contains"),
+            TokenKind::Intersects => write!(f, "intersects"),
+            TokenKind::SubsetOf => write!(f, "subset_of"),
+            TokenKind::Package => write!(f, "package"),
+            TokenKind::PolicySet => write!(f, "policy_set"),
+            TokenKind::Combining => write!(f, "combining"),
+            TokenKind::Const => write!(f, "const"),
+            TokenKind::Predicate => write!(f, "predicate"),
+            TokenKind::Let => write!(f, "let"),
+            TokenKind::Eq => write!(f, "=="),
+            TokenKind::Neq => write!(f, "!="),
+            TokenKind::Lt => write!(f, "<"),
+            TokenKind::Gt => write!(f, ">"),
+            TokenKind::LtEq => write!(f, "<="),
+            TokenKind::GtEq => write!(f, ">="),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Default => write!(f, "??"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Assign => write!(f, "="),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Dot => write!(f, "."),
+            TokenKind::LParen => write!(f, "("),
+            TokenKind::RParen => write!(f, ")"),
+            TokenKind::LBracket => write!(f, "["),
+            TokenKind::RBracket => write!(f, "]"),
+            TokenKind::LBrace => write!(f, "{{"),
+            TokenKind::RBrace => write!(f, "}}"),
+        }
+    }
+}
+
+/// A token with its position in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// The kind of token
+    pub kind: TokenKind,
+    /// Line number (1-indexed)
+    pub line: usize,
+    /// Column number (1-indexed, in characters)
+    pub column: usize,
+    /// Byte offset of the first character
+    pub offset: usize,
+    /// Length in bytes of the source text
+    pub length: usize,
+}
+
+impl Token {
+    /// Byte offset just past the last character
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+}
+
+/// Tokenize `source`, ending with an [`TokenKind::Eof`] token
+///
+/// Fails on the first character sequence that is not a valid token, such as
+/// an unterminated string or an out-of-range number.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let file = SourceFile::new("", source);
+    let token = |kind, offset: usize, length| {
+        let (line, column) = file.line_column(offset);
+        Token { kind, line, column, offset, length }
+    };
+
+    let mut tokens = Vec::new();
+    for (kind, span) in TokenKind::lexer(source).spanned() {
+        match kind {
+            Ok(kind) => tokens.push(token(kind, span.start, span.len())),
+            Err(()) => {
+                let (line, column) = file.line_column(span.start);
+                return Err(ParseError::Lex {
+                    text: source[span].to_string(),
+                    line,
+                    column,
+                });
+            },
+        }
+    }
+    tokens.push(token(TokenKind::Eof, source.len(), 0));
+    Ok(tokens)
+}
+
+/// Unescape a string literal; unknown escapes stand for the escaped character
+fn string(lex: &mut Lexer<TokenKind>) -> String {
+    let slice = lex.slice();
+    let mut value = String::new();
+    let mut chars = slice[1..slice.len() - 1].chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some(escaped) => value.push(escaped),
+            None => {},
+        }
+    }
+    value
+}
+
+/// Seconds in a chain of amounts and units (`1h30m`); `None` for a trailing
+/// amount without a unit, a word character after the last unit, or overflow
+fn duration(lex: &mut Lexer<TokenKind>) -> Option<i64> {
+    let mut seconds = 0i64;
+    let mut amount = String::new();
+    for ch in lex.slice().chars() {
+        let unit = match ch {
+            '0'..='9' => {
+                amount.push(ch);
+                continue;
+            },
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            'w' => 604_800,
+            _ => return None,
+        };
+        let n = std::mem::take(&mut amount).parse::<i64>().ok()?;
+        seconds = n.checked_mul(unit).and_then(|n| seconds.checked_add(n))?;
+    }
+    amount.is_empty().then_some(seconds)
+}
+
+/// Seconds since the epoch of an RFC 3339 timestamp not followed by a word
+/// character
+fn timestamp(lex: &mut Lexer<TokenKind>) -> Option<i64> {
+    let text = lex.slice();
+
+    // The regex takes trailing word characters too; the timestamp is the date
+    // and `T`, the time, then `Z` or a numeric offset
+    let time = "dddd-dd-ddT".len();
+    let time = time
+        + text[time..]
+            .find(|ch: char| !ch.is_ascii_digit() && !matches!(ch, ':' | '.'))
+            .unwrap_or(text.len() - time);
+    let end = match text[time..].chars().next() {
+        Some('Z' | 'z') => time + 1,
+        Some('+' | '-') => {
+            let offset = &text[time + 1..];
+            time + 1
+                + offset
+                    .find(|ch: char| !ch.is_ascii_digit() && ch != ':')
+                    .unwrap_or(offset.len())
+        },
+        _ => time,
+    };
+    if end != text.len() {
+        return None;
+    }
+    chrono::DateTime::parse_from_rfc3339(text).ok().map(|dt| dt.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokenize(source).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            kinds("policy_set A: # comment\n  x.y >= -1.5 ?? 30m"),
+            vec![
+                TokenKind::PolicySet,
+                TokenKind::Ident("A".to_string()),
+                TokenKind::Colon,
+                TokenKind::Newline,
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Dot,
+                TokenKind::Ident("y".to_string()),
+                TokenKind::GtEq,
+                TokenKind::Minus,
+                TokenKind::FloatLit(1.5),
+                TokenKind::Default,
+                TokenKind::DurationLit(1800),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(
+            kinds(r#""a\"b\n" 2024-01-15T10:00:00Z 2024-01-15T11:00:00+01:00 1h30m 12abc"#),
+            vec![
+                TokenKind::StringLit("a\"b\n".to_string()),
+                TokenKind::TimestampLit(1_705_312_800),
+                TokenKind::TimestampLit(1_705_312_800),
+                TokenKind::DurationLit(5400),
+                TokenKind::IntLit(12),
+                TokenKind::Ident("abc".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_positions() {
+        let tokens = tokenize("a\n  \"é\" b").unwrap();
+        let positions: Vec<_> =
+            tokens.iter().map(|t| (t.line, t.column, t.offset, t.length)).collect();
+        assert_eq!(
+            positions,
+            vec![(1, 1, 0, 1), (1, 2, 1, 1), (2, 3, 4, 4), (2, 7, 9, 1), (2, 8, 10, 0)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        for source in ["\"open", "1h30", "10sec", "99999999999999999999", "a ! b", "2024-01-15Tx"] {
+            assert!(
+                matches!(tokenize(source), Err(ParseError::Lex { .. })),
+                "{} should not tokenize",
+                source
+            );
+        }
+    }
+}
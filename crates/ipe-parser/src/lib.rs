@@ -1,20 +1,41 @@
 // IPE Parser - Language parser for Idempotent Predicate Engine
-// Status: Stub - To be implemented in Phase 1
 
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
-/// Parser module for predicate parser implementation (to be implemented)
-pub mod parser {}
+pub mod lexer;
+pub mod parser;
 
-/// Lexer module for lexical analysis (to be implemented)
-pub mod lexer {}
+pub use parser::parse_module;
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn stub_test() {
-        // Placeholder test
-        // Stub test - implementation pending
-    }
+use thiserror::Error;
+
+/// Parse error
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Text that is not a token
+    #[error("Invalid token '{text}' at line {line}, column {column}")]
+    Lex {
+        /// The offending text
+        text: String,
+        /// Line number (1-indexed)
+        line: usize,
+        /// Column number (1-indexed)
+        column: usize,
+    },
+
+    /// Tokens that do not form a module
+    #[error("{message} at line {line}, column {column}")]
+    Syntax {
+        /// What the grammar expected
+        message: String,
+        /// Line number (1-indexed)
+        line: usize,
+        /// Column number (1-indexed)
+        column: usize,
+    },
+
+    /// A module that parsed but whose definitions or policy names are invalid
+    #[error(transparent)]
+    Resolve(#[from] ipe_core::parser::ParseError),
 }
@@ -0,0 +1,836 @@
+//! Grammar of the predicate language, written with `nom` over tokens
+//!
+//! Each rule mirrors the hand-written `ipe_core::parser::Parser` and builds
+//! the same `ipe_core::ast` nodes with the same source locations. Rules fail
+//! softly (`nom::Err::Error`) only on their first token, so alternatives can
+//! be tried; past that, they are committed and fail with `nom::Err::Failure`.
+//! Definitions are inlined and policy names checked by
+//! [`ipe_core::parser::resolve_module`], as the core parser does.
+
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::ParseError;
+use ipe_core::ast::nodes::{
+    AggregateFunc, ArithmeticOp, BinaryOp, ComparisonOp, Condition, Definition, DefinitionKind,
+    Expression, Item, Metadata, Module, Obligation, Policy, PolicySet, Requirements, SetOperator,
+    SourceLocation, Value,
+};
+use ipe_core::combining::CombiningAlgorithm;
+use nom::branch::alt;
+use nom::combinator::{cut, map, opt};
+use nom::error::ErrorKind;
+use nom::multi::{many0, many1, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+
+type Input<'a> = &'a [Token];
+type PResult<'a, T> = IResult<Input<'a>, T, SyntaxError>;
+
+/// Parse a source file containing any number of policies and policy sets
+pub fn parse_module(source: &str) -> Result<Module, ParseError> {
+    let tokens = tokenize(source)?;
+    let module = match module(&tokens) {
+        Ok((_, module)) => module,
+        Err(nom::Err::Error(error) | nom::Err::Failure(error)) => return Err(error.into()),
+        Err(nom::Err::Incomplete(_)) => unreachable!("the token stream is complete"),
+    };
+    Ok(ipe_core::parser::resolve_module(module)?)
+}
+
+/// Why the grammar rejected the tokens
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl SyntaxError {
+    fn at(input: Input<'_>, message: impl Into<String>) -> Self {
+        let (line, column) = input.first().map_or((0, 0), |token| (token.line, token.column));
+        Self { message: message.into(), line, column }
+    }
+
+    fn unexpected(input: Input<'_>, expected: impl std::fmt::Display) -> Self {
+        let got = input.first().map_or(TokenKind::Eof, |token| token.kind.clone());
+        Self::at(input, format!("Unexpected token: expected {}, got {}", expected, got))
+    }
+}
+
+impl<'a> nom::error::ParseError<Input<'a>> for SyntaxError {
+    fn from_error_kind(input: Input<'a>, kind: ErrorKind) -> Self {
+        Self::unexpected(input, kind.description())
+    }
+
+    fn append(_: Input<'a>, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl From<SyntaxError> for ParseError {
+    fn from(error: SyntaxError) -> Self {
+        ParseError::Syntax {
+            message: error.message,
+            line: error.line,
+            column: error.column,
+        }
+    }
+}
+
+/// Fail softly, so that the caller may try something else
+fn soft<T>(error: SyntaxError) -> PResult<'static, T> {
+    Err(nom::Err::Error(error))
+}
+
+/// Fail for good
+fn failure<T>(error: SyntaxError) -> PResult<'static, T> {
+    Err(nom::Err::Failure(error))
+}
+
+/// Make a soft failure final, like `cut` for a parser that already ran
+fn commit<T>(result: PResult<'_, T>) -> PResult<'_, T> {
+    result.map_err(|error| match error {
+        nom::Err::Error(error) => nom::Err::Failure(error),
+        error => error,
+    })
+}
+
+// Tokens
+
+/// A token of `kind`
+fn token<'a>(kind: TokenKind) -> impl FnMut(Input<'a>) -> PResult<'a, &'a Token> {
+    move |input: Input<'a>| match input.split_first() {
+        Some((first, rest)) if first.kind == kind => Ok((rest, first)),
+        _ => soft(SyntaxError::unexpected(input, &kind)),
+    }
+}
+
+/// A token that `select` maps to a value
+fn select<'a, T>(
+    select: fn(&TokenKind) -> Option<T>,
+    expected: &'static str,
+) -> impl FnMut(Input<'a>) -> PResult<'a, T> {
+    move |input: Input<'a>| match input.split_first() {
+        Some((first, rest)) => match select(&first.kind) {
+            Some(value) => Ok((rest, value)),
+            None => soft(SyntaxError::unexpected(input, expected)),
+        },
+        None => soft(SyntaxError::unexpected(input, expected)),
+    }
+}
+
+fn identifier(input: Input<'_>) -> PResult<'_, String> {
+    select(
+        |kind| match kind {
+            TokenKind::Ident(name) => Some(name.clone()),
+            _ => None,
+        },
+        "identifier",
+    )(input)
+}
+
+/// A path segment after `.`, where keywords are plain names: `resource.package`
+fn path_segment(input: Input<'_>) -> PResult<'_, String> {
+    select(
+        |kind| match kind {
+            TokenKind::Ident(name) => Some(name.clone()),
+            kind if kind.is_keyword() => Some(kind.to_string()),
+            _ => None,
+        },
+        "identifier",
+    )(input)
+}
+
+fn string(input: Input<'_>) -> PResult<'_, String> {
+    select(
+        |kind| match kind {
+            TokenKind::StringLit(s) => Some(s.clone()),
+            _ => None,
+        },
+        "string literal",
+    )(input)
+}
+
+fn newlines(input: Input<'_>) -> PResult<'_, ()> {
+    map(many0(token(TokenKind::Newline)), |_| ())(input)
+}
+
+fn is(input: Input<'_>, kind: &TokenKind) -> bool {
+    input.first().is_some_and(|token| token.kind == *kind)
+}
+
+/// Location of `length` bytes starting at `start`
+fn location(start: &Token, length: usize) -> SourceLocation {
+    SourceLocation::new(start.line, start.column, length)
+        .with_span(Default::default(), start.offset)
+}
+
+// Items
+
+enum TopLevel {
+    Item(Item),
+    Definition(Definition),
+}
+
+/// `[package name]` followed by policies, policy sets and definitions
+fn module(input: Input<'_>) -> PResult<'_, Module> {
+    let (input, _) = newlines(input)?;
+    let (input, package) = opt(preceded(token(TokenKind::Package), cut(qualified_name)))(input)?;
+    let (input, items) = many0(preceded(
+        newlines,
+        alt((
+            map(policy_set, |set| TopLevel::Item(Item::PolicySet(set))),
+            map(policy, |policy| TopLevel::Item(Item::Policy(policy))),
+            map(binding(TokenKind::Const), TopLevel::Definition),
+            map(predicate, TopLevel::Definition),
+        )),
+    ))(input)?;
+    let (input, _) = newlines(input)?;
+    if !is(input, &TokenKind::Eof) {
+        return failure(SyntaxError::unexpected(
+            input,
+            "'policy', 'policy_set', 'const' or 'predicate'",
+        ));
+    }
+
+    let mut module = Module { package, ..Module::default() };
+    for item in items {
+        match item {
+            TopLevel::Item(item) => module.items.push(item),
+            TopLevel::Definition(definition) => module.definitions.push(definition),
+        }
+    }
+    Ok((input, module))
+}
+
+/// A dotted name such as `acme.deployments`
+fn qualified_name(input: Input<'_>) -> PResult<'_, String> {
+    map(separated_list1(token(TokenKind::Dot), cut(identifier)), |segments| {
+        segments.join(".")
+    })(input)
+}
+
+/// `const NAME = expr` or `let name = expr`
+fn binding<'a>(keyword: TokenKind) -> impl FnMut(Input<'a>) -> PResult<'a, Definition> {
+    move |input| {
+        let kind = match keyword {
+            TokenKind::Const => DefinitionKind::Const,
+            _ => DefinitionKind::Let,
+        };
+        let (input, start) = token(keyword.clone())(input)?;
+        let (input, (name, _, body)) =
+            cut(tuple((identifier, token(TokenKind::Assign), expression)))(input)?;
+        Ok((
+            input,
+            Definition::new(kind, name, body).with_location(location(start, start.length)),
+        ))
+    }
+}
+
+/// `predicate name: expr` or `predicate name(a, b): expr`
+fn predicate(input: Input<'_>) -> PResult<'_, Definition> {
+    let (input, start) = token(TokenKind::Predicate)(input)?;
+    let (input, (name, params, _, _, body)) = cut(tuple((
+        identifier,
+        opt(delimited(
+            token(TokenKind::LParen),
+            pair(many0(terminated(identifier, token(TokenKind::Comma))), opt(identifier)),
+            token(TokenKind::RParen),
+        )),
+        token(TokenKind::Colon),
+        newlines,
+        expression,
+    )))(input)?;
+
+    let params = params
+        .map(|(mut params, last)| {
+            params.extend(last);
+            params
+        })
+        .unwrap_or_default();
+    let definition = Definition::new(DefinitionKind::Predicate, name, body)
+        .with_params(params)
+        .with_location(location(start, start.length));
+    Ok((input, definition))
+}
+
+/// A `policy_set` block with its member policies
+fn policy_set(input: Input<'_>) -> PResult<'_, PolicySet> {
+    let (input, start) = token(TokenKind::PolicySet)(input)?;
+    let (input, (name, _, _, intent, _, combining, metadata, _, _, policies, _, _)) =
+        cut(tuple((
+            identifier,
+            token(TokenKind::Colon),
+            newlines,
+            string,
+            newlines,
+            opt(preceded(token(TokenKind::Combining), cut(terminated(combining, newlines)))),
+            opt(metadata),
+            newlines,
+            token(TokenKind::LBrace),
+            many0(preceded(newlines, policy)),
+            newlines,
+            token(TokenKind::RBrace),
+        )))(input)?;
+
+    let mut policies = policies;
+    if let Some(shared) = &metadata {
+        for policy in &mut policies {
+            inherit_metadata(policy, shared);
+        }
+    }
+    let set = PolicySet {
+        name,
+        intent,
+        combining,
+        metadata,
+        policies,
+        location: location(start, start.length),
+    };
+    Ok((input, set))
+}
+
+fn combining(input: Input<'_>) -> PResult<'_, String> {
+    let (rest, name) = string(input)?;
+    match CombiningAlgorithm::from_name(&name) {
+        Some(_) => Ok((rest, name)),
+        None => failure(SyntaxError::at(input, format!("Unknown combining algorithm '{}'", name))),
+    }
+}
+
+/// Copy shared fields into a member policy's metadata (the policy's own fields win)
+fn inherit_metadata(policy: &mut Policy, shared: &Metadata) {
+    let metadata = policy.metadata.get_or_insert_with(Metadata::new);
+    for (key, value) in &shared.fields {
+        if metadata.get(key).is_none() {
+            metadata.fields.push((key.clone(), value.clone()));
+        }
+    }
+}
+
+// Policies
+
+/// A complete policy; its `let` bindings are left for the resolver
+fn policy(input: Input<'_>) -> PResult<'_, Policy> {
+    let (input, start) = token(TokenKind::Policy)(input)?;
+    let (input, (name, _, _, intent, _, bindings, triggers, _, requirements, _, obligations)) =
+        cut(tuple((
+            identifier,
+            token(TokenKind::Colon),
+            newlines,
+            string,
+            newlines,
+            many0(terminated(binding(TokenKind::Let), newlines)),
+            triggers,
+            newlines,
+            requirements,
+            newlines,
+            many0(terminated(obligation, newlines)),
+        )))(input)?;
+    let (input, metadata) = opt(metadata)(input)?;
+
+    let (obligations, advice) =
+        obligations.into_iter().partition::<Vec<_>, _>(|(advice, _)| !advice);
+    let policy = Policy {
+        name,
+        intent,
+        triggers,
+        requirements,
+        obligations: obligations.into_iter().map(|(_, obligation)| obligation).collect(),
+        advice: advice.into_iter().map(|(_, advice)| advice).collect(),
+        metadata,
+        bindings,
+        location: location(start, start.length),
+    };
+    Ok((input, policy))
+}
+
+fn triggers(input: Input<'_>) -> PResult<'_, Vec<Condition>> {
+    preceded(
+        tuple((token(TokenKind::Triggers), token(TokenKind::When), newlines)),
+        conditions,
+    )(input)
+}
+
+/// Conditions joined by `and`
+fn conditions(input: Input<'_>) -> PResult<'_, Vec<Condition>> {
+    let (input, first) = terminated(condition, newlines)(input)?;
+    let (input, mut rest) = many0(preceded(
+        pair(token(TokenKind::And), newlines),
+        cut(terminated(condition, newlines)),
+    ))(input)?;
+    rest.insert(0, first);
+    Ok((input, rest))
+}
+
+/// A trigger or requirement, recording where it appears in the source
+fn condition(input: Input<'_>) -> PResult<'_, Condition> {
+    let (rest, expr) = expression(input)?;
+
+    // Span up to the end of the last token when the condition fits on one line
+    let start = &input[0];
+    let last = &input[input.len() - rest.len() - 1];
+    let length = if last.line == start.line { last.end() - start.offset } else { start.length };
+
+    Ok((rest, Condition::new(expr).with_location(location(start, length))))
+}
+
+fn requirements(input: Input<'_>) -> PResult<'_, Requirements> {
+    if let Ok((input, _)) = pair(token(TokenKind::Requires), newlines)(input) {
+        let (input, required) = cut(conditions)(input)?;
+        let (input, where_clause) =
+            opt(preceded(pair(token(TokenKind::Where), newlines), cut(conditions)))(input)?;
+        let requirements = match where_clause {
+            Some(where_clause) => Requirements::requires_where(required, where_clause),
+            None => Requirements::requires(required),
+        };
+        return Ok((input, requirements));
+    }
+
+    if let Ok((input, _)) = pair(token(TokenKind::Denies), newlines)(input) {
+        let (input, reason) = opt(preceded(
+            pair(token(TokenKind::With), token(TokenKind::Reason)),
+            cut(string),
+        ))(input)?;
+        return Ok((input, Requirements::denies(reason)));
+    }
+
+    failure(SyntaxError::at(
+        input,
+        "Invalid policy structure: Expected 'requires' or 'denies'",
+    ))
+}
+
+/// `with obligation name[(key: value, ...)]` or `with advice ...`, flagged
+/// `true` for advice
+fn obligation(input: Input<'_>) -> PResult<'_, (bool, Obligation)> {
+    let (input, _) = token(TokenKind::With)(input)?;
+    let (rest, kind) = cut(identifier)(input)?;
+    let advice = match kind.as_str() {
+        "obligation" => false,
+        "advice" => true,
+        _ => return failure(SyntaxError::unexpected(input, "obligation or advice")),
+    };
+
+    let (rest, (name, params)) = cut(pair(
+        identifier,
+        opt(preceded(
+            token(TokenKind::LParen),
+            cut(terminated(
+                pair(many0(terminated(param, token(TokenKind::Comma))), opt(param)),
+                token(TokenKind::RParen),
+            )),
+        )),
+    ))(rest)?;
+
+    let mut obligation = Obligation::new(name);
+    if let Some((params, last)) = params {
+        for (key, value) in params.into_iter().chain(last) {
+            obligation = obligation.with_param(key, value);
+        }
+    }
+    Ok((rest, (advice, obligation)))
+}
+
+/// `key: value`, in obligations and metadata
+fn param(input: Input<'_>) -> PResult<'_, (String, Value)> {
+    pair(identifier, cut(preceded(token(TokenKind::Colon), value)))(input)
+}
+
+fn metadata(input: Input<'_>) -> PResult<'_, Metadata> {
+    map(
+        preceded(
+            pair(token(TokenKind::Metadata), newlines),
+            cut(many1(terminated(param, newlines))),
+        ),
+        |fields| {
+            fields
+                .into_iter()
+                .fold(Metadata::new(), |m, (key, value)| m.add_field(key, value))
+        },
+    )(input)
+}
+
+// Expressions
+
+/// An expression, from `or` (loosest) down to primaries
+pub(crate) fn expression(input: Input<'_>) -> PResult<'_, Expression> {
+    let (input, first) = terminated(logical_and, newlines)(input)?;
+    let (input, rest) = many0(preceded(
+        pair(token(TokenKind::Or), newlines),
+        cut(terminated(logical_and, newlines)),
+    ))(input)?;
+    Ok((
+        input,
+        rest.into_iter().fold(first, |left, right| Expression::or(vec![left, right])),
+    ))
+}
+
+fn logical_and(input: Input<'_>) -> PResult<'_, Expression> {
+    let (input, first) = terminated(comparison, newlines)(input)?;
+    let (input, rest) = many0(preceded(
+        pair(token(TokenKind::And), newlines),
+        cut(terminated(comparison, newlines)),
+    ))(input)?;
+    Ok((
+        input,
+        rest.into_iter().fold(first, |left, right| Expression::and(vec![left, right])),
+    ))
+}
+
+/// At most one comparison, `matches` or `like`
+fn comparison(input: Input<'_>) -> PResult<'_, Expression> {
+    let operator = |kind: &TokenKind| match kind {
+        TokenKind::Matches => Some(BinaryOp::Matches),
+        TokenKind::Like => Some(BinaryOp::Like),
+        TokenKind::Eq => Some(BinaryOp::Comparison(ComparisonOp::Eq)),
+        TokenKind::Neq => Some(BinaryOp::Comparison(ComparisonOp::Neq)),
+        TokenKind::Lt => Some(BinaryOp::Comparison(ComparisonOp::Lt)),
+        TokenKind::Gt => Some(BinaryOp::Comparison(ComparisonOp::Gt)),
+        TokenKind::LtEq => Some(BinaryOp::Comparison(ComparisonOp::LtEq)),
+        TokenKind::GtEq => Some(BinaryOp::Comparison(ComparisonOp::GtEq)),
+        _ => None,
+    };
+    let (input, left) = in_expression(input)?;
+    let (input, right) = opt(pair(select(operator, "operator"), cut(in_expression)))(input)?;
+    Ok(match right {
+        Some((op, right)) => (input, Expression::binary(left, op, right)),
+        None => (input, left),
+    })
+}
+
+/// The set operators: `in`, `not in`, `contains`, `intersects`, `subset_of`
+fn in_expression(input: Input<'_>) -> PResult<'_, Expression> {
+    let (input, expr) = additive(input)?;
+
+    // `x not in y` is `not (x in y)`
+    if let Ok((rest, _)) = pair(token(TokenKind::Not), token(TokenKind::In))(input) {
+        let (rest, expr) = commit(in_rest(expr, rest))?;
+        return Ok((rest, Expression::logical_not(expr)));
+    }
+    if let Ok((rest, _)) = token(TokenKind::In)(input) {
+        return commit(in_rest(expr, rest));
+    }
+
+    let operator = |kind: &TokenKind| match kind {
+        TokenKind::Contains => Some(SetOperator::Contains),
+        TokenKind::Intersects => Some(SetOperator::Intersects),
+        TokenKind::SubsetOf => Some(SetOperator::SubsetOf),
+        _ => None,
+    };
+    let (input, right) = opt(pair(select(operator, "set operator"), cut(additive)))(input)?;
+    Ok(match right {
+        Some((op, right)) => (input, Expression::binary(expr, BinaryOp::Set(op), right)),
+        None => (input, expr),
+    })
+}
+
+/// The collection after `expr in`
+///
+/// A literal list stays an [`Expression::In`]; any other collection is tested
+/// at runtime.
+fn in_rest(expr: Expression, input: Input<'_>) -> PResult<'_, Expression> {
+    if let Ok((rest, _)) = token(TokenKind::LBracket)(input) {
+        let (rest, values) = cut(terminated(
+            separated_list1(token(TokenKind::Comma), value),
+            token(TokenKind::RBracket),
+        ))(rest)?;
+        return Ok((rest, Expression::in_list(expr, values)));
+    }
+
+    let (rest, collection) = additive(input)?;
+    Ok((rest, Expression::binary(expr, BinaryOp::Set(SetOperator::In), collection)))
+}
+
+/// `a + b` and `a - b`, left to right
+fn additive(input: Input<'_>) -> PResult<'_, Expression> {
+    left_associative(multiplicative, |kind| match kind {
+        TokenKind::Plus => Some(BinaryOp::Arithmetic(ArithmeticOp::Add)),
+        TokenKind::Minus => Some(BinaryOp::Arithmetic(ArithmeticOp::Sub)),
+        _ => None,
+    })(input)
+}
+
+/// `a * b`, `a / b` and `a % b`, left to right
+fn multiplicative(input: Input<'_>) -> PResult<'_, Expression> {
+    left_associative(default, |kind| match kind {
+        TokenKind::Star => Some(BinaryOp::Arithmetic(ArithmeticOp::Mul)),
+        TokenKind::Slash => Some(BinaryOp::Arithmetic(ArithmeticOp::Div)),
+        TokenKind::Percent => Some(BinaryOp::Arithmetic(ArithmeticOp::Mod)),
+        _ => None,
+    })(input)
+}
+
+/// `a ?? b`, which binds tighter than arithmetic, comparisons and `in`
+fn default(input: Input<'_>) -> PResult<'_, Expression> {
+    left_associative(primary, |kind| match kind {
+        TokenKind::Default => Some(BinaryOp::Default),
+        _ => None,
+    })(input)
+}
+
+/// Operands separated by the operators `operator` selects
+fn left_associative<'a>(
+    operand: fn(Input<'a>) -> PResult<'a, Expression>,
+    operator: fn(&TokenKind) -> Option<BinaryOp>,
+) -> impl FnMut(Input<'a>) -> PResult<'a, Expression> {
+    move |input| {
+        let (input, first) = operand(input)?;
+        let (input, rest) = many0(pair(select(operator, "operator"), cut(operand)))(input)?;
+        let expr = rest
+            .into_iter()
+            .fold(first, |left, (op, right)| Expression::binary(left, op, right));
+        Ok((input, expr))
+    }
+}
+
+fn primary(input: Input<'_>) -> PResult<'_, Expression> {
+    let Some((first, rest)) = input.split_first() else {
+        return soft(SyntaxError::at(input, "Unexpected end of file"));
+    };
+
+    match &first.kind {
+        TokenKind::StringLit(s) => Ok((rest, Expression::literal(Value::String(s.clone())))),
+        // Durations and timestamps are seconds
+        TokenKind::IntLit(n) | TokenKind::DurationLit(n) | TokenKind::TimestampLit(n) => {
+            Ok((rest, Expression::literal(Value::Int(*n))))
+        },
+        TokenKind::FloatLit(f) => Ok((rest, Expression::literal(Value::Float(*f)))),
+        TokenKind::BoolLit(b) => Ok((rest, Expression::literal(Value::Bool(*b)))),
+
+        TokenKind::Ident(_) => path_or_call(input),
+
+        // The `matches(text, pattern)` and `contains(text, part)` builtins
+        TokenKind::Matches | TokenKind::Contains if is(rest, &TokenKind::LParen) => {
+            commit(call(first.kind.to_string(), rest))
+        },
+
+        TokenKind::LBracket => map(value, Expression::literal)(input),
+
+        TokenKind::LParen => cut(terminated(expression, token(TokenKind::RParen)))(rest),
+
+        // Negation: `-2` is a literal, `-x` is `0 - x`
+        TokenKind::Minus => map(cut(primary), |operand| match operand {
+            Expression::Literal(Value::Int(n)) => Expression::literal(Value::Int(-n)),
+            Expression::Literal(Value::Float(f)) => Expression::literal(Value::Float(-f)),
+            operand => Expression::binary(
+                Expression::literal(Value::Int(0)),
+                BinaryOp::Arithmetic(ArithmeticOp::Sub),
+                operand,
+            ),
+        })(rest),
+
+        TokenKind::Not => map(cut(primary), Expression::logical_not)(rest),
+
+        kind => soft(SyntaxError::at(
+            input,
+            format!("Invalid expression: Unexpected token: {}", kind),
+        )),
+    }
+}
+
+fn path_or_call(input: Input<'_>) -> PResult<'_, Expression> {
+    let (rest, first) = identifier(input)?;
+    let (rest, more) = many0(preceded(token(TokenKind::Dot), cut(path_segment)))(rest)?;
+    let mut segments = vec![first];
+    segments.extend(more);
+    if !is(rest, &TokenKind::LParen) {
+        return Ok((rest, Expression::path(segments)));
+    }
+    if segments.len() > 1 {
+        return failure(SyntaxError::at(
+            rest,
+            "Invalid expression: Function calls cannot have path segments",
+        ));
+    }
+    commit(call(segments.remove(0), rest))
+}
+
+/// The `(args)` of a call to `name`
+fn call(name: String, input: Input<'_>) -> PResult<'_, Expression> {
+    let (input, _) = token(TokenKind::LParen)(input)?;
+    let (input, first) = opt(expression)(input)?;
+
+    let mut args = Vec::new();
+    let mut input = input;
+    if let Some(first) = first {
+        // `count(resource.items where <condition>)`
+        if is(input, &TokenKind::Where) {
+            return aggregate(&name, first, input);
+        }
+        let (rest, more) = many0(preceded(token(TokenKind::Comma), cut(expression)))(input)?;
+        args.push(first);
+        args.extend(more);
+        input = rest;
+    }
+    let (input, _) = token(TokenKind::RParen)(input)?;
+
+    // An aggregate over a bare path counts every element: `count(resource.items)`
+    if let (Some(func), [Expression::Path(path)]) =
+        (AggregateFunc::from_name(&name), args.as_slice())
+    {
+        let condition = Condition::new(Expression::literal(Value::Bool(true)));
+        let expr = Expression::Aggregate {
+            path: path.clone(),
+            func,
+            condition: Box::new(condition),
+        };
+        return Ok((input, expr));
+    }
+
+    Ok((input, Expression::Call { name, args }))
+}
+
+/// The `where <condition>)` rest of an aggregate over `collection`
+fn aggregate<'a>(name: &str, collection: Expression, input: Input<'a>) -> PResult<'a, Expression> {
+    let Some(func) = AggregateFunc::from_name(name) else {
+        return failure(SyntaxError::at(
+            input,
+            format!("Invalid expression: {}() is not an aggregate and takes no 'where'", name),
+        ));
+    };
+    let Expression::Path(path) = collection else {
+        return failure(SyntaxError::at(
+            input,
+            format!("Invalid expression: Aggregate {}() expects a collection path", name),
+        ));
+    };
+
+    let (input, condition) = delimited(
+        pair(token(TokenKind::Where), newlines),
+        expression,
+        pair(newlines, token(TokenKind::RParen)),
+    )(input)?;
+    Ok((
+        input,
+        Expression::Aggregate {
+            path,
+            func,
+            condition: Box::new(Condition::new(condition)),
+        },
+    ))
+}
+
+/// A literal value, for metadata, obligations and `in` lists
+fn value(input: Input<'_>) -> PResult<'_, Value> {
+    let Some((first, rest)) = input.split_first() else {
+        return soft(SyntaxError::at(input, "Unexpected end of file"));
+    };
+
+    match &first.kind {
+        TokenKind::StringLit(s) | TokenKind::Ident(s) => Ok((rest, Value::String(s.clone()))),
+        TokenKind::IntLit(n) | TokenKind::DurationLit(n) | TokenKind::TimestampLit(n) => {
+            Ok((rest, Value::Int(*n)))
+        },
+        TokenKind::FloatLit(f) => Ok((rest, Value::Float(*f))),
+        TokenKind::BoolLit(b) => Ok((rest, Value::Bool(*b))),
+        TokenKind::Minus => match cut(value)(rest)? {
+            (rest, Value::Int(n)) => Ok((rest, Value::Int(-n))),
+            (rest, Value::Float(f)) => Ok((rest, Value::Float(-f))),
+            (_, other) => failure(SyntaxError::at(
+                input,
+                format!("Invalid expression: Cannot negate {:?}", other),
+            )),
+        },
+        TokenKind::LBracket => map(
+            cut(terminated(
+                separated_list0(token(TokenKind::Comma), value),
+                token(TokenKind::RBracket),
+            )),
+            Value::Array,
+        )(rest),
+        kind => soft(SyntaxError::at(
+            input,
+            format!("Invalid expression: Expected value, got {}", kind),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipe_core::ast::nodes::{AggregateFunc, LogicalOp};
+
+    fn expr(source: &str) -> Expression {
+        let tokens = tokenize(source).unwrap();
+        let (rest, expr) = expression(&tokens).unwrap();
+        assert_eq!(rest.len(), 1, "unparsed input in {}", source);
+        expr
+    }
+
+    #[test]
+    fn test_precedence() {
+        let int = |n| Expression::literal(Value::Int(n));
+        let arith = |l, op, r| Expression::binary(l, BinaryOp::Arithmetic(op), r);
+        assert_eq!(
+            expr("1 + 2 * 3 - -4"),
+            arith(
+                arith(int(1), ArithmeticOp::Add, arith(int(2), ArithmeticOp::Mul, int(3))),
+                ArithmeticOp::Sub,
+                int(-4)
+            )
+        );
+
+        let Expression::Logical { op: LogicalOp::Or, operands } = expr("a or b\n and c") else {
+            panic!("expected `or` at the root");
+        };
+        assert!(matches!(operands[1], Expression::Logical { op: LogicalOp::And, .. }));
+
+        assert_eq!(
+            expr("x not in [1, 2]"),
+            Expression::logical_not(Expression::in_list(
+                Expression::path(vec!["x".to_string()]),
+                vec![Value::Int(1), Value::Int(2)]
+            ))
+        );
+        assert!(matches!(
+            expr("count(resource.items where price > 10)"),
+            Expression::Aggregate { func: AggregateFunc::Count, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_module() {
+        let source = r#"
+package acme.deploy
+
+const LIMIT = 3
+
+policy_set Group: "group"
+combining "deny-overrides"
+metadata
+  owner: platform
+{
+  policy A:
+    "a"
+    let big = resource.size > LIMIT
+    triggers when big
+    requires resource.approved == true
+    with obligation log(level: "info")
+}
+"#;
+        let module = parse_module(source).unwrap();
+        assert_eq!(module.package.as_deref(), Some("acme.deploy"));
+        let policy = module.policies().next().unwrap();
+        assert_eq!(policy.obligations[0].name, "log");
+        assert_eq!(
+            policy.metadata.as_ref().unwrap().get("owner"),
+            Some(&Value::String("platform".to_string()))
+        );
+        assert_eq!(policy.triggers[0].location.line, 14);
+        assert!(matches!(
+            &policy.triggers[0].expr,
+            Expression::Binary { right, .. } if **right == Expression::literal(Value::Int(3))
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let missing = parse_module("policy A: \"a\"\n  triggers when true\n");
+        assert!(matches!(missing, Err(ParseError::Syntax { line: 3, .. })), "{:?}", missing);
+
+        let unknown = parse_module("const A = B\nconst B = A\n");
+        assert!(matches!(unknown, Err(ParseError::Resolve(_))), "{:?}", unknown);
+
+        assert!(matches!(parse_module("policy A: \"unterminated"), Err(ParseError::Lex { .. })));
+    }
+}
@@ -0,0 +1,155 @@
+//! Differential test against the hand-written parser in `ipe_core`
+//!
+//! Every policy source embedded in the core crate's tests, plus mutations of
+//! each (truncations, deleted, duplicated and swapped tokens, extra line
+//! breaks), must be accepted by both parsers with identical modules or
+//! rejected by both.
+
+use ipe_core::parser::{Lexer, TokenKind};
+
+/// Sources of the core crate that embed policies in raw strings
+const CORPUS_FILES: &[&str] = &[
+    include_str!("../../ipe-core/src/parser/parse.rs"),
+    include_str!("../../ipe-core/src/parser/lexer.rs"),
+    include_str!("../../ipe-core/src/parser/format.rs"),
+    include_str!("../../ipe-core/src/parser/cst.rs"),
+    include_str!("../../ipe-core/src/interpreter.rs"),
+    include_str!("../../ipe-core/src/engine.rs"),
+    include_str!("../../ipe-core/src/explain.rs"),
+    include_str!("../../ipe-core/src/partial.rs"),
+    include_str!("../../ipe-core/src/sql.rs"),
+    include_str!("../../ipe-core/src/store.rs"),
+    include_str!("../../ipe-core/src/ast/visitor.rs"),
+];
+
+/// Inputs around token boundaries that the corpus does not cover
+const EDGE_CASES: &[&str] = &[
+    "",
+    "\n\n",
+    "# only a comment",
+    "package a.b policy A: \"a\" triggers when true requires x",
+    "package a.\npolicy A: \"a\" triggers when true requires x",
+    "policy A: \"a\\\nb\" triggers when true requires x",
+    "policy A: \"a\" triggers when x > 1h30m requires y < 2024-01-15T10:00:00+01:00",
+    "policy A: \"a\" triggers when x > 1h30 requires y",
+    "policy A: \"a\" triggers when x > 10sec requires y",
+    "policy A: \"a\" triggers when x > 2024-01-15 requires y",
+    "policy A: \"a\" triggers when x > 2024-01-15T10:00:00Zx requires y",
+    "policy A: \"a\" triggers when x > 1.5m requires y",
+    "policy A: \"a\" triggers when x > 12abc requires y",
+    "policy A: \"a\" triggers when x == 99999999999999999999 requires y",
+    "policy A: \"a\" triggers when x ! y requires z",
+    "policy A: \"a\" triggers when é.ñ == \"ü\" requires ok",
+    "policy A: \"a\" triggers when x in [] requires y",
+    "policy A: \"a\" triggers when x in [1,] requires y",
+    "policy A: \"a\" triggers when x == [] requires y",
+    "policy A: \"a\" triggers when x == [,] requires y",
+    "policy A: \"a\" triggers when f(,) requires y",
+    "policy A: \"a\" triggers when f(a,) requires y",
+    "policy A: \"a\" triggers when f(a\n, b) requires y",
+    "policy A: \"a\" triggers when a.b(c) requires y",
+    "policy A: \"a\" triggers when count(a where b > 1\n) > 0 requires y",
+    "policy A: \"a\" triggers when now(x where y) requires y",
+    "policy A: \"a\" triggers when - - 5 == --x requires y",
+    "policy A: \"a\" triggers when a == b == c requires y",
+    "policy A: \"a\" triggers when true denies with reason",
+    "policy A: \"a\" triggers when true denies with obligation log()",
+    "policy A: \"a\" triggers when true requires x with obligation log(,)",
+    "policy A: \"a\" triggers when true requires x with obligation log(a: 1,)",
+    "policy A: \"a\" triggers when true requires x with advice log(a: -[1])",
+    "policy A: \"a\" triggers when true requires x metadata\n  a: 1\n  \"b\"",
+    "predicate p(): true\npolicy A: \"a\" triggers when p() requires x",
+    "predicate p(a,): a\npolicy A: \"a\" triggers when p(1) requires x",
+    "predicate p(,): true",
+    "const A = 1\nconst A = 2",
+    "policy_set S: \"s\" combining \"nope\" { }",
+    "policy_set S: \"s\" { x }",
+    "policy_set S: \"s\" {",
+];
+
+/// The `r#"..."#` strings in `source`
+fn raw_strings(source: &str) -> Vec<&str> {
+    let mut strings = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("r#\"") {
+        rest = &rest[start + 3..];
+        let Some(end) = rest.find("\"#") else {
+            break;
+        };
+        strings.push(&rest[..end]);
+        rest = &rest[end + 2..];
+    }
+    strings
+}
+
+/// Token spans of `source`, as the core lexer sees them
+fn token_spans(source: &str) -> Vec<(usize, usize)> {
+    Lexer::new(source)
+        .tokenize()
+        .into_iter()
+        .filter(|token| token.kind != TokenKind::Eof)
+        .map(|token| (token.offset, token.end()))
+        .collect()
+}
+
+/// `source` and variants of it that are likely to hit parser edge cases
+fn mutations(source: &str) -> Vec<String> {
+    let mut variants = vec![source.to_string()];
+    variants.extend(source.char_indices().map(|(i, _)| source[..i].to_string()));
+
+    let spans = token_spans(source);
+    for (i, &(start, end)) in spans.iter().enumerate() {
+        let (before, token, after) = (&source[..start], &source[start..end], &source[end..]);
+        variants.push(format!("{}{}", before, after));
+        variants.push(format!("{}{} {}{}", before, token, token, after));
+        variants.push(format!("{}\n{}{}", before, token, after));
+        if let Some(&(next_start, next_end)) = spans.get(i + 1) {
+            let next = &source[next_start..next_end];
+            let between = &source[end..next_start];
+            variants.push(format!("{}{}{}{}{}", before, next, between, token, &source[next_end..]));
+        }
+    }
+    variants
+}
+
+#[test]
+fn test_parsers_agree() {
+    let corpus: Vec<&str> = CORPUS_FILES
+        .iter()
+        .flat_map(|file| raw_strings(file))
+        .chain(EDGE_CASES.iter().copied())
+        .collect();
+    let accepted = corpus.iter().filter(|s| ipe_core::parser::parse_module(s).is_ok()).count();
+    assert!(accepted >= 50, "only {} corpus sources parse", accepted);
+
+    let mut checked = 0;
+    let mut disagreements = Vec::new();
+    for source in &corpus {
+        for variant in mutations(source) {
+            checked += 1;
+            let core = ipe_core::parser::parse_module(&variant);
+            let ours = ipe_parser::parse_module(&variant);
+            let agree = match (&core, &ours) {
+                (Ok(core), Ok(ours)) => core == ours,
+                (Err(_), Err(_)) => true,
+                _ => false,
+            };
+            if !agree {
+                disagreements.push(format!(
+                    "{:?}\n  core: {:?}\n  ipe-parser: {:?}",
+                    variant,
+                    core.map(|_| "ok"),
+                    ours.map(|_| "ok")
+                ));
+            }
+        }
+    }
+
+    assert!(
+        disagreements.is_empty(),
+        "{} of {} inputs disagree:\n{}",
+        disagreements.len(),
+        checked,
+        disagreements.iter().take(10).cloned().collect::<Vec<_>>().join("\n")
+    );
+}